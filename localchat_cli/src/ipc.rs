@@ -0,0 +1,310 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::UnixStream;
+
+// --- IPC Structures (These should ideally be in a shared crate with the daemon and GUI) ---
+// For now, we redefine them and keep them compatible with localchat_daemon's version.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IpcPeer {
+    pub id: String,
+    pub username: String,
+    pub ip: String,
+    pub port: u16,
+    #[serde(default)]
+    pub status: Option<String>,
+    #[serde(default)]
+    pub rooms: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum GuiToDaemonCommand {
+    GetPeers,
+    SendMessage {
+        recipient_id: String,
+        content: String,
+        #[serde(default)]
+        reply_to: Option<String>, // Id of the message being answered
+    },
+    RequestHistory {
+        peer_id: String,
+        since_timestamp: Option<chrono::DateTime<chrono::Utc>>,
+        #[serde(default)]
+        around_message_id: Option<String>,
+    },
+    SetUsername { username: String },
+    ClearDaemonPeerCache,
+    SetStatus { status: Option<String> },
+    JoinRoom { room: String },
+    LeaveRoom { room: String },
+    GetRooms,
+    SendFile {
+        recipient_id: String,
+        file_path: String,
+    },
+    GetConversations,
+    MarkRead {
+        conversation_id: String,
+        message_id: Option<String>,
+    },
+    MarkAllRead,
+    EditMessage { message_id: String, content: String },
+    DeleteMessage { message_id: String },
+    ToggleReaction { message_id: String, emoji: String },
+    SetPinned { message_id: String, pinned: bool },
+    GetPinned { conversation_id: String },
+    SetStarred { message_id: String, starred: bool },
+    GetStarred,
+    SetExpiry { conversation_id: String, expiry_secs: Option<u64> },
+    GetExpiry { conversation_id: String },
+    ScheduleMessage { recipient_id: String, content: String, send_at: chrono::DateTime<chrono::Utc> },
+    EditScheduled {
+        scheduled_id: String,
+        #[serde(default)]
+        content: Option<String>, // None keeps the text
+        #[serde(default)]
+        send_at: Option<chrono::DateTime<chrono::Utc>>, // None keeps the time
+    },
+    CancelScheduled { scheduled_id: String },
+    GetScheduled,
+    SetDraft { conversation_id: String, content: String }, // Empty content removes the draft
+    GetDrafts,
+    SearchMessages {
+        query: String,
+        #[serde(default)]
+        peer_id: Option<String>,
+        #[serde(default)]
+        before: Option<chrono::DateTime<chrono::Utc>>,
+        #[serde(default)]
+        after: Option<chrono::DateTime<chrono::Utc>>,
+        #[serde(default)]
+        limit: Option<u32>,
+        #[serde(default)]
+        offset: u32,
+    },
+    GetDaemonInfo,
+    Shutdown, // The daemon answers with Success, then stops and closes every connection
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum DaemonToGuiMessage {
+    DaemonStatus {
+        is_connected_to_network: bool,
+        active_interface_name: Option<String>,
+    },
+    PeerList(Vec<IpcPeer>),
+    NewMessage(Message),
+    HistoryResponse {
+        peer_id: String,
+        messages: Vec<Message>,
+    },
+    Error(String),
+    IdentityInfo { user_id: String },
+    Success(String),
+    RoomList(Vec<String>),
+    ConversationList(Vec<ConversationSummary>),
+    MessageUpdated(Message), // Replaces the message with the same id (edited, deleted, reacted to, pinned or starred)
+    PinnedMessages {
+        conversation_id: String,
+        messages: Vec<Message>, // Most recently pinned first
+    },
+    StarredMessages(Vec<Message>), // Most recently starred first
+    ConversationTimer(ConversationTimer),
+    MessagesExpired {
+        conversation_id: String,
+        message_ids: Vec<String>,
+    },
+    ScheduledMessages(Vec<ScheduledMessage>), // Next to be sent first
+    Drafts(Vec<Draft>), // Most recently changed first
+    SearchResults {
+        query: String,
+        offset: u32,
+        hits: Vec<SearchHit>,
+        has_more: bool,
+    },
+    DaemonInfo(DaemonInfo), // The answer to GetDaemonInfo
+    DaemonStopping { reason: String }, // The daemon is shutting down and will close the connection
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DaemonInfo {
+    pub version: String,
+    pub pid: u32,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    pub socket_path: String,
+    pub ipc_clients: usize, // This CLI included
+    pub peers: usize,
+    #[serde(default)]
+    pub user_id: Option<String>, // None until a client sets a username
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub conversation_id: String,
+    pub message: Message,
+    pub snippet: String,
+    pub highlights: Vec<(usize, usize)>, // Byte ranges of matched words in `snippet`
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationTimer {
+    pub conversation_id: String,
+    pub expiry_secs: Option<u64>, // None: messages don't disappear
+    pub set_by: Option<String>,
+    pub set_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledMessage {
+    pub id: String,
+    pub recipient: String,
+    pub content: String,
+    pub send_at: chrono::DateTime<chrono::Utc>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    #[serde(default)]
+    pub last_error: Option<String>, // Why the latest attempt failed; the daemon tries again
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Draft {
+    pub conversation_id: String,
+    pub content: String,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationSummary {
+    pub conversation_id: String,
+    pub unread_count: u32,
+    pub last_read_message_id: Option<String>,
+    pub last_activity: chrono::DateTime<chrono::Utc>,
+    #[serde(default)]
+    pub last_message_preview: String,
+    #[serde(default)]
+    pub last_message_sender: String,
+    #[serde(default)]
+    pub last_message_is_self: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Message {
+    pub id: String,
+    pub sender: String,
+    pub recipient: String,
+    pub content: String,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub is_self: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attachment: Option<Attachment>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edited_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    pub deleted: bool, // Deleted for everyone; only a tombstone is left
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<String>, // Id of the message this one answers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_preview: Option<ReplyPreview>, // The parent as the sender saw it
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reactions: Vec<Reaction>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mentions: Vec<String>, // Full IDs @mentioned in a room message
+    #[serde(default)]
+    pub mentions_me: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pinned_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pinned_by: Option<String>,
+    #[serde(default)]
+    pub starred: bool, // Only for us; stars aren't shared
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_in: Option<u64>, // Seconds after `timestamp` until it disappears
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reaction {
+    pub emoji: String,
+    pub reactors: Vec<String>,
+    #[serde(default)]
+    pub by_self: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplyPreview {
+    pub sender: String,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attachment {
+    pub file_name: String,
+    pub size_bytes: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub local_path: Option<String>,
+}
+// --- End IPC Structures ---
+
+// A newline-delimited JSON connection to the daemon's Unix socket, same framing the GUI uses.
+pub struct DaemonConnection {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    writer: OwnedWriteHalf,
+}
+
+impl DaemonConnection {
+    pub async fn connect(socket_path: &str) -> Result<Self, Box<dyn Error>> {
+        let stream = UnixStream::connect(socket_path)
+            .await
+            .map_err(|e| format!("Could not connect to daemon at {}: {}", socket_path, e))?;
+        let (reader, writer) = stream.into_split();
+        Ok(Self {
+            lines: BufReader::new(reader).lines(),
+            writer,
+        })
+    }
+
+    pub async fn send(&mut self, command: &GuiToDaemonCommand) -> Result<(), Box<dyn Error>> {
+        let json_cmd = serde_json::to_string(command)?;
+        self.writer.write_all(format!("{}\n", json_cmd).as_bytes()).await?;
+        self.writer.flush().await?;
+        Ok(())
+    }
+
+    // Returns the next message from the daemon, or None once the daemon closes the socket.
+    // Lines that fail to deserialize (e.g. a newer daemon variant) are skipped.
+    pub async fn next_message(&mut self) -> Result<Option<DaemonToGuiMessage>, Box<dyn Error>> {
+        while let Some(line) = self.lines.next_line().await? {
+            let trimmed_line = line.trim();
+            if trimmed_line.is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<DaemonToGuiMessage>(trimmed_line) {
+                Ok(msg) => return Ok(Some(msg)),
+                Err(e) => eprintln!("Ignoring unrecognised message from daemon: {}. Line: {}", e, trimmed_line),
+            }
+        }
+        Ok(None)
+    }
+
+    // Sends a command and waits for the response picked out by `extract`.
+    // The daemon has no request ids, so unrelated traffic (status, events) is skipped;
+    // a DaemonToGuiMessage::Error is treated as the response and returned as Err.
+    pub async fn request<T>(
+        &mut self,
+        command: &GuiToDaemonCommand,
+        mut extract: impl FnMut(DaemonToGuiMessage) -> Option<T>,
+    ) -> Result<T, Box<dyn Error>> {
+        self.send(command).await?;
+        loop {
+            match self.next_message().await? {
+                Some(DaemonToGuiMessage::Error(err_msg)) => return Err(format!("Daemon error: {}", err_msg).into()),
+                Some(DaemonToGuiMessage::DaemonStopping { reason }) => return Err(format!("Daemon is stopping ({})", reason).into()),
+                Some(msg) => {
+                    if let Some(response) = extract(msg) {
+                        return Ok(response);
+                    }
+                }
+                None => return Err("Daemon closed the connection before responding".into()),
+            }
+        }
+    }
+}
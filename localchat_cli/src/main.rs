@@ -0,0 +1,831 @@
+use clap::{Parser, Subcommand};
+use std::error::Error;
+use std::io::{IsTerminal, Read}; // Reading message text from stdin; bold search matches only on a terminal
+use std::time::Duration;
+
+mod ipc;
+mod tui;
+
+use ipc::{DaemonConnection, DaemonToGuiMessage, GuiToDaemonCommand, IpcPeer, Message};
+
+const DEFAULT_SOCKET_PATH: &str = "/tmp/localchat_daemon.sock"; // Same default as the daemon
+const MAX_SCHEDULE_AHEAD_DAYS: i64 = 365; // The daemon refuses scheduled messages further ahead
+
+// Define CLI arguments
+#[derive(Parser, Debug)]
+#[command(name = "localchat-cli", author, version, about = "Command-line client for the LocalChat daemon", long_about = None)]
+struct Args {
+    /// Path of the daemon's Unix socket
+    #[arg(long, env = "LOCALCHAT_SOCKET_PATH", global = true, conflicts_with = "instance")]
+    socket: Option<String>,
+
+    /// Talk to the daemon of a GUI instance (uses /tmp/localchat_daemon<N>.sock, like `localchat_gui --instance N`)
+    #[arg(long, global = true)]
+    instance: Option<u16>,
+
+    /// Print daemon responses and events as JSON lines instead of human-readable text
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Seconds to wait for the daemon to answer a command
+    #[arg(long, global = true, default_value_t = 10)]
+    timeout: u64,
+
+    #[command(subcommand)]
+    command: CliCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum CliCommand {
+    /// List the peers the daemon has discovered
+    Peers,
+    /// Send a message to a peer (by id or username) or a room (#name). Use `-` as text to read it from stdin
+    Send {
+        peer: String,
+        #[arg(required = true, num_args = 1..)]
+        text: Vec<String>,
+        /// Id of a message in the same conversation to reply to (ids are in `history --json`)
+        #[arg(long)]
+        reply_to: Option<String>,
+    },
+    /// Send a file (up to 10 MB) to a peer or a room
+    SendFile { peer: String, path: std::path::PathBuf },
+    /// Change the text of a message you sent (within 15 minutes); ids are in `history --json`
+    Edit {
+        message_id: String,
+        #[arg(required = true, num_args = 1..)]
+        text: Vec<String>,
+    },
+    /// Delete a message you sent for everyone (within 24 hours)
+    Delete { message_id: String },
+    /// React to a message with an emoji; running it again takes the reaction back
+    React { message_id: String, emoji: String },
+    /// Pin a message for everyone in its conversation
+    Pin { message_id: String },
+    /// Unpin a message for everyone in its conversation
+    Unpin { message_id: String },
+    /// List the pinned messages of a conversation with a peer or a room (#name)
+    Pins { peer: String },
+    /// Star a message; stars are private and not shared with peers
+    Star { message_id: String },
+    /// Remove a message's star
+    Unstar { message_id: String },
+    /// List starred messages from every conversation, most recently starred first
+    Starred,
+    /// Show the disappearing-messages timer of a conversation with a peer or a room (#name), or set it for everyone in it
+    Timer {
+        peer: String,
+        /// How long messages stay, e.g. 30m, 1h, 1d or 1w; `off` to keep them
+        duration: Option<String>,
+    },
+    /// Send a message later. The daemon sends it at that time, or once the peer is back online
+    Schedule {
+        peer: String,
+        /// When: a time like 17:00 (tomorrow if it has passed), a delay like 30m or 2h, or an RFC 3339 timestamp
+        when: String,
+        #[arg(required = true, num_args = 1..)]
+        text: Vec<String>,
+    },
+    /// List the messages waiting to be sent, next first
+    Scheduled,
+    /// Change the text and/or time of a scheduled message; ids are in `scheduled`
+    EditScheduled {
+        scheduled_id: String,
+        /// New time, in the same forms as `schedule`
+        #[arg(long)]
+        at: Option<String>,
+        /// New text; the old text is kept if omitted
+        text: Vec<String>,
+    },
+    /// Cancel a scheduled message
+    Unschedule { scheduled_id: String },
+    /// List unsent drafts from the GUI, most recently changed first
+    Drafts,
+    /// Show the message history with a peer or a room (#name), including messages you sent
+    History {
+        peer: String,
+        /// Only return messages after this RFC 3339 timestamp
+        #[arg(long)]
+        since: Option<chrono::DateTime<chrono::Utc>>,
+    },
+    /// Set the username the daemon announces on the network
+    SetUsername { username: String },
+    /// Clear the daemon's cached peer list
+    ClearPeerCache,
+    /// Show the daemon's version, PID and uptime; with an argument, set the status other peers see (e.g. "away", or "online" to clear it)
+    Status { status: Vec<String> },
+    /// Stop the daemon (it finishes the commands it is running first)
+    Stop,
+    /// List the rooms the daemon is in
+    Rooms,
+    /// Join a room (e.g. `join lunch`)
+    Join { room: String },
+    /// Leave a room
+    Leave { room: String },
+    /// Full-text search over stored messages (content and sender names), best match first
+    Search {
+        /// Words to look for (each matches the start of a word); may be omitted when --peer, --after or --before is given
+        query: Vec<String>,
+        /// Only the conversation with this peer (by id or username) or room (#name)
+        #[arg(long)]
+        peer: Option<String>,
+        /// Only messages at or after this RFC 3339 timestamp
+        #[arg(long)]
+        after: Option<chrono::DateTime<chrono::Utc>>,
+        /// Only messages before this RFC 3339 timestamp
+        #[arg(long)]
+        before: Option<chrono::DateTime<chrono::Utc>>,
+        /// Results per page (the daemon allows up to 200)
+        #[arg(long, default_value_t = 20)]
+        limit: u32,
+        /// Page to show, starting at 1
+        #[arg(long, default_value_t = 1)]
+        page: u32,
+    },
+    /// List conversations with their unread counts, most recently active first
+    Conversations,
+    /// Mark a conversation (peer or #room) as read, or every conversation with --all
+    MarkRead {
+        #[arg(required_unless_present = "all", conflicts_with = "all")]
+        peer: Option<String>,
+        #[arg(long)]
+        all: bool,
+    },
+    /// Stream messages (received, and sent from any client) until interrupted
+    Tail {
+        /// Only show messages from this peer (by id or username)
+        #[arg(long)]
+        peer: Option<String>,
+    },
+    /// Open the full-screen terminal chat UI
+    Tui,
+}
+
+impl Args {
+    fn socket_path(&self) -> String {
+        if let Some(ref socket) = self.socket {
+            socket.clone()
+        } else if let Some(instance) = self.instance {
+            format!("/tmp/localchat_daemon{}.sock", instance)
+        } else {
+            DEFAULT_SOCKET_PATH.to_string()
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+    if let Err(e) = run(args).await {
+        eprintln!("localchat-cli: {}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let socket_path = args.socket_path();
+    let mut connection = DaemonConnection::connect(&socket_path).await?;
+    let response_timeout = Duration::from_secs(args.timeout);
+
+    match args.command {
+        CliCommand::Peers => {
+            let response = with_timeout(response_timeout, connection.request(&GuiToDaemonCommand::GetPeers, |msg| {
+                matches!(msg, DaemonToGuiMessage::PeerList(_)).then_some(msg)
+            })).await?;
+            if args.json {
+                print_json(&response)?;
+            } else if let DaemonToGuiMessage::PeerList(peers) = response {
+                if peers.is_empty() {
+                    println!("No peers found.");
+                }
+                for peer in peers {
+                    let status = peer.status.as_deref().unwrap_or("online");
+                    println!("{}\t{}\t{}:{}\t{}\t{}", peer.username, peer.id, peer.ip, peer.port, status, peer.rooms.join(","));
+                }
+            }
+        }
+        CliCommand::Send { peer, text, reply_to } => {
+            let content = if text.len() == 1 && text[0] == "-" {
+                let mut stdin_text = String::new();
+                std::io::stdin().read_to_string(&mut stdin_text)?;
+                stdin_text.trim_end().to_string()
+            } else {
+                text.join(" ")
+            };
+            if content.trim().is_empty() {
+                return Err("Refusing to send an empty message".into());
+            }
+            let recipient_id = with_timeout(response_timeout, resolve_recipient(&mut connection, &peer)).await?;
+            let command = GuiToDaemonCommand::SendMessage { recipient_id, content, reply_to };
+            let response = with_timeout(response_timeout, connection.request(&command, |msg| {
+                matches!(msg, DaemonToGuiMessage::Success(_)).then_some(msg)
+            })).await?;
+            if args.json {
+                print_json(&response)?;
+            } else if let DaemonToGuiMessage::Success(msg) = response {
+                println!("{}", msg);
+            }
+        }
+        CliCommand::SendFile { peer, path } => {
+            // The daemon reads the file, so hand it a path that doesn't depend on our working directory
+            let file_path = std::fs::canonicalize(&path)
+                .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?
+                .to_string_lossy()
+                .into_owned();
+            let recipient_id = with_timeout(response_timeout, resolve_recipient(&mut connection, &peer)).await?;
+            let command = GuiToDaemonCommand::SendFile { recipient_id, file_path };
+            let response = with_timeout(response_timeout, connection.request(&command, |msg| {
+                matches!(msg, DaemonToGuiMessage::Success(_)).then_some(msg)
+            })).await?;
+            if args.json {
+                print_json(&response)?;
+            } else if let DaemonToGuiMessage::Success(msg) = response {
+                println!("{}", msg);
+            }
+        }
+        CliCommand::Edit { .. }
+        | CliCommand::Delete { .. }
+        | CliCommand::React { .. }
+        | CliCommand::Pin { .. }
+        | CliCommand::Unpin { .. }
+        | CliCommand::Star { .. }
+        | CliCommand::Unstar { .. } => {
+            let command = match args.command {
+                CliCommand::Edit { message_id, text } => GuiToDaemonCommand::EditMessage { message_id, content: text.join(" ") },
+                CliCommand::Delete { message_id } => GuiToDaemonCommand::DeleteMessage { message_id },
+                CliCommand::React { message_id, emoji } => GuiToDaemonCommand::ToggleReaction { message_id, emoji },
+                CliCommand::Pin { message_id } => GuiToDaemonCommand::SetPinned { message_id, pinned: true },
+                CliCommand::Unpin { message_id } => GuiToDaemonCommand::SetPinned { message_id, pinned: false },
+                CliCommand::Star { message_id } => GuiToDaemonCommand::SetStarred { message_id, starred: true },
+                CliCommand::Unstar { message_id } => GuiToDaemonCommand::SetStarred { message_id, starred: false },
+                _ => unreachable!(),
+            };
+            let response = with_timeout(response_timeout, connection.request(&command, |msg| {
+                matches!(msg, DaemonToGuiMessage::Success(_)).then_some(msg)
+            })).await?;
+            if args.json {
+                print_json(&response)?;
+            } else if let DaemonToGuiMessage::Success(msg) = response {
+                println!("{}", msg);
+            }
+        }
+        CliCommand::History { peer, since } => {
+            // History is keyed by peer id; fall back to the raw argument if the peer is not currently online
+            let peer_id = match with_timeout(response_timeout, resolve_peer(&mut connection, &peer)).await {
+                Ok(resolved) => resolved.id,
+                Err(_) => peer,
+            };
+            let command = GuiToDaemonCommand::RequestHistory { peer_id, since_timestamp: since, around_message_id: None };
+            let response = with_timeout(response_timeout, connection.request(&command, |msg| {
+                matches!(msg, DaemonToGuiMessage::HistoryResponse { .. }).then_some(msg)
+            })).await?;
+            if args.json {
+                print_json(&response)?;
+            } else if let DaemonToGuiMessage::HistoryResponse { messages, .. } = response {
+                if messages.is_empty() {
+                    println!("No messages.");
+                }
+                for message in &messages {
+                    print_message(message);
+                }
+            }
+        }
+        CliCommand::Pins { peer } => {
+            // Like history, conversations with peers that went offline can still be given by full id
+            let conversation_id = match with_timeout(response_timeout, resolve_recipient(&mut connection, &peer)).await {
+                Ok(resolved) => resolved,
+                Err(_) => peer,
+            };
+            let command = GuiToDaemonCommand::GetPinned { conversation_id };
+            let response = with_timeout(response_timeout, connection.request(&command, |msg| {
+                matches!(msg, DaemonToGuiMessage::PinnedMessages { .. }).then_some(msg)
+            })).await?;
+            if args.json {
+                print_json(&response)?;
+            } else if let DaemonToGuiMessage::PinnedMessages { messages, .. } = response {
+                if messages.is_empty() {
+                    println!("No pinned messages.");
+                }
+                for message in &messages {
+                    print_message(message);
+                }
+            }
+        }
+        CliCommand::Starred => {
+            let response = with_timeout(response_timeout, connection.request(&GuiToDaemonCommand::GetStarred, |msg| {
+                matches!(msg, DaemonToGuiMessage::StarredMessages(_)).then_some(msg)
+            })).await?;
+            if args.json {
+                print_json(&response)?;
+            } else if let DaemonToGuiMessage::StarredMessages(messages) = response {
+                if messages.is_empty() {
+                    println!("No starred messages.");
+                }
+                for message in &messages {
+                    // Starred messages come from every conversation, so say which one
+                    let conversation_id = if message.is_self || message.recipient.starts_with('#') { &message.recipient } else { &message.sender };
+                    print!("{}\t", conversation_id);
+                    print_message(message);
+                }
+            }
+        }
+        CliCommand::Timer { peer, duration } => {
+            // Like history, conversations with peers that went offline can still be given by full id
+            let conversation_id = match with_timeout(response_timeout, resolve_recipient(&mut connection, &peer)).await {
+                Ok(resolved) => resolved,
+                Err(_) => peer,
+            };
+            let command = match duration {
+                Some(duration) => GuiToDaemonCommand::SetExpiry { conversation_id, expiry_secs: parse_duration(&duration)? },
+                None => GuiToDaemonCommand::GetExpiry { conversation_id },
+            };
+            let response = with_timeout(response_timeout, connection.request(&command, |msg| {
+                matches!(msg, DaemonToGuiMessage::Success(_) | DaemonToGuiMessage::ConversationTimer(_)).then_some(msg)
+            })).await?;
+            if args.json {
+                print_json(&response)?;
+            } else if let DaemonToGuiMessage::Success(msg) = response {
+                println!("{}", msg);
+            } else if let DaemonToGuiMessage::ConversationTimer(timer) = response {
+                match (timer.expiry_secs, timer.set_by) {
+                    (Some(secs), Some(set_by)) => println!("Messages disappear after {} (set by {}).", format_duration(secs), set_by),
+                    (Some(secs), None) => println!("Messages disappear after {}.", format_duration(secs)),
+                    (None, _) => println!("Disappearing messages are off."),
+                }
+            }
+        }
+        CliCommand::Schedule { .. } | CliCommand::EditScheduled { .. } | CliCommand::Unschedule { .. } => {
+            let command = match args.command {
+                CliCommand::Schedule { peer, when, text } => {
+                    // Peers that are offline now can be given by full id; the daemon waits for them
+                    let recipient_id = match with_timeout(response_timeout, resolve_recipient(&mut connection, &peer)).await {
+                        Ok(resolved) => resolved,
+                        Err(_) => peer,
+                    };
+                    GuiToDaemonCommand::ScheduleMessage { recipient_id, content: text.join(" "), send_at: parse_send_time(&when)? }
+                }
+                CliCommand::EditScheduled { scheduled_id, at, text } => {
+                    if at.is_none() && text.is_empty() {
+                        return Err("Give a new time with --at, new text, or both".into());
+                    }
+                    GuiToDaemonCommand::EditScheduled {
+                        scheduled_id,
+                        content: Some(text.join(" ")).filter(|_| !text.is_empty()),
+                        send_at: at.as_deref().map(parse_send_time).transpose()?,
+                    }
+                }
+                CliCommand::Unschedule { scheduled_id } => GuiToDaemonCommand::CancelScheduled { scheduled_id },
+                _ => unreachable!(),
+            };
+            let response = with_timeout(response_timeout, connection.request(&command, |msg| {
+                matches!(msg, DaemonToGuiMessage::Success(_)).then_some(msg)
+            })).await?;
+            if args.json {
+                print_json(&response)?;
+            } else if let DaemonToGuiMessage::Success(msg) = response {
+                println!("{}", msg);
+            }
+        }
+        CliCommand::Scheduled => {
+            let response = with_timeout(response_timeout, connection.request(&GuiToDaemonCommand::GetScheduled, |msg| {
+                matches!(msg, DaemonToGuiMessage::ScheduledMessages(_)).then_some(msg)
+            })).await?;
+            if args.json {
+                print_json(&response)?;
+            } else if let DaemonToGuiMessage::ScheduledMessages(scheduled) = response {
+                if scheduled.is_empty() {
+                    println!("No scheduled messages.");
+                }
+                for scheduled in &scheduled {
+                    let send_at = scheduled.send_at.with_timezone(&chrono::Local);
+                    println!("{}	{}	{}	{}", scheduled.id, send_at.format("%Y-%m-%d %H:%M"), scheduled.recipient, scheduled.content);
+                    if let Some(last_error) = scheduled.last_error.as_ref() {
+                        println!("    last attempt failed: {}", last_error);
+                    }
+                }
+            }
+        }
+        CliCommand::Drafts => {
+            let response = with_timeout(response_timeout, connection.request(&GuiToDaemonCommand::GetDrafts, |msg| {
+                matches!(msg, DaemonToGuiMessage::Drafts(_)).then_some(msg)
+            })).await?;
+            if args.json {
+                print_json(&response)?;
+            } else if let DaemonToGuiMessage::Drafts(drafts) = response {
+                if drafts.is_empty() {
+                    println!("No drafts.");
+                }
+                for draft in &drafts {
+                    let updated_at = draft.updated_at.with_timezone(&chrono::Local);
+                    println!("{}\t{}\t{}", draft.conversation_id, updated_at.format("%Y-%m-%d %H:%M"), draft.content);
+                }
+            }
+        }
+        CliCommand::SetUsername { username } => {
+            let command = GuiToDaemonCommand::SetUsername { username };
+            let response = with_timeout(response_timeout, connection.request(&command, |msg| {
+                matches!(msg, DaemonToGuiMessage::IdentityInfo { .. }).then_some(msg)
+            })).await?;
+            if args.json {
+                print_json(&response)?;
+            } else if let DaemonToGuiMessage::IdentityInfo { user_id } = response {
+                println!("Username set. Full ID: {}", user_id);
+            }
+        }
+        CliCommand::ClearPeerCache => {
+            let response = with_timeout(response_timeout, connection.request(&GuiToDaemonCommand::ClearDaemonPeerCache, |msg| {
+                matches!(msg, DaemonToGuiMessage::Success(_)).then_some(msg)
+            })).await?;
+            if args.json {
+                print_json(&response)?;
+            } else if let DaemonToGuiMessage::Success(msg) = response {
+                println!("{}", msg);
+            }
+        }
+        CliCommand::Status { status } if status.is_empty() => {
+            let response = with_timeout(response_timeout, connection.request(&GuiToDaemonCommand::GetDaemonInfo, |msg| {
+                matches!(msg, DaemonToGuiMessage::DaemonInfo(_)).then_some(msg)
+            })).await?;
+            if args.json {
+                print_json(&response)?;
+            } else if let DaemonToGuiMessage::DaemonInfo(info) = response {
+                let started_at = info.started_at.with_timezone(&chrono::Local);
+                println!("localchat_daemon {} (PID {})", info.version, info.pid);
+                println!("Socket:   {}", info.socket_path);
+                println!("Up since: {} ({})", started_at.format("%Y-%m-%d %H:%M"), format_uptime(chrono::Utc::now() - info.started_at));
+                println!("Clients:  {}", info.ipc_clients);
+                println!("Peers:    {}", info.peers);
+                println!("Identity: {}", info.user_id.as_deref().unwrap_or("(no username set)"));
+            }
+        }
+        CliCommand::Status { status } => {
+            // Like /status in the GUI, "online" clears the status
+            let status = Some(status.join(" ")).filter(|s| !s.trim().is_empty() && !s.trim().eq_ignore_ascii_case("online"));
+            let response = with_timeout(response_timeout, connection.request(&GuiToDaemonCommand::SetStatus { status }, |msg| {
+                matches!(msg, DaemonToGuiMessage::Success(_)).then_some(msg)
+            })).await?;
+            if args.json {
+                print_json(&response)?;
+            } else if let DaemonToGuiMessage::Success(msg) = response {
+                println!("{}", msg);
+            }
+        }
+        CliCommand::Stop => {
+            let response = with_timeout(response_timeout, connection.request(&GuiToDaemonCommand::Shutdown, |msg| {
+                matches!(msg, DaemonToGuiMessage::Success(_)).then_some(msg)
+            })).await?;
+            if args.json {
+                print_json(&response)?;
+            } else if let DaemonToGuiMessage::Success(msg) = response {
+                println!("{}", msg);
+            }
+            // The daemon closes the connection once the commands it was running have finished
+            with_timeout(response_timeout, async {
+                while connection.next_message().await?.is_some() {}
+                Ok(())
+            }).await?;
+            if !args.json {
+                println!("Daemon stopped.");
+            }
+        }
+        CliCommand::Rooms | CliCommand::Join { .. } | CliCommand::Leave { .. } => {
+            let command = match args.command {
+                CliCommand::Join { room } => GuiToDaemonCommand::JoinRoom { room },
+                CliCommand::Leave { room } => GuiToDaemonCommand::LeaveRoom { room },
+                _ => GuiToDaemonCommand::GetRooms,
+            };
+            let response = with_timeout(response_timeout, connection.request(&command, |msg| {
+                matches!(msg, DaemonToGuiMessage::RoomList(_)).then_some(msg)
+            })).await?;
+            if args.json {
+                print_json(&response)?;
+            } else if let DaemonToGuiMessage::RoomList(rooms) = response {
+                for room in rooms {
+                    println!("{}", room);
+                }
+            }
+        }
+        CliCommand::Search { query, peer, after, before, limit, page } => {
+            let peer_id = match peer {
+                // Like mark-read, conversations with peers that went offline can still be given by full id
+                Some(peer) => Some(match with_timeout(response_timeout, resolve_recipient(&mut connection, &peer)).await {
+                    Ok(resolved) => resolved,
+                    Err(_) => peer,
+                }),
+                None => None,
+            };
+            let command = GuiToDaemonCommand::SearchMessages {
+                query: query.join(" "),
+                peer_id,
+                before,
+                after,
+                limit: Some(limit),
+                offset: page.saturating_sub(1) * limit,
+            };
+            let response = with_timeout(response_timeout, connection.request(&command, |msg| {
+                matches!(msg, DaemonToGuiMessage::SearchResults { .. }).then_some(msg)
+            })).await?;
+            if args.json {
+                print_json(&response)?;
+            } else if let DaemonToGuiMessage::SearchResults { hits, has_more, .. } = response {
+                if hits.is_empty() {
+                    println!("No matches.");
+                }
+                // Matched words in bold, unless the output goes to a file or pipe
+                let use_bold = std::io::stdout().is_terminal();
+                for hit in &hits {
+                    let local_time = hit.message.timestamp.with_timezone(&chrono::Local);
+                    let sender = if hit.message.is_self { "You" } else { hit.message.sender.as_str() };
+                    println!(
+                        "{}\t[{}] {}: {}",
+                        hit.conversation_id,
+                        local_time.format("%Y-%m-%d %H:%M"),
+                        sender,
+                        highlight_snippet(&hit.snippet, &hit.highlights, use_bold)
+                    );
+                }
+                if has_more {
+                    println!("(more results: --page {})", page.max(1) + 1);
+                }
+            }
+        }
+        CliCommand::Conversations | CliCommand::MarkRead { .. } => {
+            let command = match args.command {
+                CliCommand::MarkRead { peer: Some(peer), .. } => {
+                    // Conversations with peers that went offline can still be marked by their full id
+                    let conversation_id = match with_timeout(response_timeout, resolve_recipient(&mut connection, &peer)).await {
+                        Ok(resolved) => resolved,
+                        Err(_) => peer,
+                    };
+                    GuiToDaemonCommand::MarkRead { conversation_id, message_id: None }
+                }
+                CliCommand::MarkRead { peer: None, .. } => GuiToDaemonCommand::MarkAllRead,
+                _ => GuiToDaemonCommand::GetConversations,
+            };
+            let response = with_timeout(response_timeout, connection.request(&command, |msg| {
+                matches!(msg, DaemonToGuiMessage::ConversationList(_)).then_some(msg)
+            })).await?;
+            if args.json {
+                print_json(&response)?;
+            } else if let DaemonToGuiMessage::ConversationList(conversations) = response {
+                if conversations.is_empty() {
+                    println!("No conversations yet.");
+                }
+                for conversation in conversations {
+                    let last_activity = conversation.last_activity.with_timezone(&chrono::Local);
+                    println!("{}\t{} unread\t{}", conversation.conversation_id, conversation.unread_count, last_activity.format("%Y-%m-%d %H:%M"));
+                }
+            }
+        }
+        CliCommand::Tail { peer } => {
+            let peer_filter = match peer {
+                Some(peer) => Some(with_timeout(response_timeout, resolve_recipient(&mut connection, &peer)).await?),
+                None => None,
+            };
+            if !args.json {
+                eprintln!("Waiting for messages on {} (Ctrl+C to stop)...", socket_path);
+            }
+            while let Some(msg) = connection.next_message().await? {
+                if let DaemonToGuiMessage::DaemonStopping { reason } = msg {
+                    return Err(format!("Daemon is stopping ({})", reason).into());
+                }
+                if let DaemonToGuiMessage::NewMessage(ref message) | DaemonToGuiMessage::MessageUpdated(ref message) = msg {
+                    if peer_filter.as_ref().is_some_and(|id| *id != message.sender && *id != message.recipient) {
+                        continue;
+                    }
+                    if args.json {
+                        print_json(&msg)?;
+                    } else {
+                        print_message(message);
+                    }
+                }
+            }
+            return Err("Daemon closed the connection".into());
+        }
+        CliCommand::Tui => {
+            tui::run(connection, socket_path).await?;
+        }
+    }
+    Ok(())
+}
+
+// Looks a peer up by exact id first, then by (case-insensitive) username.
+async fn resolve_peer(connection: &mut DaemonConnection, peer: &str) -> Result<IpcPeer, Box<dyn Error>> {
+    let peers = connection.request(&GuiToDaemonCommand::GetPeers, |msg| match msg {
+        DaemonToGuiMessage::PeerList(peers) => Some(peers),
+        _ => None,
+    }).await?;
+
+    if let Some(found) = peers.iter().find(|p| p.id == peer) {
+        return Ok(found.clone());
+    }
+    let by_username: Vec<&IpcPeer> = peers.iter().filter(|p| p.username.eq_ignore_ascii_case(peer)).collect();
+    match by_username.as_slice() {
+        [single] => Ok((*single).clone()),
+        [] => Err(format!("No peer with id or username '{}' (run `localchat-cli peers`)", peer).into()),
+        _ => Err(format!("Username '{}' is ambiguous, use the peer id instead (run `localchat-cli peers`)", peer).into()),
+    }
+}
+
+// Rooms ("#lunch") are passed through as-is; anything else is looked up as a peer
+async fn resolve_recipient(connection: &mut DaemonConnection, recipient: &str) -> Result<String, Box<dyn Error>> {
+    if recipient.starts_with('#') {
+        return Ok(recipient.to_string());
+    }
+    Ok(resolve_peer(connection, recipient).await?.id)
+}
+
+async fn with_timeout<T>(
+    duration: Duration,
+    future: impl std::future::Future<Output = Result<T, Box<dyn Error>>>,
+) -> Result<T, Box<dyn Error>> {
+    match tokio::time::timeout(duration, future).await {
+        Ok(result) => result,
+        Err(_) => Err(format!("Timed out after {}s waiting for the daemon", duration.as_secs()).into()),
+    }
+}
+
+fn print_json(msg: &DaemonToGuiMessage) -> Result<(), Box<dyn Error>> {
+    println!("{}", serde_json::to_string(msg)?);
+    Ok(())
+}
+
+// Wraps the highlighted byte ranges of a search snippet in ANSI bold
+fn highlight_snippet(snippet: &str, highlights: &[(usize, usize)], use_bold: bool) -> String {
+    if !use_bold {
+        return snippet.to_string();
+    }
+    let mut highlighted = String::with_capacity(snippet.len());
+    let mut position = 0;
+    for &(start, end) in highlights {
+        // Ranges come from the daemon; skip any that don't fit this snippet rather than panic
+        let (Some(before), Some(matched)) = (snippet.get(position..start), snippet.get(start..end)) else {
+            continue;
+        };
+        highlighted.push_str(before);
+        highlighted.push_str("\x1b[1m");
+        highlighted.push_str(matched);
+        highlighted.push_str("\x1b[0m");
+        position = end;
+    }
+    highlighted.push_str(snippet.get(position..).unwrap_or_default());
+    highlighted
+}
+
+// "30s", "10m", "1h", "2d", "1w" into seconds; "off" into None
+fn parse_duration(text: &str) -> Result<Option<u64>, Box<dyn Error>> {
+    let text = text.trim().to_lowercase();
+    if text == "off" {
+        return Ok(None);
+    }
+    let split_at = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let (count, unit) = text.split_at(split_at);
+    let count: u64 = count.parse().map_err(|_| format!("'{}' is not a duration like 30m, 1h, 1d or off", text))?;
+    let unit_secs = match unit {
+        "s" => 1,
+        "m" | "" => 60, // A bare number is minutes
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(format!("Unknown unit '{}' in '{}'; use s, m, h, d or w", unit, text).into()),
+    };
+    let secs = count.checked_mul(unit_secs).ok_or_else(|| format!("'{}' is too long", text))?;
+    Ok(Some(secs))
+}
+
+// "17:00" (the next time the clock shows it), a delay like "30m", or an RFC 3339 timestamp
+fn parse_send_time(text: &str) -> Result<chrono::DateTime<chrono::Utc>, Box<dyn Error>> {
+    use chrono::TimeZone;
+    let text = text.trim();
+    if let Ok(timestamp) = chrono::DateTime::parse_from_rfc3339(text) {
+        return Ok(timestamp.with_timezone(&chrono::Utc));
+    }
+    if let Ok(time) = chrono::NaiveTime::parse_from_str(text, "%H:%M") {
+        let now = chrono::Local::now();
+        let mut date = now.date_naive();
+        if time <= now.time() {
+            date = date.succ_opt().ok_or("Date out of range")?;
+        }
+        let local_time = chrono::Local.from_local_datetime(&date.and_time(time))
+            .earliest()
+            .ok_or_else(|| format!("{} doesn't exist on {} in the local time zone", text, date))?;
+        return Ok(local_time.with_timezone(&chrono::Utc));
+    }
+    match parse_duration(text) {
+        Ok(Some(secs)) => delay_from_now(secs)
+            .ok_or_else(|| format!("'{}' is too far ahead; messages can be scheduled at most {} days ahead", text, MAX_SCHEDULE_AHEAD_DAYS).into()),
+        _ => Err(format!("'{}' is not a time like 17:00, a delay like 30m or 2h, or an RFC 3339 timestamp", text).into()),
+    }
+}
+
+// Now plus a delay, or None past the daemon's scheduling limit (which also keeps the date arithmetic from overflowing)
+fn delay_from_now(secs: u64) -> Option<chrono::DateTime<chrono::Utc>> {
+    let delay = chrono::TimeDelta::try_seconds(i64::try_from(secs).ok()?)?;
+    if delay > chrono::TimeDelta::days(MAX_SCHEDULE_AHEAD_DAYS) {
+        return None;
+    }
+    chrono::Utc::now().checked_add_signed(delay)
+}
+
+// The largest whole unit: "90 minutes" stays "90m", "86400" seconds is "1d"
+fn format_duration(secs: u64) -> String {
+    match secs {
+        secs if secs % (7 * 86_400) == 0 => format!("{}w", secs / (7 * 86_400)),
+        secs if secs % 86_400 == 0 => format!("{}d", secs / 86_400),
+        secs if secs % 3_600 == 0 => format!("{}h", secs / 3_600),
+        secs if secs % 60 == 0 => format!("{}m", secs / 60),
+        secs => format!("{}s", secs),
+    }
+}
+
+// "3d 4h", "2h 5m", "42s": the two largest units
+fn format_uptime(uptime: chrono::TimeDelta) -> String {
+    let secs = uptime.num_seconds().max(0);
+    let units = [(secs / 86_400, "d"), (secs % 86_400 / 3_600, "h"), (secs % 3_600 / 60, "m"), (secs % 60, "s")];
+    let first = units.iter().position(|(count, _)| *count > 0).unwrap_or(units.len() - 1);
+    units[first..].iter().take(2).map(|(count, unit)| format!("{}{}", count, unit)).collect::<Vec<_>>().join(" ")
+}
+
+fn print_message(message: &Message) {
+    let local_time = message.timestamp.with_timezone(&chrono::Local);
+    let sender = if message.is_self { "You" } else { message.sender.as_str() };
+    if message.deleted {
+        println!("[{}] {}: (message deleted)", local_time.format("%Y-%m-%d %H:%M"), sender);
+        return;
+    }
+    let edited_marker = if message.edited_at.is_some() { " (edited)" } else { "" };
+    let mention_marker = if message.mentions_me { " (mentions you)" } else { "" };
+    let pin_marker = if message.pinned_at.is_some() { " (pinned)" } else { "" };
+    let star_marker = if message.starred { " (starred)" } else { "" };
+    let expiry_marker = match message.expires_in {
+        Some(secs) => format!(" (disappears after {})", format_duration(secs)),
+        None => String::new(),
+    };
+    println!(
+        "[{}] {}: {}{}{}{}{}{}",
+        local_time.format("%Y-%m-%d %H:%M"),
+        sender,
+        message.content,
+        edited_marker,
+        mention_marker,
+        pin_marker,
+        star_marker,
+        expiry_marker
+    );
+    if let Some(preview) = message.reply_preview.as_ref() {
+        let first_line = preview.content.lines().next().unwrap_or_default();
+        println!("    ↳ in reply to {}: {}", preview.sender, first_line);
+    } else if message.reply_to.is_some() {
+        println!("    ↳ in reply to a message that isn't available");
+    }
+    if let Some(local_path) = message.attachment.as_ref().and_then(|attachment| attachment.local_path.as_ref()) {
+        println!("    saved to {}", local_path);
+    }
+    if !message.reactions.is_empty() {
+        // "👍 2  ✅ 1", with a * on the ones we reacted with
+        let chips: Vec<String> = message.reactions.iter()
+            .map(|reaction| format!("{} {}{}", reaction.emoji, reaction.reactors.len(), if reaction.by_self { "*" } else { "" }))
+            .collect();
+        println!("    {}", chips.join("  "));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_are_read_in_seconds() {
+        assert_eq!(parse_duration("30s").unwrap(), Some(30));
+        assert_eq!(parse_duration("45").unwrap(), Some(45 * 60));
+        assert_eq!(parse_duration(" 2D ").unwrap(), Some(2 * 86_400));
+        assert_eq!(parse_duration("1w").unwrap(), Some(7 * 86_400));
+        assert_eq!(parse_duration("off").unwrap(), None);
+    }
+
+    #[test]
+    fn unreadable_or_huge_durations_are_errors() {
+        for text in ["", "soon", "1y", "-5m", "99999999999999999w", "18446744073709551616s"] {
+            assert!(parse_duration(text).is_err(), "{:?} should be refused", text);
+        }
+    }
+
+    #[test]
+    fn uptime_shows_the_two_largest_units() {
+        assert_eq!(format_uptime(chrono::TimeDelta::seconds(0)), "0s");
+        assert_eq!(format_uptime(chrono::TimeDelta::seconds(42)), "42s");
+        assert_eq!(format_uptime(chrono::TimeDelta::seconds(2 * 3_600 + 5 * 60 + 7)), "2h 5m");
+        assert_eq!(format_uptime(chrono::TimeDelta::seconds(3 * 86_400 + 4 * 3_600 + 59)), "3d 4h");
+        assert_eq!(format_uptime(chrono::TimeDelta::seconds(86_400 + 30)), "1d 0h");
+        assert_eq!(format_uptime(chrono::TimeDelta::seconds(-5)), "0s"); // Clocks a little apart
+    }
+
+    #[test]
+    fn send_times_past_the_scheduling_limit_are_refused() {
+        assert!(parse_send_time("30m").unwrap() > chrono::Utc::now());
+        assert!(parse_send_time(&format!("{}d", MAX_SCHEDULE_AHEAD_DAYS)).is_ok());
+        for text in [format!("{}d", MAX_SCHEDULE_AHEAD_DAYS + 1), "10000000000000000s".to_string(), "15250000w".to_string()] {
+            assert!(parse_send_time(&text).is_err(), "{} should be refused", text);
+        }
+    }
+}
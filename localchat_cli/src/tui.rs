@@ -0,0 +1,449 @@
+use crossterm::event::{Event, EventStream, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use futures::StreamExt; // For EventStream::next()
+use ratatui::layout::{Alignment, Constraint, Direction, Layout, Position, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::HashSet;
+use std::error::Error;
+use std::time::Duration;
+
+use crate::ipc::{DaemonConnection, DaemonToGuiMessage, GuiToDaemonCommand, IpcPeer, Message};
+
+// Same palette as the GUI's chat_area/sidemenu so the two clients feel alike
+const ACCENT_COLOR: Color = Color::Rgb(25, 118, 210);
+const SUBTLE_COLOR: Color = Color::Rgb(150, 150, 160);
+const LABEL_COLOR: Color = Color::Rgb(220, 220, 220);
+const MENTION_COLOR: Color = Color::Rgb(255, 200, 60);
+
+// The daemon doesn't push peer changes, so poll like a user pressing refresh
+const PEER_REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(PartialEq, Debug)]
+enum Focus {
+    Peers,
+    Input,
+}
+
+// Whatever woke the event loop up
+enum TuiEvent {
+    Daemon(Option<Box<DaemonToGuiMessage>>),
+    Terminal(Option<std::io::Result<Event>>),
+    RefreshTick,
+}
+
+struct TuiApp {
+    socket_path: String,
+    peers: Vec<IpcPeer>,
+    peer_list_state: ListState,
+    current_chat_peer_id: Option<String>,
+    messages: Vec<Message>,
+    unread_peer_ids: HashSet<String>, // Peers with unread messages, from the daemon's ConversationList
+    loaded_peer_ids: HashSet<String>, // Peers whose history has been requested
+    message_input: String,
+    focus: Focus,
+    scroll_from_bottom: u16,
+    status: String,
+    should_quit: bool,
+}
+
+pub async fn run(mut connection: DaemonConnection, socket_path: String) -> Result<(), Box<dyn Error>> {
+    let mut terminal = ratatui::init(); // Raw mode + alternate screen, restored by ratatui::restore (also on panic)
+    let result = run_event_loop(&mut terminal, &mut connection, socket_path).await;
+    ratatui::restore();
+    result
+}
+
+async fn run_event_loop(
+    terminal: &mut DefaultTerminal,
+    connection: &mut DaemonConnection,
+    socket_path: String,
+) -> Result<(), Box<dyn Error>> {
+    let mut app = TuiApp {
+        socket_path,
+        peers: Vec::new(),
+        peer_list_state: ListState::default(),
+        current_chat_peer_id: None,
+        messages: Vec::new(),
+        unread_peer_ids: HashSet::new(),
+        loaded_peer_ids: HashSet::new(),
+        message_input: String::new(),
+        focus: Focus::Peers,
+        scroll_from_bottom: 0,
+        status: "Connected".to_string(),
+        should_quit: false,
+    };
+
+    let mut terminal_events = EventStream::new();
+    let mut peer_refresh = tokio::time::interval(PEER_REFRESH_INTERVAL); // First tick fires immediately
+    connection.send(&GuiToDaemonCommand::GetConversations).await?; // Unread markers
+
+    while !app.should_quit {
+        terminal.draw(|frame| draw(frame, &mut app))?;
+
+        // Resolve the select to a value first so the handlers below are free to use the connection
+        let event = tokio::select! {
+            msg = connection.next_message() => TuiEvent::Daemon(msg?.map(Box::new)),
+            terminal_event = terminal_events.next() => TuiEvent::Terminal(terminal_event),
+            _ = peer_refresh.tick() => TuiEvent::RefreshTick,
+        };
+
+        match event {
+            TuiEvent::Daemon(Some(msg)) => app.handle_daemon_message(*msg),
+            TuiEvent::Daemon(None) => return Err("Daemon closed the connection".into()),
+            TuiEvent::Terminal(Some(Ok(Event::Key(key)))) if key.kind == KeyEventKind::Press => {
+                if let Some(command) = app.handle_key(key) {
+                    connection.send(&command).await?;
+                }
+            }
+            TuiEvent::Terminal(Some(Ok(_))) => {} // Resize etc. just trigger a redraw
+            TuiEvent::Terminal(Some(Err(e))) => return Err(Box::new(e)),
+            TuiEvent::Terminal(None) => return Ok(()),
+            TuiEvent::RefreshTick => connection.send(&GuiToDaemonCommand::GetPeers).await?,
+        }
+        // Selecting a peer (by key or on the first peer list) loads that conversation once and marks it read
+        for command in app.take_pending_commands() {
+            connection.send(&command).await?;
+        }
+    }
+    Ok(())
+}
+
+impl TuiApp {
+    fn handle_daemon_message(&mut self, msg: DaemonToGuiMessage) {
+        match msg {
+            DaemonToGuiMessage::PeerList(mut peers) => {
+                peers.sort_by_key(|peer| peer.username.to_lowercase());
+                self.peers = peers;
+                // Keep the selection on the same peer id across refreshes
+                let selected_index = self.current_chat_peer_id.as_ref()
+                    .and_then(|id| self.peers.iter().position(|p| p.id == *id));
+                match selected_index {
+                    Some(index) => self.peer_list_state.select(Some(index)),
+                    None if !self.peers.is_empty() && self.peer_list_state.selected().is_none() => self.select_peer(0),
+                    None => self.peer_list_state.select(self.peer_list_state.selected().filter(|i| *i < self.peers.len())),
+                }
+            }
+            DaemonToGuiMessage::NewMessage(message) => {
+                if self.messages.iter().any(|m| m.id == message.id) {
+                    return; // Duplicate
+                }
+                self.messages.push(message);
+            }
+            DaemonToGuiMessage::MessageUpdated(message) => {
+                if let Some(existing) = self.messages.iter_mut().find(|m| m.id == message.id) {
+                    *existing = message;
+                }
+            }
+            DaemonToGuiMessage::HistoryResponse { mut messages, .. } => {
+                messages.retain(|m| !self.messages.iter().any(|existing| existing.id == m.id));
+                self.messages.extend(messages);
+                self.messages.sort_by_key(|m| m.timestamp);
+            }
+            DaemonToGuiMessage::Error(err_msg) => self.status = format!("Daemon Error: {}", err_msg),
+            DaemonToGuiMessage::Success(msg) => self.status = format!("Success: {}", msg),
+            DaemonToGuiMessage::IdentityInfo { user_id } => self.status = format!("Identity: {}", user_id),
+            DaemonToGuiMessage::ConversationList(conversations) => {
+                self.unread_peer_ids = conversations.into_iter()
+                    .filter(|conversation| conversation.unread_count > 0)
+                    .map(|conversation| conversation.conversation_id)
+                    .collect();
+            }
+            DaemonToGuiMessage::SearchResults { .. } => {} // The TUI doesn't search; `localchat-cli search` does
+            DaemonToGuiMessage::DaemonInfo(_) => {} // The TUI doesn't ask; `localchat-cli status` does
+            // The TUI marks pinned and starred messages in place; the lists are for `localchat-cli pins` and `starred`
+            DaemonToGuiMessage::PinnedMessages { .. } | DaemonToGuiMessage::StarredMessages(_) | DaemonToGuiMessage::ScheduledMessages(_) | DaemonToGuiMessage::Drafts(_) => {}
+            DaemonToGuiMessage::MessagesExpired { message_ids, .. } => self.messages.retain(|m| !message_ids.contains(&m.id)),
+            DaemonToGuiMessage::ConversationTimer(timer) => {
+                self.status = match timer.expiry_secs {
+                    Some(secs) => format!("Messages with {} disappear after {}", timer.conversation_id, crate::format_duration(secs)),
+                    None => format!("Disappearing messages off with {}", timer.conversation_id),
+                };
+            }
+            DaemonToGuiMessage::RoomList(rooms) => self.status = format!("Rooms: {}", rooms.join(", ")), // The TUI only lists peers
+            DaemonToGuiMessage::DaemonStopping { reason } => self.status = format!("Daemon is stopping: {}", reason),
+            DaemonToGuiMessage::DaemonStatus { is_connected_to_network, active_interface_name } => {
+                self.status = active_interface_name.unwrap_or_else(||
+                    if is_connected_to_network { "Connected".to_string() } else { "Disconnected".to_string() }
+                );
+            }
+        }
+    }
+
+    // Returns a command to send to the daemon, if the key triggered one
+    fn handle_key(&mut self, key: KeyEvent) -> Option<GuiToDaemonCommand> {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Char('c') if ctrl => {
+                self.should_quit = true;
+                return None;
+            }
+            KeyCode::Char('r') if ctrl => return Some(GuiToDaemonCommand::GetPeers),
+            KeyCode::Tab | KeyCode::BackTab => {
+                self.focus = if self.focus == Focus::Peers { Focus::Input } else { Focus::Peers };
+                return None;
+            }
+            KeyCode::PageUp => {
+                self.scroll_from_bottom = self.scroll_from_bottom.saturating_add(5);
+                return None;
+            }
+            KeyCode::PageDown => {
+                self.scroll_from_bottom = self.scroll_from_bottom.saturating_sub(5);
+                return None;
+            }
+            _ => {}
+        }
+
+        match self.focus {
+            Focus::Peers => match key.code {
+                KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+                KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+                KeyCode::Enter | KeyCode::Right | KeyCode::Char('i') if self.current_chat_peer_id.is_some() => {
+                    self.focus = Focus::Input;
+                }
+                KeyCode::Char('r') => return Some(GuiToDaemonCommand::GetPeers),
+                KeyCode::Char('q') | KeyCode::Esc => self.should_quit = true,
+                _ => {}
+            },
+            Focus::Input => match key.code {
+                KeyCode::Enter => return self.send_message(),
+                KeyCode::Esc => self.focus = Focus::Peers,
+                KeyCode::Backspace => {
+                    self.message_input.pop();
+                }
+                KeyCode::Char(c) if !ctrl => self.message_input.push(c),
+                _ => {}
+            },
+        }
+        None
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        if self.peers.is_empty() {
+            return;
+        }
+        let current = self.peer_list_state.selected().unwrap_or(0) as isize;
+        let next = (current + delta).clamp(0, self.peers.len() as isize - 1) as usize;
+        self.select_peer(next);
+    }
+
+    fn select_peer(&mut self, index: usize) {
+        self.peer_list_state.select(Some(index));
+        let peer_id = self.peers[index].id.clone();
+        self.current_chat_peer_id = Some(peer_id);
+        self.scroll_from_bottom = 0;
+    }
+
+    fn take_pending_commands(&mut self) -> Vec<GuiToDaemonCommand> {
+        let mut commands = Vec::new();
+        let Some(peer_id) = self.current_chat_peer_id.clone() else {
+            return commands;
+        };
+        if self.loaded_peer_ids.insert(peer_id.clone()) {
+            commands.push(GuiToDaemonCommand::RequestHistory { peer_id: peer_id.clone(), since_timestamp: None, around_message_id: None });
+        }
+        // The open conversation is on screen, so whatever the daemon counts as unread there has been seen
+        if self.unread_peer_ids.remove(&peer_id) {
+            commands.push(GuiToDaemonCommand::MarkRead { conversation_id: peer_id, message_id: None });
+        }
+        commands
+    }
+
+    // Mirrors chat_area::send_text: the message shows up once the daemon echoes it back as sent
+    fn send_message(&mut self) -> Option<GuiToDaemonCommand> {
+        let content = self.message_input.trim().to_string();
+        self.message_input.clear();
+        if content.is_empty() {
+            return None;
+        }
+        let Some(recipient_id) = self.current_chat_peer_id.clone() else {
+            self.status = "Select a peer before sending".to_string();
+            return None;
+        };
+        self.scroll_from_bottom = 0;
+        Some(GuiToDaemonCommand::SendMessage { recipient_id, content, reply_to: None })
+    }
+
+    fn current_conversation(&self) -> Vec<&Message> {
+        match self.current_chat_peer_id.as_ref() {
+            Some(peer_id) => self.messages.iter()
+                .filter(|m| if m.is_self { m.recipient == *peer_id } else { m.sender == *peer_id })
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, app: &mut TuiApp) {
+    let [main_area, status_area] = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(3), Constraint::Length(1)])
+        .areas(frame.area());
+    let [peers_area, chat_area] = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Length(30), Constraint::Min(20)])
+        .areas(main_area);
+    let [messages_area, input_area] = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(1), Constraint::Length(3)])
+        .areas(chat_area);
+
+    draw_peers(frame, app, peers_area);
+    draw_messages(frame, app, messages_area);
+    draw_input(frame, app, input_area);
+
+    let help = match app.focus {
+        Focus::Peers => "↑↓ select  Enter chat  r refresh  Tab input  q quit",
+        Focus::Input => "Enter send  Esc peers  PgUp/PgDn scroll  Ctrl+C quit",
+    };
+    let status_line = Line::from(vec![
+        Span::styled(format!(" {} ", app.status), Style::default().fg(LABEL_COLOR)),
+        Span::styled(format!("| {} | {}", app.socket_path, help), Style::default().fg(SUBTLE_COLOR)),
+    ]);
+    frame.render_widget(Paragraph::new(status_line), status_area);
+}
+
+fn focused_block(title: &str, is_focused: bool) -> Block<'_> {
+    let border_color = if is_focused { ACCENT_COLOR } else { SUBTLE_COLOR };
+    Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(border_color))
+        .title(Span::styled(title, Style::default().fg(LABEL_COLOR).add_modifier(Modifier::BOLD)))
+}
+
+fn draw_peers(frame: &mut Frame, app: &mut TuiApp, area: Rect) {
+    let block = focused_block(" Peers ", app.focus == Focus::Peers);
+    if app.peers.is_empty() {
+        let empty_state = Paragraph::new(vec![
+            Line::from(""),
+            Line::styled("No peers found", Style::default().fg(SUBTLE_COLOR).add_modifier(Modifier::ITALIC)),
+            Line::styled("Press r to refresh", Style::default().fg(SUBTLE_COLOR)),
+        ])
+        .alignment(Alignment::Center)
+        .block(block);
+        frame.render_widget(empty_state, area);
+        return;
+    }
+
+    let items: Vec<ListItem> = app.peers.iter().map(|peer| {
+        let mut spans = vec![Span::raw("👤 "), Span::styled(peer.username.clone(), Style::default().fg(LABEL_COLOR))];
+        if app.unread_peer_ids.contains(&peer.id) {
+            spans.push(Span::styled(" ●", Style::default().fg(ACCENT_COLOR)));
+        }
+        ListItem::new(Line::from(spans))
+    }).collect();
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(Style::default().bg(ACCENT_COLOR).add_modifier(Modifier::BOLD));
+    frame.render_stateful_widget(list, area, &mut app.peer_list_state);
+}
+
+fn draw_messages(frame: &mut Frame, app: &TuiApp, area: Rect) {
+    let title = match app.current_chat_peer_id.as_ref().and_then(|id| app.peers.iter().find(|p| p.id == *id)) {
+        Some(peer) => format!(" {} ", peer.username),
+        None => " Chat ".to_string(),
+    };
+    let block = focused_block(&title, false);
+    let inner_width = area.width.saturating_sub(2).max(1) as usize;
+    let inner_height = area.height.saturating_sub(2) as usize;
+
+    let conversation = app.current_conversation();
+    let mut lines: Vec<Line> = Vec::new();
+    if app.current_chat_peer_id.is_none() {
+        lines.push(Line::styled("Select a peer to start chatting", Style::default().fg(SUBTLE_COLOR)));
+    }
+
+    // Group messages by day, like chat_area
+    let mut last_message_date = None;
+    for message in conversation {
+        let current_date = message.timestamp.date_naive();
+        if last_message_date != Some(current_date) {
+            let date_text = if current_date == chrono::Utc::now().date_naive() {
+                "Today".to_string()
+            } else if current_date == (chrono::Utc::now() - chrono::Duration::days(1)).date_naive() {
+                "Yesterday".to_string()
+            } else {
+                message.timestamp.format("%B %d, %Y").to_string()
+            };
+            lines.push(Line::styled(format!("── {} ──", date_text), Style::default().fg(SUBTLE_COLOR)).alignment(Alignment::Center));
+            last_message_date = Some(current_date);
+        }
+
+        let (sender, sender_color) = if message.is_self { ("You", ACCENT_COLOR) } else { (message.sender.as_str(), LABEL_COLOR) };
+        let edited_marker = if message.edited_at.is_some() && !message.deleted { " · edited" } else { "" };
+        let mut header = vec![
+            Span::styled(sender.to_string(), Style::default().fg(sender_color).add_modifier(Modifier::BOLD)),
+            Span::styled(
+                format!("  {}{}", message.timestamp.with_timezone(&chrono::Local).format("%H:%M"), edited_marker),
+                Style::default().fg(SUBTLE_COLOR)
+            ),
+        ];
+        if message.mentions_me {
+            header.push(Span::styled("  @you", Style::default().fg(MENTION_COLOR).add_modifier(Modifier::BOLD)));
+        }
+        if message.pinned_at.is_some() {
+            header.push(Span::styled("  📌", Style::default().fg(SUBTLE_COLOR)));
+        }
+        if message.starred {
+            header.push(Span::styled("  ★", Style::default().fg(SUBTLE_COLOR)));
+        }
+        lines.push(Line::from(header));
+        if message.deleted {
+            lines.push(Line::styled("  Message deleted", Style::default().fg(SUBTLE_COLOR).add_modifier(Modifier::ITALIC)));
+            continue;
+        }
+        if let Some(preview) = message.reply_preview.as_ref() {
+            // One line of the quoted parent, cut to fit so the line count stays exact
+            let quote = format!("  ↳ {}: {}", preview.sender, preview.content.lines().next().unwrap_or_default());
+            let quote: String = quote.chars().take(inner_width.max(1)).collect();
+            lines.push(Line::styled(quote, Style::default().fg(SUBTLE_COLOR).add_modifier(Modifier::ITALIC)));
+        }
+        // Wrap ourselves (by chars) so the line count is exact for bottom-anchored scrolling
+        for content_line in message.content.lines() {
+            let chars: Vec<char> = content_line.chars().collect();
+            let chunk_width = inner_width.saturating_sub(2).max(1);
+            if chars.is_empty() {
+                lines.push(Line::raw(""));
+            }
+            for chunk in chars.chunks(chunk_width) {
+                lines.push(Line::raw(format!("  {}", chunk.iter().collect::<String>())));
+            }
+        }
+        if !message.reactions.is_empty() {
+            let mut spans = vec![Span::raw("  ")];
+            for reaction in &message.reactions {
+                let color = if reaction.by_self { ACCENT_COLOR } else { SUBTLE_COLOR };
+                spans.push(Span::styled(format!("{} {} ", reaction.emoji, reaction.reactors.len()), Style::default().fg(color)));
+            }
+            lines.push(Line::from(spans)); // Not wrapped; a message rarely has more than a few
+        }
+    }
+
+    // Stick to the bottom unless the user scrolled up
+    let max_scroll = lines.len().saturating_sub(inner_height);
+    let scroll = max_scroll.saturating_sub(app.scroll_from_bottom as usize);
+    frame.render_widget(Paragraph::new(lines).block(block).scroll((scroll as u16, 0)), area);
+}
+
+fn draw_input(frame: &mut Frame, app: &TuiApp, area: Rect) {
+    let is_focused = app.focus == Focus::Input;
+    let block = focused_block(" Message ", is_focused);
+    let visible_width = area.width.saturating_sub(4) as usize;
+    // Show the tail of long input so the cursor stays visible
+    let input_chars: Vec<char> = app.message_input.chars().collect();
+    let visible_start = input_chars.len().saturating_sub(visible_width);
+    let visible_input: String = input_chars[visible_start..].iter().collect();
+
+    let input = if app.message_input.is_empty() && !is_focused {
+        Paragraph::new(Span::styled("Type a message...", Style::default().fg(SUBTLE_COLOR)))
+    } else {
+        Paragraph::new(format!(" {}", visible_input))
+    };
+    frame.render_widget(input.block(block), area);
+
+    if is_focused {
+        let cursor_x = area.x + 2 + (input_chars.len() - visible_start) as u16;
+        frame.set_cursor_position(Position::new(cursor_x.min(area.right().saturating_sub(2)), area.y + 1));
+    }
+}
@@ -0,0 +1,121 @@
+// File attachments: the sender inlines the file as base64 in the Message line, the receiver writes it
+// to its attachments directory and hands IPC clients only the local path.
+// Receiving is opt-in (the "attachments" section of the daemon config): any host on the LAN can send us a file.
+use base64::Engine;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+use crate::Attachment;
+
+// Whole file travels in one JSON line, so keep it modest
+pub const MAX_ATTACHMENT_BYTES: u64 = 10 * 1024 * 1024;
+
+// "attachments" in the daemon config. With `receive` off (the default) a message with a file still arrives,
+// showing the file's name and size, but nothing is written to disk.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ReceiveConfig {
+    pub receive: bool,
+    pub max_total_mb: u64, // Cap on everything in the attachments directory; files that would go over it are refused
+}
+
+impl Default for ReceiveConfig {
+    fn default() -> Self {
+        Self { receive: false, max_total_mb: 500 }
+    }
+}
+
+// Where received files go and whether (and how much) to accept
+#[derive(Debug, Clone)]
+pub struct AttachmentInbox {
+    pub dir: PathBuf,
+    pub config: ReceiveConfig,
+}
+
+// Reads a local file into an Attachment ready to go over TCP
+pub fn read_for_sending(file_path: &str) -> Result<Attachment, String> {
+    let path = Path::new(file_path);
+    let metadata = std::fs::metadata(path).map_err(|e| format!("Cannot read '{}': {}", file_path, e))?;
+    if !metadata.is_file() {
+        return Err(format!("'{}' is not a regular file", file_path));
+    }
+    if metadata.len() > MAX_ATTACHMENT_BYTES {
+        return Err(format!(
+            "'{}' is {} bytes; files up to {} MB can be sent",
+            file_path, metadata.len(), MAX_ATTACHMENT_BYTES / (1024 * 1024)
+        ));
+    }
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .ok_or_else(|| format!("'{}' has no file name", file_path))?;
+    let file_bytes = std::fs::read(path).map_err(|e| format!("Cannot read '{}': {}", file_path, e))?;
+
+    Ok(Attachment {
+        file_name,
+        size_bytes: file_bytes.len() as u64,
+        data_base64: Some(base64::engine::general_purpose::STANDARD.encode(&file_bytes)),
+        local_path: Some(path.to_string_lossy().into_owned()), // Stripped before sending
+    })
+}
+
+// Decodes an incoming attachment into the inbox directory and replaces the inline data with the saved path
+pub fn save_received(attachment: &mut Attachment, message_id: &str, inbox: &AttachmentInbox) -> Result<(), String> {
+    let encoded = attachment.data_base64.take().ok_or("attachment has no data")?;
+    if !inbox.config.receive {
+        return Err("receiving files is off (set attachments.receive in the daemon config)".to_string());
+    }
+    let file_bytes = base64::engine::general_purpose::STANDARD
+        .decode(encoded.as_bytes())
+        .map_err(|e| format!("invalid attachment data: {}", e))?;
+    if file_bytes.len() as u64 > MAX_ATTACHMENT_BYTES {
+        return Err(format!("attachment is {} bytes, over the {} byte limit", file_bytes.len(), MAX_ATTACHMENT_BYTES));
+    }
+    let attachments_dir = inbox.dir.as_path();
+    let max_total_bytes = inbox.config.max_total_mb * 1024 * 1024;
+    let used_bytes = directory_size(attachments_dir);
+    if used_bytes + file_bytes.len() as u64 > max_total_bytes {
+        return Err(format!(
+            "attachments directory holds {} bytes; this file would go over the {} MB limit (attachments.max_total_mb)",
+            used_bytes, inbox.config.max_total_mb
+        ));
+    }
+
+    // Never trust the sender's name as a path: keep only the final component
+    let safe_file_name = Path::new(&attachment.file_name)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .filter(|name| !name.is_empty() && name != "." && name != "..")
+        .unwrap_or_else(|| "attachment".to_string());
+    // Prefix with the message ID so two files with the same name don't overwrite each other
+    let target_path = attachments_dir.join(format!("{}_{}", message_id, safe_file_name));
+
+    std::fs::create_dir_all(attachments_dir)
+        .map_err(|e| format!("cannot create {}: {}", attachments_dir.display(), e))?;
+    std::fs::write(&target_path, &file_bytes)
+        .map_err(|e| format!("cannot write {}: {}", target_path.display(), e))?;
+
+    attachment.file_name = safe_file_name;
+    attachment.size_bytes = file_bytes.len() as u64;
+    attachment.local_path = Some(target_path.to_string_lossy().into_owned());
+    Ok(())
+}
+
+// Total size of the files directly in `dir` (received files are never in subdirectories); 0 if it doesn't exist yet
+fn directory_size(dir: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .filter_map(|entry| entry.ok()?.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+// Received files go under the user's Downloads folder, one directory per daemon instance
+pub fn get_attachments_dir(instance_number: u16) -> PathBuf {
+    dirs::download_dir()
+        .map(|dir| dir.join("LocalChat").join(format!("instance_{}", instance_number)))
+        .unwrap_or_else(|| PathBuf::from(format!("/tmp/localchat_attachments_{}", instance_number)))
+}
@@ -0,0 +1,38 @@
+// Optional daemon configuration file (JSON). A missing file means defaults, so the daemon runs unconfigured out of the box.
+use serde::Deserialize;
+use std::path::Path;
+
+use crate::attachments::ReceiveConfig;
+use crate::hooks::HookRule;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct DaemonConfig {
+    pub hooks: Vec<HookRule>,
+    pub attachments: ReceiveConfig,
+}
+
+impl DaemonConfig {
+    pub fn load(config_file_path: &str) -> DaemonConfig {
+        if !Path::new(config_file_path).exists() {
+            tracing::info!("No daemon config at {}, using defaults.", config_file_path);
+            return DaemonConfig::default();
+        }
+        match std::fs::read_to_string(config_file_path) {
+            Ok(config_json) => match serde_json::from_str::<DaemonConfig>(&config_json) {
+                Ok(config) => {
+                    tracing::info!("Loaded daemon config from {} ({} hook rule(s)).", config_file_path, config.hooks.len());
+                    config
+                }
+                Err(e) => {
+                    tracing::error!("Failed to parse daemon config {}: {}. Using defaults.", config_file_path, e);
+                    DaemonConfig::default()
+                }
+            },
+            Err(e) => {
+                tracing::error!("Failed to read daemon config {}: {}. Using defaults.", config_file_path, e);
+                DaemonConfig::default()
+            }
+        }
+    }
+}
@@ -0,0 +1,122 @@
+// Editing and deleting sent messages. The author's daemon sends a MessageChange line over the same peer TCP
+// protocol as messages; every daemon (the author's included) checks it against the stored original before applying it.
+use serde::{Deserialize, Serialize};
+
+use crate::Message;
+
+// How long after sending a message it can still be changed, measured by the daemon applying the change
+pub const EDIT_WINDOW: chrono::TimeDelta = chrono::TimeDelta::minutes(15);
+pub const DELETE_WINDOW: chrono::TimeDelta = chrono::TimeDelta::hours(24);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ChangeKind {
+    Edit { content: String },
+    Delete, // For everyone: the content is wiped and the message shown as a tombstone
+}
+
+// Sent between daemons instead of a Message; refers to a message the receiver already has
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageChange {
+    pub message_id: String,
+    pub sender: String, // Must be the original message's sender
+    pub change: ChangeKind,
+    pub timestamp: chrono::DateTime<chrono::Utc>, // When the change was made
+}
+
+// One line on the peer TCP connection. Untagged so plain Message lines keep their old shape;
+// daemons without support for the newer kinds of line log them as undecodable and move on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PeerPacket {
+    Message(Box<Message>),
+    Change(MessageChange),
+    Reaction(crate::reactions::ReactionChange),
+    Pin(crate::pins::PinChange),
+    Timer(crate::expiry::TimerChange),
+}
+
+// Whether `change` may be applied to `original` now; the error says why not
+pub fn check_change(original: &Message, change: &MessageChange, now: chrono::DateTime<chrono::Utc>) -> Result<(), String> {
+    if original.sender != change.sender {
+        return Err("only the sender can change a message".to_string());
+    }
+    if original.deleted {
+        return Err("the message was deleted".to_string());
+    }
+    let age = now - original.timestamp;
+    match change.change {
+        ChangeKind::Edit { ref content } => {
+            if content.trim().is_empty() {
+                return Err("the new text is empty (delete the message instead)".to_string());
+            }
+            if original.attachment.is_some() {
+                return Err("messages with a file can't be edited".to_string());
+            }
+            if age > EDIT_WINDOW {
+                return Err(format!("messages can only be edited for {} minutes", EDIT_WINDOW.num_minutes()));
+            }
+        }
+        ChangeKind::Delete => {
+            if age > DELETE_WINDOW {
+                return Err(format!("messages can only be deleted for {} hours", DELETE_WINDOW.num_hours()));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sent_at(timestamp: chrono::DateTime<chrono::Utc>) -> Message {
+        crate::test_message("m1", "alice - 1", "hello", timestamp)
+    }
+
+    fn change(sender: &str, change: ChangeKind) -> MessageChange {
+        MessageChange { message_id: "m1".to_string(), sender: sender.to_string(), change, timestamp: chrono::Utc::now() }
+    }
+
+    fn edit(content: &str) -> ChangeKind {
+        ChangeKind::Edit { content: content.to_string() }
+    }
+
+    #[test]
+    fn edits_are_allowed_up_to_the_edit_window() {
+        let now = chrono::Utc::now();
+        let original = sent_at(now - EDIT_WINDOW);
+        assert!(check_change(&original, &change("alice - 1", edit("hello!")), now).is_ok());
+        let original = sent_at(now - EDIT_WINDOW - chrono::TimeDelta::seconds(1));
+        assert!(check_change(&original, &change("alice - 1", edit("hello!")), now).is_err());
+    }
+
+    #[test]
+    fn deletes_are_allowed_up_to_the_delete_window() {
+        let now = chrono::Utc::now();
+        let original = sent_at(now - EDIT_WINDOW - chrono::TimeDelta::minutes(1));
+        assert!(check_change(&original, &change("alice - 1", ChangeKind::Delete), now).is_ok());
+        let original = sent_at(now - DELETE_WINDOW);
+        assert!(check_change(&original, &change("alice - 1", ChangeKind::Delete), now).is_ok());
+        let original = sent_at(now - DELETE_WINDOW - chrono::TimeDelta::seconds(1));
+        assert!(check_change(&original, &change("alice - 1", ChangeKind::Delete), now).is_err());
+    }
+
+    #[test]
+    fn only_the_sender_can_change_a_message() {
+        let now = chrono::Utc::now();
+        let original = sent_at(now);
+        assert!(check_change(&original, &change("mallory - 3", edit("hijacked")), now).is_err());
+        assert!(check_change(&original, &change("mallory - 3", ChangeKind::Delete), now).is_err());
+    }
+
+    #[test]
+    fn deleted_messages_and_blank_edits_are_refused() {
+        let now = chrono::Utc::now();
+        let original = sent_at(now);
+        assert!(check_change(&original, &change("alice - 1", edit("  \n")), now).is_err());
+        let mut deleted = sent_at(now);
+        deleted.deleted = true;
+        assert!(check_change(&deleted, &change("alice - 1", edit("back again")), now).is_err());
+        assert!(check_change(&deleted, &change("alice - 1", ChangeKind::Delete), now).is_err());
+    }
+}
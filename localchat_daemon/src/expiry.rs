@@ -0,0 +1,158 @@
+// Disappearing messages. Each conversation can have a timer; anyone in it can change it, and the change goes to the
+// other participants as a TimerChange line over the peer TCP protocol (the newest change wins). Every message sent
+// while a timer is set carries it in `expires_in`, and each daemon purges its copy once that time has passed.
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::Message;
+
+// Shortest and longest timer accepted; longer ones from a peer are cut to the maximum
+pub const MIN_EXPIRY_SECS: u64 = 30;
+pub const MAX_EXPIRY_SECS: u64 = 4 * 7 * 24 * 60 * 60; // 4 weeks
+
+// How often the purger looks for expired messages
+pub const PURGE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(15);
+
+// Sent between daemons; sets or clears the timer of a conversation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimerChange {
+    pub recipient: String, // The conversation as the setter sees it: our full ID or a room
+    pub setter: String, // Full ID of whoever changed it
+    pub expiry_secs: Option<u64>, // None turns disappearing messages off
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+}
+
+// The conversation a received timer change is for, from this daemon's point of view
+pub fn conversation_for(change: &TimerChange) -> &str {
+    if crate::presence::is_room_id(&change.recipient) { &change.recipient } else { &change.setter }
+}
+
+pub fn check_expiry(expiry_secs: Option<u64>) -> Result<(), String> {
+    match expiry_secs {
+        Some(secs) if !(MIN_EXPIRY_SECS..=MAX_EXPIRY_SECS).contains(&secs) => Err(format!(
+            "the timer must be between {} seconds and {} days",
+            MIN_EXPIRY_SECS,
+            MAX_EXPIRY_SECS / (24 * 60 * 60)
+        )),
+        _ => Ok(()),
+    }
+}
+
+// When a message disappears, if it has a timer
+pub fn expires_at(message: &Message) -> Option<chrono::DateTime<chrono::Utc>> {
+    let expires_in = message.expires_in?.clamp(MIN_EXPIRY_SECS, MAX_EXPIRY_SECS);
+    Some(message.timestamp + chrono::TimeDelta::seconds(expires_in as i64))
+}
+
+// "1 hour", "3 days"; for responses and logs
+pub fn describe(expiry_secs: Option<u64>) -> String {
+    let Some(secs) = expiry_secs else {
+        return "off".to_string();
+    };
+    let (count, unit) = match secs {
+        secs if secs % 86_400 == 0 => (secs / 86_400, "day"),
+        secs if secs % 3_600 == 0 => (secs / 3_600, "hour"),
+        secs if secs % 60 == 0 => (secs / 60, "minute"),
+        secs => (secs, "second"),
+    };
+    format!("{} {}{}", count, unit, if count == 1 { "" } else { "s" })
+}
+
+// Removes the file of a received message that expired or was deleted for everyone. Only files the daemon saved
+// itself are touched: the attachment of a message we sent points at the user's own copy.
+pub fn remove_attachment(message: &Message, attachments_dir: &Path) {
+    if message.is_self {
+        return;
+    }
+    let Some(local_path) = message.attachment.as_ref().and_then(|attachment| attachment.local_path.as_ref()) else {
+        return;
+    };
+    let local_path = Path::new(local_path);
+    if !local_path.starts_with(attachments_dir) {
+        tracing::warn!("Not removing attachment {} of message ID: {}: outside {}", local_path.display(), message.id, attachments_dir.display());
+        return;
+    }
+    match std::fs::remove_file(local_path) {
+        Ok(()) => tracing::info!("Removed attachment {} of message ID: {}", local_path.display(), message.id),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => tracing::warn!("Could not remove attachment {} of message ID: {}: {}", local_path.display(), message.id, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(expires_in: Option<u64>, is_self: bool, local_path: Option<&Path>) -> Message {
+        let mut message = crate::test_message("m1", "alice - 1", "", at("2026-01-01T12:00:00Z"));
+        message.is_self = is_self;
+        message.expires_in = expires_in;
+        message.attachment = local_path.map(|path| crate::Attachment {
+            file_name: "notes.txt".to_string(),
+            size_bytes: 5,
+            data_base64: None,
+            local_path: Some(path.to_string_lossy().into_owned()),
+        });
+        message
+    }
+
+    fn at(rfc3339: &str) -> chrono::DateTime<chrono::Utc> {
+        rfc3339.parse().unwrap()
+    }
+
+    #[test]
+    fn expires_at_adds_the_timer_to_the_send_time() {
+        assert_eq!(expires_at(&message(None, false, None)), None);
+        assert_eq!(expires_at(&message(Some(3_600), false, None)), Some(at("2026-01-01T13:00:00Z")));
+    }
+
+    #[test]
+    fn expires_at_clamps_timers_from_peers() {
+        assert_eq!(expires_at(&message(Some(0), false, None)), Some(at("2026-01-01T12:00:30Z")));
+        assert_eq!(expires_at(&message(Some(u64::MAX), false, None)), Some(at("2026-01-29T12:00:00Z")));
+    }
+
+    #[test]
+    fn check_expiry_accepts_only_the_supported_range() {
+        assert!(check_expiry(None).is_ok());
+        assert!(check_expiry(Some(MIN_EXPIRY_SECS)).is_ok());
+        assert!(check_expiry(Some(MAX_EXPIRY_SECS)).is_ok());
+        assert!(check_expiry(Some(MIN_EXPIRY_SECS - 1)).is_err());
+        assert!(check_expiry(Some(MAX_EXPIRY_SECS + 1)).is_err());
+    }
+
+    #[test]
+    fn describe_uses_the_largest_whole_unit() {
+        assert_eq!(describe(None), "off");
+        assert_eq!(describe(Some(30)), "30 seconds");
+        assert_eq!(describe(Some(60)), "1 minute");
+        assert_eq!(describe(Some(90)), "90 seconds");
+        assert_eq!(describe(Some(7_200)), "2 hours");
+        assert_eq!(describe(Some(86_400)), "1 day");
+        assert_eq!(describe(Some(MAX_EXPIRY_SECS)), "28 days");
+    }
+
+    #[test]
+    fn remove_attachment_only_touches_received_files_in_the_attachments_dir() {
+        let attachments_dir = std::env::temp_dir().join(format!("localchat_expiry_test_{}", std::process::id()));
+        std::fs::create_dir_all(&attachments_dir).unwrap();
+        let received = attachments_dir.join("received.txt");
+        let sent = attachments_dir.join("sent.txt");
+        let outside = std::env::temp_dir().join(format!("localchat_expiry_test_{}_outside.txt", std::process::id()));
+        for path in [&received, &sent, &outside] {
+            std::fs::write(path, "hello").unwrap();
+        }
+
+        remove_attachment(&message(None, false, Some(&received)), &attachments_dir);
+        remove_attachment(&message(None, true, Some(&sent)), &attachments_dir);
+        remove_attachment(&message(None, false, Some(&outside)), &attachments_dir);
+        assert!(!received.exists());
+        assert!(sent.exists());
+        assert!(outside.exists());
+        // Already gone is fine
+        remove_attachment(&message(None, false, Some(&received)), &attachments_dir);
+
+        std::fs::remove_dir_all(&attachments_dir).unwrap();
+        std::fs::remove_file(&outside).unwrap();
+    }
+}
@@ -0,0 +1,285 @@
+// Automation hooks: rules from the daemon config that run a local command or POST to a local URL
+// when a message arrives or a peer joins. Whatever the hook answers is sent back as a chat reply.
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use crate::store::conversation_id_for;
+use crate::{process_gui_command, DaemonToGuiMessage, GuiClients, GuiToDaemonCommand, IpcPeer, Message, SharedMessageStore, UserIdentity};
+
+const HOOK_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Replies per conversation within REPLY_WINDOW; more are dropped. Two daemons whose hooks answer each other
+// would otherwise reply back and forth without end.
+const MAX_REPLIES_PER_WINDOW: usize = 5;
+const REPLY_WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct HookRule {
+    #[serde(default)]
+    pub name: Option<String>, // Only used in logs
+    pub on: HookEvent,
+    #[serde(default)]
+    pub from_peer: Option<String>, // Regex matched against the peer's full ID and username
+    #[serde(default)]
+    pub content_matches: Option<String>, // Regex matched against the message content (message hooks only)
+    pub action: HookAction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HookEvent {
+    Message,
+    PeerJoined,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HookAction {
+    Command(String), // Run with `sh -c`, event JSON on stdin, stdout is the reply
+    Webhook(String), // POST the event JSON, response body is the reply
+}
+
+pub enum HookTrigger {
+    Message(Box<Message>),
+    PeerJoined(IpcPeer),
+}
+
+// JSON handed to hooks on stdin / in the POST body
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum HookPayload<'a> {
+    Message { message: &'a Message },
+    PeerJoined { peer: &'a IpcPeer },
+}
+
+struct CompiledHook {
+    rule: HookRule,
+    from_peer: Option<Regex>,
+    content_matches: Option<Regex>,
+}
+
+impl CompiledHook {
+    fn label(&self, index: usize) -> String {
+        self.rule.name.clone().unwrap_or_else(|| format!("hook #{}", index + 1))
+    }
+}
+
+// Cheap to clone; shared by the peer TCP handler and the mDNS browse loop
+#[derive(Clone)]
+pub struct HookRunner {
+    hooks: Arc<Vec<CompiledHook>>,
+    http_client: reqwest::Client,
+    peers_map: Arc<Mutex<HashMap<String, IpcPeer>>>,
+    user_identity_arc: Arc<Mutex<Option<UserIdentity>>>,
+    message_store: SharedMessageStore, // Replies are stored and echoed like any sent message
+    gui_clients: GuiClients,
+    recent_replies: Arc<std::sync::Mutex<HashMap<String, VecDeque<Instant>>>>, // Per conversation, for the reply limit
+}
+
+impl HookRunner {
+    pub fn new(
+        rules: Vec<HookRule>,
+        peers_map: Arc<Mutex<HashMap<String, IpcPeer>>>,
+        user_identity_arc: Arc<Mutex<Option<UserIdentity>>>,
+        message_store: SharedMessageStore,
+        gui_clients: GuiClients,
+    ) -> Self {
+        let mut hooks = Vec::new();
+        for (index, rule) in rules.into_iter().enumerate() {
+            let from_peer = match rule.from_peer.as_deref().map(Regex::new).transpose() {
+                Ok(regex) => regex,
+                Err(e) => {
+                    tracing::error!("Hooks: skipping hook #{}: invalid from_peer regex: {}", index + 1, e);
+                    continue;
+                }
+            };
+            let content_matches = match rule.content_matches.as_deref().map(Regex::new).transpose() {
+                Ok(regex) => regex,
+                Err(e) => {
+                    tracing::error!("Hooks: skipping hook #{}: invalid content_matches regex: {}", index + 1, e);
+                    continue;
+                }
+            };
+            hooks.push(CompiledHook { rule, from_peer, content_matches });
+        }
+        tracing::info!("Hooks: {} rule(s) active.", hooks.len());
+
+        Self {
+            hooks: Arc::new(hooks),
+            http_client: reqwest::Client::new(),
+            peers_map,
+            user_identity_arc,
+            message_store,
+            gui_clients,
+            recent_replies: Arc::new(std::sync::Mutex::new(HashMap::new())),
+        }
+    }
+
+    // Counts a reply to `conversation_id` if it is under the limit; false means it must be dropped
+    fn take_reply_slot(&self, conversation_id: &str) -> bool {
+        let now = Instant::now();
+        let mut recent_replies = self.recent_replies.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        recent_replies.retain(|_, sent_at| {
+            sent_at.retain(|at| now.duration_since(*at) < REPLY_WINDOW);
+            !sent_at.is_empty()
+        });
+        let sent_at = recent_replies.entry(conversation_id.to_string()).or_default();
+        if sent_at.len() >= MAX_REPLIES_PER_WINDOW {
+            return false;
+        }
+        sent_at.push_back(now);
+        true
+    }
+
+    // Runs every matching hook in the background; never blocks the caller
+    pub async fn dispatch(&self, trigger: HookTrigger) {
+        if self.hooks.is_empty() {
+            return;
+        }
+
+        // Replies go to the conversation the message came in: the room for room messages, the sender otherwise
+        let (event, peer_id, peer_username, content, conversation_id) = match &trigger {
+            HookTrigger::Message(message) => {
+                let username = self.peers_map.lock().await.get(&message.sender).map(|p| p.username.clone());
+                (HookEvent::Message, message.sender.clone(), username, Some(message.content.as_str()), conversation_id_for(message).to_string())
+            }
+            HookTrigger::PeerJoined(peer) => (HookEvent::PeerJoined, peer.id.clone(), Some(peer.username.clone()), None, peer.id.clone()),
+        };
+        let payload = match &trigger {
+            HookTrigger::Message(message) => HookPayload::Message { message },
+            HookTrigger::PeerJoined(peer) => HookPayload::PeerJoined { peer },
+        };
+        let payload_json = match serde_json::to_string(&payload) {
+            Ok(json) => json,
+            Err(e) => {
+                tracing::error!("Hooks: failed to serialize hook payload: {}", e);
+                return;
+            }
+        };
+
+        for (index, hook) in self.hooks.iter().enumerate() {
+            if hook.rule.on != event {
+                continue;
+            }
+            if let Some(ref from_peer) = hook.from_peer {
+                let peer_matches = from_peer.is_match(&peer_id)
+                    || peer_username.as_deref().is_some_and(|username| from_peer.is_match(username));
+                if !peer_matches {
+                    continue;
+                }
+            }
+            if let Some(ref content_matches) = hook.content_matches {
+                if !content.is_some_and(|text| content_matches.is_match(text)) {
+                    continue;
+                }
+            }
+
+            let runner = self.clone();
+            let payload_json = payload_json.clone();
+            let peer_id = peer_id.clone();
+            let reply_to = conversation_id.clone();
+            tokio::spawn(async move {
+                runner.run_hook(index, &payload_json, event, peer_id, reply_to).await;
+            });
+        }
+    }
+
+    async fn run_hook(&self, index: usize, payload_json: &str, event: HookEvent, peer_id: String, reply_to: String) {
+        let hook = &self.hooks[index];
+        let label = hook.label(index);
+        tracing::info!("Hooks: running '{}' for {:?} from '{}'", label, event, peer_id);
+
+        let output = match &hook.rule.action {
+            HookAction::Command(command) => run_command(command, payload_json, event, &peer_id).await,
+            HookAction::Webhook(url) => self.post_webhook(url, payload_json).await,
+        };
+        let reply = match output {
+            Ok(output) => extract_reply(&output),
+            Err(e) => {
+                tracing::warn!("Hooks: '{}' failed: {}", label, e);
+                return;
+            }
+        };
+
+        if let Some(reply_content) = reply {
+            if !self.take_reply_slot(&reply_to) {
+                tracing::warn!("Hooks: dropping reply from '{}' to '{}': over {} replies in {:?}", label, reply_to, MAX_REPLIES_PER_WINDOW, REPLY_WINDOW);
+                return;
+            }
+            // Same path as a GUI SendMessage, so replies behave exactly like typed messages
+            let command = GuiToDaemonCommand::SendMessage { recipient_id: reply_to.clone(), content: reply_content, reply_to: None };
+            match process_gui_command(command, self.peers_map.clone(), self.user_identity_arc.clone(), self.message_store.clone(), self.gui_clients.clone()).await {
+                DaemonToGuiMessage::Success(_) => tracing::info!("Hooks: '{}' replied to '{}'", label, reply_to),
+                DaemonToGuiMessage::Error(err_msg) => tracing::warn!("Hooks: '{}' reply to '{}' failed: {}", label, reply_to, err_msg),
+                other => tracing::warn!("Hooks: unexpected response sending reply for '{}': {:?}", label, other),
+            }
+        }
+    }
+
+    async fn post_webhook(&self, url: &str, payload_json: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let response = self.http_client
+            .post(url)
+            .header("Content-Type", "application/json")
+            .body(payload_json.to_string())
+            .timeout(HOOK_TIMEOUT)
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!("webhook {} returned {}", url, status).into());
+        }
+        Ok(response.text().await?)
+    }
+}
+
+async fn run_command(command: &str, payload_json: &str, event: HookEvent, peer_id: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let event_name = match event {
+        HookEvent::Message => "message",
+        HookEvent::PeerJoined => "peer_joined",
+    };
+    let mut child = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("LOCALCHAT_EVENT", event_name)
+        .env("LOCALCHAT_PEER_ID", peer_id)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .kill_on_drop(true) // So a hook that hits the timeout doesn't linger
+        .spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // A hook that never reads stdin closes the pipe early; that's fine
+        let _ = stdin.write_all(payload_json.as_bytes()).await;
+    }
+
+    let output = tokio::time::timeout(HOOK_TIMEOUT, child.wait_with_output())
+        .await
+        .map_err(|_| format!("command timed out after {}s", HOOK_TIMEOUT.as_secs()))??;
+    if !output.status.success() {
+        return Err(format!("command exited with {}", output.status).into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// Hooks may answer with plain text or with JSON like {"reply": "..."}; empty output means "no reply"
+fn extract_reply(output: &str) -> Option<String> {
+    let trimmed_output = output.trim();
+    if trimmed_output.is_empty() {
+        return None;
+    }
+    if let Ok(serde_json::Value::Object(object)) = serde_json::from_str::<serde_json::Value>(trimmed_output) {
+        return object.get("reply")
+            .and_then(|reply| reply.as_str())
+            .map(|reply| reply.trim().to_string())
+            .filter(|reply| !reply.is_empty());
+    }
+    Some(trimmed_output.to_string())
+}
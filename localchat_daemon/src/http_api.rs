@@ -0,0 +1,239 @@
+// Opt-in localhost HTTP/WebSocket bridge for dashboards and bots written in other languages.
+// Enabled by setting LOCALCHAT_HTTP_PORT; every request needs the bearer token stored in the user's data directory.
+use axum::extract::ws::{Message as WsMessage, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, Query, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use rand::Rng;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::{DirBuilder, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt};
+use std::path::Path as FsPath;
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex};
+
+use crate::{next_gui_client_id, process_gui_command, DaemonToGuiMessage, GuiClients, GUI_CLIENT_QUEUE, GuiToDaemonCommand, IpcPeer, SharedMessageStore, UserIdentity};
+
+#[derive(Clone)]
+struct ApiState {
+    api_token: Arc<String>,
+    peers_map: Arc<Mutex<HashMap<String, IpcPeer>>>,
+    user_identity: Arc<Mutex<Option<UserIdentity>>>,
+    gui_clients: GuiClients,
+    message_store: SharedMessageStore,
+}
+
+#[derive(Debug, Deserialize)]
+struct SendRequest {
+    recipient_id: String,
+    content: String,
+    #[serde(default)]
+    reply_to: Option<String>, // Id of the message being answered
+}
+
+#[derive(Debug, Deserialize)]
+struct HistoryQuery {
+    since: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+// Reads the API token, generating a random one on first use. The file is created 0600 in one step (never readable
+// by others, not even briefly), and an existing one is only trusted if it is a regular file owned by this user
+// that nobody else can read or write; otherwise the API stays off rather than accept a token someone else knows.
+pub fn load_or_create_api_token(token_file_path: &FsPath) -> Result<String, Box<dyn Error>> {
+    if let Some(token_dir) = token_file_path.parent() {
+        DirBuilder::new().recursive(true).mode(0o700).create(token_dir)?;
+    }
+
+    match OpenOptions::new().write(true).create_new(true).mode(0o600).open(token_file_path) {
+        Ok(mut token_file) => {
+            let new_token = generate_api_token();
+            token_file.write_all(new_token.as_bytes())?;
+            tracing::info!("Generated new HTTP API token at {}", token_file_path.display());
+            return Ok(new_token);
+        }
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    // symlink_metadata, so a link pointing at someone else's file isn't followed
+    let metadata = std::fs::symlink_metadata(token_file_path)?;
+    if !metadata.file_type().is_file() {
+        return Err("not a regular file".into());
+    }
+    // SAFETY: geteuid has no preconditions and can't fail
+    let current_uid = unsafe { libc::geteuid() };
+    if metadata.uid() != current_uid {
+        return Err(format!("owned by uid {}, not by this user (uid {})", metadata.uid(), current_uid).into());
+    }
+    if metadata.mode() & 0o077 != 0 {
+        return Err(format!("mode is {:o}; it must not be accessible to others (chmod 600)", metadata.mode() & 0o777).into());
+    }
+
+    let existing_token = std::fs::read_to_string(token_file_path)?;
+    let trimmed_token = existing_token.trim();
+    if trimmed_token.is_empty() {
+        // Ours and private, just empty (e.g. a write that was interrupted): fill it in
+        let new_token = generate_api_token();
+        OpenOptions::new().write(true).truncate(true).open(token_file_path)?.write_all(new_token.as_bytes())?;
+        tracing::info!("Generated new HTTP API token at {}", token_file_path.display());
+        return Ok(new_token);
+    }
+    tracing::info!("Loaded HTTP API token from {}", token_file_path.display());
+    Ok(trimmed_token.to_string())
+}
+
+fn generate_api_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&rand::distributions::Alphanumeric)
+        .take(40)
+        .map(char::from)
+        .collect()
+}
+
+pub async fn serve(
+    http_port: u16,
+    api_token: String,
+    peers_map: Arc<Mutex<HashMap<String, IpcPeer>>>,
+    user_identity: Arc<Mutex<Option<UserIdentity>>>,
+    gui_clients: GuiClients,
+    message_store: SharedMessageStore,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let state = ApiState {
+        api_token: Arc::new(api_token),
+        peers_map,
+        user_identity,
+        gui_clients,
+        message_store,
+    };
+
+    let app = Router::new()
+        .route("/api/peers", get(get_peers))
+        .route("/api/history/{peer_id}", get(get_history))
+        .route("/api/send", post(send_message))
+        .route("/api/events", get(events_websocket))
+        .layer(middleware::from_fn_with_state(state.clone(), require_bearer_token))
+        .with_state(state);
+
+    // Loopback only: the API is meant for local tools, never for the LAN
+    let listen_addr = format!("127.0.0.1:{}", http_port);
+    let listener = tokio::net::TcpListener::bind(&listen_addr).await?;
+    tracing::info!("HTTP API listening on http://{}", listen_addr);
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+// Accepts `Authorization: Bearer <token>`, or `?access_token=<token>` since browsers can't set headers on WebSockets
+async fn require_bearer_token(State(state): State<ApiState>, request: Request, next: Next) -> Response {
+    let header_token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_string());
+    let query_token = request.uri().query().and_then(|query| {
+        query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == "access_token")
+            .map(|(_, value)| value.to_string())
+    });
+
+    match header_token.or(query_token) {
+        Some(token) if tokens_match(&token, &state.api_token) => next.run(request).await,
+        _ => {
+            tracing::warn!("HTTP API: rejected unauthenticated request to {}", request.uri().path());
+            error_response(StatusCode::UNAUTHORIZED, "Missing or invalid bearer token".to_string())
+        }
+    }
+}
+
+// Compares without short-circuiting so response timing doesn't leak how much of the token matched
+fn tokens_match(provided: &str, expected: &str) -> bool {
+    provided.len() == expected.len()
+        && provided.bytes().zip(expected.bytes()).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+fn error_response(status: StatusCode, message: String) -> Response {
+    (status, Json(serde_json::json!({ "error": message }))).into_response()
+}
+
+async fn get_peers(State(state): State<ApiState>) -> Response {
+    match process_gui_command(GuiToDaemonCommand::GetPeers, state.peers_map, state.user_identity, state.message_store, state.gui_clients).await {
+        DaemonToGuiMessage::PeerList(peers) => Json(peers).into_response(),
+        DaemonToGuiMessage::Error(err_msg) => error_response(StatusCode::INTERNAL_SERVER_ERROR, err_msg),
+        other => error_response(StatusCode::INTERNAL_SERVER_ERROR, format!("Unexpected daemon response: {:?}", other)),
+    }
+}
+
+async fn get_history(
+    State(state): State<ApiState>,
+    Path(peer_id): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> Response {
+    let command = GuiToDaemonCommand::RequestHistory { peer_id, since_timestamp: query.since, around_message_id: None };
+    match process_gui_command(command, state.peers_map, state.user_identity, state.message_store, state.gui_clients).await {
+        response @ DaemonToGuiMessage::HistoryResponse { .. } => Json(response).into_response(),
+        DaemonToGuiMessage::Error(err_msg) => error_response(StatusCode::BAD_REQUEST, err_msg),
+        other => error_response(StatusCode::INTERNAL_SERVER_ERROR, format!("Unexpected daemon response: {:?}", other)),
+    }
+}
+
+async fn send_message(State(state): State<ApiState>, Json(request): Json<SendRequest>) -> Response {
+    if request.content.trim().is_empty() {
+        return error_response(StatusCode::BAD_REQUEST, "Message content must not be empty".to_string());
+    }
+    let command = GuiToDaemonCommand::SendMessage { recipient_id: request.recipient_id, content: request.content, reply_to: request.reply_to };
+    match process_gui_command(command, state.peers_map, state.user_identity, state.message_store, state.gui_clients).await {
+        DaemonToGuiMessage::Success(msg) => Json(serde_json::json!({ "status": msg })).into_response(),
+        DaemonToGuiMessage::Error(err_msg) => error_response(StatusCode::BAD_GATEWAY, err_msg),
+        other => error_response(StatusCode::INTERNAL_SERVER_ERROR, format!("Unexpected daemon response: {:?}", other)),
+    }
+}
+
+async fn events_websocket(State(state): State<ApiState>, ws: WebSocketUpgrade) -> Response {
+    ws.on_upgrade(move |socket| stream_events(socket, state.gui_clients))
+}
+
+// Registers the socket as one more IPC client, so it sees exactly the DaemonToGuiMessage events the GUI gets
+async fn stream_events(mut socket: WebSocket, gui_clients: GuiClients) {
+    let client_id = next_gui_client_id();
+    let (to_ws_sender, mut to_ws_receiver) = mpsc::channel::<DaemonToGuiMessage>(GUI_CLIENT_QUEUE);
+    gui_clients.lock().await.insert(client_id, to_ws_sender);
+    tracing::info!("HTTP API: WebSocket event client {} connected", client_id);
+
+    loop {
+        tokio::select! {
+            daemon_message = to_ws_receiver.recv() => {
+                // None: dropped from GuiClients for falling behind
+                let Some(daemon_message) = daemon_message else {
+                    tracing::warn!("HTTP API: WebSocket client {} fell behind on events; closing it.", client_id);
+                    break;
+                };
+                match serde_json::to_string(&daemon_message) {
+                    Ok(json_message) => {
+                        if let Err(e) = socket.send(WsMessage::Text(json_message.into())).await {
+                            tracing::warn!("HTTP API: failed to forward event to WebSocket client {}: {}", client_id, e);
+                            break;
+                        }
+                    }
+                    Err(e) => tracing::error!("HTTP API: failed to serialize event for WebSocket: {}", e),
+                }
+            },
+            incoming = socket.recv() => {
+                match incoming {
+                    Some(Ok(WsMessage::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => {} // The event stream is one-way; ignore anything the client sends
+                }
+            },
+        }
+    }
+
+    gui_clients.lock().await.remove(&client_id);
+    tracing::info!("HTTP API: WebSocket event client {} disconnected", client_id);
+}
@@ -0,0 +1,245 @@
+// When this daemon started and whether it has been asked to stop. IPC clients see the first in DaemonInfo and ask
+// for the second with Shutdown (SIGTERM and SIGINT ask too); main waits for it instead of running forever,
+// then lets the commands already running finish before it tears everything down.
+// Also the per-instance lock that keeps a second daemon from starting on the same socket.
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+use tokio::net::UnixStream;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
+
+static STARTED_AT: LazyLock<chrono::DateTime<chrono::Utc>> = LazyLock::new(chrono::Utc::now);
+
+// Why a stop was requested, once it was; a watch channel so any number of tasks can wait for it
+static SHUTDOWN: LazyLock<watch::Sender<Option<String>>> = LazyLock::new(|| watch::Sender::new(None));
+
+// Commands being processed right now (sends, edits, ...); shutdown waits for these to reach zero
+static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+// How long startup waits for a daemon that holds the lock but no longer answers on the socket, i.e. one that is
+// still finishing a graceful shutdown (a restart from the GUI starts the new one as soon as the old stops answering)
+const LOCK_WAIT: Duration = Duration::from_secs(10);
+
+// What a client is told about the daemon it is attached to (answer to GetDaemonInfo)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DaemonInfo {
+    pub version: String,
+    pub pid: u32,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    pub socket_path: String,
+    pub ipc_clients: usize, // Connected GUIs, CLIs and WebSockets, the asking one included
+    pub peers: usize, // Discovered over mDNS
+    #[serde(default)]
+    pub user_id: Option<String>, // The identity announced on mDNS; None until a client sets a username
+}
+
+// Held while a command runs; dropping it (however the command ends) takes it off the in-flight count
+pub struct InFlight(());
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        IN_FLIGHT.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// Called first thing in main, so the start time isn't the first GetDaemonInfo
+pub fn mark_started() {
+    LazyLock::force(&STARTED_AT);
+}
+
+pub fn started_at() -> chrono::DateTime<chrono::Utc> {
+    *STARTED_AT
+}
+
+// Only the first request counts; the reason it gives is the one clients are told
+pub fn request_shutdown(reason: &str) {
+    let first = SHUTDOWN.send_if_modified(|current| {
+        if current.is_some() {
+            return false;
+        }
+        *current = Some(reason.to_string());
+        true
+    });
+    if first {
+        tracing::info!("Shutdown requested: {}", reason);
+    } else {
+        tracing::info!("Shutdown requested again ({}); already stopping.", reason);
+    }
+}
+
+pub fn is_shutting_down() -> bool {
+    SHUTDOWN.borrow().is_some()
+}
+
+// Returns the reason once a shutdown has been requested (right away if it already was)
+pub async fn shutdown_requested() -> String {
+    let mut shutdown_rx = SHUTDOWN.subscribe();
+    let reason = match shutdown_rx.wait_for(|reason| reason.is_some()).await {
+        Ok(reason) => reason.clone().unwrap_or_default(),
+        Err(_) => String::new(), // The sender is a static and never dropped
+    };
+    reason
+}
+
+// Registers a command as in flight, or returns None when the daemon is stopping and shouldn't start new work.
+// The count goes up before the check, so drain_in_flight can't miss a command that got past it.
+pub fn begin_work() -> Option<InFlight> {
+    IN_FLIGHT.fetch_add(1, Ordering::SeqCst);
+    let guard = InFlight(());
+    if is_shutting_down() {
+        return None; // Dropping the guard undoes the increment
+    }
+    Some(guard)
+}
+
+// Waits until no command is in flight, or the timeout passes; returns how many were still running
+pub async fn drain_in_flight(timeout: Duration) -> usize {
+    let started = Instant::now();
+    loop {
+        let in_flight = IN_FLIGHT.load(Ordering::SeqCst);
+        if in_flight == 0 || started.elapsed() >= timeout {
+            return in_flight;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+// SIGTERM (systemd, kill) and SIGINT (Ctrl+C) request a graceful shutdown. A second signal while stopping
+// exits at once, for when something hangs.
+pub fn spawn_signal_handler() -> std::io::Result<()> {
+    let mut sigterm = signal(SignalKind::terminate())?;
+    let mut sigint = signal(SignalKind::interrupt())?;
+    tokio::spawn(async move {
+        loop {
+            let name = tokio::select! {
+                _ = sigterm.recv() => "SIGTERM",
+                _ = sigint.recv() => "SIGINT",
+            };
+            if is_shutting_down() {
+                tracing::warn!("{} received while already stopping; exiting now.", name);
+                std::process::exit(1);
+            }
+            request_shutdown(&format!("{} received", name));
+        }
+    });
+    Ok(())
+}
+
+// An exclusive flock on <socket path>.lock, held for the daemon's whole life, with its PID written inside.
+// The kernel releases it when the process exits, however it exits, so a crash or kill -9 never leaves it stuck.
+// The file itself stays behind on purpose: deleting it would let two daemons lock two different files.
+pub struct InstanceLock {
+    _file: File,
+}
+
+impl InstanceLock {
+    pub async fn acquire(lock_path: &str, socket_path: &str) -> Result<Self, Box<dyn Error>> {
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(lock_path)?;
+        let started = Instant::now();
+        let mut logged_wait = false;
+        loop {
+            match file.try_lock() {
+                Ok(()) => break,
+                Err(TryLockError::WouldBlock) => {
+                    let answering = UnixStream::connect(socket_path).await.is_ok();
+                    if answering || started.elapsed() >= LOCK_WAIT {
+                        let holder = match std::fs::read_to_string(lock_path).ok().and_then(|pid| pid.trim().parse::<u32>().ok()) {
+                            Some(pid) => format!(" (PID {})", pid),
+                            None => String::new(),
+                        };
+                        return Err(format!(
+                            "Another localchat_daemon{} is already running for {} (lock file {}). Stop it first, or start this one with another LOCALCHAT_SOCKET_PATH.",
+                            holder, socket_path, lock_path
+                        ).into());
+                    }
+                    if !logged_wait {
+                        tracing::info!("Lock {} is held by a daemon that isn't answering (probably still stopping); waiting up to {:?}.", lock_path, LOCK_WAIT);
+                        logged_wait = true;
+                    }
+                    tokio::time::sleep(Duration::from_millis(200)).await;
+                }
+                Err(TryLockError::Error(e)) => return Err(format!("Failed to lock {}: {}", lock_path, e).into()),
+            }
+        }
+        // Only written once the lock is ours, so the PID in the file is always the holder's
+        file.set_len(0)?;
+        file.write_all(format!("{}\n", std::process::id()).as_bytes())?;
+        file.flush()?;
+        tracing::info!("Holding instance lock {}", lock_path);
+        Ok(Self { _file: file })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // Lock and socket paths unique to one test, removed when it ends
+    struct TestPaths {
+        lock: PathBuf,
+        socket: PathBuf,
+    }
+
+    impl TestPaths {
+        fn new(name: &str) -> Self {
+            let base = std::env::temp_dir().join(format!("localchat_lock_test_{}_{}", std::process::id(), name));
+            Self { lock: base.with_extension("sock.lock"), socket: base.with_extension("sock") }
+        }
+
+        fn lock(&self) -> &str {
+            self.lock.to_str().unwrap()
+        }
+
+        fn socket(&self) -> &str {
+            self.socket.to_str().unwrap()
+        }
+    }
+
+    impl Drop for TestPaths {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.lock);
+            let _ = std::fs::remove_file(&self.socket);
+        }
+    }
+
+    #[tokio::test]
+    async fn the_holder_writes_its_pid_and_releases_on_drop() {
+        let paths = TestPaths::new("release");
+        let lock = InstanceLock::acquire(paths.lock(), paths.socket()).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&paths.lock).unwrap(), format!("{}\n", std::process::id()));
+        drop(lock);
+        assert!(InstanceLock::acquire(paths.lock(), paths.socket()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn a_second_daemon_is_refused_while_the_first_answers() {
+        let paths = TestPaths::new("answering");
+        let _lock = InstanceLock::acquire(paths.lock(), paths.socket()).await.unwrap();
+        let _listener = tokio::net::UnixListener::bind(&paths.socket).unwrap();
+        let started = Instant::now();
+        let error = InstanceLock::acquire(paths.lock(), paths.socket()).await.err().unwrap().to_string();
+        assert!(started.elapsed() < LOCK_WAIT / 2, "refused without waiting");
+        assert!(error.contains(&format!("(PID {})", std::process::id())), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn a_stopping_holder_is_waited_for() {
+        let paths = TestPaths::new("stopping");
+        let lock = InstanceLock::acquire(paths.lock(), paths.socket()).await.unwrap();
+        // Nothing listens on the socket, like a daemon that is finishing its shutdown
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            drop(lock);
+        });
+        let started = Instant::now();
+        assert!(InstanceLock::acquire(paths.lock(), paths.socket()).await.is_ok());
+        assert!(started.elapsed() >= Duration::from_millis(400));
+        assert!(started.elapsed() < LOCK_WAIT);
+    }
+}
@@ -1,13 +1,29 @@
 use tokio::net::{UnixListener, UnixStream, TcpListener, TcpStream};
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
 use tokio::sync::{mpsc, Mutex};
 use serde::{Deserialize, Serialize};
 use std::error::Error;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::collections::HashMap;
 use std::net::IpAddr;
 use std::env; // For reading environment variables
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+mod attachments; // Sending and saving files attached to messages
+mod config; // Optional daemon config file
+mod edits; // Editing and deleting sent messages
+mod expiry; // Disappearing messages
+mod hooks; // Bot/automation hooks from the config
+mod lifecycle; // Start time, and stopping on request
+mod http_api; // Opt-in localhost HTTP/WebSocket API
+mod mentions; // @mentions in room messages
+mod pins; // Pinned messages
+mod presence; // Status and joined rooms advertised over mDNS
+mod reactions; // Emoji reactions on messages
+mod scheduled; // Messages queued to be sent later
+mod store; // SQLite message history
 
 // mDNS related imports
 use mdns_sd::{ServiceDaemon, ServiceInfo, ServiceEvent};
@@ -24,6 +40,10 @@ pub struct IpcPeer {
     pub username: String,
     pub ip: String,
     pub port: u16,
+    #[serde(default)]
+    pub status: Option<String>, // From the peer's TXT "status", e.g. "away"
+    #[serde(default)]
+    pub rooms: Vec<String>, // Rooms the peer has joined (TXT "rooms"), e.g. ["#lunch"]
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -32,13 +52,89 @@ pub enum GuiToDaemonCommand {
     SendMessage {
         recipient_id: String,
         content: String,
+        #[serde(default)]
+        reply_to: Option<String>, // Id of a message in the same conversation this one answers
     },
     RequestHistory {
         peer_id: String,
         since_timestamp: Option<chrono::DateTime<chrono::Utc>>,
+        #[serde(default)]
+        around_message_id: Option<String>, // Load from shortly before this message onwards instead of the latest page
     },
     SetUsername { username: String }, // New command
     ClearDaemonPeerCache, // Added command
+    SetStatus { status: Option<String> }, // None clears the status (back to "online")
+    JoinRoom { room: String },
+    LeaveRoom { room: String },
+    GetRooms,
+    SendFile {
+        recipient_id: String, // Peer ID or room ("#name")
+        file_path: String, // Path on this machine; the daemon reads and sends the file
+    },
+    GetConversations,
+    MarkRead {
+        conversation_id: String,
+        message_id: Option<String>, // Read up to this message; None = up to the latest one
+    },
+    MarkAllRead,
+    EditMessage {
+        message_id: String, // A message this daemon's user sent
+        content: String,
+    },
+    DeleteMessage { message_id: String }, // Deletes it for everyone in the conversation
+    ToggleReaction {
+        message_id: String, // Any message in one of our conversations
+        emoji: String, // Added if we haven't reacted with it yet, removed otherwise
+    },
+    SetPinned {
+        message_id: String, // Any message in one of our conversations; pinned for everyone in it
+        pinned: bool,
+    },
+    GetPinned { conversation_id: String },
+    SetStarred {
+        message_id: String, // Starred only for us; peers never see it
+        starred: bool,
+    },
+    GetStarred,
+    SetExpiry {
+        conversation_id: String, // Peer's full ID or room; the timer applies to everyone in it
+        expiry_secs: Option<u64>, // None turns disappearing messages off
+    },
+    GetExpiry { conversation_id: String },
+    ScheduleMessage {
+        recipient_id: String, // Peer ID or room; the peer doesn't have to be online now
+        content: String,
+        send_at: chrono::DateTime<chrono::Utc>,
+    },
+    EditScheduled {
+        scheduled_id: String,
+        #[serde(default)]
+        content: Option<String>, // None keeps the text
+        #[serde(default)]
+        send_at: Option<chrono::DateTime<chrono::Utc>>, // None keeps the time
+    },
+    CancelScheduled { scheduled_id: String },
+    GetScheduled,
+    SetDraft {
+        conversation_id: String,
+        content: String, // Empty removes the draft
+    },
+    GetDrafts,
+    SearchMessages {
+        query: String, // Words matched (as prefixes) against content and sender names; may be empty when filtering by peer or date
+        #[serde(default)]
+        peer_id: Option<String>, // Only this conversation (peer's full ID or room)
+        #[serde(default)]
+        before: Option<chrono::DateTime<chrono::Utc>>,
+        #[serde(default)]
+        after: Option<chrono::DateTime<chrono::Utc>>,
+        #[serde(default)]
+        limit: Option<u32>, // Hits per page; defaults to store::DEFAULT_SEARCH_RESULTS
+        #[serde(default)]
+        offset: u32, // Hits to skip, for the next page
+    },
+    GetDaemonInfo,
+    Shutdown, // Stops the daemon; the client gets a Success reply first
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -56,6 +152,82 @@ pub enum DaemonToGuiMessage {
     Error(String),
     IdentityInfo { user_id: String },
     Success(String),
+    RoomList(Vec<String>), // Rooms this daemon is in, "#everyone" first
+    ConversationList(Vec<ConversationSummary>), // Sent on request and pushed whenever unread counts change
+    MessageUpdated(Message), // A stored message was edited, deleted, reacted to, pinned or starred; replaces the one with the same id
+    PinnedMessages {
+        conversation_id: String,
+        messages: Vec<Message>, // Most recently pinned first
+    }, // Sent on request and pushed whenever a pin in the conversation changes
+    StarredMessages(Vec<Message>), // Most recently starred first; sent on request and pushed whenever a star changes
+    ConversationTimer(ConversationTimer), // Sent on request and pushed whenever anyone in the conversation changes it
+    MessagesExpired {
+        conversation_id: String,
+        message_ids: Vec<String>, // Gone from the store; clients remove them from view
+    },
+    ScheduledMessages(Vec<ScheduledMessage>), // Next to be sent first; sent on request and pushed whenever one is added, changed or sent
+    Drafts(Vec<Draft>), // Most recently changed first; the answer to SetDraft and GetDrafts
+    SearchResults {
+        query: String,
+        offset: u32, // Echoed from the request
+        hits: Vec<SearchHit>, // Best match first (newest first when the query is empty)
+        has_more: bool, // Another page is available at offset + hits.len()
+    },
+    DaemonInfo(lifecycle::DaemonInfo), // Version, PID and uptime; the answer to GetDaemonInfo
+    DaemonStopping { reason: String }, // Pushed to every client when a graceful shutdown starts; the connection closes soon after
+}
+
+// One search result: the message plus a short excerpt of it with the matched words marked
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub conversation_id: String,
+    pub message: Message,
+    pub snippet: String, // Excerpt of the content around the matches
+    pub highlights: Vec<(usize, usize)>, // Byte ranges of matched words in `snippet`
+}
+
+// Disappearing-messages timer of one conversation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationTimer {
+    pub conversation_id: String,
+    pub expiry_secs: Option<u64>, // None: messages don't disappear
+    pub set_by: Option<String>, // Full ID of whoever set it last; None if it was never set
+    pub set_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+// A message waiting in the daemon to be sent at `send_at`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledMessage {
+    pub id: String, // Only names the scheduled entry; the message gets its own ID when it is sent
+    pub recipient: String, // Peer's full ID or room
+    pub content: String,
+    pub send_at: chrono::DateTime<chrono::Utc>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>, // Why the latest attempt failed; it is tried again
+}
+
+// Text typed into a conversation but not sent yet, kept for every client
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Draft {
+    pub conversation_id: String,
+    pub content: String,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+// Read state of one conversation (a peer's full ID or a room), from the message store
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationSummary {
+    pub conversation_id: String,
+    pub unread_count: u32, // Received messages after the last-read one
+    pub last_read_message_id: Option<String>,
+    pub last_activity: chrono::DateTime<chrono::Utc>, // Timestamp of the newest message, sent or received
+    #[serde(default)]
+    pub last_message_preview: String, // Content of the newest message
+    #[serde(default)]
+    pub last_message_sender: String,
+    #[serde(default)]
+    pub last_message_is_self: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)] // Make sure chrono is a dependency if using it here
@@ -66,6 +238,98 @@ pub struct Message {
     pub content: String,
     pub timestamp: chrono::DateTime<chrono::Utc>,
     pub is_self: bool, // This will be determined by the GUI based on sender
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attachment: Option<Attachment>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edited_at: Option<chrono::DateTime<chrono::Utc>>, // Last edit, if the sender changed the text
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub deleted: bool, // Deleted for everyone; content and attachment are gone
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<String>, // Id of the message this one answers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_preview: Option<ReplyPreview>, // The parent as the sender saw it, for anyone who doesn't have it
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reactions: Vec<Reaction>, // Filled in from the store for IPC clients; never sent between daemons
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mentions: Vec<String>, // Full IDs of the peers @mentioned in a room message, resolved by the sender's daemon
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub mentions_me: bool, // Set by the receiving daemon when `mentions` has its own ID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pinned_at: Option<chrono::DateTime<chrono::Utc>>, // Filled in from the store; pins only travel as PinChange
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pinned_by: Option<String>, // Full ID of whoever pinned it
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub starred: bool, // Starred by this daemon's user; never sent between daemons
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_in: Option<u64>, // Seconds after `timestamp` at which every copy is purged; the conversation's timer when sent
+}
+
+// Everyone who reacted to a message with one emoji
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reaction {
+    pub emoji: String,
+    pub reactors: Vec<String>, // Full IDs, in the order they reacted
+    #[serde(default)]
+    pub by_self: bool, // We are one of the reactors
+}
+
+// Who wrote the message being replied to and how it started
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplyPreview {
+    pub sender: String,
+    pub content: String, // First REPLY_PREVIEW_CHARS characters
+}
+
+// Enough of the parent to recognise it in a quote
+const REPLY_PREVIEW_CHARS: usize = 200;
+
+// Longest line a peer may send: the biggest attachment as base64 plus room for the rest of the message.
+// A connection sending more without a newline is dropped instead of buffered without end.
+const MAX_PEER_LINE_BYTES: u64 = attachments::MAX_ATTACHMENT_BYTES.div_ceil(3) * 4 + 256 * 1024;
+
+impl ReplyPreview {
+    fn of(parent: &Message) -> Self {
+        Self { sender: parent.sender.clone(), content: parent.content.chars().take(REPLY_PREVIEW_CHARS).collect() }
+    }
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+// The message the unit tests start from: received in #everyone, with nothing else set; tests change the fields they need
+#[cfg(test)]
+fn test_message(id: &str, sender: &str, content: &str, timestamp: chrono::DateTime<chrono::Utc>) -> Message {
+    Message {
+        id: id.to_string(),
+        sender: sender.to_string(),
+        recipient: "#everyone".to_string(),
+        content: content.to_string(),
+        timestamp,
+        is_self: false,
+        attachment: None,
+        edited_at: None,
+        deleted: false,
+        reply_to: None,
+        reply_preview: None,
+        reactions: Vec::new(),
+        mentions: Vec::new(),
+        mentions_me: false,
+        pinned_at: None,
+        pinned_by: None,
+        starred: false,
+        expires_in: None,
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attachment {
+    pub file_name: String,
+    pub size_bytes: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_base64: Option<String>, // File contents; only present between daemons, never sent to IPC clients
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub local_path: Option<String>, // Where the file is on this machine (sender's original or receiver's saved copy)
 }
 // --- End IPC Structures ---
 
@@ -92,8 +356,43 @@ fn get_daemon_socket_path() -> String {
         .unwrap_or_else(|_| "/tmp/localchat_daemon.sock".to_string()) // Default if not set
 }
 
+// Config file with hook rules etc.; LOCALCHAT_CONFIG_PATH overrides the per-instance default
+fn get_daemon_config_path(instance_number: u16) -> String {
+    env::var("LOCALCHAT_CONFIG_PATH").unwrap_or_else(|_| {
+        dirs::config_dir()
+            .map(|dir| dir.join("localchat").join(format!("daemon_config_{}.json", instance_number)).to_string_lossy().into_owned())
+            .unwrap_or_else(|| format!("/tmp/localchat_daemon_config_{}.json", instance_number))
+    })
+}
+
+// LOCALCHAT_DB_PATH overrides where the message history lives (handy for tests and multiple instances)
+fn get_daemon_db_path(instance_number: u16) -> PathBuf {
+    if let Ok(db_path) = env::var("LOCALCHAT_DB_PATH") {
+        return PathBuf::from(db_path);
+    }
+    dirs::data_local_dir()
+        .map(|dir| dir.join("localchat"))
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join(format!("localchat_messages_{}.sqlite3", instance_number))
+}
+
+// Per-user (not /tmp, where another local user could plant a token they know); None without a home directory
+fn get_daemon_api_token_path(instance_number: u16) -> Option<PathBuf> {
+    dirs::data_local_dir().map(|dir| dir.join("localchat").join(format!("api_token_{}", instance_number)))
+}
+
+// The HTTP API is disabled unless a port is given
+fn get_daemon_http_port() -> Option<u16> {
+    env::var("LOCALCHAT_HTTP_PORT")
+        .ok()
+        .and_then(|s| s.parse().ok())
+}
+
 const MDNS_SERVICE_TYPE: &str = "_localchat._tcp.local.";
 
+// How long a graceful shutdown waits for commands already running (a send to a slow peer, say) to finish
+const SHUTDOWN_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
 fn get_local_ip_and_interface_name() -> Option<(IpAddr, String)> {
     if let Ok(interfaces) = NetworkInterface::show() {
         for itf in interfaces {
@@ -115,11 +414,76 @@ struct ConnectedClient {
     // If each client needs to receive specific messages, add a tx channel here
 }
 
+// All IPC clients currently attached to the daemon (GUI, localchat-cli, ...), keyed by a per-connection id.
+// Events such as NewMessage are fanned out to every entry; command responses go only to the requesting connection.
+type GuiClients = Arc<Mutex<HashMap<u64, mpsc::Sender<DaemonToGuiMessage>>>>;
+
+// Events queued for one IPC client before it counts as fallen behind and is dropped (see broadcast_to_gui_clients)
+const GUI_CLIENT_QUEUE: usize = 256;
+
+// The message history database, shared by every task that sends, receives or looks up messages
+type SharedMessageStore = Arc<Mutex<store::MessageStore>>;
+
+static NEXT_GUI_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
+
+// Set once the current identity is registered and browsed for on mDNS (by the first SetUsername after start)
+static MDNS_ANNOUNCED: AtomicBool = AtomicBool::new(false);
+
+// Unique id for a new entry in GuiClients (Unix socket connections and HTTP API WebSockets share the map)
+fn next_gui_client_id() -> u64 {
+    NEXT_GUI_CLIENT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+// Sends an event to every connected IPC client, dropping clients whose channel has closed or is full.
+// Never waits on a client: the senders are copied out of the lock and try_send is used, so one slow reader
+// (a piped `localchat-cli tail`, a WebSocket) can't stall other clients, and a connection handler that
+// broadcasts into its own channel can't wait on itself. A dropped client's handler sees its channel close and disconnects.
+// Returns the number of clients the event was delivered to.
+async fn broadcast_to_gui_clients(gui_clients: &GuiClients, message: DaemonToGuiMessage) -> usize {
+    let clients: Vec<(u64, mpsc::Sender<DaemonToGuiMessage>)> =
+        gui_clients.lock().await.iter().map(|(client_id, tx)| (*client_id, tx.clone())).collect();
+    let mut delivered = 0;
+    let mut dropped_client_ids = Vec::new();
+    for (client_id, tx) in clients {
+        match tx.try_send(message.clone()) {
+            Ok(()) => delivered += 1,
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                tracing::warn!("Dropping IPC client {}: it fell {} events behind.", client_id, GUI_CLIENT_QUEUE);
+                dropped_client_ids.push(client_id);
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => {
+                tracing::warn!("Dropping IPC client {} whose channel has closed.", client_id);
+                dropped_client_ids.push(client_id);
+            }
+        }
+    }
+    if !dropped_client_ids.is_empty() {
+        let mut clients = gui_clients.lock().await;
+        for client_id in dropped_client_ids {
+            clients.remove(&client_id);
+        }
+    }
+    delivered
+}
+
+// Pushes the current unread counts to every IPC client, e.g. after a message arrived or was read
+async fn broadcast_conversation_list(message_store: &SharedMessageStore, gui_clients: &GuiClients) {
+    // Box<dyn Error> isn't Send, so turn it into a String before the await below
+    let summaries = message_store.lock().await.conversation_summaries().map_err(|e| e.to_string());
+    match summaries {
+        Ok(summaries) => {
+            broadcast_to_gui_clients(gui_clients, DaemonToGuiMessage::ConversationList(summaries)).await;
+        }
+        Err(e) => tracing::error!("Failed to load conversation summaries: {}", e),
+    }
+}
+
 // Shared state for the daemon (e.g., peer list, active connections)
 // Arc<tokio::sync::Mutex<...>> would be used for actual shared mutable state
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
+    lifecycle::mark_started();
     let daemon_tcp_port = get_daemon_tcp_port();
     let daemon_socket_path = get_daemon_socket_path();
 
@@ -132,6 +496,8 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let instance_number = instance_number_str.parse::<u16>().unwrap_or(0); // Default to 0 if not found
 
     let identity_file_path = format!("/tmp/localchat_daemon_identity_{}.json", instance_number);
+    let presence_file_path = format!("/tmp/localchat_daemon_presence_{}.json", instance_number);
+    let attachments_dir = attachments::get_attachments_dir(instance_number);
 
     tracing_subscriber::fmt()
         .with_env_filter(
@@ -142,10 +508,33 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     tracing::info!("LocalChat Daemon starting on TCP port: {}, Socket: {}", daemon_tcp_port, daemon_socket_path);
 
+    // Before anything is bound, so a signal never kills the daemon half set up without cleaning up
+    if let Err(e) = lifecycle::spawn_signal_handler() {
+        tracing::error!("Failed to install signal handlers: {}", e);
+        return Err(Box::new(e) as Box<dyn Error>);
+    }
+
+    // One daemon per socket: a second one exits here instead of taking the socket away from the first
+    let daemon_lock_path = format!("{}.lock", daemon_socket_path);
+    let _instance_lock = match lifecycle::InstanceLock::acquire(&daemon_lock_path, &daemon_socket_path).await {
+        Ok(instance_lock) => instance_lock, // Held until main returns
+        Err(e) => {
+            tracing::error!("{}", e);
+            return Err(e);
+        }
+    };
+
+    // With the lock held, a socket file left behind (by a crash or kill -9) can't be connected to and is removed.
+    // One that answers belongs to a daemon running without the lock (a build from before it), so it is left alone.
     if Path::new(&daemon_socket_path).exists() {
-        tracing::info!("Removing existing socket file at {}", daemon_socket_path);
+        if UnixStream::connect(&daemon_socket_path).await.is_ok() {
+            let message = format!("A daemon is already answering on {}; not starting a second one. Stop it first.", daemon_socket_path);
+            tracing::error!("{}", message);
+            return Err(message.into());
+        }
+        tracing::info!("Removing stale socket file at {} (nothing is listening on it)", daemon_socket_path);
         if let Err(e) = std::fs::remove_file(&daemon_socket_path) {
-            tracing::error!("Failed to remove existing socket file: {}. Please check permissions or remove manually.", e);
+            tracing::error!("Failed to remove stale socket file: {}. Please check permissions or remove manually.", e);
             return Err(Box::new(e) as Box<dyn Error>);
         }
     }
@@ -161,7 +550,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
     tracing::info!("Daemon listening on Unix socket: {}", daemon_socket_path);
 
     let peers_map: Arc<Mutex<HashMap<String, IpcPeer>>> = Arc::new(Mutex::new(HashMap::new()));
-    let active_gui_tx: Arc<Mutex<Option<mpsc::Sender<DaemonToGuiMessage>>>> = Arc::new(Mutex::new(None));
+    let gui_clients: GuiClients = Arc::new(Mutex::new(HashMap::new()));
     
     // Attempt to load persistent UserIdentity
     let mut loaded_identity: Option<UserIdentity> = None;
@@ -185,6 +574,25 @@ async fn main() -> Result<(), Box<dyn Error>> {
     }
 
     let user_identity: Arc<Mutex<Option<UserIdentity>>> = Arc::new(Mutex::new(loaded_identity));
+    let presence: Arc<Mutex<presence::Presence>> = Arc::new(Mutex::new(presence::Presence::load(&presence_file_path)));
+
+    let db_path = get_daemon_db_path(instance_number);
+    let message_store: SharedMessageStore = match store::MessageStore::open(&db_path) {
+        Ok(message_store) => Arc::new(Mutex::new(message_store)),
+        Err(e) => {
+            tracing::error!("Failed to open message store at {}: {}", db_path.display(), e);
+            return Err(e);
+        }
+    };
+
+    let daemon_config = config::DaemonConfig::load(&get_daemon_config_path(instance_number));
+    let attachment_inbox = attachments::AttachmentInbox { dir: attachments_dir.clone(), config: daemon_config.attachments.clone() };
+    if attachment_inbox.config.receive {
+        tracing::info!("Receiving files into {} (up to {} MB in total).", attachments_dir.display(), attachment_inbox.config.max_total_mb);
+    } else {
+        tracing::info!("Receiving files is off; set attachments.receive in the daemon config to save files peers send.");
+    }
+    let hook_runner = hooks::HookRunner::new(daemon_config.hooks, peers_map.clone(), user_identity.clone(), message_store.clone(), gui_clients.clone());
 
     // --- mDNS Setup (daemon only, registration deferred) ---
     let mdns_daemon = match ServiceDaemon::new() {
@@ -200,56 +608,74 @@ async fn main() -> Result<(), Box<dyn Error>> {
     // --- End mDNS Setup ---
 
     // Clone for the IPC accept loop
-    let active_gui_tx_ipc_clone = active_gui_tx.clone(); 
+    let gui_clients_ipc_clone = gui_clients.clone();
     let peers_map_ipc_clone = peers_map.clone();
     // let instance_name_ipc_clone = arc_instance_name.clone(); // Old instance name, will be replaced by user_identity
     let user_identity_ipc_clone = user_identity.clone();
     let mdns_daemon_ipc_clone = mdns_daemon.clone(); // Pass Arc<ServiceDaemon>
+    let hook_runner_ipc_clone = hook_runner.clone(); // The mDNS browse loop fires peer_joined hooks
+    let presence_ipc_clone = presence.clone();
+    let message_store_ipc_clone = message_store.clone();
 
-    // IPC Listener Loop (for GUI connections)
-    tokio::spawn(async move {
+    // IPC Listener Loop (for GUI and CLI connections)
+    let ipc_accept_task = tokio::spawn(async move {
         loop {
             match listener.accept().await {
                 Ok((stream, _addr)) => {
-                    tracing::info!("Accepted new GUI connection");
+                    let client_id = next_gui_client_id();
+                    tracing::info!("Accepted new IPC connection (client {})", client_id);
                     let current_peers_map_clone = peers_map_ipc_clone.clone();
                     // let current_instance_name_clone = instance_name_ipc_clone.clone(); // Old
                     let current_user_identity_clone = user_identity_ipc_clone.clone();
                     let current_mdns_daemon_clone = mdns_daemon_ipc_clone.clone(); 
                     let identity_file_path_clone = identity_file_path.clone(); // Clone for handler
+                    let current_hook_runner_clone = hook_runner_ipc_clone.clone();
+                    let current_presence_clone = presence_ipc_clone.clone();
+                    let current_message_store_clone = message_store_ipc_clone.clone();
 
-                    let (to_gui_sender, to_gui_receiver) = mpsc::channel::<DaemonToGuiMessage>(32);
-                    *active_gui_tx_ipc_clone.lock().await = Some(to_gui_sender);
-                    
-                    // Clone Arc for the handler and for passing to mDNS initialization separately
-                    let active_gui_tx_for_handler = active_gui_tx_ipc_clone.clone();
-                    let active_gui_tx_for_mDNS = active_gui_tx_ipc_clone.clone();
+                    // Every client gets its own event channel so a CLI attaching doesn't steal events from the GUI
+                    let (to_gui_sender, to_gui_receiver) = mpsc::channel::<DaemonToGuiMessage>(GUI_CLIENT_QUEUE);
+                    gui_clients_ipc_clone.lock().await.insert(client_id, to_gui_sender);
+                    let gui_clients_for_handler = gui_clients_ipc_clone.clone();
 
                     tokio::spawn(async move {
                         handle_gui_connection(
                             stream, 
+                            client_id,
                             current_peers_map_clone, 
                             to_gui_receiver, 
-                            active_gui_tx_for_handler, // For this GUI handler's direct use
+                            gui_clients_for_handler, // So the handler can deregister itself on disconnect
                             current_user_identity_clone, 
                             current_mdns_daemon_clone, 
                             daemon_tcp_port, 
-                            active_gui_tx_for_mDNS, // To be used by mDNS setup
-                            identity_file_path_clone // Pass identity file path
+                            identity_file_path_clone, // Pass identity file path
+                            current_hook_runner_clone,
+                            current_presence_clone,
+                            current_message_store_clone
                         ).await;
                     });
                 }
                 Err(e) => {
-                    tracing::error!("Failed to accept incoming GUI connection: {}", e);
+                    tracing::error!("Failed to accept incoming IPC connection: {}", e);
                 }
             }
         }
     });
 
+    // Disappearing messages go from the store, the attached clients and the attachments folder
+    tokio::spawn(run_expiry_purger(message_store.clone(), gui_clients.clone(), attachments_dir.clone()));
+
+    // Scheduled messages go out through the normal send path once they are due
+    tokio::spawn(run_scheduler(peers_map.clone(), user_identity.clone(), message_store.clone(), gui_clients.clone()));
+
     // TCP Listener for peer-to-peer messages - this should ideally also only start after identity is confirmed
     // For now, it will start, but handle_peer_tcp_connection might need checks or rely on GUI not sending messages too early
-    let tcp_listener_active_gui_tx = active_gui_tx.clone();
-    tokio::spawn(async move {
+    let tcp_listener_gui_clients = gui_clients.clone();
+    let tcp_listener_hook_runner = hook_runner.clone();
+    let tcp_listener_presence = presence.clone();
+    let tcp_listener_message_store = message_store.clone();
+    let tcp_listener_user_identity = user_identity.clone();
+    let tcp_listener_task = tokio::spawn(async move {
         let listen_addr = format!("0.0.0.0:{}", daemon_tcp_port); // Use dynamic TCP port
         tracing::info!("Starting TCP listener for peer messages on {}", listen_addr);
         match TcpListener::bind(&listen_addr).await {
@@ -258,9 +684,22 @@ async fn main() -> Result<(), Box<dyn Error>> {
                     match tcp_listener.accept().await {
                         Ok((socket, addr)) => {
                             tracing::info!("Accepted new TCP connection from peer: {}", addr);
-                            let gui_tx_clone_for_tcp_handler = tcp_listener_active_gui_tx.clone();
+                            let gui_clients_for_tcp_handler = tcp_listener_gui_clients.clone();
+                            let hook_runner_for_tcp_handler = tcp_listener_hook_runner.clone();
+                            let presence_for_tcp_handler = tcp_listener_presence.clone();
+                            let attachment_inbox_for_tcp_handler = attachment_inbox.clone();
+                            let message_store_for_tcp_handler = tcp_listener_message_store.clone();
+                            let user_identity_for_tcp_handler = tcp_listener_user_identity.clone();
                             tokio::spawn(async move {
-                                handle_peer_tcp_connection(socket, gui_tx_clone_for_tcp_handler).await;
+                                handle_peer_tcp_connection(
+                                    socket,
+                                    gui_clients_for_tcp_handler,
+                                    hook_runner_for_tcp_handler,
+                                    presence_for_tcp_handler,
+                                    attachment_inbox_for_tcp_handler,
+                                    message_store_for_tcp_handler,
+                                    user_identity_for_tcp_handler
+                                ).await;
                             });
                         }
                         Err(e) => {
@@ -276,23 +715,113 @@ async fn main() -> Result<(), Box<dyn Error>> {
         }
     });
 
-    // Keep the main task alive
-    std::future::pending::<()>().await;
+    // Local HTTP/WebSocket API for scripts and dashboards (only when LOCALCHAT_HTTP_PORT is set)
+    let mut http_api_task = None;
+    if let Some(http_port) = get_daemon_http_port() {
+        let api_token = match get_daemon_api_token_path(instance_number) {
+            Some(api_token_file_path) => http_api::load_or_create_api_token(&api_token_file_path)
+                .map_err(|e| format!("token file {}: {}", api_token_file_path.display(), e)),
+            None => Err("there is no per-user data directory for the token file".to_string()),
+        };
+        match api_token {
+            Ok(api_token) => {
+                let http_peers_map = peers_map.clone();
+                let http_user_identity = user_identity.clone();
+                let http_gui_clients = gui_clients.clone();
+                let http_message_store = message_store.clone();
+                http_api_task = Some(tokio::spawn(async move {
+                    if let Err(e) = http_api::serve(http_port, api_token, http_peers_map, http_user_identity, http_gui_clients, http_message_store).await {
+                        tracing::error!("HTTP API on port {} stopped: {}", http_port, e);
+                    }
+                }));
+            }
+            Err(e) => {
+                tracing::error!("HTTP API disabled: could not load or create the API token ({})", e);
+            }
+        }
+    } else {
+        tracing::info!("HTTP API disabled (set LOCALCHAT_HTTP_PORT to enable it).");
+    }
+
+    // Runs until an IPC client asks it to stop (e.g. Restart or Stop in the GUI's settings) or a signal arrives
+    let reason = lifecycle::shutdown_requested().await;
+    tracing::info!("Daemon stopping ({}).", reason);
+
+    // No new IPC clients, peer connections or HTTP requests from here on; commands already running carry on
+    ipc_accept_task.abort();
+    tcp_listener_task.abort();
+    if let Some(http_api_task) = http_api_task {
+        http_api_task.abort();
+    }
+
+    let notified_clients = broadcast_to_gui_clients(&gui_clients, DaemonToGuiMessage::DaemonStopping { reason }).await;
+    tracing::info!("Told {} IPC client(s) the daemon is stopping.", notified_clients);
+
+    // Peers drop us from their lists now instead of showing a ghost until the record's TTL runs out
+    let own_fullname = user_identity.lock().await.as_ref()
+        .map(|identity| format!("{}.{}", identity.m_dns_instance_name, MDNS_SERVICE_TYPE));
+    if let Some(own_fullname) = own_fullname {
+        match mdns_daemon.unregister(&own_fullname) {
+            Ok(status_rx) => match tokio::time::timeout(Duration::from_secs(2), status_rx.recv_async()).await {
+                Ok(Ok(status)) => tracing::info!("Unregistered mDNS service '{}': {:?}", own_fullname, status),
+                Ok(Err(e)) => tracing::warn!("No answer unregistering mDNS service '{}': {}", own_fullname, e),
+                Err(_) => tracing::warn!("Timed out unregistering mDNS service '{}'", own_fullname),
+            },
+            Err(e) => tracing::warn!("Failed to unregister mDNS service '{}': {}", own_fullname, e),
+        }
+    }
+    if let Err(e) = mdns_daemon.shutdown() {
+        tracing::warn!("Failed to shut down the mDNS daemon: {}", e);
+    }
+
+    // Sends (and other commands) that were already running get to finish and store their result
+    let still_running = lifecycle::drain_in_flight(SHUTDOWN_DRAIN_TIMEOUT).await;
+    if still_running > 0 {
+        tracing::warn!("{} command(s) still running after {:?}; stopping anyway.", still_running, SHUTDOWN_DRAIN_TIMEOUT);
+    }
+
+    // Gives the connection handlers a moment to write DaemonStopping and the last responses out
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // Holding the lock until the process exits keeps the purger and incoming peer messages from writing after the checkpoint
+    let message_store_guard = message_store.lock().await;
+    match message_store_guard.checkpoint() {
+        Ok(()) => tracing::info!("Message store flushed."),
+        Err(e) => tracing::warn!("Failed to flush the message store: {}", e),
+    }
+
+    if let Err(e) = std::fs::remove_file(&daemon_socket_path) {
+        tracing::warn!("Failed to remove socket file {}: {}", daemon_socket_path, e);
+    }
+    tracing::info!("Daemon stopped.");
     Ok(())
 }
 
-async fn handle_peer_tcp_connection(socket: TcpStream, gui_sender_arc: Arc<Mutex<Option<mpsc::Sender<DaemonToGuiMessage>>>>) {
+async fn handle_peer_tcp_connection(
+    socket: TcpStream,
+    gui_clients: GuiClients,
+    hook_runner: hooks::HookRunner,
+    presence: Arc<Mutex<presence::Presence>>,
+    attachment_inbox: attachments::AttachmentInbox,
+    message_store: SharedMessageStore,
+    user_identity_arc: Arc<Mutex<Option<UserIdentity>>>
+) {
     let (reader, _writer) = tokio::io::split(socket);
     let mut buf_reader = BufReader::new(reader);
     let mut line_buffer = String::new();
 
     loop {
         line_buffer.clear();
-        match buf_reader.read_line(&mut line_buffer).await {
+        // take() stops the read at the limit; a full-length read without a newline means the line is too long
+        match (&mut buf_reader).take(MAX_PEER_LINE_BYTES).read_line(&mut line_buffer).await {
             Ok(0) => {
                 tracing::info!("Peer TCP connection closed (EOF).");
                 break;
             }
+            Ok(bytes_read) if bytes_read as u64 >= MAX_PEER_LINE_BYTES && !line_buffer.ends_with('\n') => {
+                tracing::warn!("[TCP_RECV] Peer sent a line over {} bytes; dropping the connection.", MAX_PEER_LINE_BYTES);
+                break;
+            }
             Ok(bytes_read) => {
                 let trimmed_line = line_buffer.trim();
                 if trimmed_line.is_empty() {
@@ -300,23 +829,120 @@ async fn handle_peer_tcp_connection(socket: TcpStream, gui_sender_arc: Arc<Mutex
                 }
                 tracing::trace!("[TCP_RECV] Read {} bytes. Raw data: '{}'", bytes_read, trimmed_line);
                 tracing::trace!("[TCP_RECV] Attempting to deserialize: '{}'", trimmed_line);
-                match serde_json::from_str::<Message>(trimmed_line) {
-                    Ok(mut received_message) => {
+                match serde_json::from_str::<edits::PeerPacket>(trimmed_line) {
+                    Ok(edits::PeerPacket::Message(received_message)) => {
+                        let mut received_message = *received_message;
                         tracing::info!("[TCP_RECV] Deserialized message ID: {}, From: {}, To: {}", received_message.id, received_message.sender, received_message.recipient);
                         received_message.is_self = false; 
+                        // A new message can't arrive already edited or deleted; those only come as MessageChange
+                        received_message.edited_at = None;
+                        received_message.deleted = false;
+                        received_message.reactions.clear(); // Reactions only come as ReactionChange
+                        received_message.pinned_at = None; // Pins only come as PinChange
+                        received_message.pinned_by = None;
+                        received_message.starred = false;
+                        if expiry::expires_at(&received_message).is_some_and(|expires_at| expires_at <= chrono::Utc::now()) {
+                            // Resent after we purged it, or a sender's clock far ahead of ours
+                            tracing::info!("[TCP_RECV] Dropping already expired message ID: {}", received_message.id);
+                            continue;
+                        }
+                        received_message.mentions.truncate(mentions::MAX_MENTIONS);
+                        let own_id = user_identity_arc.lock().await.as_ref().map(|identity| identity.full_message_id.clone());
+                        received_message.mentions_me = own_id.is_some_and(|own_id| received_message.mentions.contains(&own_id));
+                        // The parent may be missing here (joined the room later, history cleared); the preview stands in for it
+                        if let Some(preview) = received_message.reply_preview.as_mut() {
+                            preview.content = preview.content.chars().take(REPLY_PREVIEW_CHARS).collect();
+                        }
 
-                        let gui_message = DaemonToGuiMessage::NewMessage(received_message.clone());
-                        let guard = gui_sender_arc.lock().await;
-                        if let Some(tx) = guard.as_ref() {
-                            tracing::trace!("[TCP_RECV] Forwarding message ID: {} to GUI channel.", received_message.id);
-                            if let Err(e) = tx.send(gui_message).await {
-                                tracing::warn!("[TCP_RECV] Failed to send message ID: {} to GUI channel: {}. GUI client might have disconnected.", received_message.id, e);
-                            } else {
-                                tracing::info!("[TCP_RECV] Successfully forwarded message ID: {} to active GUI.", received_message.id);
+                        // Room messages are fanned out by the sender from possibly stale mDNS data; drop rooms we left
+                        if presence::is_room_id(&received_message.recipient) && !presence.lock().await.is_member(&received_message.recipient) {
+                            tracing::info!("[TCP_RECV] Dropping message ID: {} for room '{}' we are not in.", received_message.id, received_message.recipient);
+                            continue;
+                        }
+
+                        if let Some(attachment) = received_message.attachment.as_mut() {
+                            match attachments::save_received(attachment, &received_message.id, &attachment_inbox) {
+                                Ok(()) => tracing::info!("[TCP_RECV] Saved attachment of message ID: {} to {:?}", received_message.id, attachment.local_path),
+                                Err(e) => {
+                                    tracing::warn!("[TCP_RECV] Could not save attachment of message ID: {}: {}", received_message.id, e);
+                                    // Never pass on a path the sender made up
+                                    attachment.data_base64 = None;
+                                    attachment.local_path = None;
+                                }
+                            }
+                        }
+
+                        // Peers may retry a send that actually arrived; only the first copy is shown
+                        match message_store.lock().await.insert_message(&received_message) {
+                            Ok(true) => {}
+                            Ok(false) => {
+                                tracing::info!("[TCP_RECV] Ignoring duplicate message ID: {}", received_message.id);
+                                continue;
                             }
+                            Err(e) => tracing::error!("[TCP_RECV] Failed to store message ID: {}: {}", received_message.id, e),
+                        }
+
+                        let gui_message = DaemonToGuiMessage::NewMessage(received_message.clone());
+                        tracing::trace!("[TCP_RECV] Forwarding message ID: {} to IPC clients.", received_message.id);
+                        let delivered = broadcast_to_gui_clients(&gui_clients, gui_message).await;
+                        if delivered > 0 {
+                            tracing::info!("[TCP_RECV] Successfully forwarded message ID: {} to {} IPC client(s).", received_message.id, delivered);
                         } else {
                             tracing::warn!("[TCP_RECV] No active GUI client to forward message ID: {} to.", received_message.id);
                         }
+
+                        broadcast_conversation_list(&message_store, &gui_clients).await;
+
+                        hook_runner.dispatch(hooks::HookTrigger::Message(Box::new(received_message))).await;
+                    }
+                    Ok(edits::PeerPacket::Change(change)) => {
+                        tracing::info!("[TCP_RECV] Change to message ID: {} from {}: {:?}", change.message_id, change.sender, change.change);
+                        match apply_message_change(&change, &message_store).await {
+                            Ok((original, updated_message)) => {
+                                // Deleted for everyone: the file we saved goes too, as when the message expires
+                                if updated_message.deleted {
+                                    expiry::remove_attachment(&original, &attachment_inbox.dir);
+                                }
+                                broadcast_to_gui_clients(&gui_clients, DaemonToGuiMessage::MessageUpdated(updated_message)).await;
+                                broadcast_conversation_list(&message_store, &gui_clients).await; // The preview may have changed
+                            }
+                            Err(reason) => {
+                                tracing::warn!("[TCP_RECV] Rejected change to message ID: {} from {}: {}", change.message_id, change.sender, reason);
+                            }
+                        }
+                    }
+                    Ok(edits::PeerPacket::Reaction(reaction)) => {
+                        tracing::info!("[TCP_RECV] Reaction {} to message ID: {} from {} (added: {})", reaction.emoji, reaction.message_id, reaction.reactor, reaction.added);
+                        match apply_reaction(&reaction, false, &message_store).await {
+                            Ok(updated_message) => {
+                                broadcast_to_gui_clients(&gui_clients, DaemonToGuiMessage::MessageUpdated(updated_message)).await;
+                            }
+                            Err(reason) => {
+                                tracing::warn!("[TCP_RECV] Rejected reaction to message ID: {} from {}: {}", reaction.message_id, reaction.reactor, reason);
+                            }
+                        }
+                    }
+                    Ok(edits::PeerPacket::Pin(pin)) => {
+                        tracing::info!("[TCP_RECV] Pin change to message ID: {} from {} (pinned: {})", pin.message_id, pin.pinner, pin.pinned);
+                        match apply_pin(&pin, false, &message_store).await {
+                            Ok(updated_message) => broadcast_pin_update(updated_message, &message_store, &gui_clients).await,
+                            Err(reason) => {
+                                tracing::warn!("[TCP_RECV] Rejected pin change to message ID: {} from {}: {}", pin.message_id, pin.pinner, reason);
+                            }
+                        }
+                    }
+                    Ok(edits::PeerPacket::Timer(timer)) => {
+                        let conversation_id = expiry::conversation_for(&timer).to_string();
+                        tracing::info!("[TCP_RECV] Timer of '{}' set to {} by {}", conversation_id, expiry::describe(timer.expiry_secs), timer.setter);
+                        if presence::is_room_id(&conversation_id) && !presence.lock().await.is_member(&conversation_id) {
+                            tracing::info!("[TCP_RECV] Ignoring timer change for room '{}' we are not in.", conversation_id);
+                            continue;
+                        }
+                        if let Err(reason) = expiry::check_expiry(timer.expiry_secs) {
+                            tracing::warn!("[TCP_RECV] Rejected timer change for '{}' from {}: {}", conversation_id, timer.setter, reason);
+                            continue;
+                        }
+                        apply_timer(&conversation_id, &timer, &message_store, &gui_clients).await;
                     }
                     Err(e) => {
                         tracing::warn!("Failed to deserialize TCP message from peer: {}. Line: '{}'", e, trimmed_line);
@@ -334,14 +960,17 @@ async fn handle_peer_tcp_connection(socket: TcpStream, gui_sender_arc: Arc<Mutex
 
 async fn handle_gui_connection(
     stream: UnixStream, 
+    client_id: u64,
     peers_map: Arc<Mutex<HashMap<String, IpcPeer>>>,
     mut messages_from_daemon_tasks: mpsc::Receiver<DaemonToGuiMessage>,
-    active_gui_tx_arc: Arc<Mutex<Option<mpsc::Sender<DaemonToGuiMessage>>>>,
+    gui_clients: GuiClients,
     user_identity_arc: Arc<Mutex<Option<UserIdentity>>>,
     mdns_daemon: Arc<ServiceDaemon>,
     daemon_tcp_port: u16,
-    active_gui_tx_for_mDNS: Arc<Mutex<Option<mpsc::Sender<DaemonToGuiMessage>>>>,
-    identity_file_path: String
+    identity_file_path: String,
+    hook_runner: hooks::HookRunner,
+    presence: Arc<Mutex<presence::Presence>>,
+    message_store: SharedMessageStore
 ) {
     let (reader, mut writer) = tokio::io::split(stream);
     let mut buf_reader = BufReader::new(reader);
@@ -355,7 +984,7 @@ async fn handle_gui_connection(
     if let Ok(json_status) = serde_json::to_string(&initial_status) {
         if writer.write_all(format!("{}\n", json_status).as_bytes()).await.is_err() {
             tracing::warn!("Failed to send initial status to GUI, closing connection.");
-            *active_gui_tx_arc.lock().await = None;
+            gui_clients.lock().await.remove(&client_id);
             return;
         }
     }
@@ -380,66 +1009,94 @@ async fn handle_gui_connection(
                         tracing::debug!("Received from GUI: {}", trimmed_line);
                         match serde_json::from_str::<GuiToDaemonCommand>(trimmed_line) {
                             Ok(command) => {
+                                // Once the daemon is stopping nothing new is started (no re-registering on mDNS, no sends);
+                                // commands that got in first are waited for before it exits
+                                let Some(_in_flight) = lifecycle::begin_work() else {
+                                    let err_response = DaemonToGuiMessage::Error("The daemon is shutting down.".to_string());
+                                    if let Ok(json_err) = serde_json::to_string(&err_response) {
+                                        let _ = writer.write_all(format!("{}\n", json_err).as_bytes()).await;
+                                    }
+                                    line_buffer.clear();
+                                    continue;
+                                };
                                 match command {
                                     GuiToDaemonCommand::SetUsername { username } => {
                                         tracing::info!("Processing SetUsername from GUI: {}", username);
                                         let mut identity_guard = user_identity_arc.lock().await;
-                                        let mut rng = rand::thread_rng();
-                                        let suffix: String = rng.sample_iter(&rand::distributions::Alphanumeric)
-                                            .take(8)
-                                            .map(char::from)
-                                            .collect();
-                                        
-                                        let sanitized_username_for_mdns = username
-                                            .chars()
-                                            .filter(|c| c.is_alphanumeric())
-                                            .collect::<String>();
-                                        let m_dns_instance_name = format!("{}_{}", 
-                                            if sanitized_username_for_mdns.is_empty() { "LocalChat" } else { &sanitized_username_for_mdns }, 
-                                            suffix
-                                        );
-                                        let full_message_id = format!("{} - {}", username, suffix);
-
-                                        let new_identity = UserIdentity {
-                                            user_provided_name: username.clone(),
-                                            m_dns_instance_name: m_dns_instance_name.clone(),
-                                            full_message_id: full_message_id.clone(),
-                                        };
-                                        *identity_guard = Some(new_identity.clone()); // Store in memory
-                                        drop(identity_guard); // Release lock before async operations
-
-                                        // Save the new identity to file
-                                        match serde_json::to_string_pretty(&new_identity) {
-                                            Ok(identity_json) => {
-                                                if let Err(e) = std::fs::write(&identity_file_path, identity_json) {
-                                                    tracing::error!("Failed to save UserIdentity to {}: {}", identity_file_path, e);
-                                                } else {
-                                                    tracing::info!("Successfully saved UserIdentity to {}", identity_file_path);
+                                        // The same name again (a GUI reconnecting, a second client attaching) keeps the identity as it is:
+                                        // a new suffix would be a new full_message_id, i.e. a new contact for every peer
+                                        let unchanged_identity = identity_guard.clone().filter(|identity| identity.user_provided_name == username);
+                                        let (full_message_id, needs_announcing) = if let Some(current_identity) = unchanged_identity {
+                                            drop(identity_guard);
+                                            tracing::info!("Username unchanged; keeping identity '{}'", current_identity.full_message_id);
+                                            // Only a daemon that loaded its identity from file and hasn't announced it yet registers now
+                                            (current_identity.full_message_id, !MDNS_ANNOUNCED.swap(true, Ordering::SeqCst))
+                                        } else {
+                                            let rng = rand::thread_rng();
+                                            let suffix: String = rng.sample_iter(&rand::distributions::Alphanumeric)
+                                                .take(8)
+                                                .map(char::from)
+                                                .collect();
+
+                                            let sanitized_username_for_mdns = username
+                                                .chars()
+                                                .filter(|c| c.is_alphanumeric())
+                                                .collect::<String>();
+                                            let m_dns_instance_name = format!("{}_{}",
+                                                if sanitized_username_for_mdns.is_empty() { "LocalChat" } else { &sanitized_username_for_mdns },
+                                                suffix
+                                            );
+                                            let full_message_id = format!("{} - {}", username, suffix);
+
+                                            let new_identity = UserIdentity {
+                                                user_provided_name: username.clone(),
+                                                m_dns_instance_name: m_dns_instance_name.clone(),
+                                                full_message_id: full_message_id.clone(),
+                                            };
+                                            *identity_guard = Some(new_identity.clone()); // Store in memory
+                                            drop(identity_guard); // Release lock before async operations
+
+                                            // Save the new identity to file
+                                            match serde_json::to_string_pretty(&new_identity) {
+                                                Ok(identity_json) => {
+                                                    if let Err(e) = std::fs::write(&identity_file_path, identity_json) {
+                                                        tracing::error!("Failed to save UserIdentity to {}: {}", identity_file_path, e);
+                                                    } else {
+                                                        tracing::info!("Successfully saved UserIdentity to {}", identity_file_path);
+                                                    }
+                                                }
+                                                Err(e) => {
+                                                    tracing::error!("Failed to serialize UserIdentity for saving: {}", e);
                                                 }
                                             }
-                                            Err(e) => {
-                                                tracing::error!("Failed to serialize UserIdentity for saving: {}", e);
-                                            }
-                                        }
 
-                                        tracing::info!("User identity set/updated: Full ID = '{}', mDNS Name = '{}'", full_message_id, m_dns_instance_name);
+                                            tracing::info!("User identity set/updated: Full ID = '{}', mDNS Name = '{}'", full_message_id, m_dns_instance_name);
+                                            MDNS_ANNOUNCED.store(true, Ordering::SeqCst);
+                                            (full_message_id, true)
+                                        };
 
                                         // Spawn mDNS initialization (this will re-register if needed)
-                                        let mdns_daemon_clone = mdns_daemon.clone();
-                                        let user_identity_clone_for_mdns = user_identity_arc.clone();
-                                        let peers_map_clone_for_mdns = peers_map.clone();
-                                        tokio::spawn(async move {
-                                            tracing::info!("Spawning mDNS initialization/update task...");
-                                            if let Err(e) = initialize_mdns_and_register(
-                                                mdns_daemon_clone,
-                                                user_identity_clone_for_mdns,
-                                                peers_map_clone_for_mdns,
-                                                daemon_tcp_port
-                                            ).await {
-                                                tracing::error!("mDNS initialization/update failed: {}", e);
-                                                // Notify GUI of mDNS failure? (Consider adding this through active_gui_tx_arc)
-                                            }
-                                        });
+                                        if needs_announcing {
+                                            let mdns_daemon_clone = mdns_daemon.clone();
+                                            let user_identity_clone_for_mdns = user_identity_arc.clone();
+                                            let peers_map_clone_for_mdns = peers_map.clone();
+                                            let hook_runner_clone_for_mdns = hook_runner.clone();
+                                            let presence_clone_for_mdns = presence.clone();
+                                            tokio::spawn(async move {
+                                                tracing::info!("Spawning mDNS initialization/update task...");
+                                                if let Err(e) = initialize_mdns_and_register(
+                                                    mdns_daemon_clone,
+                                                    user_identity_clone_for_mdns,
+                                                    peers_map_clone_for_mdns,
+                                                    daemon_tcp_port,
+                                                    hook_runner_clone_for_mdns,
+                                                    presence_clone_for_mdns
+                                                ).await {
+                                                    tracing::error!("mDNS initialization/update failed: {}", e);
+                                                    // Notify GUI of mDNS failure? (Consider adding this through gui_clients)
+                                                }
+                                            });
+                                        }
 
                                         // Send IdentityInfo back to GUI
                                         let identity_msg = DaemonToGuiMessage::IdentityInfo { user_id: full_message_id };
@@ -468,10 +1125,42 @@ async fn handle_gui_connection(
                                             tracing::error!("Failed to serialize command response for GUI");
                                         }
                                     }
-                                    _ => { // Other commands (GetPeers, SendMessage, RequestHistory)
-                                        // These are processed by process_gui_command
-                                        let response = process_gui_command(command, peers_map.clone(), user_identity_arc.clone()).await;
+                                    GuiToDaemonCommand::Shutdown => {
+                                        // Answered before stopping, so the client knows the request arrived
+                                        let response = DaemonToGuiMessage::Success("Daemon is stopping.".to_string());
                                         if let Ok(json_response) = serde_json::to_string(&response) {
+                                            let _ = writer.write_all(format!("{}\n", json_response).as_bytes()).await;
+                                            let _ = writer.flush().await;
+                                        }
+                                        lifecycle::request_shutdown(&format!("Shutdown command from IPC client {}", client_id));
+                                    }
+                                    GuiToDaemonCommand::SetStatus { .. }
+                                    | GuiToDaemonCommand::JoinRoom { .. }
+                                    | GuiToDaemonCommand::LeaveRoom { .. }
+                                    | GuiToDaemonCommand::GetRooms => {
+                                        let is_room_change = matches!(command, GuiToDaemonCommand::JoinRoom { .. } | GuiToDaemonCommand::LeaveRoom { .. });
+                                        let response = process_presence_command(command, &presence, &mdns_daemon, &user_identity_arc, daemon_tcp_port).await;
+                                        if is_room_change && matches!(response, DaemonToGuiMessage::RoomList(_)) {
+                                            // Every attached client (this one included) keeps a room list, so they all get the update
+                                            broadcast_to_gui_clients(&gui_clients, response).await;
+                                        } else if let Ok(json_response) = serde_json::to_string(&response) {
+                                            tracing::debug!("Sending to GUI (command response): {}", json_response);
+                                            if writer.write_all(format!("{}\n", json_response).as_bytes()).await.is_err() {
+                                                tracing::warn!("Failed to send command response to GUI: {}", trimmed_line);
+                                                break;
+                                            }
+                                        } else {
+                                            tracing::error!("Failed to serialize command response for GUI");
+                                        }
+                                    }
+                                    _ => { // Other commands (GetPeers, SendMessage, SendFile, RequestHistory, conversations)
+                                        // These are processed by process_gui_command
+                                        let is_read_change = matches!(command, GuiToDaemonCommand::MarkRead { .. } | GuiToDaemonCommand::MarkAllRead);
+                                        let response = process_gui_command(command, peers_map.clone(), user_identity_arc.clone(), message_store.clone(), gui_clients.clone()).await;
+                                        if is_read_change && matches!(response, DaemonToGuiMessage::ConversationList(_)) {
+                                            // Other clients show the same badges, so they all get the new counts
+                                            broadcast_to_gui_clients(&gui_clients, response).await;
+                                        } else if let Ok(json_response) = serde_json::to_string(&response) {
                                             tracing::debug!("Sending to GUI (command response): {}", json_response);
                                             if writer.write_all(format!("{}\n", json_response).as_bytes()).await.is_err() {
                                                 tracing::warn!("Failed to send command response to GUI: {}", trimmed_line);
@@ -502,7 +1191,12 @@ async fn handle_gui_connection(
                     }
                 }
             },
-            Some(daemon_message) = messages_from_daemon_tasks.recv() => {
+            daemon_message = messages_from_daemon_tasks.recv() => {
+                // None: the sender was removed from GuiClients because this client fell behind
+                let Some(daemon_message) = daemon_message else {
+                    tracing::warn!("IPC client {} was dropped from event delivery; closing its connection.", client_id);
+                    break;
+                };
                 // Log for messages forwarded from other tasks (like TCP listener)
                 if let DaemonToGuiMessage::NewMessage(ref msg) = daemon_message {
                     tracing::info!("Forwarding NewMessage (from other peer) to GUI: {:?}", msg);
@@ -517,22 +1211,20 @@ async fn handle_gui_connection(
                     tracing::error!("Failed to serialize daemon message for GUI");
                 }
             },
-            else => {
-                tracing::info!("Both GUI command stream and internal daemon message channel closed.");
-                break;
-            }
         }
     }
-    tracing::info!("GUI connection handler finished.");
-    *active_gui_tx_arc.lock().await = None;
-    tracing::info!("Cleared active GUI sender.");
+    tracing::info!("GUI connection handler finished (client {}).", client_id);
+    gui_clients.lock().await.remove(&client_id);
+    tracing::info!("Removed IPC client {} from connected clients.", client_id);
 }
 
 async fn initialize_mdns_and_register(
     mdns_daemon: Arc<ServiceDaemon>,
     user_identity_arc: Arc<Mutex<Option<UserIdentity>>>, 
     peers_map: Arc<Mutex<HashMap<String, IpcPeer>>>,
-    daemon_tcp_port: u16
+    daemon_tcp_port: u16,
+    hook_runner: hooks::HookRunner,
+    presence: Arc<Mutex<presence::Presence>>
 ) -> Result<(), Box<dyn Error>> {
     let identity_guard = user_identity_arc.lock().await;
     let current_identity = match identity_guard.as_ref() {
@@ -545,52 +1237,8 @@ async fn initialize_mdns_and_register(
     // Dropping the guard quickly
     drop(identity_guard);
 
-    let (host_ip, iface_name) = get_local_ip_and_interface_name()
-        .unwrap_or_else(|| (IpAddr::V4("0.0.0.0".parse().unwrap()), "DefaultIface".to_string()));
-
-    // Use m_dns_instance_name for the service instance field, and full_message_id for TXT record
-    let m_dns_instance_name = &current_identity.m_dns_instance_name;
-
-    // Sanitize interface name to create a valid hostname component
-    let mut sanitized_hostname_component = iface_name
-        .chars()
-        .map(|c| if c.is_alphanumeric() || c == '-' { c.to_ascii_lowercase() } else { '-' })
-        .collect::<String>();
-    
-    // Remove leading/trailing hyphens and ensure it's not empty
-    sanitized_hostname_component = sanitized_hostname_component.trim_matches('-').to_string();
-    if sanitized_hostname_component.is_empty() || sanitized_hostname_component == "-" {
-        sanitized_hostname_component = "localchat-host".to_string();
-    }
-    let service_host_fqdn = format!("{}.local.", sanitized_hostname_component);
-
-    let own_full_registered_name_for_check = format!("{}.{}", m_dns_instance_name, MDNS_SERVICE_TYPE);
-
-    let mut txt_records = HashMap::new();
-    txt_records.insert("username".to_string(), current_identity.user_provided_name.clone()); // The human-readable name
-    txt_records.insert("full_id".to_string(), current_identity.full_message_id.clone()); // The ID used for messages
-    txt_records.insert("version".to_string(), env!("CARGO_PKG_VERSION").to_string());
-
-    tracing::info!("Registering mDNS service: Instance Name='{}', User Provided='{}', Full ID='{}', Host='{}', Port={}", 
-        m_dns_instance_name, current_identity.user_provided_name.clone(), current_identity.full_message_id.clone(), service_host_fqdn.clone(), daemon_tcp_port);
-
-    let service_info = ServiceInfo::new(
-        MDNS_SERVICE_TYPE,
-        m_dns_instance_name,       // Instance name (e.g., "MyFriendlyName_suffix")
-        &service_host_fqdn,       // Host FQDN (e.g., "mymachine.local.") - pass as borrow
-        host_ip,
-        daemon_tcp_port,
-        Some(txt_records)
-    ).map_err(|e| {
-        tracing::error!("Failed to create ServiceInfo: {}", e);
-        Box::new(e) as Box<dyn Error>
-    })?;
-
-    mdns_daemon.register(service_info.clone()).map_err(|e| {
-        tracing::error!("Failed to register mDNS service: {}", e);
-        Box::new(e) as Box<dyn Error>
-    })?;
-    tracing::info!("Registered mDNS service: '{}' on type {}", m_dns_instance_name, MDNS_SERVICE_TYPE);
+    let presence_snapshot = presence.lock().await.clone();
+    let own_full_registered_name_for_check = register_own_mdns_service(&mdns_daemon, &current_identity, &presence_snapshot, daemon_tcp_port)?;
 
     let browser = mdns_daemon.browse(MDNS_SERVICE_TYPE).map_err(|e| {
         tracing::error!("Failed to start mDNS browser: {}", e);
@@ -629,6 +1277,13 @@ async fn initialize_mdns_and_register(
                                 .map(|s| s.to_string())
                                 .unwrap_or_else(|| discovered_fullname.split('.').next().unwrap_or_default().to_string());
 
+                            // Both are optional; older daemons don't advertise them
+                            let peer_status = info.get_property_val_str("status")
+                                .map(|s| s.to_string())
+                                .filter(|s| !s.is_empty());
+                            let peer_rooms = info.get_property_val_str("rooms")
+                                .map(presence::parse_rooms_txt)
+                                .unwrap_or_default();
 
                             let mut chosen_peer_ip_str: Option<String> = None;
                             for peer_ip_addr in info.get_addresses() {
@@ -643,14 +1298,23 @@ async fn initialize_mdns_and_register(
                                 tracing::info!("mDNS: Service Resolved: ID='{}', DisplayName='{}', Addr='{}:{}', FullName='{}'", 
                                              &peer_message_id, &peer_display_name, &peer_ip_str, peer_port, discovered_fullname);
 
-                                let mut peers = peers_map_mdns_clone.lock().await;
-                                peers.insert(peer_message_id.clone(), IpcPeer { 
-                                    id: peer_message_id, // Use the unique full_message_id from TXT record
+                                let resolved_peer = IpcPeer { 
+                                    id: peer_message_id.clone(), // Use the unique full_message_id from TXT record
                                     username: peer_display_name, 
                                     ip: peer_ip_str, 
-                                    port: peer_port 
-                                });
+                                    port: peer_port,
+                                    status: peer_status,
+                                    rooms: peer_rooms,
+                                };
+                                let mut peers = peers_map_mdns_clone.lock().await;
+                                let is_new_peer = peers.insert(peer_message_id, resolved_peer.clone()).is_none();
                                 tracing::info!("mDNS: Updated peer list, size: {}", peers.len());
+                                drop(peers); // Hooks look the peer list up again
+
+                                // Services get re-resolved periodically; only a previously unknown peer counts as joining
+                                if is_new_peer {
+                                    hook_runner.dispatch(hooks::HookTrigger::PeerJoined(resolved_peer)).await;
+                                }
                             } else {
                                 tracing::warn!("mDNS: Resolved service {} but no suitable IPv4 address found. Addresses: {:?}", 
                                              discovered_fullname, info.get_addresses());
@@ -688,6 +1352,10 @@ async fn initialize_mdns_and_register(
                         _ => { /* Other events like ServiceFound (before resolve), etc. */ }
                     }
                 }
+                Err(_) if lifecycle::is_shutting_down() => {
+                    tracing::info!("mDNS: Stopped browsing, the daemon is stopping.");
+                    break; // The mDNS daemon was shut down; its channels are closed for good
+                }
                 Err(e) => {
                     tracing::error!("mDNS: Error receiving browse event: {}", e);
                     tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
@@ -700,101 +1368,1119 @@ async fn initialize_mdns_and_register(
     Ok(())
 }
 
-async fn process_gui_command(
-    command: GuiToDaemonCommand, 
-    peers_map: Arc<Mutex<HashMap<String, IpcPeer>>>, 
-    user_identity_arc: Arc<Mutex<Option<UserIdentity>>>
-) -> DaemonToGuiMessage {
-    match command {
-        GuiToDaemonCommand::SetUsername { .. } => {
-            tracing::warn!("SetUsername command unexpectedly reached process_gui_command.");
-            DaemonToGuiMessage::Error("SetUsername should be handled internally by connection handler.".to_string())
-        }
-        GuiToDaemonCommand::GetPeers => {
-            let peers = peers_map.lock().await;
-            let peer_list: Vec<IpcPeer> = peers.values().cloned().collect();
-            tracing::info!("Responding to GetPeers with {} peers", peer_list.len());
-            DaemonToGuiMessage::PeerList(peer_list)
-        }
-        GuiToDaemonCommand::ClearDaemonPeerCache => { 
-            let mut peers = peers_map.lock().await;
-            peers.clear();
+// Announces (or re-announces) this daemon's service; returns the service fullname used to skip ourselves while browsing
+fn register_own_mdns_service(
+    mdns_daemon: &ServiceDaemon,
+    current_identity: &UserIdentity,
+    presence: &presence::Presence,
+    daemon_tcp_port: u16
+) -> Result<String, Box<dyn Error>> {
+    let (host_ip, iface_name) = get_local_ip_and_interface_name()
+        .unwrap_or_else(|| (IpAddr::V4("0.0.0.0".parse().unwrap()), "DefaultIface".to_string()));
+
+    // Use m_dns_instance_name for the service instance field, and full_message_id for TXT record
+    let m_dns_instance_name = &current_identity.m_dns_instance_name;
+
+    // Sanitize interface name to create a valid hostname component
+    let mut sanitized_hostname_component = iface_name
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' { c.to_ascii_lowercase() } else { '-' })
+        .collect::<String>();
+    
+    // Remove leading/trailing hyphens and ensure it's not empty
+    sanitized_hostname_component = sanitized_hostname_component.trim_matches('-').to_string();
+    if sanitized_hostname_component.is_empty() || sanitized_hostname_component == "-" {
+        sanitized_hostname_component = "localchat-host".to_string();
+    }
+    let service_host_fqdn = format!("{}.local.", sanitized_hostname_component);
+
+    let own_full_registered_name_for_check = format!("{}.{}", m_dns_instance_name, MDNS_SERVICE_TYPE);
+
+    let mut txt_records = HashMap::new();
+    txt_records.insert("username".to_string(), current_identity.user_provided_name.clone()); // The human-readable name
+    txt_records.insert("full_id".to_string(), current_identity.full_message_id.clone()); // The ID used for messages
+    txt_records.insert("version".to_string(), env!("CARGO_PKG_VERSION").to_string());
+    if let Some(ref status) = presence.status {
+        txt_records.insert("status".to_string(), status.clone());
+    }
+    if !presence.rooms.is_empty() {
+        txt_records.insert("rooms".to_string(), presence.rooms_txt_value());
+    }
+
+    tracing::info!("Registering mDNS service: Instance Name='{}', User Provided='{}', Full ID='{}', Host='{}', Port={}", 
+        m_dns_instance_name, current_identity.user_provided_name.clone(), current_identity.full_message_id.clone(), service_host_fqdn.clone(), daemon_tcp_port);
+
+    let service_info = ServiceInfo::new(
+        MDNS_SERVICE_TYPE,
+        m_dns_instance_name,       // Instance name (e.g., "MyFriendlyName_suffix")
+        &service_host_fqdn,       // Host FQDN (e.g., "mymachine.local.") - pass as borrow
+        host_ip,
+        daemon_tcp_port,
+        Some(txt_records)
+    ).map_err(|e| {
+        tracing::error!("Failed to create ServiceInfo: {}", e);
+        Box::new(e) as Box<dyn Error>
+    })?;
+
+    // Registering the same fullname again replaces the record and re-announces it with the new TXT data
+    mdns_daemon.register(service_info).map_err(|e| {
+        tracing::error!("Failed to register mDNS service: {}", e);
+        Box::new(e) as Box<dyn Error>
+    })?;
+    tracing::info!("Registered mDNS service: '{}' on type {}", m_dns_instance_name, MDNS_SERVICE_TYPE);
+
+    Ok(own_full_registered_name_for_check)
+}
+
+// Pushes a status/room change out over mDNS. Before a username is set there is nothing registered yet;
+// the first registration picks the change up.
+async fn refresh_mdns_registration(
+    mdns_daemon: &ServiceDaemon,
+    user_identity_arc: &Arc<Mutex<Option<UserIdentity>>>,
+    presence: &presence::Presence,
+    daemon_tcp_port: u16
+) -> Result<(), Box<dyn Error>> {
+    let current_identity = match user_identity_arc.lock().await.clone() {
+        Some(identity) => identity,
+        None => return Ok(()),
+    };
+    register_own_mdns_service(mdns_daemon, &current_identity, presence, daemon_tcp_port)?;
+    Ok(())
+}
+
+async fn process_gui_command(
+    command: GuiToDaemonCommand, 
+    peers_map: Arc<Mutex<HashMap<String, IpcPeer>>>, 
+    user_identity_arc: Arc<Mutex<Option<UserIdentity>>>,
+    message_store: SharedMessageStore,
+    gui_clients: GuiClients
+) -> DaemonToGuiMessage {
+    // The scheduler, hooks and the HTTP API come in here without an IPC connection, so they are counted here too
+    let Some(_in_flight) = lifecycle::begin_work() else {
+        return DaemonToGuiMessage::Error("The daemon is shutting down.".to_string());
+    };
+    match command {
+        GuiToDaemonCommand::SetUsername { .. } => {
+            tracing::warn!("SetUsername command unexpectedly reached process_gui_command.");
+            DaemonToGuiMessage::Error("SetUsername should be handled internally by connection handler.".to_string())
+        }
+        GuiToDaemonCommand::Shutdown => {
+            tracing::warn!("Shutdown command unexpectedly reached process_gui_command.");
+            DaemonToGuiMessage::Error("Shutdown is only accepted from an IPC connection.".to_string())
+        }
+        GuiToDaemonCommand::GetDaemonInfo => {
+            let info = lifecycle::DaemonInfo {
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                pid: std::process::id(),
+                started_at: lifecycle::started_at(),
+                socket_path: get_daemon_socket_path(),
+                ipc_clients: gui_clients.lock().await.len(),
+                peers: peers_map.lock().await.len(),
+                user_id: if MDNS_ANNOUNCED.load(Ordering::SeqCst) {
+                    user_identity_arc.lock().await.as_ref().map(|identity| identity.full_message_id.clone())
+                } else {
+                    None
+                },
+            };
+            DaemonToGuiMessage::DaemonInfo(info)
+        }
+        GuiToDaemonCommand::GetPeers => {
+            let peers = peers_map.lock().await;
+            let peer_list: Vec<IpcPeer> = peers.values().cloned().collect();
+            tracing::info!("Responding to GetPeers with {} peers", peer_list.len());
+            DaemonToGuiMessage::PeerList(peer_list)
+        }
+        GuiToDaemonCommand::ClearDaemonPeerCache => { 
+            let mut peers = peers_map.lock().await;
+            peers.clear();
             tracing::info!("Daemon peer cache cleared at GUI request.");
             DaemonToGuiMessage::Success("Daemon peer cache cleared.".to_string())
         }
-        GuiToDaemonCommand::SendMessage { ref recipient_id, ref content } => { 
-            let identity_guard = user_identity_arc.lock().await;
-            let current_user_full_id = match identity_guard.as_ref() {
-                Some(identity) => identity.full_message_id.clone(),
-                None => {
-                    tracing::warn!("SendMessage: User identity not set. Cannot send message.");
-                    return DaemonToGuiMessage::Error("Cannot send message: User identity not set. Please set username first.".to_string());
-                }
+        GuiToDaemonCommand::SendMessage { recipient_id, content, reply_to } => {
+            let mut message_to_send = match new_outgoing_message(&user_identity_arc, recipient_id, content, None, &message_store).await {
+                Ok(message) => message,
+                Err(err_response) => return err_response,
             };
-            drop(identity_guard); // Release lock
-
-            let peer_info: Option<IpcPeer>;
-            {
-                let peers_guard = peers_map.lock().await;
-                peer_info = peers_guard.get(recipient_id).cloned();
-            }
-
-            if let Some(recipient_peer) = peer_info {
-                tracing::info!("Attempting to send message to peer IpcPeer {{ id: {}, username: {}, ip: {}, port: {} }}, content: \"{}\"", 
-                    recipient_peer.id, recipient_peer.username, recipient_peer.ip, recipient_peer.port, content);
-                let target_addr = format!("{}:{}", recipient_peer.ip, recipient_peer.port);
-                
-                match TcpStream::connect(&target_addr).await {
-                    Ok(mut stream) => {
-                        let message_to_send = Message {
-                            id: uuid::Uuid::new_v4().to_string(),
-                            sender: current_user_full_id.clone(), // Use the full_message_id
-                            recipient: recipient_id.clone(),
-                            content: content.clone(),
-                            timestamp: chrono::Utc::now(),
-                            is_self: false, // This will be set to true by the sending GUI for its own echo
-                        };
-                        tracing::debug!("[TCP_SEND] Constructed message struct for peer {}: ID={}", recipient_peer.id, message_to_send.id);
-
-                        tracing::trace!("[TCP_SEND] Attempting to serialize message ID: {}", message_to_send.id);
-                        match serde_json::to_string(&message_to_send) {
-                            Ok(json_payload) => {
-                                tracing::trace!("[TCP_SEND] Message ID: {} serialized. Attempting to write to stream for peer {}.
-Payload (first 100 chars): {:.100}", 
-                                    message_to_send.id, recipient_peer.id, json_payload);
-                                if let Err(e) = stream.write_all(format!("{}\n", json_payload).as_bytes()).await {
-                                    tracing::error!("[TCP_SEND] Failed to write message ID: {} to TCP stream for peer {}: {}", message_to_send.id, recipient_peer.id, e);
-                                    return DaemonToGuiMessage::Error(format!("Failed to send message to {}: {}", recipient_peer.username, e));
-                                }
-                                tracing::trace!("[TCP_SEND] Message ID: {} written to stream. Attempting to flush for peer {}.", message_to_send.id, recipient_peer.id);
-                                if let Err(e) = stream.flush().await {
-                                    tracing::error!("[TCP_SEND] Failed to flush TCP stream for peer {}: {}", recipient_peer.id, e);
-                                    return DaemonToGuiMessage::Error(format!("Network error sending to {}: {}", recipient_peer.username, e));
-                                }
-                                tracing::info!("[TCP_SEND] Successfully sent message ID: {} to peer {} ({})", message_to_send.id, recipient_peer.username, target_addr);
-                                
-                                return DaemonToGuiMessage::Success(format!("Message successfully sent to {}", recipient_peer.username));
-                            }
-                            Err(e) => {
-                                tracing::error!("[TCP_SEND] Failed to serialize message ID: {} for TCP sending: {}", message_to_send.id, e);
-                                return DaemonToGuiMessage::Error(format!("Internal error: Failed to prepare message: {}", e));
-                            }
-                        }
+            if presence::is_room_id(&message_to_send.recipient) {
+                let known_peers: Vec<IpcPeer> = peers_map.lock().await.values().cloned().collect();
+                message_to_send.mentions = mentions::find_mentions(&message_to_send.content, &known_peers);
+            }
+            if let Some(parent_id) = reply_to {
+                let parent = message_store.lock().await.get_message(&parent_id).map_err(|e| e.to_string());
+                match parent {
+                    Ok(Some(parent)) if store::conversation_id_for(&parent) != message_to_send.recipient => {
+                        return DaemonToGuiMessage::Error("You can only reply to a message in the same conversation.".to_string());
+                    }
+                    Ok(Some(parent)) if parent.deleted => {
+                        return DaemonToGuiMessage::Error("That message was deleted.".to_string());
+                    }
+                    Ok(Some(parent)) => {
+                        message_to_send.reply_preview = Some(ReplyPreview::of(&parent));
+                        message_to_send.reply_to = Some(parent_id);
                     }
+                    Ok(None) => return DaemonToGuiMessage::Error(format!("Message '{}' not found.", parent_id)),
                     Err(e) => {
-                        tracing::error!("[TCP_SEND] Failed to connect to peer {} (ID: {}) at {}: {}", recipient_peer.username, recipient_peer.id, target_addr, e);
-                        DaemonToGuiMessage::Error(format!("Could not connect to {}: {}", recipient_peer.username, e))
+                        tracing::error!("Failed to look up reply parent ID: {}: {}", parent_id, e);
+                        return DaemonToGuiMessage::Error(format!("Could not look up message '{}': {}", parent_id, e));
+                    }
+                }
+            }
+            let response = deliver_outgoing_message(&message_to_send, &peers_map).await;
+            record_sent_message(message_to_send, &response, &message_store, &gui_clients).await;
+            response
+        }
+        GuiToDaemonCommand::SendFile { recipient_id, file_path } => {
+            let attachment = match attachments::read_for_sending(&file_path) {
+                Ok(attachment) => attachment,
+                Err(err_msg) => {
+                    tracing::warn!("SendFile: {}", err_msg);
+                    return DaemonToGuiMessage::Error(err_msg);
+                }
+            };
+            // Clients that don't know about attachments still see which file was sent
+            let content = format!("📎 {}", attachment.file_name);
+            let message_to_send = match new_outgoing_message(&user_identity_arc, recipient_id, content, Some(attachment), &message_store).await {
+                Ok(message) => message,
+                Err(err_response) => return err_response,
+            };
+            let response = deliver_outgoing_message(&message_to_send, &peers_map).await;
+            record_sent_message(message_to_send, &response, &message_store, &gui_clients).await;
+            response
+        }
+        GuiToDaemonCommand::SetStatus { .. }
+        | GuiToDaemonCommand::JoinRoom { .. }
+        | GuiToDaemonCommand::LeaveRoom { .. }
+        | GuiToDaemonCommand::GetRooms => {
+            tracing::warn!("Presence command unexpectedly reached process_gui_command: {:?}", command);
+            DaemonToGuiMessage::Error("Status and room commands are only available over the IPC socket.".to_string())
+        }
+        GuiToDaemonCommand::EditMessage { message_id, content } => {
+            send_message_change(message_id, edits::ChangeKind::Edit { content }, &peers_map, &message_store, &gui_clients).await
+        }
+        GuiToDaemonCommand::DeleteMessage { message_id } => {
+            send_message_change(message_id, edits::ChangeKind::Delete, &peers_map, &message_store, &gui_clients).await
+        }
+        GuiToDaemonCommand::ToggleReaction { message_id, emoji } => {
+            send_reaction(message_id, emoji, &user_identity_arc, &peers_map, &message_store, &gui_clients).await
+        }
+        GuiToDaemonCommand::SetPinned { message_id, pinned } => {
+            send_pin(message_id, pinned, &user_identity_arc, &peers_map, &message_store, &gui_clients).await
+        }
+        GuiToDaemonCommand::GetPinned { conversation_id } => {
+            let pinned = message_store.lock().await.pinned_messages(&conversation_id).map_err(|e| e.to_string());
+            match pinned {
+                Ok(messages) => DaemonToGuiMessage::PinnedMessages { conversation_id, messages },
+                Err(e) => {
+                    tracing::error!("GetPinned for '{}' failed: {}", conversation_id, e);
+                    DaemonToGuiMessage::Error(format!("Could not load pinned messages of '{}': {}", conversation_id, e))
+                }
+            }
+        }
+        GuiToDaemonCommand::SetStarred { message_id, starred } => {
+            let starred_result = {
+                let store = message_store.lock().await;
+                match store.get_message(&message_id) {
+                    Ok(Some(message)) if message.deleted => Err("the message was deleted".to_string()),
+                    Ok(Some(_)) => store.set_starred(&message_id, starred, chrono::Utc::now())
+                        .and_then(|()| Ok((store.get_message(&message_id)?, store.starred_messages()?)))
+                        .map_err(|e| e.to_string()),
+                    Ok(None) => Err(format!("message '{}' not found", message_id)),
+                    Err(e) => Err(e.to_string()),
+                }
+            };
+            match starred_result {
+                Ok((updated_message, starred_messages)) => {
+                    if let Some(updated_message) = updated_message {
+                        broadcast_to_gui_clients(&gui_clients, DaemonToGuiMessage::MessageUpdated(updated_message)).await;
                     }
+                    broadcast_to_gui_clients(&gui_clients, DaemonToGuiMessage::StarredMessages(starred_messages)).await;
+                    DaemonToGuiMessage::Success(if starred { "Message starred.".to_string() } else { "Message unstarred.".to_string() })
+                }
+                Err(reason) => DaemonToGuiMessage::Error(format!("Cannot star: {}.", reason)),
+            }
+        }
+        GuiToDaemonCommand::SetExpiry { conversation_id, expiry_secs } => {
+            send_timer(conversation_id, expiry_secs, &user_identity_arc, &peers_map, &message_store, &gui_clients).await
+        }
+        GuiToDaemonCommand::GetExpiry { conversation_id } => {
+            let timer = message_store.lock().await.conversation_timer(&conversation_id).map_err(|e| e.to_string());
+            match timer {
+                Ok(timer) => DaemonToGuiMessage::ConversationTimer(timer.unwrap_or(ConversationTimer {
+                    conversation_id,
+                    expiry_secs: None,
+                    set_by: None,
+                    set_at: None,
+                })),
+                Err(e) => {
+                    tracing::error!("GetExpiry for '{}' failed: {}", conversation_id, e);
+                    DaemonToGuiMessage::Error(format!("Could not load the timer of '{}': {}", conversation_id, e))
+                }
+            }
+        }
+        GuiToDaemonCommand::ScheduleMessage { recipient_id, content, send_at } => {
+            schedule_message(recipient_id, content, send_at, &message_store, &gui_clients).await
+        }
+        GuiToDaemonCommand::EditScheduled { scheduled_id, content, send_at } => {
+            edit_scheduled(scheduled_id, content, send_at, &message_store, &gui_clients).await
+        }
+        GuiToDaemonCommand::CancelScheduled { scheduled_id } => {
+            let deleted = message_store.lock().await.delete_scheduled(&scheduled_id).map_err(|e| e.to_string());
+            match deleted {
+                Ok(true) => {
+                    tracing::info!("Cancelled scheduled message ID: {}", scheduled_id);
+                    broadcast_scheduled(&message_store, &gui_clients).await;
+                    DaemonToGuiMessage::Success("Scheduled message cancelled.".to_string())
+                }
+                Ok(false) => DaemonToGuiMessage::Error(format!("Scheduled message '{}' not found (it may have been sent already).", scheduled_id)),
+                Err(e) => {
+                    tracing::error!("Failed to cancel scheduled message ID: {}: {}", scheduled_id, e);
+                    DaemonToGuiMessage::Error(format!("Could not cancel scheduled message '{}': {}", scheduled_id, e))
+                }
+            }
+        }
+        GuiToDaemonCommand::GetScheduled => {
+            let scheduled = message_store.lock().await.scheduled_messages().map_err(|e| e.to_string());
+            match scheduled {
+                Ok(scheduled) => DaemonToGuiMessage::ScheduledMessages(scheduled),
+                Err(e) => {
+                    tracing::error!("GetScheduled failed: {}", e);
+                    DaemonToGuiMessage::Error(format!("Could not load scheduled messages: {}", e))
+                }
+            }
+        }
+        GuiToDaemonCommand::SetDraft { conversation_id, content } => {
+            let drafts = {
+                let store = message_store.lock().await;
+                store.set_draft(&conversation_id, &content, chrono::Utc::now())
+                    .and_then(|()| store.drafts())
+                    .map_err(|e| e.to_string())
+            };
+            match drafts {
+                Ok(drafts) => DaemonToGuiMessage::Drafts(drafts),
+                Err(e) => {
+                    tracing::error!("Failed to save the draft of '{}': {}", conversation_id, e);
+                    DaemonToGuiMessage::Error(format!("Could not save the draft of '{}': {}", conversation_id, e))
+                }
+            }
+        }
+        GuiToDaemonCommand::GetDrafts => {
+            let drafts = message_store.lock().await.drafts().map_err(|e| e.to_string());
+            match drafts {
+                Ok(drafts) => DaemonToGuiMessage::Drafts(drafts),
+                Err(e) => {
+                    tracing::error!("GetDrafts failed: {}", e);
+                    DaemonToGuiMessage::Error(format!("Could not load drafts: {}", e))
+                }
+            }
+        }
+        GuiToDaemonCommand::GetStarred => {
+            let starred = message_store.lock().await.starred_messages().map_err(|e| e.to_string());
+            match starred {
+                Ok(messages) => DaemonToGuiMessage::StarredMessages(messages),
+                Err(e) => {
+                    tracing::error!("GetStarred failed: {}", e);
+                    DaemonToGuiMessage::Error(format!("Could not load starred messages: {}", e))
+                }
+            }
+        }
+        GuiToDaemonCommand::GetConversations => {
+            conversation_list_response(&message_store).await
+        }
+        GuiToDaemonCommand::MarkRead { conversation_id, message_id } => {
+            let marked = message_store.lock().await.mark_read(&conversation_id, message_id.as_deref()).map_err(|e| e.to_string());
+            match marked {
+                Ok(changed) => {
+                    tracing::debug!("MarkRead '{}' up to {:?}: changed={}", conversation_id, message_id, changed);
+                    conversation_list_response(&message_store).await
+                }
+                Err(e) => {
+                    tracing::error!("MarkRead for '{}' failed: {}", conversation_id, e);
+                    DaemonToGuiMessage::Error(format!("Could not mark '{}' as read: {}", conversation_id, e))
+                }
+            }
+        }
+        GuiToDaemonCommand::MarkAllRead => {
+            let marked = message_store.lock().await.mark_all_read().map_err(|e| e.to_string());
+            match marked {
+                Ok(changed_count) => {
+                    tracing::info!("Marked all conversations as read ({} changed)", changed_count);
+                    conversation_list_response(&message_store).await
+                }
+                Err(e) => {
+                    tracing::error!("MarkAllRead failed: {}", e);
+                    DaemonToGuiMessage::Error(format!("Could not mark conversations as read: {}", e))
+                }
+            }
+        }
+        GuiToDaemonCommand::SearchMessages { query, peer_id, before, after, limit, offset } => {
+            let query = query.trim().to_string();
+            if query.is_empty() && peer_id.is_none() && after.is_none() && before.is_none() {
+                return DaemonToGuiMessage::Error("Search needs some text, a peer or a date range.".to_string());
+            }
+            let search = store::SearchFilter {
+                conversation_id: peer_id.as_deref(),
+                after,
+                before,
+                limit: limit.map_or(store::DEFAULT_SEARCH_RESULTS, |limit| limit as usize),
+                offset: offset as usize,
+            };
+            let found = message_store.lock().await.search_messages(&query, &search);
+            match found {
+                Ok((hits, has_more)) => {
+                    tracing::info!("SearchMessages '{}' (peer {:?}, {:?} - {:?}, offset {}): {} hit(s)", query, peer_id, after, before, offset, hits.len());
+                    DaemonToGuiMessage::SearchResults { query, offset, hits, has_more }
+                }
+                Err(e) => {
+                    tracing::error!("SearchMessages '{}' failed: {}", query, e);
+                    DaemonToGuiMessage::Error(format!("Search failed: {}", e))
+                }
+            }
+        }
+        GuiToDaemonCommand::RequestHistory { peer_id, since_timestamp, around_message_id } => {
+            // peer_id is a conversation: a peer's full ID or a room
+            let history = match around_message_id {
+                Some(ref message_id) => message_store.lock().await.conversation_around(&peer_id, message_id),
+                None => message_store.lock().await.conversation_history(&peer_id, since_timestamp, store::MAX_HISTORY_MESSAGES),
+            };
+            match history {
+                Ok(messages) => {
+                    tracing::info!("Responding to RequestHistory for '{}' with {} message(s)", peer_id, messages.len());
+                    DaemonToGuiMessage::HistoryResponse { peer_id, messages }
+                }
+                Err(e) => {
+                    tracing::error!("RequestHistory for '{}' failed: {}", peer_id, e);
+                    DaemonToGuiMessage::Error(format!("Could not load history for '{}': {}", peer_id, e))
+                }
+            }
+        }
+    }
+}
+
+async fn conversation_list_response(message_store: &SharedMessageStore) -> DaemonToGuiMessage {
+    let summaries = message_store.lock().await.conversation_summaries();
+    match summaries {
+        Ok(summaries) => DaemonToGuiMessage::ConversationList(summaries),
+        Err(e) => {
+            tracing::error!("Failed to load conversation summaries: {}", e);
+            DaemonToGuiMessage::Error(format!("Could not load conversations: {}", e))
+        }
+    }
+}
+
+// Stores a message we sent and shows it in every attached client, so each of them has the full conversation.
+// Replying counts as having read the conversation. Failed sends are not recorded; the sender sees the error instead.
+async fn record_sent_message(
+    mut message: Message,
+    delivery_response: &DaemonToGuiMessage,
+    message_store: &SharedMessageStore,
+    gui_clients: &GuiClients
+) {
+    if !matches!(delivery_response, DaemonToGuiMessage::Success(_)) {
+        return;
+    }
+    message.is_self = true;
+    {
+        let store = message_store.lock().await;
+        if let Err(e) = store.insert_message(&message) {
+            tracing::error!("Failed to store sent message ID: {}: {}", message.id, e);
+        } else if let Err(e) = store.mark_read(store::conversation_id_for(&message), None) {
+            tracing::error!("Failed to mark '{}' as read after sending: {}", message.recipient, e);
+        }
+    }
+    broadcast_to_gui_clients(gui_clients, DaemonToGuiMessage::NewMessage(message)).await;
+    broadcast_conversation_list(message_store, gui_clients).await;
+}
+
+// Edits or deletes a message we sent: checked here first, then sent to the conversation like a message
+// (a peer, or every room member), and applied to our own store once it went out
+async fn send_message_change(
+    message_id: String,
+    change_kind: edits::ChangeKind,
+    peers_map: &Arc<Mutex<HashMap<String, IpcPeer>>>,
+    message_store: &SharedMessageStore,
+    gui_clients: &GuiClients
+) -> DaemonToGuiMessage {
+    let original = message_store.lock().await.get_message(&message_id).map_err(|e| e.to_string());
+    let original = match original {
+        Ok(Some(message)) if message.is_self => message,
+        Ok(Some(_)) => return DaemonToGuiMessage::Error("You can only change messages you sent.".to_string()),
+        Ok(None) => return DaemonToGuiMessage::Error(format!("Message '{}' not found.", message_id)),
+        Err(e) => {
+            tracing::error!("Failed to look up message ID: {}: {}", message_id, e);
+            return DaemonToGuiMessage::Error(format!("Could not look up message '{}': {}", message_id, e));
+        }
+    };
+    let is_delete = matches!(change_kind, edits::ChangeKind::Delete);
+    let change = edits::MessageChange {
+        message_id,
+        sender: original.sender.clone(), // Our ID when it was sent, even if the username changed since
+        change: change_kind,
+        timestamp: chrono::Utc::now(),
+    };
+    if let Err(reason) = edits::check_change(&original, &change, change.timestamp) {
+        return DaemonToGuiMessage::Error(format!("Cannot change this message: {}.", reason));
+    }
+
+    let json_payload = match serde_json::to_string(&edits::PeerPacket::Change(change.clone())) {
+        Ok(json_payload) => json_payload,
+        Err(e) => return DaemonToGuiMessage::Error(format!("Internal error: Failed to prepare change: {}", e)),
+    };
+    let delivery_response = deliver_line(&original.recipient, &json_payload, &change.message_id, peers_map).await;
+    if !matches!(delivery_response, DaemonToGuiMessage::Success(_)) {
+        return delivery_response; // Not applied anywhere, so the user can try again
+    }
+
+    // Only the sender may delete, so this is always our own message and there is no received file to remove
+    match apply_message_change(&change, message_store).await {
+        Ok((_, updated_message)) => {
+            broadcast_to_gui_clients(gui_clients, DaemonToGuiMessage::MessageUpdated(updated_message)).await;
+            broadcast_conversation_list(message_store, gui_clients).await;
+            DaemonToGuiMessage::Success(if is_delete { "Message deleted.".to_string() } else { "Message edited.".to_string() })
+        }
+        Err(reason) => DaemonToGuiMessage::Error(format!("Could not change the message: {}", reason)),
+    }
+}
+
+// Checks a change against the stored original and applies it. Returns the original (with the attachment a delete
+// drops from the store) and the updated message for IPC clients.
+async fn apply_message_change(change: &edits::MessageChange, message_store: &SharedMessageStore) -> Result<(Message, Message), String> {
+    let store = message_store.lock().await;
+    let original = store.get_message(&change.message_id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "no such message here".to_string())?;
+    edits::check_change(&original, change, chrono::Utc::now())?;
+    match change.change {
+        edits::ChangeKind::Edit { ref content } => store.edit_message(&change.message_id, content, change.timestamp),
+        edits::ChangeKind::Delete => store.delete_message(&change.message_id),
+    }.map_err(|e| e.to_string())?;
+    let updated_message = store.get_message(&change.message_id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "message disappeared while changing it".to_string())?;
+    Ok((original, updated_message))
+}
+
+// Adds our reaction with `emoji` to a message, or takes it back if it's there, and tells the conversation's peers
+async fn send_reaction(
+    message_id: String,
+    emoji: String,
+    user_identity_arc: &Arc<Mutex<Option<UserIdentity>>>,
+    peers_map: &Arc<Mutex<HashMap<String, IpcPeer>>>,
+    message_store: &SharedMessageStore,
+    gui_clients: &GuiClients
+) -> DaemonToGuiMessage {
+    let Some(current_user_full_id) = user_identity_arc.lock().await.as_ref().map(|identity| identity.full_message_id.clone()) else {
+        return DaemonToGuiMessage::Error("Cannot react: User identity not set. Please set username first.".to_string());
+    };
+    let lookup = {
+        let store = message_store.lock().await;
+        store.get_message(&message_id)
+            .and_then(|message| Ok((message, store.own_reaction(&message_id, &emoji)?)))
+            .map_err(|e| e.to_string())
+    };
+    let (original, existing_reactor) = match lookup {
+        Ok((Some(message), existing_reactor)) => (message, existing_reactor),
+        Ok((None, _)) => return DaemonToGuiMessage::Error(format!("Message '{}' not found.", message_id)),
+        Err(e) => {
+            tracing::error!("Failed to look up message ID: {}: {}", message_id, e);
+            return DaemonToGuiMessage::Error(format!("Could not look up message '{}': {}", message_id, e));
+        }
+    };
+    let reaction = reactions::ReactionChange {
+        message_id,
+        added: existing_reactor.is_none(),
+        reactor: existing_reactor.unwrap_or(current_user_full_id), // Take back a reaction under the ID it was made with
+        emoji,
+        timestamp: chrono::Utc::now(),
+    };
+    if let Err(reason) = reactions::check_reaction(&original, &reaction) {
+        return DaemonToGuiMessage::Error(format!("Cannot react: {}.", reason));
+    }
+
+    let json_payload = match serde_json::to_string(&edits::PeerPacket::Reaction(reaction.clone())) {
+        Ok(json_payload) => json_payload,
+        Err(e) => return DaemonToGuiMessage::Error(format!("Internal error: Failed to prepare reaction: {}", e)),
+    };
+    let delivery_response = deliver_line(store::conversation_id_for(&original), &json_payload, &reaction.message_id, peers_map).await;
+    if !matches!(delivery_response, DaemonToGuiMessage::Success(_)) {
+        return delivery_response;
+    }
+
+    match apply_reaction(&reaction, true, message_store).await {
+        Ok(updated_message) => {
+            broadcast_to_gui_clients(gui_clients, DaemonToGuiMessage::MessageUpdated(updated_message)).await;
+            DaemonToGuiMessage::Success(if reaction.added { "Reaction added.".to_string() } else { "Reaction removed.".to_string() })
+        }
+        Err(reason) => DaemonToGuiMessage::Error(format!("Could not react: {}", reason)),
+    }
+}
+
+// Stores a reaction (ours, or one a peer sent) and returns the message with its updated reactions
+async fn apply_reaction(reaction: &reactions::ReactionChange, is_self: bool, message_store: &SharedMessageStore) -> Result<Message, String> {
+    let store = message_store.lock().await;
+    let message = store.get_message(&reaction.message_id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "no such message here".to_string())?;
+    reactions::check_reaction(&message, reaction)?;
+    // In a direct conversation the only other person who can react is the peer
+    let conversation_id = store::conversation_id_for(&message);
+    if !is_self && !presence::is_room_id(conversation_id) && reaction.reactor != conversation_id {
+        return Err("the reactor is not in this conversation".to_string());
+    }
+    store.set_reaction(&reaction.message_id, &reaction.reactor, &reaction.emoji, is_self, reaction.added, reaction.timestamp)
+        .map_err(|e| e.to_string())?;
+    store.get_message(&reaction.message_id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "message disappeared while reacting".to_string())
+}
+
+// Pins or unpins a message for everyone in its conversation
+async fn send_pin(
+    message_id: String,
+    pinned: bool,
+    user_identity_arc: &Arc<Mutex<Option<UserIdentity>>>,
+    peers_map: &Arc<Mutex<HashMap<String, IpcPeer>>>,
+    message_store: &SharedMessageStore,
+    gui_clients: &GuiClients
+) -> DaemonToGuiMessage {
+    let Some(current_user_full_id) = user_identity_arc.lock().await.as_ref().map(|identity| identity.full_message_id.clone()) else {
+        return DaemonToGuiMessage::Error("Cannot pin: User identity not set. Please set username first.".to_string());
+    };
+    let lookup = {
+        let store = message_store.lock().await;
+        store.get_message(&message_id)
+            .and_then(|message| match message {
+                Some(message) => {
+                    let pinned_count = store.pinned_messages(store::conversation_id_for(&message))?.len();
+                    Ok(Some((message, pinned_count)))
                 }
+                None => Ok(None),
+            })
+            .map_err(|e| e.to_string())
+    };
+    let (original, pinned_count) = match lookup {
+        Ok(Some(found)) => found,
+        Ok(None) => return DaemonToGuiMessage::Error(format!("Message '{}' not found.", message_id)),
+        Err(e) => {
+            tracing::error!("Failed to look up message ID: {}: {}", message_id, e);
+            return DaemonToGuiMessage::Error(format!("Could not look up message '{}': {}", message_id, e));
+        }
+    };
+    if original.pinned_at.is_some() == pinned {
+        return DaemonToGuiMessage::Success(if pinned { "Already pinned.".to_string() } else { "Not pinned.".to_string() });
+    }
+    let pin = pins::PinChange {
+        message_id,
+        pinner: current_user_full_id,
+        pinned,
+        timestamp: chrono::Utc::now(),
+    };
+    if let Err(reason) = pins::check_pin(&original, &pin, pinned_count) {
+        return DaemonToGuiMessage::Error(format!("Cannot pin: {}.", reason));
+    }
+
+    let json_payload = match serde_json::to_string(&edits::PeerPacket::Pin(pin.clone())) {
+        Ok(json_payload) => json_payload,
+        Err(e) => return DaemonToGuiMessage::Error(format!("Internal error: Failed to prepare pin: {}", e)),
+    };
+    let delivery_response = deliver_line(store::conversation_id_for(&original), &json_payload, &pin.message_id, peers_map).await;
+    if !matches!(delivery_response, DaemonToGuiMessage::Success(_)) {
+        return delivery_response;
+    }
+
+    match apply_pin(&pin, true, message_store).await {
+        Ok(updated_message) => {
+            broadcast_pin_update(updated_message, message_store, gui_clients).await;
+            DaemonToGuiMessage::Success(if pinned { "Message pinned.".to_string() } else { "Message unpinned.".to_string() })
+        }
+        Err(reason) => DaemonToGuiMessage::Error(format!("Could not pin: {}", reason)),
+    }
+}
+
+// Stores a pin change (ours, or one a peer sent) and returns the updated message
+async fn apply_pin(pin: &pins::PinChange, is_self: bool, message_store: &SharedMessageStore) -> Result<Message, String> {
+    let store = message_store.lock().await;
+    let message = store.get_message(&pin.message_id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "no such message here".to_string())?;
+    let conversation_id = store::conversation_id_for(&message);
+    // Same rule as for reactions: in a direct conversation only the peer can pin besides us
+    if !is_self && !presence::is_room_id(conversation_id) && pin.pinner != conversation_id {
+        return Err("the pinner is not in this conversation".to_string());
+    }
+    let pinned_count = store.pinned_messages(conversation_id).map_err(|e| e.to_string())?.len();
+    pins::check_pin(&message, pin, pinned_count)?;
+    store.set_pinned(&pin.message_id, pin.pinned.then_some(pin.pinner.as_str()), pin.timestamp)
+        .map_err(|e| e.to_string())?;
+    store.get_message(&pin.message_id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "message disappeared while pinning".to_string())
+}
+
+// Shows a pin change in every attached client: the message itself and its conversation's pinned strip
+async fn broadcast_pin_update(updated_message: Message, message_store: &SharedMessageStore, gui_clients: &GuiClients) {
+    let conversation_id = store::conversation_id_for(&updated_message).to_string();
+    let pinned = message_store.lock().await.pinned_messages(&conversation_id).map_err(|e| e.to_string());
+    broadcast_to_gui_clients(gui_clients, DaemonToGuiMessage::MessageUpdated(updated_message)).await;
+    match pinned {
+        Ok(messages) => {
+            broadcast_to_gui_clients(gui_clients, DaemonToGuiMessage::PinnedMessages { conversation_id, messages }).await;
+        }
+        Err(e) => tracing::error!("Failed to load pinned messages of '{}': {}", conversation_id, e),
+    }
+}
+
+// Changes the disappearing-messages timer of a conversation for everyone in it
+async fn send_timer(
+    conversation_id: String,
+    expiry_secs: Option<u64>,
+    user_identity_arc: &Arc<Mutex<Option<UserIdentity>>>,
+    peers_map: &Arc<Mutex<HashMap<String, IpcPeer>>>,
+    message_store: &SharedMessageStore,
+    gui_clients: &GuiClients
+) -> DaemonToGuiMessage {
+    let Some(current_user_full_id) = user_identity_arc.lock().await.as_ref().map(|identity| identity.full_message_id.clone()) else {
+        return DaemonToGuiMessage::Error("Cannot set the timer: User identity not set. Please set username first.".to_string());
+    };
+    if let Err(reason) = expiry::check_expiry(expiry_secs) {
+        return DaemonToGuiMessage::Error(format!("Cannot set the timer: {}.", reason));
+    }
+    let timer = expiry::TimerChange {
+        recipient: conversation_id.clone(),
+        setter: current_user_full_id,
+        expiry_secs,
+        timestamp: chrono::Utc::now(),
+    };
+    let json_payload = match serde_json::to_string(&edits::PeerPacket::Timer(timer.clone())) {
+        Ok(json_payload) => json_payload,
+        Err(e) => return DaemonToGuiMessage::Error(format!("Internal error: Failed to prepare timer change: {}", e)),
+    };
+    // Agreed on with the conversation first: a timer only one side knows about would purge only one side's copies
+    let delivery_response = deliver_line(&conversation_id, &json_payload, &conversation_id, peers_map).await;
+    if !matches!(delivery_response, DaemonToGuiMessage::Success(_)) {
+        return delivery_response;
+    }
+    apply_timer(&conversation_id, &timer, message_store, gui_clients).await;
+    DaemonToGuiMessage::Success(match expiry_secs {
+        Some(_) => format!("Messages in {} now disappear after {}.", conversation_id, expiry::describe(expiry_secs)),
+        None => format!("Disappearing messages turned off in {}.", conversation_id),
+    })
+}
+
+// Stores a timer change (ours, or one a peer sent) unless a newer one is already stored, and shows it in every client
+async fn apply_timer(conversation_id: &str, timer: &expiry::TimerChange, message_store: &SharedMessageStore, gui_clients: &GuiClients) {
+    let applied = {
+        let store = message_store.lock().await;
+        store.set_conversation_timer(conversation_id, timer.expiry_secs, &timer.setter, timer.timestamp)
+            .and_then(|changed| Ok((changed, store.conversation_timer(conversation_id)?)))
+            .map_err(|e| e.to_string())
+    };
+    match applied {
+        Ok((true, Some(stored_timer))) => {
+            broadcast_to_gui_clients(gui_clients, DaemonToGuiMessage::ConversationTimer(stored_timer)).await;
+        }
+        Ok(_) => tracing::info!("Ignoring timer change for '{}' from {}: a newer one is stored", conversation_id, timer.setter),
+        Err(e) => tracing::error!("Failed to store the timer of '{}': {}", conversation_id, e),
+    }
+}
+
+// Purges expired messages now and then until the daemon exits. The expiry time is stored with each message,
+// so messages that ran out while the daemon was down go on its first pass.
+async fn run_expiry_purger(message_store: SharedMessageStore, gui_clients: GuiClients, attachments_dir: PathBuf) {
+    let mut interval = tokio::time::interval(expiry::PURGE_INTERVAL);
+    loop {
+        interval.tick().await;
+        let expired = message_store.lock().await.take_expired_messages(chrono::Utc::now()).map_err(|e| e.to_string());
+        let expired = match expired {
+            Ok(expired) => expired,
+            Err(e) => {
+                tracing::error!("Failed to purge expired messages: {}", e);
+                continue;
             }
-            else {
-                tracing::warn!("SendMessage: Recipient peer with ID '{}' not found.", recipient_id);
-                DaemonToGuiMessage::Error(format!("Recipient '{}' not found.", recipient_id))
+        };
+        if expired.is_empty() {
+            continue;
+        }
+        tracing::info!("Purged {} expired message(s)", expired.len());
+        let mut expired_by_conversation: HashMap<String, Vec<String>> = HashMap::new();
+        for message in &expired {
+            expiry::remove_attachment(message, &attachments_dir);
+            expired_by_conversation.entry(store::conversation_id_for(message).to_string()).or_default().push(message.id.clone());
+        }
+        for (conversation_id, message_ids) in expired_by_conversation {
+            broadcast_to_gui_clients(&gui_clients, DaemonToGuiMessage::MessagesExpired { conversation_id, message_ids }).await;
+        }
+        broadcast_conversation_list(&message_store, &gui_clients).await; // Previews and unread counts may have changed
+    }
+}
+
+// Queues a message to be sent at `send_at`. The peer doesn't have to be online yet; everything else
+// about the message is checked when it is sent, like any other message.
+async fn schedule_message(
+    recipient_id: String,
+    content: String,
+    send_at: chrono::DateTime<chrono::Utc>,
+    message_store: &SharedMessageStore,
+    gui_clients: &GuiClients
+) -> DaemonToGuiMessage {
+    let now = chrono::Utc::now();
+    if recipient_id.trim().is_empty() {
+        return DaemonToGuiMessage::Error("Cannot schedule: no recipient given.".to_string());
+    }
+    if let Err(reason) = scheduled::check_content(&content).and_then(|()| scheduled::check_send_at(send_at, now)) {
+        return DaemonToGuiMessage::Error(format!("Cannot schedule: {}.", reason));
+    }
+    let scheduled = ScheduledMessage {
+        id: uuid::Uuid::new_v4().to_string(),
+        recipient: recipient_id,
+        content,
+        send_at,
+        created_at: now,
+        last_error: None,
+    };
+    let insert_result = {
+        let store = message_store.lock().await;
+        match store.scheduled_messages() {
+            Ok(existing) => scheduled::check_count(existing.len())
+                .and_then(|()| store.insert_scheduled(&scheduled).map_err(|e| e.to_string())),
+            Err(e) => Err(e.to_string()),
+        }
+    };
+    match insert_result {
+        Ok(()) => {
+            tracing::info!("Scheduled message ID: {} to {} for {}", scheduled.id, scheduled.recipient, scheduled.send_at);
+            broadcast_scheduled(message_store, gui_clients).await;
+            DaemonToGuiMessage::Success(format!(
+                "Message to {} scheduled for {} (ID: {}).",
+                scheduled.recipient, scheduled::describe_send_time(&scheduled), scheduled.id
+            ))
+        }
+        Err(reason) => DaemonToGuiMessage::Error(format!("Cannot schedule: {}.", reason)),
+    }
+}
+
+// Changes the text and/or time of a message that hasn't been sent yet
+async fn edit_scheduled(
+    scheduled_id: String,
+    content: Option<String>,
+    send_at: Option<chrono::DateTime<chrono::Utc>>,
+    message_store: &SharedMessageStore,
+    gui_clients: &GuiClients
+) -> DaemonToGuiMessage {
+    let update_result = {
+        let store = message_store.lock().await;
+        match store.get_scheduled(&scheduled_id) {
+            Ok(Some(existing)) => {
+                let checked = match send_at {
+                    Some(send_at) => scheduled::check_send_at(send_at, chrono::Utc::now()),
+                    None => Ok(()),
+                };
+                let content = content.unwrap_or(existing.content);
+                let send_at = send_at.unwrap_or(existing.send_at);
+                checked
+                    .and_then(|()| scheduled::check_content(&content))
+                    .and_then(|()| store.update_scheduled(&scheduled_id, &content, send_at).map_err(|e| e.to_string()))
+            }
+            Ok(None) => Ok(false),
+            Err(e) => Err(e.to_string()),
+        }
+    };
+    match update_result {
+        Ok(true) => {
+            tracing::info!("Edited scheduled message ID: {}", scheduled_id);
+            broadcast_scheduled(message_store, gui_clients).await;
+            DaemonToGuiMessage::Success("Scheduled message updated.".to_string())
+        }
+        Ok(false) => DaemonToGuiMessage::Error(format!("Scheduled message '{}' not found (it may have been sent already).", scheduled_id)),
+        Err(reason) => DaemonToGuiMessage::Error(format!("Cannot change the scheduled message: {}.", reason)),
+    }
+}
+
+// Pushes the scheduled list to every attached client after it changed
+async fn broadcast_scheduled(message_store: &SharedMessageStore, gui_clients: &GuiClients) {
+    let scheduled = message_store.lock().await.scheduled_messages().map_err(|e| e.to_string());
+    match scheduled {
+        Ok(scheduled) => {
+            broadcast_to_gui_clients(gui_clients, DaemonToGuiMessage::ScheduledMessages(scheduled)).await;
+        }
+        Err(e) => tracing::error!("Failed to load scheduled messages: {}", e),
+    }
+}
+
+// Sends scheduled messages once they are due and their recipient is around, until the daemon exits.
+// Each one goes through process_gui_command as a SendMessage, so it is stored and shown like any sent message.
+async fn run_scheduler(
+    peers_map: Arc<Mutex<HashMap<String, IpcPeer>>>,
+    user_identity_arc: Arc<Mutex<Option<UserIdentity>>>,
+    message_store: SharedMessageStore,
+    gui_clients: GuiClients
+) {
+    let mut interval = tokio::time::interval(scheduled::SCHEDULER_INTERVAL);
+    let mut retry_after: HashMap<String, tokio::time::Instant> = HashMap::new(); // Failed ones wait RETRY_DELAY between attempts
+    loop {
+        interval.tick().await;
+        if lifecycle::is_shutting_down() {
+            break; // Whatever is due is still stored and goes out after the next start
+        }
+        if user_identity_arc.lock().await.is_none() {
+            continue; // Nothing can be sent before a username is set
+        }
+        let due = message_store.lock().await.due_scheduled(chrono::Utc::now()).map_err(|e| e.to_string());
+        let due = match due {
+            Ok(due) => due,
+            Err(e) => {
+                tracing::error!("Failed to load due scheduled messages: {}", e);
+                continue;
+            }
+        };
+        let now = tokio::time::Instant::now();
+        retry_after.retain(|scheduled_id, until| *until > now && due.iter().any(|scheduled| &scheduled.id == scheduled_id));
+        let mut attempted = false;
+        for scheduled in due {
+            // Waiting for the peer (or anyone in the room) to come online is not a failure
+            if retry_after.contains_key(&scheduled.id) || !recipient_online(&scheduled.recipient, &peers_map).await {
+                continue;
+            }
+            if lifecycle::is_shutting_down() {
+                break; // Not a failure: the rest is sent after the next start
+            }
+            attempted = true;
+            let command = GuiToDaemonCommand::SendMessage {
+                recipient_id: scheduled.recipient.clone(),
+                content: scheduled.content.clone(),
+                reply_to: None,
+            };
+            let response = process_gui_command(command, peers_map.clone(), user_identity_arc.clone(), message_store.clone(), gui_clients.clone()).await;
+            if let DaemonToGuiMessage::Success(ref result) = response {
+                tracing::info!("Sent scheduled message ID: {} to {}: {}", scheduled.id, scheduled.recipient, result);
+                let deleted = message_store.lock().await.delete_scheduled(&scheduled.id).map_err(|e| e.to_string());
+                if let Err(e) = deleted {
+                    tracing::error!("Failed to remove sent scheduled message ID: {}: {}", scheduled.id, e);
+                }
+                continue;
+            }
+            let reason = match response {
+                DaemonToGuiMessage::Error(reason) => reason,
+                other => format!("unexpected response {:?}", other),
+            };
+            tracing::warn!("Scheduled message ID: {} to {} not sent, trying again in {}s: {}",
+                scheduled.id, scheduled.recipient, scheduled::RETRY_DELAY.as_secs(), reason);
+            retry_after.insert(scheduled.id.clone(), now + scheduled::RETRY_DELAY);
+            let recorded = message_store.lock().await.set_scheduled_error(&scheduled.id, &reason).map_err(|e| e.to_string());
+            if let Err(e) = recorded {
+                tracing::error!("Failed to record the error of scheduled message ID: {}: {}", scheduled.id, e);
             }
         }
-        GuiToDaemonCommand::RequestHistory { .. } => {
-            tracing::info!("RequestHistory command received (not implemented yet)");
-            DaemonToGuiMessage::Error("History feature not yet implemented".to_string())
+        if attempted {
+            broadcast_scheduled(&message_store, &gui_clients).await;
         }
     }
-} 
\ No newline at end of file
+}
+
+// Whether a message to `recipient` can go out now: the peer is discovered, or someone else is in the room
+async fn recipient_online(recipient: &str, peers_map: &Arc<Mutex<HashMap<String, IpcPeer>>>) -> bool {
+    if presence::is_room_id(recipient) {
+        !room_members(recipient, peers_map).await.is_empty()
+    } else {
+        peers_map.lock().await.contains_key(recipient)
+    }
+}
+
+// Known peers in a room; every peer is in the broadcast room
+async fn room_members(room: &str, peers_map: &Arc<Mutex<HashMap<String, IpcPeer>>>) -> Vec<IpcPeer> {
+    peers_map.lock().await
+        .values()
+        .filter(|peer| room == presence::BROADCAST_ROOM || peer.rooms.iter().any(|peer_room| peer_room == room))
+        .cloned()
+        .collect()
+}
+
+// Builds a message from this daemon's user, with the conversation's timer; fails if no username has been set yet
+async fn new_outgoing_message(
+    user_identity_arc: &Arc<Mutex<Option<UserIdentity>>>,
+    recipient_id: String,
+    content: String,
+    attachment: Option<Attachment>,
+    message_store: &SharedMessageStore
+) -> Result<Message, DaemonToGuiMessage> {
+    let current_user_full_id = match user_identity_arc.lock().await.as_ref() {
+        Some(identity) => identity.full_message_id.clone(),
+        None => {
+            tracing::warn!("SendMessage: User identity not set. Cannot send message.");
+            return Err(DaemonToGuiMessage::Error("Cannot send message: User identity not set. Please set username first.".to_string()));
+        }
+    };
+    let timer = message_store.lock().await.conversation_timer(&recipient_id).map_err(|e| e.to_string());
+    let expires_in = match timer {
+        Ok(timer) => timer.and_then(|timer| timer.expiry_secs),
+        Err(e) => {
+            // Sending it without a timer could keep it around longer than the conversation agreed to
+            tracing::error!("Failed to look up the timer of '{}': {}", recipient_id, e);
+            return Err(DaemonToGuiMessage::Error(format!("Cannot send message: could not look up the conversation's timer: {}", e)));
+        }
+    };
+    Ok(Message {
+        id: uuid::Uuid::new_v4().to_string(),
+        sender: current_user_full_id, // Use the full_message_id
+        recipient: recipient_id,
+        content,
+        timestamp: chrono::Utc::now(),
+        is_self: false, // Set to true when the sent copy is stored and echoed to IPC clients
+        attachment,
+        edited_at: None,
+        deleted: false,
+        reply_to: None,
+        reply_preview: None,
+        reactions: Vec::new(),
+        mentions: Vec::new(),
+        mentions_me: false,
+        pinned_at: None,
+        pinned_by: None,
+        starred: false,
+        expires_in,
+    })
+}
+
+// Sends a message to its recipient: a single peer, or every known member of a room
+async fn deliver_outgoing_message(message: &Message, peers_map: &Arc<Mutex<HashMap<String, IpcPeer>>>) -> DaemonToGuiMessage {
+    // The sender's local file path is nobody else's business
+    let mut wire_message = message.clone();
+    if let Some(attachment) = wire_message.attachment.as_mut() {
+        attachment.local_path = None;
+    }
+    tracing::debug!("[TCP_SEND] Constructed message struct for {}: ID={}", wire_message.recipient, wire_message.id);
+    tracing::trace!("[TCP_SEND] Attempting to serialize message ID: {}", wire_message.id);
+    let json_payload = match serde_json::to_string(&wire_message) {
+        Ok(json_payload) => json_payload,
+        Err(e) => {
+            tracing::error!("[TCP_SEND] Failed to serialize message ID: {} for TCP sending: {}", wire_message.id, e);
+            return DaemonToGuiMessage::Error(format!("Internal error: Failed to prepare message: {}", e));
+        }
+    };
+
+    deliver_line(&message.recipient, &json_payload, &message.id, peers_map).await
+}
+
+// Sends one serialized line (a message or a change to one) to a peer, or to every known member of a room
+async fn deliver_line(
+    recipient: &str,
+    json_payload: &str,
+    message_id: &str,
+    peers_map: &Arc<Mutex<HashMap<String, IpcPeer>>>
+) -> DaemonToGuiMessage {
+    if presence::is_room_id(recipient) {
+        let room = recipient;
+        let members = room_members(room, peers_map).await;
+        if members.is_empty() {
+            tracing::info!("[TCP_SEND] No other members of {} online; message ID: {} not sent anywhere.", room, message_id);
+            return DaemonToGuiMessage::Success(format!("No one else is in {} right now.", room));
+        }
+
+        let mut delivered = 0;
+        let mut failures = Vec::new();
+        for member in &members {
+            match send_line_to_peer(member, json_payload, message_id).await {
+                Ok(()) => delivered += 1,
+                Err(err_msg) => failures.push(err_msg),
+            }
+        }
+        if delivered == 0 {
+            return DaemonToGuiMessage::Error(format!("Could not reach anyone in {}: {}", room, failures.join("; ")));
+        }
+        if !failures.is_empty() {
+            tracing::warn!("[TCP_SEND] Message ID: {} missed {} member(s) of {}: {}", message_id, failures.len(), room, failures.join("; "));
+        }
+        return DaemonToGuiMessage::Success(format!("Message sent to {} of {} member(s) of {}", delivered, members.len(), room));
+    }
+
+    let peer_info = peers_map.lock().await.get(recipient).cloned();
+    match peer_info {
+        Some(recipient_peer) => match send_line_to_peer(&recipient_peer, json_payload, message_id).await {
+            Ok(()) => DaemonToGuiMessage::Success(format!("Message successfully sent to {}", recipient_peer.username)),
+            Err(err_msg) => DaemonToGuiMessage::Error(err_msg),
+        },
+        None => {
+            tracing::warn!("SendMessage: Recipient peer with ID '{}' not found.", recipient);
+            DaemonToGuiMessage::Error(format!("Recipient '{}' not found.", recipient))
+        }
+    }
+}
+
+// One short-lived TCP connection per message, same as the receiving side expects
+async fn send_line_to_peer(recipient_peer: &IpcPeer, json_payload: &str, message_id: &str) -> Result<(), String> {
+    tracing::info!("Attempting to send message to peer IpcPeer {{ id: {}, username: {}, ip: {}, port: {} }}, message ID: {}", 
+        recipient_peer.id, recipient_peer.username, recipient_peer.ip, recipient_peer.port, message_id);
+    let target_addr = format!("{}:{}", recipient_peer.ip, recipient_peer.port);
+
+    let mut stream = TcpStream::connect(&target_addr).await.map_err(|e| {
+        tracing::error!("[TCP_SEND] Failed to connect to peer {} (ID: {}) at {}: {}", recipient_peer.username, recipient_peer.id, target_addr, e);
+        format!("Could not connect to {}: {}", recipient_peer.username, e)
+    })?;
+    tracing::trace!("[TCP_SEND] Message ID: {} serialized. Attempting to write to stream for peer {}. Payload (first 100 chars): {:.100}", 
+        message_id, recipient_peer.id, json_payload);
+    if let Err(e) = stream.write_all(format!("{}\n", json_payload).as_bytes()).await {
+        tracing::error!("[TCP_SEND] Failed to write message ID: {} to TCP stream for peer {}: {}", message_id, recipient_peer.id, e);
+        return Err(format!("Failed to send message to {}: {}", recipient_peer.username, e));
+    }
+    tracing::trace!("[TCP_SEND] Message ID: {} written to stream. Attempting to flush for peer {}.", message_id, recipient_peer.id);
+    if let Err(e) = stream.flush().await {
+        tracing::error!("[TCP_SEND] Failed to flush TCP stream for peer {}: {}", recipient_peer.id, e);
+        return Err(format!("Network error sending to {}: {}", recipient_peer.username, e));
+    }
+    tracing::info!("[TCP_SEND] Successfully sent message ID: {} to peer {} ({})", message_id, recipient_peer.username, target_addr);
+    Ok(())
+}
+
+// Status and room membership changes; each one is saved and re-announced over mDNS
+async fn process_presence_command(
+    command: GuiToDaemonCommand,
+    presence_arc: &Arc<Mutex<presence::Presence>>,
+    mdns_daemon: &ServiceDaemon,
+    user_identity_arc: &Arc<Mutex<Option<UserIdentity>>>,
+    daemon_tcp_port: u16
+) -> DaemonToGuiMessage {
+    let mut presence_guard = presence_arc.lock().await;
+    let response = match command {
+        GuiToDaemonCommand::GetRooms => return DaemonToGuiMessage::RoomList(presence_guard.room_list()),
+        GuiToDaemonCommand::SetStatus { status } => {
+            let status = status
+                .map(|s| s.trim().chars().take(64).collect::<String>())
+                .filter(|s| !s.is_empty());
+            tracing::info!("Status set to {:?}", status);
+            let response = match status {
+                Some(ref s) => DaemonToGuiMessage::Success(format!("Status set to '{}'.", s)),
+                None => DaemonToGuiMessage::Success("Status cleared.".to_string()),
+            };
+            presence_guard.status = status;
+            response
+        }
+        GuiToDaemonCommand::JoinRoom { room } => match presence::normalize_room_name(&room) {
+            Some(room) if room == presence::BROADCAST_ROOM => return DaemonToGuiMessage::RoomList(presence_guard.room_list()),
+            Some(room) => {
+                tracing::info!("Joined room {}", room);
+                presence_guard.rooms.insert(room);
+                DaemonToGuiMessage::RoomList(presence_guard.room_list())
+            }
+            None => return DaemonToGuiMessage::Error(format!("'{}' is not a valid room name (use letters, digits, '-' or '_').", room)),
+        },
+        GuiToDaemonCommand::LeaveRoom { room } => match presence::normalize_room_name(&room) {
+            Some(room) if room == presence::BROADCAST_ROOM => {
+                return DaemonToGuiMessage::Error(format!("{} can't be left.", presence::BROADCAST_ROOM));
+            }
+            Some(room) if presence_guard.rooms.remove(&room) => {
+                tracing::info!("Left room {}", room);
+                DaemonToGuiMessage::RoomList(presence_guard.room_list())
+            }
+            _ => return DaemonToGuiMessage::Error(format!("Not in room '{}'.", room)),
+        },
+        other => return DaemonToGuiMessage::Error(format!("Not a status or room command: {:?}", other)),
+    };
+    presence_guard.save();
+    let presence_snapshot = presence_guard.clone();
+    drop(presence_guard);
+
+    // Peers learn about the change from the re-announced TXT record
+    if let Err(e) = refresh_mdns_registration(mdns_daemon, user_identity_arc, &presence_snapshot, daemon_tcp_port).await {
+        tracing::error!("Failed to re-announce mDNS service after presence change: {}", e);
+    }
+    response
+}
@@ -0,0 +1,88 @@
+// @mentions in room messages. The sending daemon resolves "@username" against the peers it knows and puts their
+// full IDs on the message; the receiving daemon flags messages that mention its own ID.
+use crate::IpcPeer;
+
+// More than this is a broadcast, not a mention; the rest are dropped on receipt
+pub const MAX_MENTIONS: usize = 50;
+
+// Full IDs of the peers mentioned in `content`, in order of first mention. Usernames may contain spaces,
+// so after each '@' the longest username the text starts with wins ("@Ann Lee" over "@Ann").
+pub fn find_mentions(content: &str, peers: &[IpcPeer]) -> Vec<String> {
+    let mut mentioned_ids: Vec<String> = Vec::new();
+    for (at_index, _) in content.match_indices('@') {
+        // "@" inside a word (an email address) isn't a mention
+        if content[..at_index].chars().next_back().is_some_and(|c| c.is_alphanumeric()) {
+            continue;
+        }
+        let rest = &content[at_index + 1..];
+        let mentioned_peer = peers.iter()
+            .filter(|peer| !peer.username.is_empty() && starts_with_name(rest, &peer.username))
+            .max_by_key(|peer| peer.username.len());
+        if let Some(peer) = mentioned_peer {
+            if !mentioned_ids.contains(&peer.id) {
+                mentioned_ids.push(peer.id.clone());
+            }
+        }
+    }
+    mentioned_ids.truncate(MAX_MENTIONS);
+    mentioned_ids
+}
+
+// Case-insensitive, and the name must end at a word boundary ("@bob" doesn't mention "bo")
+fn starts_with_name(text: &str, name: &str) -> bool {
+    let name_char_count = name.chars().count();
+    let candidate: String = text.chars().take(name_char_count).collect();
+    let next_char = text.chars().nth(name_char_count);
+    candidate.to_lowercase() == name.to_lowercase() && !next_char.is_some_and(|c| c.is_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer(username: &str, id_suffix: &str) -> IpcPeer {
+        IpcPeer {
+            id: format!("{} - {}", username, id_suffix),
+            username: username.to_string(),
+            ip: "192.0.2.1".to_string(),
+            port: 12345,
+            status: None,
+            rooms: Vec::new(),
+        }
+    }
+
+    fn peers() -> Vec<IpcPeer> {
+        vec![peer("bob", "1"), peer("Ann", "2"), peer("Ann Lee", "3"), peer("", "4")]
+    }
+
+    #[test]
+    fn mentions_are_found_in_order_without_duplicates() {
+        let mentions = find_mentions("@bob, ask @ann and then @Bob again", &peers());
+        assert_eq!(mentions, vec!["bob - 1", "Ann - 2"]);
+    }
+
+    #[test]
+    fn the_longest_matching_username_wins() {
+        assert_eq!(find_mentions("thanks @Ann Lee!", &peers()), vec!["Ann Lee - 3"]);
+        assert_eq!(find_mentions("thanks @Ann Leeds", &peers()), vec!["Ann - 2"]);
+    }
+
+    #[test]
+    fn names_must_end_at_a_word_boundary() {
+        assert!(find_mentions("@bobby and @bob_x", &peers()).is_empty());
+        assert_eq!(find_mentions("(@bob)", &peers()), vec!["bob - 1"]);
+    }
+
+    #[test]
+    fn email_addresses_and_unknown_names_are_not_mentions() {
+        assert!(find_mentions("mail bob@bob.example or @carol", &peers()).is_empty());
+        assert!(find_mentions("a lone @ sign", &peers()).is_empty());
+    }
+
+    #[test]
+    fn mentions_are_capped() {
+        let many: Vec<IpcPeer> = (0..MAX_MENTIONS + 5).map(|n| peer(&format!("user{}", n), "x")).collect();
+        let content: String = many.iter().map(|peer| format!("@{} ", peer.username)).collect();
+        assert_eq!(find_mentions(&content, &many).len(), MAX_MENTIONS);
+    }
+}
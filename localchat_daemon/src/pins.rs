@@ -0,0 +1,66 @@
+// Pinned messages. Anyone in a conversation can pin or unpin one of its messages; the change goes to the other
+// participants as a PinChange line over the peer TCP protocol. Stars are private and never leave the daemon.
+use serde::{Deserialize, Serialize};
+
+use crate::Message;
+
+// Beyond this a pinned strip stops being a shortlist
+pub const MAX_PINS_PER_CONVERSATION: usize = 50;
+
+// Sent between daemons; pins or unpins a message the receiver already has
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PinChange {
+    pub message_id: String,
+    pub pinner: String, // Full ID of whoever pinned or unpinned it
+    pub pinned: bool,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+}
+
+// Whether `change` may be applied to `message`, which has `pinned_count` pinned neighbours; the error says why not
+pub fn check_pin(message: &Message, change: &PinChange, pinned_count: usize) -> Result<(), String> {
+    if message.deleted {
+        return Err("the message was deleted".to_string());
+    }
+    if change.pinned && message.pinned_at.is_none() && pinned_count >= MAX_PINS_PER_CONVERSATION {
+        return Err(format!("a conversation can have at most {} pinned messages", MAX_PINS_PER_CONVERSATION));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pin(pinned: bool) -> PinChange {
+        PinChange { message_id: "m1".to_string(), pinner: "bob - 2".to_string(), pinned, timestamp: chrono::Utc::now() }
+    }
+
+    fn message() -> Message {
+        crate::test_message("m1", "alice - 1", "agenda", chrono::Utc::now())
+    }
+
+    #[test]
+    fn deleted_messages_cannot_be_pinned_or_unpinned() {
+        let mut deleted = message();
+        deleted.deleted = true;
+        assert!(check_pin(&deleted, &pin(true), 0).is_err());
+        assert!(check_pin(&deleted, &pin(false), 0).is_err());
+    }
+
+    #[test]
+    fn new_pins_stop_at_the_cap() {
+        assert!(check_pin(&message(), &pin(true), 0).is_ok());
+        assert!(check_pin(&message(), &pin(true), MAX_PINS_PER_CONVERSATION - 1).is_ok());
+        assert!(check_pin(&message(), &pin(true), MAX_PINS_PER_CONVERSATION).is_err());
+        // Unpinning is always allowed, so a full conversation can make room
+        assert!(check_pin(&message(), &pin(false), MAX_PINS_PER_CONVERSATION).is_ok());
+    }
+
+    #[test]
+    fn re_pinning_a_pinned_message_at_the_cap_is_allowed() {
+        let mut pinned = message();
+        pinned.pinned_at = Some(chrono::Utc::now());
+        pinned.pinned_by = Some("alice - 1".to_string());
+        assert!(check_pin(&pinned, &pin(true), MAX_PINS_PER_CONVERSATION).is_ok());
+    }
+}
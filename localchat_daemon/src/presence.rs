@@ -0,0 +1,104 @@
+// What this daemon advertises about its user besides the identity: a free-form status ("away", "busy", ...)
+// and the rooms it has joined. Both travel in the mDNS TXT record and survive restarts via a small JSON file.
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+// Every daemon is implicitly a member; messages sent here go to all discovered peers
+pub const BROADCAST_ROOM: &str = "#everyone";
+
+// A single TXT value is limited to 255 bytes, so the advertised room list has to stay short
+const MAX_ROOMS_TXT_LEN: usize = 240;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Presence {
+    #[serde(skip)]
+    file_path: String,
+    pub status: Option<String>,
+    pub rooms: BTreeSet<String>, // Joined rooms, always stored normalized ("#name")
+}
+
+impl Presence {
+    // Missing or unreadable files just mean "online, no rooms"
+    pub fn load(file_path: &str) -> Self {
+        let mut presence = match std::fs::read_to_string(file_path) {
+            Ok(presence_json) => match serde_json::from_str::<Presence>(&presence_json) {
+                Ok(presence) => {
+                    tracing::info!("Loaded presence from {}: status={:?}, rooms={:?}", file_path, presence.status, presence.rooms);
+                    presence
+                }
+                Err(e) => {
+                    tracing::error!("Failed to parse presence file {}: {}. Starting without status or rooms.", file_path, e);
+                    Presence::default()
+                }
+            },
+            Err(_) => Presence::default(),
+        };
+        presence.file_path = file_path.to_string();
+        presence
+    }
+
+    pub fn save(&self) {
+        match serde_json::to_string_pretty(self) {
+            Ok(presence_json) => {
+                if let Err(e) = std::fs::write(&self.file_path, presence_json) {
+                    tracing::error!("Failed to save presence to {}: {}", self.file_path, e);
+                }
+            }
+            Err(e) => tracing::error!("Failed to serialize presence: {}", e),
+        }
+    }
+
+    pub fn is_member(&self, room: &str) -> bool {
+        room == BROADCAST_ROOM || self.rooms.contains(room)
+    }
+
+    // Rooms as shown to IPC clients: the broadcast channel first, then the joined rooms
+    pub fn room_list(&self) -> Vec<String> {
+        std::iter::once(BROADCAST_ROOM.to_string()).chain(self.rooms.iter().cloned()).collect()
+    }
+
+    // TXT "rooms" value: comma-separated, truncated at a room boundary if it would not fit
+    pub fn rooms_txt_value(&self) -> String {
+        let mut value = String::new();
+        for room in &self.rooms {
+            if value.len() + room.len() + 1 > MAX_ROOMS_TXT_LEN {
+                tracing::warn!("mDNS: too many rooms to advertise; '{}' and later rooms are left out of the TXT record.", room);
+                break;
+            }
+            if !value.is_empty() {
+                value.push(',');
+            }
+            value.push_str(room);
+        }
+        value
+    }
+}
+
+// Room IDs share the recipient field with peer IDs; peers can't start with '#'
+pub fn is_room_id(id: &str) -> bool {
+    id.starts_with('#')
+}
+
+// "#Team Chat" / "team-chat" -> "#team-chat". None if nothing usable is left.
+pub fn normalize_room_name(name: &str) -> Option<String> {
+    let cleaned: String = name
+        .trim()
+        .trim_start_matches('#')
+        .chars()
+        .map(|c| if c.is_whitespace() { '-' } else { c.to_ascii_lowercase() })
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+        .take(32)
+        .collect();
+    let cleaned = cleaned.trim_matches('-');
+    if cleaned.is_empty() {
+        None
+    } else {
+        Some(format!("#{}", cleaned))
+    }
+}
+
+// Parses a peer's TXT "rooms" value
+pub fn parse_rooms_txt(value: &str) -> Vec<String> {
+    value.split(',').filter_map(normalize_room_name).collect()
+}
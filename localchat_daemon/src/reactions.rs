@@ -0,0 +1,71 @@
+// Emoji reactions on messages. Reacting sends a ReactionChange line over the peer TCP protocol to everyone in the
+// conversation; each daemon keeps one row per (message, reactor, emoji) and hands clients the per-emoji totals.
+use serde::{Deserialize, Serialize};
+
+use crate::Message;
+
+// Longest emoji accepted, in chars; enough for ZWJ sequences like 👩‍💻 and skin tones
+const MAX_EMOJI_CHARS: usize = 8;
+
+// Sent between daemons; adds or removes one reactor's emoji on a message the receiver already has
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReactionChange {
+    pub message_id: String,
+    pub reactor: String, // Full ID of whoever reacted
+    pub emoji: String,
+    pub added: bool, // false: the reactor took the reaction back
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+}
+
+// Reactions are a single emoji, not free text
+pub fn is_valid_emoji(emoji: &str) -> bool {
+    let char_count = emoji.chars().count();
+    is_keycap(emoji)
+        || (char_count > 0
+            && char_count <= MAX_EMOJI_CHARS
+            && !emoji.chars().any(|c| c.is_alphanumeric() || c.is_whitespace() || c.is_control()))
+}
+
+// Keycap emoji like 1️⃣ and #️⃣ start with a plain digit (or # or *), followed by the keycap mark and usually a
+// variation selector in between; the digit would otherwise fail the no-alphanumerics check above
+fn is_keycap(emoji: &str) -> bool {
+    let mut chars = emoji.chars();
+    matches!(chars.next(), Some('0'..='9' | '#' | '*')) && matches!(chars.as_str(), "\u{FE0F}\u{20E3}" | "\u{20E3}")
+}
+
+// Whether `change` may be applied to `message`; the error says why not
+pub fn check_reaction(message: &Message, change: &ReactionChange) -> Result<(), String> {
+    if message.deleted {
+        return Err("the message was deleted".to_string());
+    }
+    if !is_valid_emoji(&change.emoji) {
+        return Err(format!("'{}' is not a single emoji", change.emoji));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_emoji_are_accepted() {
+        for emoji in ["👍", "❤️", "🎉", "👍🏽", "👩\u{200D}💻", "🇩🇪", "#️⃣"] {
+            assert!(is_valid_emoji(emoji), "{} should be accepted", emoji);
+        }
+    }
+
+    #[test]
+    fn keycap_emoji_are_accepted() {
+        for emoji in ["1️⃣", "0\u{20E3}", "*️⃣", "9\u{FE0F}\u{20E3}"] {
+            assert!(is_valid_emoji(emoji), "{} should be accepted", emoji);
+        }
+    }
+
+    #[test]
+    fn text_and_overlong_reactions_are_refused() {
+        for text in ["", " ", "ok", "1", "12\u{20E3}", "a\u{20E3}", "👍 ", "\n", "👍👍👍👍👍👍👍👍👍"] {
+            assert!(!is_valid_emoji(text), "{:?} should be refused", text);
+        }
+    }
+}
@@ -0,0 +1,75 @@
+// Scheduled messages. They wait in the message store until their time, then go out through the same path as
+// SendMessage; a message for a peer who is offline by then waits until the peer is back (for a room, until
+// someone is in it).
+use crate::ScheduledMessage;
+
+// Limits so a script can't fill the store with messages for the year 3000
+pub const MAX_SCHEDULED_MESSAGES: usize = 200;
+pub const MAX_SCHEDULE_AHEAD_DAYS: i64 = 365;
+
+// How often the scheduler looks for messages that are due
+pub const SCHEDULER_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+// After a failed attempt the message waits this long before it is tried again
+pub const RETRY_DELAY: std::time::Duration = std::time::Duration::from_secs(60);
+
+pub fn check_content(content: &str) -> Result<(), String> {
+    if content.trim().is_empty() {
+        return Err("a scheduled message needs some text".to_string());
+    }
+    Ok(())
+}
+
+// Whether another message may be scheduled while `scheduled_count` are already waiting
+pub fn check_count(scheduled_count: usize) -> Result<(), String> {
+    if scheduled_count >= MAX_SCHEDULED_MESSAGES {
+        return Err(format!("at most {} messages can be scheduled at a time", MAX_SCHEDULED_MESSAGES));
+    }
+    Ok(())
+}
+
+// Whether a message may be scheduled (or rescheduled) for this time; the error says why not.
+// A message still waiting for an offline peer keeps its past time when only its text is edited.
+pub fn check_send_at(send_at: chrono::DateTime<chrono::Utc>, now: chrono::DateTime<chrono::Utc>) -> Result<(), String> {
+    if send_at <= now {
+        return Err("the time to send it has already passed".to_string());
+    }
+    if send_at > now + chrono::TimeDelta::days(MAX_SCHEDULE_AHEAD_DAYS) {
+        return Err(format!("messages can be scheduled at most {} days ahead", MAX_SCHEDULE_AHEAD_DAYS));
+    }
+    Ok(())
+}
+
+// "Mon 17:00" in the daemon's local time, for responses and logs
+pub fn describe_send_time(scheduled: &ScheduledMessage) -> String {
+    scheduled.send_at.with_timezone(&chrono::Local).format("%a %b %d, %H:%M").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_must_not_be_blank() {
+        assert!(check_content("stand-up in 5").is_ok());
+        assert!(check_content("").is_err());
+        assert!(check_content(" \n\t").is_err());
+    }
+
+    #[test]
+    fn send_at_must_be_in_the_future_but_not_too_far() {
+        let now = chrono::Utc::now();
+        assert!(check_send_at(now + chrono::TimeDelta::minutes(1), now).is_ok());
+        assert!(check_send_at(now + chrono::TimeDelta::days(MAX_SCHEDULE_AHEAD_DAYS), now).is_ok());
+        assert!(check_send_at(now, now).is_err());
+        assert!(check_send_at(now - chrono::TimeDelta::seconds(1), now).is_err());
+        assert!(check_send_at(now + chrono::TimeDelta::days(MAX_SCHEDULE_AHEAD_DAYS) + chrono::TimeDelta::seconds(1), now).is_err());
+    }
+
+    #[test]
+    fn the_number_of_waiting_messages_is_capped() {
+        assert!(check_count(0).is_ok());
+        assert!(check_count(MAX_SCHEDULED_MESSAGES - 1).is_ok());
+        assert!(check_count(MAX_SCHEDULED_MESSAGES).is_err());
+    }
+}
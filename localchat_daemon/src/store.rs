@@ -0,0 +1,938 @@
+// SQLite message store: every message this daemon sends or receives, grouped by conversation
+// (the other peer's ID for direct messages, the room for room messages).
+use rusqlite::{params, Connection, OptionalExtension};
+use std::error::Error;
+use std::path::Path;
+
+use crate::{Attachment, ConversationSummary, ConversationTimer, Draft, Message, Reaction, ReplyPreview, ScheduledMessage, SearchHit};
+
+// Bumped whenever migrate() gains a step; stored in PRAGMA user_version
+const SCHEMA_VERSION: i32 = 11;
+
+// Upper bound for one history page so a huge conversation can't flood the IPC socket
+pub const MAX_HISTORY_MESSAGES: usize = 500;
+
+// Search hits per page when the client doesn't ask for a number, and the most it may ask for
+pub const DEFAULT_SEARCH_RESULTS: usize = 50;
+pub const MAX_SEARCH_RESULTS: usize = 200;
+
+// Words of context FTS5 keeps around the matches in a snippet
+const SNIPPET_TOKENS: i64 = 16;
+
+// Marks FTS5 puts around matched words in snippets; turned into SearchHit::highlights and removed
+const MATCH_START: char = '\u{2}';
+const MATCH_END: char = '\u{3}';
+
+// Columns message_from_row reads, in its order; queries add their own columns after these
+const MESSAGE_COLUMNS: &str =
+    "id, sender, recipient, content, timestamp_ms, is_self, attachment_json, edited_at_ms, deleted, reply_to_id, reply_preview_json, \
+     mentions_json, mentions_me, pinned_at_ms, pinned_by, starred_at_ms, expires_in_secs";
+const MESSAGE_COLUMN_COUNT: usize = 17;
+
+// Columns scheduled_from_row reads, in its order
+const SCHEDULED_COLUMNS: &str = "id, recipient, content, send_at_ms, created_at_ms, last_error";
+
+// Messages loaded before a message we jump to (search hit), so it is shown in context
+const CONTEXT_MESSAGES_BEFORE: i64 = 25;
+
+pub struct MessageStore {
+    conn: Connection,
+}
+
+// Everything about a search except the query text
+pub struct SearchFilter<'a> {
+    pub conversation_id: Option<&'a str>,
+    pub after: Option<chrono::DateTime<chrono::Utc>>,
+    pub before: Option<chrono::DateTime<chrono::Utc>>,
+    pub limit: usize,
+    pub offset: usize,
+}
+
+// Which conversation a message belongs to, from this daemon's point of view
+pub fn conversation_id_for(message: &Message) -> &str {
+    if message.is_self || crate::presence::is_room_id(&message.recipient) {
+        &message.recipient
+    } else {
+        &message.sender
+    }
+}
+
+impl MessageStore {
+    pub fn open(db_path: &Path) -> Result<Self, Box<dyn Error>> {
+        if let Some(parent_dir) = db_path.parent() {
+            std::fs::create_dir_all(parent_dir)?;
+        }
+        let conn = Connection::open(db_path)?;
+        // WAL keeps readers (history, search) from blocking on the writer
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "foreign_keys", "ON")?;
+        let mut store = Self { conn };
+        store.migrate()?;
+        tracing::info!("Message store opened at {}", db_path.display());
+        Ok(store)
+    }
+
+    // Moves everything in the WAL into the database file and truncates the WAL, so a stopped daemon leaves
+    // a self-contained .sqlite3 behind. Called at shutdown, with the store locked so no write is half done.
+    pub fn checkpoint(&self) -> Result<(), Box<dyn Error>> {
+        let (busy, wal_frames, checkpointed_frames): (i64, i64, i64) =
+            self.conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+        if busy != 0 {
+            return Err(format!("checkpoint blocked: {} of {} WAL frames written", checkpointed_frames, wal_frames).into());
+        }
+        Ok(())
+    }
+
+    fn migrate(&mut self) -> Result<(), Box<dyn Error>> {
+        let current_version: i32 = self.conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if current_version > SCHEMA_VERSION {
+            return Err(format!(
+                "message store schema version {} is newer than this daemon supports ({})",
+                current_version, SCHEMA_VERSION
+            ).into());
+        }
+
+        let tx = self.conn.transaction()?;
+        if current_version < 1 {
+            // `seq` is the stable integer key (rowid) other tables and indexes refer to
+            tx.execute_batch(
+                "CREATE TABLE messages (
+                    seq INTEGER PRIMARY KEY AUTOINCREMENT,
+                    id TEXT NOT NULL UNIQUE,
+                    conversation_id TEXT NOT NULL,
+                    sender TEXT NOT NULL,
+                    recipient TEXT NOT NULL,
+                    content TEXT NOT NULL,
+                    timestamp_ms INTEGER NOT NULL,
+                    is_self INTEGER NOT NULL,
+                    attachment_json TEXT
+                );
+                CREATE INDEX idx_messages_conversation_time ON messages(conversation_id, timestamp_ms);
+                CREATE INDEX idx_messages_time ON messages(timestamp_ms);",
+            )?;
+        }
+        if current_version < 2 {
+            // Read position per conversation; everything received after last_read_seq is unread
+            tx.execute_batch(
+                "CREATE TABLE conversation_reads (
+                    conversation_id TEXT PRIMARY KEY,
+                    last_read_seq INTEGER NOT NULL,
+                    last_read_message_id TEXT
+                );",
+            )?;
+        }
+        if current_version < 3 {
+            // Full-text index over content and sender names. It is an external-content table (the text lives only
+            // in `messages`), kept in sync by triggers and filled from the existing rows on upgrade.
+            tx.execute_batch(
+                "CREATE VIRTUAL TABLE messages_fts USING fts5(
+                    content, sender,
+                    content = 'messages', content_rowid = 'seq',
+                    tokenize = 'unicode61 remove_diacritics 2'
+                );
+                CREATE TRIGGER messages_fts_insert AFTER INSERT ON messages BEGIN
+                    INSERT INTO messages_fts (rowid, content, sender) VALUES (new.seq, new.content, new.sender);
+                END;
+                CREATE TRIGGER messages_fts_delete AFTER DELETE ON messages BEGIN
+                    INSERT INTO messages_fts (messages_fts, rowid, content, sender) VALUES ('delete', old.seq, old.content, old.sender);
+                END;
+                CREATE TRIGGER messages_fts_update AFTER UPDATE OF content, sender ON messages BEGIN
+                    INSERT INTO messages_fts (messages_fts, rowid, content, sender) VALUES ('delete', old.seq, old.content, old.sender);
+                    INSERT INTO messages_fts (rowid, content, sender) VALUES (new.seq, new.content, new.sender);
+                END;
+                INSERT INTO messages_fts (messages_fts) VALUES ('rebuild');",
+            )?;
+        }
+        if current_version < 4 {
+            // Edits and deletions for everyone; a deleted message keeps its row (and place in the thread) as a tombstone
+            tx.execute_batch(
+                "ALTER TABLE messages ADD COLUMN edited_at_ms INTEGER;
+                ALTER TABLE messages ADD COLUMN deleted INTEGER NOT NULL DEFAULT 0;",
+            )?;
+        }
+        if current_version < 5 {
+            // Replies: the parent's id, plus the preview the sender attached in case the parent never reaches us
+            tx.execute_batch(
+                "ALTER TABLE messages ADD COLUMN reply_to_id TEXT;
+                ALTER TABLE messages ADD COLUMN reply_preview_json TEXT;
+                CREATE INDEX idx_messages_reply_to ON messages(reply_to_id) WHERE reply_to_id IS NOT NULL;",
+            )?;
+        }
+        if current_version < 6 {
+            // One row per reactor and emoji; is_self marks ours even after a username change alters our ID
+            tx.execute_batch(
+                "CREATE TABLE reactions (
+                    message_id TEXT NOT NULL,
+                    reactor TEXT NOT NULL,
+                    emoji TEXT NOT NULL,
+                    is_self INTEGER NOT NULL,
+                    reacted_at_ms INTEGER NOT NULL,
+                    PRIMARY KEY (message_id, reactor, emoji)
+                ) WITHOUT ROWID;",
+            )?;
+        }
+        if current_version < 7 {
+            // @mentions: who a room message mentions, and whether that includes us
+            tx.execute_batch(
+                "ALTER TABLE messages ADD COLUMN mentions_json TEXT;
+                ALTER TABLE messages ADD COLUMN mentions_me INTEGER NOT NULL DEFAULT 0;",
+            )?;
+        }
+        if current_version < 8 {
+            // Pins are shared with the conversation; stars are ours alone
+            tx.execute_batch(
+                "ALTER TABLE messages ADD COLUMN pinned_at_ms INTEGER;
+                ALTER TABLE messages ADD COLUMN pinned_by TEXT;
+                ALTER TABLE messages ADD COLUMN starred_at_ms INTEGER;
+                CREATE INDEX idx_messages_pinned ON messages(conversation_id, pinned_at_ms) WHERE pinned_at_ms IS NOT NULL;
+                CREATE INDEX idx_messages_starred ON messages(starred_at_ms) WHERE starred_at_ms IS NOT NULL;",
+            )?;
+        }
+        if current_version < 9 {
+            // Disappearing messages: each message's own timer (and when it runs out, for the purger),
+            // and the timer per conversation; expiry_secs NULL is "off", kept so the newest change still wins
+            tx.execute_batch(
+                "ALTER TABLE messages ADD COLUMN expires_in_secs INTEGER;
+                ALTER TABLE messages ADD COLUMN expires_at_ms INTEGER;
+                CREATE INDEX idx_messages_expires_at ON messages(expires_at_ms) WHERE expires_at_ms IS NOT NULL;
+                CREATE TABLE conversation_timers (
+                    conversation_id TEXT PRIMARY KEY,
+                    expiry_secs INTEGER,
+                    set_by TEXT NOT NULL,
+                    set_at_ms INTEGER NOT NULL
+                );",
+            )?;
+        }
+        if current_version < 10 {
+            // Messages waiting to be sent; last_error is why the latest attempt failed, shown to the user
+            tx.execute_batch(
+                "CREATE TABLE scheduled_messages (
+                    id TEXT PRIMARY KEY,
+                    recipient TEXT NOT NULL,
+                    content TEXT NOT NULL,
+                    send_at_ms INTEGER NOT NULL,
+                    created_at_ms INTEGER NOT NULL,
+                    last_error TEXT
+                );
+                CREATE INDEX idx_scheduled_messages_send_at ON scheduled_messages(send_at_ms);",
+            )?;
+        }
+        if current_version < 11 {
+            // Unsent text per conversation, so it survives restarts and other clients can see it
+            tx.execute_batch(
+                "CREATE TABLE drafts (
+                    conversation_id TEXT PRIMARY KEY,
+                    content TEXT NOT NULL,
+                    updated_at_ms INTEGER NOT NULL
+                ) WITHOUT ROWID;",
+            )?;
+        }
+        tx.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        tx.commit()?;
+        if current_version != SCHEMA_VERSION {
+            tracing::info!("Message store migrated from schema version {} to {}", current_version, SCHEMA_VERSION);
+        }
+        Ok(())
+    }
+
+    // Returns false if a message with this ID is already stored (peers may resend)
+    pub fn insert_message(&self, message: &Message) -> Result<bool, Box<dyn Error>> {
+        let attachment_json = message.attachment.as_ref().map(serde_json::to_string).transpose()?;
+        let reply_preview_json = message.reply_preview.as_ref().map(serde_json::to_string).transpose()?;
+        let mentions_json = if message.mentions.is_empty() { None } else { Some(serde_json::to_string(&message.mentions)?) };
+        let inserted_rows = self.conn.execute(
+            "INSERT OR IGNORE INTO messages
+                 (id, conversation_id, sender, recipient, content, timestamp_ms, is_self, attachment_json, reply_to_id, reply_preview_json,
+                  mentions_json, mentions_me, expires_in_secs, expires_at_ms)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            params![
+                message.id,
+                conversation_id_for(message),
+                message.sender,
+                message.recipient,
+                message.content,
+                message.timestamp.timestamp_millis(),
+                message.is_self,
+                attachment_json,
+                message.reply_to,
+                reply_preview_json,
+                mentions_json,
+                message.mentions_me,
+                message.expires_in.map(|secs| secs as i64),
+                crate::expiry::expires_at(message).map(|expires_at| expires_at.timestamp_millis()),
+            ],
+        )?;
+        Ok(inserted_rows > 0)
+    }
+
+    // One message by id, whichever conversation it is in
+    pub fn get_message(&self, message_id: &str) -> Result<Option<Message>, Box<dyn Error>> {
+        let mut message = self.conn.query_row(
+            &format!("SELECT {} FROM messages WHERE id = ?1", MESSAGE_COLUMNS),
+            params![message_id],
+            message_from_row,
+        ).optional()?;
+        if let Some(message) = message.as_mut() {
+            self.load_reactions(std::slice::from_mut(message))?;
+        }
+        Ok(message)
+    }
+
+    // New text for a message; the FTS index follows through its update trigger
+    pub fn edit_message(&self, message_id: &str, content: &str, edited_at: chrono::DateTime<chrono::Utc>) -> Result<(), Box<dyn Error>> {
+        self.conn.execute(
+            "UPDATE messages SET content = ?2, edited_at_ms = ?3 WHERE id = ?1",
+            params![message_id, content, edited_at.timestamp_millis()],
+        )?;
+        Ok(())
+    }
+
+    // Deletes a message for everyone: the row stays as a tombstone without content, attachment, pin or star,
+    // and replies stop quoting its text
+    pub fn delete_message(&self, message_id: &str) -> Result<(), Box<dyn Error>> {
+        self.conn.execute(
+            "UPDATE messages SET content = '', attachment_json = NULL, deleted = 1, pinned_at_ms = NULL, pinned_by = NULL, starred_at_ms = NULL
+             WHERE id = ?1",
+            params![message_id],
+        )?;
+        self.conn.execute(
+            "UPDATE messages SET reply_preview_json = NULL WHERE reply_to_id = ?1",
+            params![message_id],
+        )?;
+        self.conn.execute("DELETE FROM reactions WHERE message_id = ?1", params![message_id])?;
+        Ok(())
+    }
+
+    // The reactor ID we used for `emoji` on a message, if we reacted with it
+    pub fn own_reaction(&self, message_id: &str, emoji: &str) -> Result<Option<String>, Box<dyn Error>> {
+        let reactor = self.conn.query_row(
+            "SELECT reactor FROM reactions WHERE message_id = ?1 AND emoji = ?2 AND is_self = 1",
+            params![message_id, emoji],
+            |row| row.get(0),
+        ).optional()?;
+        Ok(reactor)
+    }
+
+    // Adds or removes one reaction; adding one that is already there (a resent frame) changes nothing
+    pub fn set_reaction(
+        &self,
+        message_id: &str,
+        reactor: &str,
+        emoji: &str,
+        is_self: bool,
+        added: bool,
+        reacted_at: chrono::DateTime<chrono::Utc>,
+    ) -> Result<(), Box<dyn Error>> {
+        if added {
+            self.conn.execute(
+                "INSERT OR IGNORE INTO reactions (message_id, reactor, emoji, is_self, reacted_at_ms) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![message_id, reactor, emoji, is_self, reacted_at.timestamp_millis()],
+            )?;
+        } else {
+            self.conn.execute(
+                "DELETE FROM reactions WHERE message_id = ?1 AND reactor = ?2 AND emoji = ?3",
+                params![message_id, reactor, emoji],
+            )?;
+        }
+        Ok(())
+    }
+
+    // Pins (`pinned_by` set) or unpins (None) a message
+    pub fn set_pinned(&self, message_id: &str, pinned_by: Option<&str>, pinned_at: chrono::DateTime<chrono::Utc>) -> Result<(), Box<dyn Error>> {
+        self.conn.execute(
+            "UPDATE messages SET pinned_by = ?2, pinned_at_ms = CASE WHEN ?2 IS NULL THEN NULL ELSE ?3 END WHERE id = ?1",
+            params![message_id, pinned_by, pinned_at.timestamp_millis()],
+        )?;
+        Ok(())
+    }
+
+    // A conversation's pinned messages, most recently pinned first
+    pub fn pinned_messages(&self, conversation_id: &str) -> Result<Vec<Message>, Box<dyn Error>> {
+        let mut statement = self.conn.prepare(&format!(
+            "SELECT {} FROM messages WHERE conversation_id = ?1 AND pinned_at_ms IS NOT NULL ORDER BY pinned_at_ms DESC",
+            MESSAGE_COLUMNS
+        ))?;
+        let mut messages = statement
+            .query_map(params![conversation_id], message_from_row)?
+            .collect::<Result<Vec<Message>, _>>()?;
+        self.load_reactions(&mut messages)?;
+        Ok(messages)
+    }
+
+    pub fn set_starred(&self, message_id: &str, starred: bool, starred_at: chrono::DateTime<chrono::Utc>) -> Result<(), Box<dyn Error>> {
+        let starred_at_ms = starred.then(|| starred_at.timestamp_millis());
+        self.conn.execute("UPDATE messages SET starred_at_ms = ?2 WHERE id = ?1", params![message_id, starred_at_ms])?;
+        Ok(())
+    }
+
+    // Starred messages from every conversation, most recently starred first
+    pub fn starred_messages(&self) -> Result<Vec<Message>, Box<dyn Error>> {
+        let mut statement = self.conn.prepare(&format!(
+            "SELECT {} FROM messages WHERE starred_at_ms IS NOT NULL ORDER BY starred_at_ms DESC LIMIT ?1",
+            MESSAGE_COLUMNS
+        ))?;
+        let mut messages = statement
+            .query_map(params![MAX_HISTORY_MESSAGES as i64], message_from_row)?
+            .collect::<Result<Vec<Message>, _>>()?;
+        self.load_reactions(&mut messages)?;
+        Ok(messages)
+    }
+
+    // Fills in Message::reactions: one entry per emoji in the order they were first used, reactors in the order they reacted
+    fn load_reactions(&self, messages: &mut [Message]) -> Result<(), Box<dyn Error>> {
+        let mut statement = self.conn.prepare_cached(
+            "SELECT emoji, reactor, is_self FROM reactions WHERE message_id = ?1 ORDER BY reacted_at_ms, reactor",
+        )?;
+        for message in messages.iter_mut() {
+            let mut rows = statement.query(params![message.id])?;
+            while let Some(row) = rows.next()? {
+                let emoji: String = row.get(0)?;
+                let reactor: String = row.get(1)?;
+                let is_self: bool = row.get(2)?;
+                let position = match message.reactions.iter().position(|reaction| reaction.emoji == emoji) {
+                    Some(position) => position,
+                    None => {
+                        message.reactions.push(Reaction { emoji, reactors: Vec::new(), by_self: false });
+                        message.reactions.len() - 1
+                    }
+                };
+                let reaction = &mut message.reactions[position];
+                reaction.reactors.push(reactor);
+                reaction.by_self |= is_self;
+            }
+        }
+        Ok(())
+    }
+
+    // The latest messages of a conversation (optionally only those after `since`), oldest first
+    pub fn conversation_history(
+        &self,
+        conversation_id: &str,
+        since: Option<chrono::DateTime<chrono::Utc>>,
+        limit: usize,
+    ) -> Result<Vec<Message>, Box<dyn Error>> {
+        let since_ms = since.map(|ts| ts.timestamp_millis()).unwrap_or(i64::MIN);
+        let mut statement = self.conn.prepare(&format!(
+            "SELECT {} FROM messages
+             WHERE conversation_id = ?1 AND timestamp_ms > ?2
+             ORDER BY timestamp_ms DESC, seq DESC LIMIT ?3",
+            MESSAGE_COLUMNS
+        ))?;
+        let mut messages = statement
+            .query_map(params![conversation_id, since_ms, limit.min(MAX_HISTORY_MESSAGES) as i64], message_from_row)?
+            .collect::<Result<Vec<Message>, _>>()?;
+        messages.reverse();
+        self.load_reactions(&mut messages)?;
+        Ok(messages)
+    }
+
+    // A conversation from a given message onwards (plus a few messages before it), oldest first.
+    // Used to show a search hit in context even when it is older than the latest history page.
+    // Empty if the message isn't in this conversation.
+    pub fn conversation_around(&self, conversation_id: &str, message_id: &str) -> Result<Vec<Message>, Box<dyn Error>> {
+        let mut statement = self.conn.prepare(&format!(
+            "SELECT {} FROM messages
+             WHERE conversation_id = ?1 AND timestamp_ms >= COALESCE((
+                 SELECT timestamp_ms FROM messages
+                 WHERE conversation_id = ?1 AND timestamp_ms <= (SELECT timestamp_ms FROM messages WHERE id = ?2)
+                 ORDER BY timestamp_ms DESC LIMIT 1 OFFSET ?3
+             ), 0)
+             AND EXISTS (SELECT 1 FROM messages WHERE id = ?2 AND conversation_id = ?1)
+             ORDER BY timestamp_ms ASC, seq ASC LIMIT ?4",
+            MESSAGE_COLUMNS
+        ))?;
+        let mut messages = statement
+            .query_map(params![conversation_id, message_id, CONTEXT_MESSAGES_BEFORE, MAX_HISTORY_MESSAGES as i64], message_from_row)?
+            .collect::<Result<Vec<Message>, _>>()?;
+        self.load_reactions(&mut messages)?;
+        Ok(messages)
+    }
+
+    // Full-text search over content and sender names, best match first; returns one page of hits and whether
+    // there are more. Every word of `query` must match the start of a word (so "lun" finds "lunch").
+    // An empty query lists the filter's messages newest first instead.
+    pub fn search_messages(&self, query: &str, filter: &SearchFilter<'_>) -> Result<(Vec<SearchHit>, bool), Box<dyn Error>> {
+        let after_ms = filter.after.map(|ts| ts.timestamp_millis()).unwrap_or(i64::MIN);
+        let before_ms = filter.before.map(|ts| ts.timestamp_millis()).unwrap_or(i64::MAX);
+        let limit = filter.limit.clamp(1, MAX_SEARCH_RESULTS);
+        // One extra row tells us whether another page exists
+        let page_size = (limit + 1) as i64;
+
+        let mut hits = if query.trim().is_empty() {
+            let mut statement = self.conn.prepare(&format!(
+                "SELECT {}, conversation_id FROM messages
+                 WHERE (?1 IS NULL OR conversation_id = ?1) AND timestamp_ms >= ?2 AND timestamp_ms < ?3 AND deleted = 0
+                 ORDER BY timestamp_ms DESC, seq DESC LIMIT ?4 OFFSET ?5",
+                MESSAGE_COLUMNS
+            ))?;
+            let rows = statement.query_map(
+                params![filter.conversation_id, after_ms, before_ms, page_size, filter.offset as i64],
+                |row| {
+                    let message = message_from_row(row)?;
+                    let snippet = plain_snippet(&message.content);
+                    Ok(SearchHit { conversation_id: row.get(MESSAGE_COLUMN_COUNT)?, message, snippet, highlights: Vec::new() })
+                },
+            )?;
+            rows.collect::<Result<Vec<SearchHit>, _>>()?
+        } else {
+            let Some(match_expression) = fts_match_expression(query) else {
+                return Ok((Vec::new(), false)); // Only punctuation: nothing FTS5 could match
+            };
+            // The MATCH drives the query through the index; the other filters only look at matching rows.
+            // bm25() ranks rarer and denser matches first, weighting content above sender names.
+            // The page is ranked and cut first; snippet() (the expensive part) then runs only for its rows.
+            // Deleted messages keep their sender in the index, so they are filtered out like in the empty-query branch.
+            // The FTS columns are named like the message columns, so they stay inside the CTE and the snippet subquery.
+            let mut statement = self.conn.prepare(&format!(
+                "WITH page AS (
+                     SELECT messages.seq AS page_seq, bm25(messages_fts, 1.0, 0.5) AS page_score, messages.timestamp_ms AS page_timestamp_ms
+                     FROM messages_fts JOIN messages ON messages.seq = messages_fts.rowid
+                     WHERE messages_fts MATCH ?1
+                       AND (?2 IS NULL OR messages.conversation_id = ?2) AND messages.timestamp_ms >= ?3 AND messages.timestamp_ms < ?4
+                       AND messages.deleted = 0
+                     ORDER BY page_score, page_timestamp_ms DESC
+                     LIMIT ?5 OFFSET ?9
+                 )
+                 SELECT {}, conversation_id,
+                        (SELECT snippet(messages_fts, 0, ?6, ?7, '…', ?8) FROM messages_fts WHERE messages_fts MATCH ?1 AND messages_fts.rowid = page.page_seq)
+                 FROM page JOIN messages ON messages.seq = page.page_seq
+                 ORDER BY page.page_score, page.page_timestamp_ms DESC",
+                MESSAGE_COLUMNS
+            ))?;
+            let rows = statement.query_map(
+                params![
+                    match_expression,
+                    filter.conversation_id,
+                    after_ms,
+                    before_ms,
+                    page_size,
+                    MATCH_START.to_string(),
+                    MATCH_END.to_string(),
+                    SNIPPET_TOKENS,
+                    filter.offset as i64,
+                ],
+                |row| {
+                    let marked_snippet: String = row.get(MESSAGE_COLUMN_COUNT + 1)?;
+                    let (snippet, highlights) = split_highlights(&marked_snippet);
+                    Ok(SearchHit { conversation_id: row.get(MESSAGE_COLUMN_COUNT)?, message: message_from_row(row)?, snippet, highlights })
+                },
+            )?;
+            rows.collect::<Result<Vec<SearchHit>, _>>()?
+        };
+        let has_more = hits.len() > limit;
+        hits.truncate(limit);
+        Ok((hits, has_more))
+    }
+
+    // One entry per conversation with stored messages, most recently active first.
+    // The preview columns are bare columns next to MAX(), so SQLite takes them from the newest message.
+    pub fn conversation_summaries(&self) -> Result<Vec<ConversationSummary>, Box<dyn Error>> {
+        let mut statement = self.conn.prepare(
+            "SELECT m.conversation_id,
+                    MAX(m.timestamp_ms) AS last_activity_ms,
+                    SUM(CASE WHEN m.is_self = 0 AND m.seq > COALESCE(r.last_read_seq, 0) THEN 1 ELSE 0 END),
+                    r.last_read_message_id,
+                    m.content,
+                    m.sender,
+                    m.is_self
+             FROM messages m LEFT JOIN conversation_reads r ON r.conversation_id = m.conversation_id
+             GROUP BY m.conversation_id
+             ORDER BY last_activity_ms DESC",
+        )?;
+        let summaries = statement
+            .query_map([], |row| {
+                let last_activity_ms: i64 = row.get(1)?;
+                Ok(ConversationSummary {
+                    conversation_id: row.get(0)?,
+                    unread_count: row.get(2)?,
+                    last_read_message_id: row.get(3)?,
+                    last_activity: chrono::DateTime::from_timestamp_millis(last_activity_ms).unwrap_or_default(),
+                    last_message_preview: row.get(4)?,
+                    last_message_sender: row.get(5)?,
+                    last_message_is_self: row.get(6)?,
+                })
+            })?
+            .collect::<Result<Vec<ConversationSummary>, _>>()?;
+        Ok(summaries)
+    }
+
+    // Marks a conversation read up to `message_id`, or up to its latest message if None.
+    // The read position only moves forward; returns false if nothing changed.
+    pub fn mark_read(&self, conversation_id: &str, message_id: Option<&str>) -> Result<bool, Box<dyn Error>> {
+        let target: Option<(i64, String)> = match message_id {
+            Some(message_id) => self.conn.query_row(
+                "SELECT seq, id FROM messages WHERE conversation_id = ?1 AND id = ?2",
+                params![conversation_id, message_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            ).optional()?,
+            None => self.conn.query_row(
+                "SELECT seq, id FROM messages WHERE conversation_id = ?1 ORDER BY seq DESC LIMIT 1",
+                params![conversation_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            ).optional()?,
+        };
+        let Some((last_read_seq, last_read_message_id)) = target else {
+            return Ok(false);
+        };
+        let updated_rows = self.conn.execute(
+            "INSERT INTO conversation_reads (conversation_id, last_read_seq, last_read_message_id) VALUES (?1, ?2, ?3)
+             ON CONFLICT(conversation_id) DO UPDATE SET
+                 last_read_seq = excluded.last_read_seq,
+                 last_read_message_id = excluded.last_read_message_id
+             WHERE excluded.last_read_seq > conversation_reads.last_read_seq",
+            params![conversation_id, last_read_seq, last_read_message_id],
+        )?;
+        Ok(updated_rows > 0)
+    }
+
+    // The disappearing-messages timer of a conversation; None if it was never set
+    pub fn conversation_timer(&self, conversation_id: &str) -> Result<Option<ConversationTimer>, Box<dyn Error>> {
+        let timer = self.conn.query_row(
+            "SELECT expiry_secs, set_by, set_at_ms FROM conversation_timers WHERE conversation_id = ?1",
+            params![conversation_id],
+            |row| {
+                let expiry_secs: Option<i64> = row.get(0)?;
+                let set_at_ms: i64 = row.get(2)?;
+                Ok(ConversationTimer {
+                    conversation_id: conversation_id.to_string(),
+                    expiry_secs: expiry_secs.map(|secs| secs as u64),
+                    set_by: Some(row.get(1)?),
+                    set_at: chrono::DateTime::from_timestamp_millis(set_at_ms),
+                })
+            },
+        ).optional()?;
+        Ok(timer)
+    }
+
+    // Sets a conversation's timer unless a newer change is already stored; returns false if it was older
+    pub fn set_conversation_timer(
+        &self,
+        conversation_id: &str,
+        expiry_secs: Option<u64>,
+        set_by: &str,
+        set_at: chrono::DateTime<chrono::Utc>,
+    ) -> Result<bool, Box<dyn Error>> {
+        let updated_rows = self.conn.execute(
+            "INSERT INTO conversation_timers (conversation_id, expiry_secs, set_by, set_at_ms) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(conversation_id) DO UPDATE SET
+                 expiry_secs = excluded.expiry_secs,
+                 set_by = excluded.set_by,
+                 set_at_ms = excluded.set_at_ms
+             WHERE excluded.set_at_ms > conversation_timers.set_at_ms",
+            params![conversation_id, expiry_secs.map(|secs| secs as i64), set_by, set_at.timestamp_millis()],
+        )?;
+        Ok(updated_rows > 0)
+    }
+
+    // Removes every message whose timer ran out by `now`, with its reactions and the quotes of it in replies,
+    // and returns them so the caller can remove attachment files and tell clients
+    pub fn take_expired_messages(&self, now: chrono::DateTime<chrono::Utc>) -> Result<Vec<Message>, Box<dyn Error>> {
+        let tx = self.conn.unchecked_transaction()?;
+        let expired = {
+            let mut statement = tx.prepare(&format!(
+                "SELECT {} FROM messages WHERE expires_at_ms <= ?1 ORDER BY expires_at_ms",
+                MESSAGE_COLUMNS
+            ))?;
+            let expired = statement
+                .query_map(params![now.timestamp_millis()], message_from_row)?
+                .collect::<Result<Vec<Message>, _>>()?;
+            expired
+        };
+        for message in &expired {
+            tx.execute("DELETE FROM reactions WHERE message_id = ?1", params![message.id])?;
+            tx.execute("UPDATE messages SET reply_preview_json = NULL WHERE reply_to_id = ?1", params![message.id])?;
+            tx.execute("DELETE FROM messages WHERE id = ?1", params![message.id])?; // The FTS delete trigger drops it from search
+        }
+        tx.commit()?;
+        Ok(expired)
+    }
+
+    pub fn insert_scheduled(&self, scheduled: &ScheduledMessage) -> Result<(), Box<dyn Error>> {
+        self.conn.execute(
+            "INSERT INTO scheduled_messages (id, recipient, content, send_at_ms, created_at_ms, last_error)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                scheduled.id,
+                scheduled.recipient,
+                scheduled.content,
+                scheduled.send_at.timestamp_millis(),
+                scheduled.created_at.timestamp_millis(),
+                scheduled.last_error,
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_scheduled(&self, scheduled_id: &str) -> Result<Option<ScheduledMessage>, Box<dyn Error>> {
+        let scheduled = self.conn.query_row(
+            &format!("SELECT {} FROM scheduled_messages WHERE id = ?1", SCHEDULED_COLUMNS),
+            params![scheduled_id],
+            scheduled_from_row,
+        ).optional()?;
+        Ok(scheduled)
+    }
+
+    // New text and time for a scheduled message; the last error goes since it is a fresh attempt.
+    // Returns false if it was sent or cancelled in the meantime.
+    pub fn update_scheduled(&self, scheduled_id: &str, content: &str, send_at: chrono::DateTime<chrono::Utc>) -> Result<bool, Box<dyn Error>> {
+        let updated_rows = self.conn.execute(
+            "UPDATE scheduled_messages SET content = ?2, send_at_ms = ?3, last_error = NULL WHERE id = ?1",
+            params![scheduled_id, content, send_at.timestamp_millis()],
+        )?;
+        Ok(updated_rows > 0)
+    }
+
+    pub fn set_scheduled_error(&self, scheduled_id: &str, last_error: &str) -> Result<(), Box<dyn Error>> {
+        self.conn.execute(
+            "UPDATE scheduled_messages SET last_error = ?2 WHERE id = ?1",
+            params![scheduled_id, last_error],
+        )?;
+        Ok(())
+    }
+
+    // Returns false if there was no such scheduled message
+    pub fn delete_scheduled(&self, scheduled_id: &str) -> Result<bool, Box<dyn Error>> {
+        let deleted_rows = self.conn.execute("DELETE FROM scheduled_messages WHERE id = ?1", params![scheduled_id])?;
+        Ok(deleted_rows > 0)
+    }
+
+    // Every scheduled message, the next one to go first
+    pub fn scheduled_messages(&self) -> Result<Vec<ScheduledMessage>, Box<dyn Error>> {
+        let mut statement = self.conn.prepare(&format!(
+            "SELECT {} FROM scheduled_messages ORDER BY send_at_ms, created_at_ms",
+            SCHEDULED_COLUMNS
+        ))?;
+        let scheduled = statement.query_map([], scheduled_from_row)?.collect::<Result<Vec<ScheduledMessage>, _>>()?;
+        Ok(scheduled)
+    }
+
+    // Scheduled messages whose time has come by `now`, oldest first
+    pub fn due_scheduled(&self, now: chrono::DateTime<chrono::Utc>) -> Result<Vec<ScheduledMessage>, Box<dyn Error>> {
+        let mut statement = self.conn.prepare(&format!(
+            "SELECT {} FROM scheduled_messages WHERE send_at_ms <= ?1 ORDER BY send_at_ms, created_at_ms",
+            SCHEDULED_COLUMNS
+        ))?;
+        let due = statement
+            .query_map(params![now.timestamp_millis()], scheduled_from_row)?
+            .collect::<Result<Vec<ScheduledMessage>, _>>()?;
+        Ok(due)
+    }
+
+    // Saves the unsent text of a conversation; empty text removes the draft
+    pub fn set_draft(&self, conversation_id: &str, content: &str, updated_at: chrono::DateTime<chrono::Utc>) -> Result<(), Box<dyn Error>> {
+        if content.trim().is_empty() {
+            self.conn.execute("DELETE FROM drafts WHERE conversation_id = ?1", params![conversation_id])?;
+        } else {
+            self.conn.execute(
+                "INSERT INTO drafts (conversation_id, content, updated_at_ms) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(conversation_id) DO UPDATE SET content = excluded.content, updated_at_ms = excluded.updated_at_ms",
+                params![conversation_id, content, updated_at.timestamp_millis()],
+            )?;
+        }
+        Ok(())
+    }
+
+    // Every draft, most recently changed first
+    pub fn drafts(&self) -> Result<Vec<Draft>, Box<dyn Error>> {
+        let mut statement = self.conn.prepare("SELECT conversation_id, content, updated_at_ms FROM drafts ORDER BY updated_at_ms DESC")?;
+        let drafts = statement
+            .query_map([], |row| {
+                let updated_at_ms: i64 = row.get(2)?;
+                Ok(Draft {
+                    conversation_id: row.get(0)?,
+                    content: row.get(1)?,
+                    updated_at: chrono::DateTime::from_timestamp_millis(updated_at_ms).unwrap_or_default(),
+                })
+            })?
+            .collect::<Result<Vec<Draft>, _>>()?;
+        Ok(drafts)
+    }
+
+    // Moves every conversation's read position to its latest message; returns how many changed
+    pub fn mark_all_read(&self) -> Result<usize, Box<dyn Error>> {
+        let updated_rows = self.conn.execute(
+            "INSERT INTO conversation_reads (conversation_id, last_read_seq, last_read_message_id)
+             SELECT m.conversation_id, m.seq, m.id FROM messages m
+             WHERE m.seq = (SELECT MAX(seq) FROM messages WHERE conversation_id = m.conversation_id)
+             ON CONFLICT(conversation_id) DO UPDATE SET
+                 last_read_seq = excluded.last_read_seq,
+                 last_read_message_id = excluded.last_read_message_id
+             WHERE excluded.last_read_seq > conversation_reads.last_read_seq",
+            [],
+        )?;
+        Ok(updated_rows)
+    }
+}
+
+// Expects SCHEDULED_COLUMNS
+fn scheduled_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<ScheduledMessage> {
+    let send_at_ms: i64 = row.get(3)?;
+    let created_at_ms: i64 = row.get(4)?;
+    Ok(ScheduledMessage {
+        id: row.get(0)?,
+        recipient: row.get(1)?,
+        content: row.get(2)?,
+        send_at: chrono::DateTime::from_timestamp_millis(send_at_ms).unwrap_or_default(),
+        created_at: chrono::DateTime::from_timestamp_millis(created_at_ms).unwrap_or_default(),
+        last_error: row.get(5)?,
+    })
+}
+
+// Expects MESSAGE_COLUMNS first
+fn message_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Message> {
+    let timestamp_ms: i64 = row.get(4)?;
+    let attachment_json: Option<String> = row.get(6)?;
+    let edited_at_ms: Option<i64> = row.get(7)?;
+    let reply_preview_json: Option<String> = row.get(10)?;
+    let mentions_json: Option<String> = row.get(11)?;
+    let pinned_at_ms: Option<i64> = row.get(13)?;
+    let starred_at_ms: Option<i64> = row.get(15)?;
+    let expires_in_secs: Option<i64> = row.get(16)?;
+    Ok(Message {
+        id: row.get(0)?,
+        sender: row.get(1)?,
+        recipient: row.get(2)?,
+        content: row.get(3)?,
+        timestamp: chrono::DateTime::from_timestamp_millis(timestamp_ms).unwrap_or_default(),
+        is_self: row.get(5)?,
+        // A row we can't decode just loses its attachment rather than failing the whole history
+        attachment: attachment_json.and_then(|json| serde_json::from_str::<Attachment>(&json).ok()),
+        edited_at: edited_at_ms.and_then(chrono::DateTime::from_timestamp_millis),
+        deleted: row.get(8)?,
+        reply_to: row.get(9)?,
+        reply_preview: reply_preview_json.and_then(|json| serde_json::from_str::<ReplyPreview>(&json).ok()),
+        reactions: Vec::new(), // Filled in by load_reactions where needed
+        mentions: mentions_json.and_then(|json| serde_json::from_str(&json).ok()).unwrap_or_default(),
+        mentions_me: row.get(12)?,
+        pinned_at: pinned_at_ms.and_then(chrono::DateTime::from_timestamp_millis),
+        pinned_by: row.get(14)?,
+        starred: starred_at_ms.is_some(),
+        expires_in: expires_in_secs.map(|secs| secs as u64),
+    })
+}
+
+// Turns what the user typed into an FTS5 query: every word quoted (so FTS5 operators and punctuation are
+// taken literally) and matched as a prefix. None if no word has anything FTS5 indexes.
+fn fts_match_expression(query: &str) -> Option<String> {
+    let terms: Vec<String> = query
+        .split_whitespace()
+        .filter(|word| word.chars().any(char::is_alphanumeric))
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect();
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
+// Removes the match marks from an FTS5 snippet, remembering where they were
+fn split_highlights(marked_snippet: &str) -> (String, Vec<(usize, usize)>) {
+    let mut snippet = String::with_capacity(marked_snippet.len());
+    let mut highlights = Vec::new();
+    let mut match_start = None;
+    for c in marked_snippet.chars() {
+        match c {
+            MATCH_START => match_start = Some(snippet.len()),
+            MATCH_END => {
+                if let Some(start) = match_start.take() {
+                    highlights.push((start, snippet.len()));
+                }
+            }
+            _ => snippet.push(c),
+        }
+    }
+    (snippet, highlights)
+}
+
+// Snippet for listings without a query: the start of the first line
+fn plain_snippet(content: &str) -> String {
+    let first_line = content.lines().next().unwrap_or_default();
+    let mut snippet: String = first_line.chars().take(100).collect();
+    if snippet.len() < content.len() {
+        snippet.push('…');
+    }
+    snippet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A store that lives only as long as the test
+    fn memory_store() -> MessageStore {
+        MessageStore::open(Path::new(":memory:")).unwrap()
+    }
+
+    fn message(id: &str, sender: &str, content: &str, minutes_ago: i64) -> Message {
+        crate::test_message(id, sender, content, chrono::Utc::now() - chrono::Duration::minutes(minutes_ago))
+    }
+
+    fn search(store: &MessageStore, query: &str, limit: usize) -> (Vec<SearchHit>, bool) {
+        let filter = SearchFilter { conversation_id: None, after: None, before: None, limit, offset: 0 };
+        store.search_messages(query, &filter).unwrap()
+    }
+
+    #[test]
+    fn match_expression_quotes_each_word_as_a_prefix() {
+        assert_eq!(fts_match_expression("lunch plans").as_deref(), Some("\"lunch\"* \"plans\"*"));
+        assert_eq!(fts_match_expression("  spaced\tout ").as_deref(), Some("\"spaced\"* \"out\"*"));
+    }
+
+    #[test]
+    fn match_expression_escapes_quotes_and_drops_punctuation() {
+        assert_eq!(fts_match_expression("say \"hi\"").as_deref(), Some("\"say\"* \"\"\"hi\"\"\"*"));
+        assert_eq!(fts_match_expression("what ?! now").as_deref(), Some("\"what\"* \"now\"*"));
+        assert_eq!(fts_match_expression("?! ..."), None);
+        assert_eq!(fts_match_expression("   "), None);
+    }
+
+    #[test]
+    fn split_highlights_returns_byte_ranges_without_the_marks() {
+        let marked = format!("see {}you{} at {}café{}", MATCH_START, MATCH_END, MATCH_START, MATCH_END);
+        let (snippet, highlights) = split_highlights(&marked);
+        assert_eq!(snippet, "see you at café");
+        assert_eq!(highlights, vec![(4, 7), (11, 16)]);
+        assert_eq!(&snippet[11..16], "café");
+    }
+
+    #[test]
+    fn split_highlights_ignores_an_unmatched_end_mark() {
+        let (snippet, highlights) = split_highlights(&format!("plain{} text", MATCH_END));
+        assert_eq!(snippet, "plain text");
+        assert!(highlights.is_empty());
+    }
+
+    #[test]
+    fn search_finds_prefixes_and_skips_deleted_messages() {
+        let store = memory_store();
+        store.insert_message(&message("a", "alice - 1", "lunch at noon?", 3)).unwrap();
+        store.insert_message(&message("b", "bob - 2", "lunchbox forgotten again", 2)).unwrap();
+        store.insert_message(&message("c", "lunch - 3", "unrelated", 1)).unwrap();
+        store.delete_message("c").unwrap();
+
+        let (hits, has_more) = search(&store, "lunch", 10);
+        let mut ids: Vec<&str> = hits.iter().map(|hit| hit.message.id.as_str()).collect();
+        ids.sort();
+        assert_eq!(ids, vec!["a", "b"]); // "c" still matches on its sender, but is deleted
+        assert!(!has_more);
+        let hit = hits.iter().find(|hit| hit.message.id == "a").unwrap();
+        assert_eq!(hit.highlights.len(), 1);
+        let (start, end) = hit.highlights[0];
+        assert_eq!(&hit.snippet[start..end], "lunch");
+    }
+
+    #[test]
+    fn search_pages_report_whether_more_remain() {
+        let store = memory_store();
+        for minutes_ago in 0..3 {
+            store.insert_message(&message(&format!("m{}", minutes_ago), "alice - 1", "standup notes", minutes_ago)).unwrap();
+        }
+        let (hits, has_more) = search(&store, "standup", 2);
+        assert_eq!(hits.len(), 2);
+        assert!(has_more);
+        let (hits, has_more) = search(&store, "", 3);
+        assert_eq!(hits.len(), 3);
+        assert!(!has_more);
+    }
+}
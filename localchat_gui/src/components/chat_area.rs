@@ -1,19 +1,199 @@
 use eframe::egui;
-use crate::{Message, GuiToDaemonCommand}; // Removed unused IpcPeer import
+use crate::{conversation_id, Attachment, Message, GuiToDaemonCommand, ScheduledMessage}; // Removed unused IpcPeer import
+use crate::slash_commands::{self, ParsedInput, SlashCommand};
+use crate::components::markdown::{self, MarkdownStyle};
+use crate::keybindings::{Action, Keybindings};
 use tokio::sync::mpsc; // For Sender type
 use std::sync::Arc;
-use uuid;
+use std::collections::HashMap;
 
-// Updated to accept current_chat_peer_id and the list of peers
+// A message to bring into view, e.g. a search hit opened from the History panel
+pub struct MessageJump {
+    pub message_id: String,
+    scrolled: bool, // Scrolled to it already; the user may scroll away again
+    highlight_until: Option<f64>, // egui time at which the highlight ends, set once it's on screen
+    give_up_at: Option<f64>, // egui time after which a message that never loaded is reported as unavailable
+}
+
+impl MessageJump {
+    pub fn new(message_id: String) -> Self {
+        Self { message_id, scrolled: false, highlight_until: None, give_up_at: None }
+    }
+}
+
+// Offered in a message's context menu; chips under a bubble take any emoji a peer used
+const QUICK_REACTIONS: [&str; 6] = ["👍", "✅", "👀", "😂", "❤", "🎉"];
+
+// Rows in the @mention popup; keep typing to narrow it down
+const MAX_MENTION_COMPLETIONS: usize = 8;
+
+// How long to wait for the daemon to load a quoted message that isn't in the open conversation yet
+const REPLY_JUMP_TIMEOUT_SECS: f64 = 5.0;
+
+// Offered by the timer button; any other length can be set with /timer
+const TIMER_CHOICES: [(Option<u64>, &str); 5] = [
+    (None, "Off"),
+    (Some(5 * 60), "5 minutes"),
+    (Some(60 * 60), "1 hour"),
+    (Some(24 * 60 * 60), "1 day"),
+    (Some(7 * 24 * 60 * 60), "1 week"),
+];
+
+// Offered by the schedule button, in slash_commands::parse_send_time's words; the field below them takes any other time
+const SCHEDULE_CHOICES: [(&str, &str); 4] = [
+    ("In 30 minutes", "30m"),
+    ("In 1 hour", "1h"),
+    ("In 3 hours", "3h"),
+    ("Tomorrow 9:00", "tomorrow 9:00"),
+];
+
+// The input grows with its text up to this many lines, then scrolls
+const MAX_INPUT_LINES: f32 = 6.0;
+
+// Same limits the daemon enforces (edits.rs); only used to decide which menu entries to offer
+const EDIT_WINDOW_MINUTES: i64 = 15;
+const DELETE_WINDOW_HOURS: i64 = 24;
+
+// Chat area state that has to survive between frames
+pub struct ChatAreaState {
+    pub message_jump: Option<MessageJump>, // Stays pending until the message has loaded
+    editing: Option<EditingMessage>, // Own message whose text is in the input field for editing
+    replying_to: Option<ReplyTarget>, // Message the next one sent answers; only one of this and `editing` is set
+    pub mention_candidates: Vec<String>, // Usernames @mention autocompletion offers; empty outside rooms
+    pub pinned_messages: HashMap<String, Vec<Message>>, // Per conversation, most recently pinned first (from the daemon)
+    pins_expanded: bool, // The pinned strip lists every pin instead of only the latest
+    pub conversation_timers: HashMap<String, Option<u64>>, // Disappearing-messages timer per conversation, in seconds
+    pub scheduled_messages: Vec<ScheduledMessage>, // Waiting in the daemon, for every conversation, next first
+    editing_scheduled: Option<ScheduledEdit>, // Scheduled message whose time and text are open for editing
+    schedule_time_input: String, // Time typed into the schedule button's menu
+}
+
+struct ScheduledEdit {
+    scheduled_id: String,
+    time_text: String,
+    original_time_text: String, // Left as it is, the time isn't sent (a past time waiting for an offline peer stays valid)
+    content: String,
+    error: Option<String>, // Why the last Save didn't go through
+}
+
+impl ScheduledEdit {
+    fn of(scheduled: &ScheduledMessage) -> Self {
+        let time_text = scheduled.send_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string();
+        Self {
+            scheduled_id: scheduled.id.clone(),
+            original_time_text: time_text.clone(),
+            time_text,
+            content: scheduled.content.clone(),
+            error: None,
+        }
+    }
+}
+
+struct ReplyTarget {
+    message_id: String,
+    conversation_id: String,
+    sender: String,
+    preview: String,
+}
+
+struct EditingMessage {
+    message_id: String,
+    conversation_id: String,
+    original_content: String,
+}
+
+impl ChatAreaState {
+    pub fn new() -> Self {
+        Self {
+            message_jump: None,
+            editing: None,
+            replying_to: None,
+            mention_candidates: Vec::new(),
+            pinned_messages: HashMap::new(),
+            pins_expanded: false,
+            conversation_timers: HashMap::new(),
+            scheduled_messages: Vec::new(),
+            editing_scheduled: None,
+            schedule_time_input: String::new(),
+        }
+    }
+
+    // The input holds one of our sent messages for editing rather than new text
+    pub fn is_editing(&self) -> bool {
+        self.editing.is_some()
+    }
+
+    // Keeps the pinned strip in step with MessageUpdated; a deleted message is unpinned by the daemon
+    pub fn update_pinned(&mut self, message: &Message) {
+        if let Some(pinned) = self.pinned_messages.get_mut(conversation_id(message)) {
+            if message.deleted {
+                pinned.retain(|m| m.id != message.id);
+            } else if let Some(existing) = pinned.iter_mut().find(|m| m.id == message.id) {
+                *existing = message.clone();
+            }
+        }
+    }
+}
+
+const MESSAGE_INPUT_ID: &str = "chat_message_input";
+
+// Puts the cursor in the message input, e.g. after picking a conversation in the quick switcher
+pub fn focus_input(ctx: &egui::Context) {
+    ctx.memory_mut(|m| m.request_focus(egui::Id::new(MESSAGE_INPUT_ID)));
+}
+
+// What the chat area shows and reacts to but never changes
+pub struct ChatAreaView<'a> {
+    pub messages: &'a [Message], // Only the open conversation's messages
+    pub current_chat_peer_id: &'a Option<String>,
+    pub keybindings: &'a Keybindings,
+}
+
+// Updated to accept current_chat_peer_id and the list of peers.
+// Returns a slash command typed by the user for ChatApp to run; plain text is sent from here.
 pub fn show(
     ui: &mut egui::Ui, 
-    messages: &mut Vec<Message>, 
+    view: ChatAreaView,
     message_input: &mut String, 
-    current_user_id: &str, // Placeholder for the actual current user's ID/name
-    current_chat_peer_id: &Option<String>,
     gui_to_daemon_tx: &Option<mpsc::Sender<GuiToDaemonCommand>>,
-    rt: &Arc<tokio::runtime::Runtime>
-) {
+    rt: &Arc<tokio::runtime::Runtime>,
+    slash_hint: &mut Option<String>,
+    view_state: &mut ChatAreaState
+) -> Option<SlashCommand> {
+    let ChatAreaView { messages, current_chat_peer_id, keybindings } = view;
+    let mut slash_command = None;
+    // Pinned messages sit above the message list; clicking one scrolls to it like a quote does
+    let pinned = current_chat_peer_id.as_ref()
+        .and_then(|id| view_state.pinned_messages.get(id))
+        .filter(|pinned| !pinned.is_empty());
+    let clicked_pin = match pinned {
+        Some(pinned) => show_pinned_strip(ui, pinned, &mut view_state.pins_expanded, gui_to_daemon_tx, rt),
+        None => None,
+    };
+    // Messages waiting to be sent here come next, each with edit and cancel
+    let scheduled: Vec<&ScheduledMessage> = match current_chat_peer_id.as_ref() {
+        Some(id) => view_state.scheduled_messages.iter().filter(|scheduled| &scheduled.recipient == id).collect(),
+        None => Vec::new(),
+    };
+    if scheduled.is_empty() {
+        view_state.editing_scheduled = None;
+    } else {
+        show_scheduled_strip(ui, &scheduled, &mut view_state.editing_scheduled, gui_to_daemon_tx, rt);
+    }
+    let message_jump = &mut view_state.message_jump;
+    // An edit belongs to the conversation it was started in
+    if view_state.editing.as_ref().is_some_and(|editing| Some(&editing.conversation_id) != current_chat_peer_id.as_ref()) {
+        view_state.editing = None;
+        message_input.clear();
+    }
+    if view_state.replying_to.as_ref().is_some_and(|reply| Some(&reply.conversation_id) != current_chat_peer_id.as_ref()) {
+        view_state.replying_to = None;
+    }
+    let mut start_editing = None; // Set from a bubble's context menu, applied after the message list
+    let mut start_reply = None; // Likewise
+    let mut open_parent = clicked_pin; // Id of a quoted (or pinned) message the user clicked
+    // Quoted messages are shown from the loaded conversation when possible
+    let messages_by_id: HashMap<&str, &Message> = messages.iter().map(|message| (message.id.as_str(), message)).collect();
     // Modern styling for chat bubbles and text - optimized for dark background
     let self_bubble_color = egui::Color32::from_rgb(25, 118, 210);    // Modern blue
     let other_bubble_color = egui::Color32::from_rgb(66, 66, 66);     // Dark gray that works on black
@@ -22,17 +202,24 @@ pub fn show(
     let self_timestamp_color = egui::Color32::from_rgb(200, 200, 200); // Light gray for timestamp
     let other_timestamp_color = egui::Color32::from_rgb(180, 180, 180); // Slightly darker gray for timestamps
     let accent_color = self_bubble_color;                             // Use blue as accent color throughout the app
+    let jump_highlight_color = egui::Color32::from_rgb(255, 200, 60);  // Outline of a message opened from History
+    let self_link_color = egui::Color32::from_rgb(200, 230, 255);      // Links stay readable on the blue bubble
+    let other_link_color = egui::Color32::from_rgb(110, 180, 255);
+    let mention_color = egui::Color32::from_rgb(255, 190, 80);         // @names, and the outline of messages that mention us
 
     // UI Design Constants
     let bubble_radius = 12;  // Changed from 12.0 to 12 (u8)
-    let input_height = 40.0;
     let input_radius = 20.0;
     let button_size = 38.0;
     
     let total_area = ui.available_rect_before_wrap();
     
     // Calculate fixed heights for input area - increased for visibility
-    let input_area_height = 70.0; // Increased from 60.0 to 70.0 for better visibility
+    // A longer message makes it taller: the text's height is measured when the input is drawn (last frame)
+    let input_row_height = ui.text_style_height(&egui::TextStyle::Body);
+    let input_text_height_id = egui::Id::new("chat_message_input_height");
+    let input_text_height = ui.data(|d| d.get_temp::<f32>(input_text_height_id)).unwrap_or(input_row_height);
+    let input_area_height = 70.0 + (input_text_height.min(input_row_height * MAX_INPUT_LINES) - input_row_height).max(0.0);
     
     // Create the message area (everything except input area)
     let message_area_rect = egui::Rect::from_min_max(
@@ -71,6 +258,17 @@ pub fn show(
         .show(&mut message_ui, |ui| {
             ui.add_space(12.0);
             
+            if current_chat_peer_id.is_none() {
+                ui.vertical_centered(|ui| {
+                    ui.add_space(40.0);
+                    ui.label(
+                        egui::RichText::new("Pick a peer or room on the left to start chatting")
+                            .size(14.0)
+                            .color(egui::Color32::from_rgb(160, 160, 160))
+                    );
+                });
+            }
+
             // Group messages by day
             let mut last_message_date = None;
             
@@ -99,18 +297,30 @@ pub fn show(
                     last_message_date = Some(current_date);
                 }
                 
-                // Print message for debugging
-                println!("Processing message: is_self={}, sender={}, content={}", 
-                    message.is_self, message.sender, message.content);
-                
                 // Message bubble styling
-                let (bubble_color, text_color, timestamp_color) = if message.is_self {
-                    (self_bubble_color, self_text_color, self_timestamp_color)
+                let (bubble_color, text_color, timestamp_color, link_color) = if message.is_self {
+                    (self_bubble_color, self_text_color, self_timestamp_color, self_link_color)
                 } else {
-                    (other_bubble_color, other_text_color, other_timestamp_color)
+                    (other_bubble_color, other_text_color, other_timestamp_color, other_link_color)
+                };
+                let markdown_style = MarkdownStyle {
+                    text_color,
+                    secondary_color: timestamp_color,
+                    link_color,
+                    mention_color,
+                    mention_names: message.mentions.iter().map(|id| crate::display_name(id, &[])).collect(),
+                    text_size: 14.0,
                 };
                 
                 let alignment = if message.is_self { egui::Align::Max } else { egui::Align::Min };
+                let is_jump_target = message_jump.as_ref().is_some_and(|jump| jump.message_id == message.id);
+                let bubble_stroke = if is_jump_target {
+                    egui::Stroke::new(2.0, jump_highlight_color)
+                } else if message.mentions_me {
+                    egui::Stroke::new(1.5, mention_color)
+                } else {
+                    egui::Stroke::NONE
+                };
 
                 ui.with_layout(egui::Layout::top_down(alignment), |ui| {
                     let max_width = ui.available_width() * 0.7; // Max width for messages is 70% of area
@@ -129,18 +339,22 @@ pub fn show(
                         ui.add_space(1.0);
                     }
                     
-                    // Fixed content width calculation - using a simpler approach
-                    // Min width to ensure visibility even for short messages
+                    // The bubble is as wide as its widest line (longer lines wrap at max_width);
+                    // short messages still get room for the timestamp
                     let min_width = 100.0;
-                    // Estimate width based on character count
-                    let estimated_width = ((message.content.len() as f32 * 8.0) + 40.0).max(min_width).min(max_width);
-                    
+                    let mut content_width = markdown::content_width(ui, &message.content, &markdown_style);
+                    if let Some(ref attachment) = message.attachment {
+                        content_width = content_width.max(attachment_width(ui, attachment));
+                    }
+                    let bubble_width = (content_width + 24.0).clamp(min_width, max_width); // + the bubble's inner margins
+
                     ui.allocate_ui_with_layout(
-                        egui::vec2(estimated_width, 0.0),
+                        egui::vec2(bubble_width, 0.0),
                         egui::Layout::top_down(egui::Align::Min),
                         |ui| {
-                            egui::Frame::new()
+                            let bubble = egui::Frame::new()
                                 .fill(bubble_color)
+                                .stroke(bubble_stroke)
                                 .corner_radius(egui::CornerRadius {
                                     ne: bubble_radius,
                                     nw: bubble_radius,
@@ -149,8 +363,30 @@ pub fn show(
                                 })
                                 .inner_margin(egui::vec2(12.0, 8.0))
                                 .show(ui, |ui: &mut egui::Ui| {
-                                    // Message content
-                                    ui.label(egui::RichText::new(&message.content).color(text_color).size(14.0));
+                                    if message.deleted {
+                                        // Tombstone: the sender deleted it for everyone
+                                        ui.label(egui::RichText::new("🚫 Message deleted").color(timestamp_color).size(13.0).italics());
+                                    } else {
+                                        if let Some(ref parent_id) = message.reply_to {
+                                            let quote = show_reply_quote(
+                                                ui,
+                                                messages_by_id.get(parent_id.as_str()).copied(),
+                                                message.reply_preview.as_ref(),
+                                                text_color,
+                                                timestamp_color
+                                            );
+                                            if quote.clicked() {
+                                                open_parent = Some(parent_id.clone());
+                                            }
+                                        }
+
+                                        // Message content, with markdown formatting and clickable links
+                                        markdown::show(ui, &message.content, &markdown_style);
+
+                                        if let Some(ref attachment) = message.attachment {
+                                            show_attachment(ui, attachment, timestamp_color);
+                                        }
+                                    }
                                     
                                     // Timestamp with right alignment
                                     ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
@@ -159,8 +395,75 @@ pub fn show(
                                                 .size(10.0)
                                                 .color(timestamp_color)
                                         );
+                                        if let Some(edited_at) = message.edited_at.filter(|_| !message.deleted) {
+                                            ui.label(egui::RichText::new("edited").size(10.0).italics().color(timestamp_color))
+                                                .on_hover_text(edited_at.with_timezone(&chrono::Local).format("Edited %b %d, %H:%M").to_string());
+                                        }
+                                        if let Some(ref pinned_by) = message.pinned_by {
+                                            ui.label(egui::RichText::new("📌").size(10.0).color(timestamp_color))
+                                                .on_hover_text(format!("Pinned by {}", crate::display_name(pinned_by, &[])));
+                                        }
+                                        if message.starred {
+                                            ui.label(egui::RichText::new("⭐").size(10.0).color(timestamp_color)).on_hover_text("Starred");
+                                        }
+                                        // The timer comes from the sender's daemon; one too long for a date isn't shown
+                                        let expires_at = message.expires_in
+                                            .and_then(|expires_in| chrono::TimeDelta::try_seconds(i64::try_from(expires_in).ok()?))
+                                            .and_then(|timer| message.timestamp.checked_add_signed(timer));
+                                        if let Some(expires_at) = expires_at {
+                                            ui.label(egui::RichText::new("⏱").size(10.0).color(timestamp_color))
+                                                .on_hover_text(expires_at.with_timezone(&chrono::Local).format("Disappears %b %d, %H:%M").to_string());
+                                        }
+                                    });
+                                });
+                            // Right-click a message to reply to it, or one of our own to edit or delete it
+                            if !message.deleted {
+                                let age = chrono::Utc::now() - message.timestamp;
+                                let can_delete = message.is_self && age < chrono::Duration::hours(DELETE_WINDOW_HOURS);
+                                bubble.response.clone().interact(egui::Sense::click()).context_menu(|ui| {
+                                    ui.horizontal(|ui| {
+                                        for emoji in QUICK_REACTIONS {
+                                            let already_reacted = message.reactions.iter().any(|reaction| reaction.emoji == emoji && reaction.by_self);
+                                            if ui.selectable_label(already_reacted, egui::RichText::new(emoji).size(16.0)).clicked() {
+                                                toggle_reaction(&message.id, emoji, gui_to_daemon_tx, rt);
+                                                ui.close_menu();
+                                            }
+                                        }
                                     });
+                                    ui.separator();
+                                    if ui.button("↩ Reply").clicked() {
+                                        start_reply = Some(message.clone());
+                                        ui.close_menu();
+                                    }
+                                    let is_pinned = message.pinned_at.is_some();
+                                    if ui.button(if is_pinned { "📌 Unpin" } else { "📌 Pin for everyone" }).clicked() {
+                                        send_command(GuiToDaemonCommand::SetPinned { message_id: message.id.clone(), pinned: !is_pinned }, gui_to_daemon_tx, rt);
+                                        ui.close_menu();
+                                    }
+                                    if ui.button(if message.starred { "⭐ Remove star" } else { "⭐ Star" }).clicked() {
+                                        send_command(GuiToDaemonCommand::SetStarred { message_id: message.id.clone(), starred: !message.starred }, gui_to_daemon_tx, rt);
+                                        ui.close_menu();
+                                    }
+                                    if can_edit(message) && ui.button("✏ Edit").clicked() {
+                                        start_editing = Some(message.clone());
+                                        ui.close_menu();
+                                    }
+                                    if can_delete && ui.button("🗑 Delete for everyone").clicked() {
+                                        send_command(GuiToDaemonCommand::DeleteMessage { message_id: message.id.clone() }, gui_to_daemon_tx, rt);
+                                        ui.close_menu();
+                                    }
                                 });
+                            }
+                            if !message.reactions.is_empty() && !message.deleted {
+                                show_reaction_chips(ui, message, timestamp_color, gui_to_daemon_tx, rt);
+                            }
+                            if let Some(jump) = message_jump.as_mut().filter(|_| is_jump_target) {
+                                if !jump.scrolled {
+                                    bubble.response.scroll_to_me(Some(egui::Align::Center));
+                                    jump.scrolled = true;
+                                    jump.highlight_until = Some(ui.input(|i| i.time) + 2.0);
+                                }
+                            }
                         }
                     );
                 });
@@ -169,11 +472,163 @@ pub fn show(
             ui.add_space(12.0);
         });
 
+    let message_input_id = egui::Id::new(MESSAGE_INPUT_ID);
+    let input_has_focus = ui.memory(|m| m.has_focus(message_input_id));
+    let now = ui.input(|i| i.time);
+    // Up in the empty input opens our last sent message for editing
+    if start_editing.is_none() && start_reply.is_none() && view_state.editing.is_none() && message_input.is_empty()
+        && input_has_focus && keybindings.consume(ui.ctx(), Action::EditLastMessage)
+    {
+        match messages.iter().rev().find(|message| message.is_self && !message.deleted) {
+            Some(message) if can_edit(message) => start_editing = Some(message.clone()),
+            Some(_) => *slash_hint = Some(format!("Your last message can't be edited any more (only for {} minutes, and not files).", EDIT_WINDOW_MINUTES)),
+            None => *slash_hint = Some("You haven't sent anything here yet.".to_string()),
+        }
+    }
+    if let Some(message) = start_reply {
+        if view_state.editing.take().is_some() {
+            message_input.clear(); // The input held the text being edited
+        }
+        view_state.replying_to = Some(ReplyTarget {
+            conversation_id: conversation_id(&message).to_string(),
+            sender: if message.is_self { "You".to_string() } else { message.sender.clone() },
+            preview: quote_text(&message.content),
+            message_id: message.id,
+        });
+        ui.memory_mut(|m| m.request_focus(message_input_id));
+    }
+    if let Some(parent_id) = open_parent {
+        let mut jump = MessageJump::new(parent_id.clone());
+        if !messages_by_id.contains_key(parent_id.as_str()) {
+            // Not loaded (older than the history we have): ask the daemon for the messages around it
+            if let Some(peer_id) = current_chat_peer_id.clone() {
+                send_command(
+                    GuiToDaemonCommand::RequestHistory { peer_id, since_timestamp: None, around_message_id: Some(parent_id) },
+                    gui_to_daemon_tx,
+                    rt
+                );
+            }
+            jump.give_up_at = Some(now + REPLY_JUMP_TIMEOUT_SECS);
+        }
+        *message_jump = Some(jump);
+    }
+    if let Some(message) = start_editing {
+        view_state.replying_to = None;
+        *message_input = message.content.clone();
+        view_state.editing = Some(EditingMessage {
+            conversation_id: conversation_id(&message).to_string(),
+            message_id: message.id,
+            original_content: message.content,
+        });
+        continue_typing(ui, message_input, message_input_id); // Cursor after the text
+    }
+    if view_state.editing.is_some() && ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+        view_state.editing = None;
+        message_input.clear();
+    }
+    if view_state.replying_to.is_some() && ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+        view_state.replying_to = None;
+    }
+
+    // The highlight fades out a couple of seconds after the jump
+    if message_jump.as_ref().and_then(|jump| jump.highlight_until).is_some_and(|until| now > until) {
+        *message_jump = None;
+    }
+    // A quoted message the daemon couldn't find (deleted from our history, or sent before we joined the room)
+    if message_jump.as_ref().filter(|jump| !jump.scrolled).and_then(|jump| jump.give_up_at).is_some_and(|until| now > until) {
+        *message_jump = None;
+        *slash_hint = Some("The original message isn't available here.".to_string());
+    }
+
+    // Slash command or @mention autocompletion (or the last hint) floats just above the input area
+    let completions = input_completions(message_input, &view_state.mention_candidates);
+    let popup_id = egui::Id::new("slash_command_popup");
+    let mut selected_completion = ui.data(|d| d.get_temp::<usize>(popup_id)).unwrap_or(0);
+    let mut accepted_completion = None;
+    if completions.is_empty() {
+        selected_completion = 0;
+    } else {
+        // Consumed before the TextEdit sees them, so arrows/Tab drive the popup instead of the cursor
+        if ui.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown)) {
+            selected_completion += 1;
+        }
+        if ui.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp)) {
+            selected_completion = selected_completion.checked_sub(1).unwrap_or(completions.len() - 1);
+        }
+        selected_completion %= completions.len();
+        if ui.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::Tab)) {
+            accepted_completion = Some(selected_completion);
+        }
+    }
+    ui.data_mut(|d| d.insert_temp(popup_id, selected_completion));
+    // Taken before the input sees it, so sending doesn't also add a line break; the new-line shortcut is the input's return key
+    let send_pressed = input_has_focus && keybindings.consume(ui.ctx(), Action::SendMessage);
+
+    if !completions.is_empty() || slash_hint.is_some() || view_state.editing.is_some() || view_state.replying_to.is_some() {
+        egui::Area::new(popup_id)
+            .order(egui::Order::Foreground)
+            .pivot(egui::Align2::LEFT_BOTTOM)
+            .fixed_pos(input_area_rect.left_top() + egui::vec2(10.0, -4.0))
+            .show(ui.ctx(), |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.set_max_width((input_area_rect.width() - 20.0).max(200.0));
+                    if !completions.is_empty() {
+                        for (index, completion) in completions.iter().enumerate() {
+                            let row = ui.horizontal(|ui| {
+                                let label = egui::RichText::new(&completion.label).monospace().color(accent_color.gamma_multiply(1.4));
+                                ui.selectable_label(index == selected_completion, label).clicked()
+                                    | ui.label(egui::RichText::new(completion.description).size(12.0).color(egui::Color32::from_rgb(170, 170, 180))).clicked()
+                            });
+                            if row.inner {
+                                accepted_completion = Some(index);
+                            }
+                        }
+                        ui.label(egui::RichText::new("Tab to complete · ↑/↓ to choose").size(10.0).color(egui::Color32::from_rgb(130, 130, 140)));
+                    } else if view_state.editing.is_some() {
+                        let mut cancelled = false;
+                        ui.horizontal(|ui| {
+                            ui.label(egui::RichText::new(format!("✏ Editing message · {} to save · Esc to cancel", keybindings.describe(Action::SendMessage))).size(12.0).color(egui::Color32::from_rgb(220, 220, 230)));
+                            cancelled = ui.small_button("✕").clicked();
+                        });
+                        if cancelled {
+                            view_state.editing = None;
+                            message_input.clear();
+                        }
+                    } else if let Some(reply) = view_state.replying_to.as_ref() {
+                        let mut cancelled = false;
+                        ui.horizontal(|ui| {
+                            ui.label(egui::RichText::new(format!("↩ Replying to {}: {} · Esc to cancel", reply.sender, reply.preview))
+                                .size(12.0)
+                                .color(egui::Color32::from_rgb(220, 220, 230)));
+                            cancelled = ui.small_button("✕").clicked();
+                        });
+                        if cancelled {
+                            view_state.replying_to = None;
+                        }
+                    } else if let Some(hint) = slash_hint.as_ref() {
+                        let mut dismissed = false;
+                        ui.horizontal(|ui| {
+                            ui.label(egui::RichText::new(hint.as_str()).size(12.0).color(egui::Color32::from_rgb(220, 220, 230)));
+                            dismissed = ui.small_button("✕").clicked();
+                        });
+                        if dismissed {
+                            *slash_hint = None;
+                        }
+                    }
+                });
+            });
+    }
+
+    if let Some(index) = accepted_completion {
+        *message_input = completions[index].completed_input.clone();
+        continue_typing(ui, message_input, message_input_id);
+    }
+
     // Then, render the input area with simpler layout to ensure controls are visible
     ui.allocate_ui_at_rect(input_area_rect, |ui| {
         ui.horizontal_centered(|ui| {
-            // Reserve fixed space for the button on the right
-            let button_area_width = button_size + 16.0;
+            // Reserve fixed space for the buttons on the right
+            let button_area_width = 3.0 * button_size + 32.0;
             
             // Define a color that matches the application background
             let input_common_color = egui::Color32::from_rgb(18, 20, 24); // Darker to match app background
@@ -190,20 +645,129 @@ pub fn show(
                 .stroke(egui::Stroke::new(1.0, input_common_color)) // Use the same color for stroke
                 .inner_margin(egui::vec2(12.0, 6.0))
                 .show(ui, |ui| {
-                    // Clean text edit with custom styling
-                    ui.add_sized(
-                        [input_width - 24.0, input_height - 12.0],
-                        egui::TextEdit::singleline(message_input)
-                            .hint_text("Type a message...")
-                            .text_color(egui::Color32::from_rgb(240, 240, 240))
-                            .margin(egui::vec2(4.0, 8.0))
-                            .background_color(input_common_color) // Use the common color variable
-                            .frame(false) // Disable the default frame/border completely
-                    );
+                    // Clean text edit with custom styling; past MAX_INPUT_LINES it scrolls
+                    egui::ScrollArea::vertical()
+                        .id_salt("chat_message_input_scroll")
+                        .max_height(input_row_height * MAX_INPUT_LINES + 16.0) // + the text edit's margins
+                        .stick_to_bottom(true)
+                        .show(ui, |ui| {
+                            let output = egui::TextEdit::multiline(message_input)
+                                .id(message_input_id)
+                                .desired_rows(1)
+                                .desired_width(input_width - 24.0)
+                                .return_key(keybindings.shortcut(Action::InsertNewline))
+                                .lock_focus(!completions.is_empty()) // Tab completes instead of moving focus
+                                .hint_text(format!("Type a message... (/ for commands, {} for a new line)", keybindings.describe(Action::InsertNewline)))
+                                .text_color(egui::Color32::from_rgb(240, 240, 240))
+                                .margin(egui::vec2(4.0, 8.0))
+                                .background_color(input_common_color) // Use the common color variable
+                                .frame(false) // Disable the default frame/border completely
+                                .show(ui);
+                            let text_height = output.galley.size().y.max(input_row_height);
+                            if text_height != input_text_height {
+                                ui.data_mut(|d| d.insert_temp(input_text_height_id, text_height));
+                                ui.ctx().request_repaint(); // Resize the input area right away
+                            }
+                        });
                 });
             
             // Empty space between text input and button
             ui.add_space(8.0);
+
+            // Disappearing-messages timer of the open conversation; changing it changes it for everyone in it
+            let timer = current_chat_peer_id.as_ref().and_then(|id| view_state.conversation_timers.get(id).copied().flatten());
+            let timer_button = ui.add_enabled(
+                current_chat_peer_id.is_some(),
+                egui::Button::new(egui::RichText::new("⏱").size(16.0).color(egui::Color32::WHITE))
+                    .fill(if timer.is_some() { accent_color } else { egui::Color32::from_rgb(45, 45, 50) })
+                    .corner_radius(button_size / 2.0)
+                    .stroke(egui::Stroke::NONE)
+                    .min_size(egui::vec2(button_size, button_size))
+            ).on_hover_text(match timer {
+                Some(secs) => format!("New messages disappear after {}", slash_commands::describe_duration(secs)),
+                None => "Disappearing messages: off".to_string(),
+            });
+            let timer_popup_id = ui.make_persistent_id("timer_popup");
+            if timer_button.clicked() {
+                ui.memory_mut(|m| m.toggle_popup(timer_popup_id));
+            }
+            egui::popup::popup_above_or_below_widget(
+                ui,
+                timer_popup_id,
+                &timer_button,
+                egui::AboveOrBelow::Above,
+                egui::PopupCloseBehavior::CloseOnClick,
+                |ui| {
+                    ui.set_min_width(140.0);
+                    ui.label(egui::RichText::new("Disappearing messages").size(12.0).strong());
+                    for (expiry_secs, label) in TIMER_CHOICES {
+                        if ui.selectable_label(timer == expiry_secs, label).clicked() {
+                            if let Some(conversation_id) = current_chat_peer_id.clone() {
+                                send_command(GuiToDaemonCommand::SetExpiry { conversation_id, expiry_secs }, gui_to_daemon_tx, rt);
+                            }
+                        }
+                    }
+                }
+            );
+            ui.add_space(8.0);
+
+            // Sends what's typed at a later time; /later does the same from the keyboard
+            let schedule_button = ui.add_enabled(
+                current_chat_peer_id.is_some() && view_state.editing.is_none(),
+                egui::Button::new(egui::RichText::new("🕓").size(16.0).color(egui::Color32::WHITE))
+                    .fill(egui::Color32::from_rgb(45, 45, 50))
+                    .corner_radius(button_size / 2.0)
+                    .stroke(egui::Stroke::NONE)
+                    .min_size(egui::vec2(button_size, button_size))
+            ).on_hover_text("Send later");
+            let schedule_popup_id = ui.make_persistent_id("schedule_popup");
+            if schedule_button.clicked() {
+                ui.memory_mut(|m| m.toggle_popup(schedule_popup_id));
+            }
+            let mut picked_send_at = None;
+            egui::popup::popup_above_or_below_widget(
+                ui,
+                schedule_popup_id,
+                &schedule_button,
+                egui::AboveOrBelow::Above,
+                egui::PopupCloseBehavior::CloseOnClickOutside, // The time field needs clicks
+                |ui| {
+                    ui.set_min_width(180.0);
+                    ui.label(egui::RichText::new("Send later").size(12.0).strong());
+                    for (label, when) in SCHEDULE_CHOICES {
+                        if ui.selectable_label(false, label).clicked() {
+                            picked_send_at = Some(slash_commands::parse_send_time(when));
+                        }
+                    }
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        let time_field = ui.add(
+                            egui::TextEdit::singleline(&mut view_state.schedule_time_input)
+                                .desired_width(110.0)
+                                .hint_text("17:00")
+                        );
+                        // Enter here schedules; it must not also send the message right away
+                        let entered = time_field.lost_focus() && ui.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::Enter));
+                        if ui.small_button("Schedule").clicked() || entered {
+                            picked_send_at = Some(slash_commands::parse_send_time(&view_state.schedule_time_input));
+                        }
+                    });
+                }
+            );
+            if let Some(picked_send_at) = picked_send_at {
+                match (picked_send_at, slash_commands::parse(message_input), current_chat_peer_id.clone()) {
+                    (Err(help), _, _) => *slash_hint = Some(help),
+                    (Ok(send_at), ParsedInput::Text(content), Some(recipient_id)) if !content.is_empty() => {
+                        send_command(GuiToDaemonCommand::ScheduleMessage { recipient_id, content, send_at }, gui_to_daemon_tx, rt);
+                        *slash_hint = Some(format!("Scheduled for {}.", slash_commands::describe_send_time(send_at)));
+                        message_input.clear();
+                        view_state.schedule_time_input.clear();
+                        ui.memory_mut(|m| m.close_popup());
+                    }
+                    _ => *slash_hint = Some("Type the message to send later first (commands can't be scheduled).".to_string()),
+                }
+            }
+            ui.add_space(8.0);
             
             // Modern send button with paper airplane icon
             let send_button = ui.add_sized(
@@ -216,71 +780,466 @@ pub fn show(
                 .stroke(egui::Stroke::NONE)
             );
             
-            // Check if enter was pressed
-            let enter_pressed = ui.input(|i| i.key_pressed(egui::Key::Enter));
-            
-            if send_button.clicked() || enter_pressed {
-                send_message(message_input, current_user_id, current_chat_peer_id, gui_to_daemon_tx, rt, messages);
+            if let Some(editing) = view_state.editing.as_ref().filter(|_| send_button.clicked() || send_pressed) {
+                // Editing takes the text as it is; slash commands don't apply
+                let new_content = message_input.trim().to_string();
+                if new_content.is_empty() {
+                    *slash_hint = Some("Use \"Delete for everyone\" to remove a message.".to_string());
+                } else {
+                    if new_content != editing.original_content {
+                        send_command(
+                            GuiToDaemonCommand::EditMessage { message_id: editing.message_id.clone(), content: new_content },
+                            gui_to_daemon_tx,
+                            rt
+                        );
+                    }
+                    view_state.editing = None;
+                    message_input.clear();
+                }
+            } else if send_button.clicked() || send_pressed {
+                // Enter on a half-typed command or name picks the highlighted completion instead of sending
+                if let Some(completion) = completions.get(selected_completion).filter(|completion| !completion.is_complete) {
+                    *message_input = completion.completed_input.clone();
+                    continue_typing(ui, message_input, message_input_id);
+                } else {
+                    match slash_commands::parse(message_input) {
+                        ParsedInput::Text(text) => {
+                            if text.is_empty() {
+                                message_input.clear(); // Also clear if it was just whitespace
+                            } else {
+                                let reply_to = view_state.replying_to.as_ref().map(|reply| reply.message_id.clone());
+                                if send_text(text, reply_to, current_chat_peer_id, gui_to_daemon_tx, rt) {
+                                    message_input.clear(); // Clear input field once the message is on its way
+                                    view_state.replying_to = None;
+                                    *slash_hint = None;
+                                }
+                            }
+                        }
+                        ParsedInput::Command(command) => {
+                            message_input.clear();
+                            slash_command = Some(command);
+                        }
+                        ParsedInput::Invalid(help) => {
+                            // Keep the input so a typo can be fixed
+                            *slash_hint = Some(help);
+                        }
+                    }
+                }
             }
             
             ui.add_space(10.0); // Right margin
         });
     });
+
+    slash_command
 }
 
-// Helper function to send a message
-fn send_message(
-    message_input: &mut String,
-    current_user_id: &str,
-    current_chat_peer_id: &Option<String>,
+// Our own text messages can be edited for a while (the daemon checks again)
+fn can_edit(message: &Message) -> bool {
+    let age = chrono::Utc::now() - message.timestamp;
+    message.is_self && message.attachment.is_none() && age < chrono::Duration::minutes(EDIT_WINDOW_MINUTES)
+}
+
+// One entry in the autocompletion popup
+struct InputCompletion {
+    label: String,
+    description: &'static str,
+    completed_input: String, // The whole input once this is picked
+    is_complete: bool, // The input already spells it out, so Enter sends instead of completing
+}
+
+// Completions for what is being typed: a slash command name, or a "@name" at the end of the input
+fn input_completions(input: &str, mention_candidates: &[String]) -> Vec<InputCompletion> {
+    let typed_command = input.trim_start_matches('/');
+    let command_completions: Vec<InputCompletion> = slash_commands::completions(input).into_iter()
+        .map(|spec| InputCompletion {
+            label: spec.usage.to_string(),
+            description: spec.description,
+            completed_input: format!("/{} ", spec.name),
+            is_complete: spec.name == typed_command,
+        })
+        .collect();
+    if !command_completions.is_empty() {
+        return command_completions;
+    }
+
+    // Names may contain spaces, so everything after the last '@' counts as the partial name
+    let Some(at_index) = input.rfind('@') else {
+        return Vec::new();
+    };
+    let partial_name = &input[at_index + 1..];
+    let at_word_start = input[..at_index].chars().next_back().is_none_or(char::is_whitespace);
+    if !at_word_start || partial_name.contains('\n') {
+        return Vec::new();
+    }
+    let partial_name_lowercase = partial_name.to_lowercase();
+    let mut names: Vec<&String> = mention_candidates.iter()
+        .filter(|name| name.to_lowercase().starts_with(&partial_name_lowercase))
+        .collect();
+    names.sort_by_key(|name| name.to_lowercase());
+    names.dedup();
+    names.into_iter()
+        .take(MAX_MENTION_COMPLETIONS)
+        .map(|name| InputCompletion {
+            label: format!("@{}", name),
+            description: "",
+            completed_input: format!("{}@{} ", &input[..at_index], name),
+            is_complete: name.to_lowercase() == partial_name_lowercase,
+        })
+        .collect()
+}
+
+// After a completion: cursor at the end of the input, focus back in it
+fn continue_typing(ui: &mut egui::Ui, message_input: &str, message_input_id: egui::Id) {
+    if let Some(mut state) = egui::TextEdit::load_state(ui.ctx(), message_input_id) {
+        let end_of_input = egui::text::CCursor::new(message_input.chars().count());
+        state.cursor.set_char_range(Some(egui::text::CCursorRange::one(end_of_input)));
+        state.store(ui.ctx(), message_input_id);
+    }
+    ui.memory_mut(|m| m.request_focus(message_input_id));
+}
+
+// The quoted parent at the top of a reply bubble; click it to scroll to the original.
+// `parent` is the original if it's loaded; otherwise the preview the sender attached stands in for it.
+fn show_reply_quote(
+    ui: &mut egui::Ui,
+    parent: Option<&Message>,
+    preview: Option<&crate::ReplyPreview>,
+    text_color: egui::Color32,
+    secondary_color: egui::Color32
+) -> egui::Response {
+    let (sender, text) = match (parent, preview) {
+        (Some(parent), _) if parent.deleted => (None, "Message deleted".to_string()),
+        (Some(parent), _) => (Some(if parent.is_self { "You".to_string() } else { parent.sender.clone() }), quote_text(&parent.content)),
+        (None, Some(preview)) => (Some(preview.sender.clone()), quote_text(&preview.content)),
+        (None, None) => (None, "Original message not available".to_string()),
+    };
+    let quote = egui::Frame::new()
+        .fill(egui::Color32::from_black_alpha(40))
+        .stroke(egui::Stroke::new(1.0, secondary_color.gamma_multiply(0.5)))
+        .corner_radius(6.0)
+        .inner_margin(egui::vec2(8.0, 4.0))
+        .show(ui, |ui| {
+            if let Some(sender) = sender {
+                ui.label(egui::RichText::new(sender).size(11.0).strong().color(text_color));
+                ui.label(egui::RichText::new(text).size(12.0).color(secondary_color));
+            } else {
+                ui.label(egui::RichText::new(text).size(12.0).italics().color(secondary_color));
+            }
+        });
+    let response = quote.response.interact(egui::Sense::click());
+    if response.hovered() {
+        ui.ctx().set_cursor_icon(egui::CursorIcon::PointingHand);
+    }
+    ui.add_space(4.0);
+    response.on_hover_text("Show original message")
+}
+
+// The strip of pinned messages above the message list: the latest pin, or all of them when expanded.
+// Returns the id of a pin the user clicked, to scroll to it.
+fn show_pinned_strip(
+    ui: &mut egui::Ui,
+    pinned: &[Message],
+    expanded: &mut bool,
     gui_to_daemon_tx: &Option<mpsc::Sender<GuiToDaemonCommand>>,
-    rt: &Arc<tokio::runtime::Runtime>,
-    messages: &mut Vec<Message>
-) {
-    if !message_input.trim().is_empty() {
-        if let Some(recipient_id) = current_chat_peer_id.as_ref() {
-            if !recipient_id.is_empty() { // Ensure a peer is actually selected
-                let content_to_send = message_input.trim().to_string();
-                message_input.clear(); // Clear input field immediately
-
-                let command = GuiToDaemonCommand::SendMessage {
-                    recipient_id: recipient_id.clone(),
-                    content: content_to_send.clone(), // Use cloned content
-                };
+    rt: &Arc<tokio::runtime::Runtime>
+) -> Option<String> {
+    let strip_color = egui::Color32::from_rgb(30, 30, 34);
+    let text_color = egui::Color32::from_rgb(220, 220, 220);
+    let subtle_color = egui::Color32::from_rgb(150, 150, 160);
+    let mut clicked_pin = None;
+    egui::Frame::new()
+        .fill(strip_color)
+        .corner_radius(8.0)
+        .inner_margin(egui::vec2(12.0, 6.0))
+        .show(ui, |ui| {
+            ui.set_width(ui.available_width());
+            let shown = if *expanded { pinned } else { &pinned[..1] };
+            egui::ScrollArea::vertical()
+                .max_height(150.0)
+                .id_salt("pinned_strip_scroll_area")
+                .show(ui, |ui| {
+                    for (index, message) in shown.iter().enumerate() {
+                        ui.horizontal(|ui| {
+                            ui.label(egui::RichText::new("📌").size(12.0).color(subtle_color));
+                            // Leave room on the right for the unpin button (and the expand toggle on the first row)
+                            let reserved_width = if index == 0 && pinned.len() > 1 { 110.0 } else { 30.0 };
+                            let sender = if message.is_self { "You".to_string() } else { message.sender.clone() };
+                            let pinned_by = message.pinned_by.as_deref().map_or_else(String::new, |id| crate::display_name(id, &[]));
+                            ui.scope(|ui| {
+                                ui.set_max_width((ui.available_width() - reserved_width).max(40.0));
+                                let text = egui::RichText::new(format!("{}: {}", sender, quote_text(&message.content))).size(12.0).color(text_color);
+                                let row = ui.add(egui::Label::new(text).truncate().sense(egui::Sense::click()))
+                                    .on_hover_text(format!("Pinned by {} · click to show the message", pinned_by));
+                                if row.hovered() {
+                                    ui.ctx().set_cursor_icon(egui::CursorIcon::PointingHand);
+                                }
+                                if row.clicked() {
+                                    clicked_pin = Some(message.id.clone());
+                                }
+                            });
+                            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                                if ui.small_button("✕").on_hover_text("Unpin for everyone").clicked() {
+                                    send_command(GuiToDaemonCommand::SetPinned { message_id: message.id.clone(), pinned: false }, gui_to_daemon_tx, rt);
+                                }
+                                if index == 0 && pinned.len() > 1 {
+                                    let toggle_text = if *expanded { "▴ Less".to_string() } else { format!("▾ {} pinned", pinned.len()) };
+                                    if ui.small_button(toggle_text).clicked() {
+                                        *expanded = !*expanded;
+                                    }
+                                }
+                            });
+                        });
+                    }
+                });
+        });
+    ui.add_space(4.0);
+    clicked_pin
+}
 
-                // Add to local messages immediately with is_self = true
-                let new_message = Message {
-                    id: uuid::Uuid::new_v4().to_string(), // Generate unique ID
-                    sender: current_user_id.to_string(),    // Current user is the sender
-                    recipient: recipient_id.clone(),
-                    content: content_to_send.clone(), // Use cloned content
-                    timestamp: chrono::Utc::now(),
-                    is_self: true, // This message is from the current user
-                };
-                messages.push(new_message);
-                println!("GUI: Locally added self-message to chat area. Content: {}", content_to_send);
-
-                if let Some(tx) = gui_to_daemon_tx {
-                    let tx_clone = tx.clone();
-                    // let rt_clone = rt.clone(); // rt is Arc, cloning it like this is fine, or just use rt directly
-                    rt.spawn(async move {
-                        println!("GUI: Sending SendMessage command to daemon for content: {}", content_to_send);
-                        if let Err(e) = tx_clone.send(command).await {
-                            eprintln!("Failed to send SendMessage command: {}", e);
-                            // Optionally, update the local message to indicate failure or provide a retry mechanism
+// The messages scheduled for the open conversation, next first. Editing happens in place:
+// the time and text of the one being edited turn into fields.
+fn show_scheduled_strip(
+    ui: &mut egui::Ui,
+    scheduled: &[&ScheduledMessage],
+    editing: &mut Option<ScheduledEdit>,
+    gui_to_daemon_tx: &Option<mpsc::Sender<GuiToDaemonCommand>>,
+    rt: &Arc<tokio::runtime::Runtime>
+) {
+    let strip_color = egui::Color32::from_rgb(30, 30, 34);
+    let text_color = egui::Color32::from_rgb(220, 220, 220);
+    let subtle_color = egui::Color32::from_rgb(150, 150, 160);
+    let warning_color = egui::Color32::from_rgb(230, 170, 60);
+    // Sent or cancelled (maybe from another client) while it was open
+    if editing.as_ref().is_some_and(|edit| !scheduled.iter().any(|message| message.id == edit.scheduled_id)) {
+        *editing = None;
+    }
+    let mut start_editing = None;
+    let mut stop_editing = false;
+    egui::Frame::new()
+        .fill(strip_color)
+        .corner_radius(8.0)
+        .inner_margin(egui::vec2(12.0, 6.0))
+        .show(ui, |ui| {
+            ui.set_width(ui.available_width());
+            egui::ScrollArea::vertical()
+                .max_height(150.0)
+                .id_salt("scheduled_strip_scroll_area")
+                .show(ui, |ui| {
+                    for message in scheduled {
+                        if let Some(edit) = editing.as_mut().filter(|edit| edit.scheduled_id == message.id) {
+                            ui.horizontal(|ui| {
+                                ui.label(egui::RichText::new("🕓").size(12.0).color(subtle_color));
+                                let time_field = ui.add(
+                                    egui::TextEdit::singleline(&mut edit.time_text)
+                                        .desired_width(120.0)
+                                        .hint_text("17:00, 2h, tomorrow 9:00")
+                                );
+                                let content_field = ui.add(
+                                    egui::TextEdit::singleline(&mut edit.content).desired_width((ui.available_width() - 110.0).max(80.0))
+                                );
+                                // Enter saves; it must not also send what's in the chat input
+                                let entered = (time_field.lost_focus() || content_field.lost_focus())
+                                    && ui.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::Enter));
+                                if ui.small_button("Save").clicked() || entered {
+                                    let send_at = if edit.time_text == edit.original_time_text {
+                                        Ok(None)
+                                    } else {
+                                        slash_commands::parse_send_time(&edit.time_text).map(Some)
+                                    };
+                                    match send_at {
+                                        Ok(_) if edit.content.trim().is_empty() => {
+                                            edit.error = Some("Use ✕ to cancel a scheduled message.".to_string());
+                                        }
+                                        Ok(send_at) => {
+                                            send_command(
+                                                GuiToDaemonCommand::EditScheduled {
+                                                    scheduled_id: edit.scheduled_id.clone(),
+                                                    content: Some(edit.content.trim().to_string()),
+                                                    send_at,
+                                                },
+                                                gui_to_daemon_tx,
+                                                rt
+                                            );
+                                            stop_editing = true;
+                                        }
+                                        Err(help) => edit.error = Some(help),
+                                    }
+                                }
+                                if ui.small_button("Cancel").clicked() {
+                                    stop_editing = true;
+                                }
+                            });
+                            if let Some(error) = edit.error.as_ref() {
+                                ui.label(egui::RichText::new(error).size(11.0).color(warning_color));
+                            }
+                            continue;
                         }
-                    });
-                } else {
-                    eprintln!("Error: gui_to_daemon_tx is None, cannot send message.");
-                    // Message is already added locally, but won't be sent. Consider visual indication.
+                        ui.horizontal(|ui| {
+                            ui.label(egui::RichText::new("🕓").size(12.0).color(subtle_color));
+                            ui.scope(|ui| {
+                                ui.set_max_width((ui.available_width() - 80.0).max(40.0)); // Room for the buttons
+                                let when = slash_commands::describe_send_time(message.send_at);
+                                let text = egui::RichText::new(format!("{} · {}", when, quote_text(&message.content))).size(12.0).color(text_color);
+                                ui.add(egui::Label::new(text).truncate()).on_hover_text(&message.content);
+                            });
+                            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                                if ui.small_button("✕").on_hover_text("Cancel this message").clicked() {
+                                    send_command(GuiToDaemonCommand::CancelScheduled { scheduled_id: message.id.clone() }, gui_to_daemon_tx, rt);
+                                }
+                                if ui.small_button("✏").on_hover_text("Change the text or time").clicked() {
+                                    start_editing = Some(ScheduledEdit::of(message));
+                                }
+                                if let Some(last_error) = message.last_error.as_ref() {
+                                    ui.label(egui::RichText::new("⚠").size(12.0).color(warning_color))
+                                        .on_hover_text(format!("Not sent yet: {} The daemon will try again.", last_error));
+                                } else if message.send_at <= chrono::Utc::now() {
+                                    ui.label(egui::RichText::new("⏳").size(12.0).color(subtle_color))
+                                        .on_hover_text("Waiting for the recipient to come online");
+                                }
+                            });
+                        });
+                    }
+                });
+        });
+    if stop_editing {
+        *editing = None;
+    }
+    if start_editing.is_some() {
+        *editing = start_editing;
+    }
+    ui.add_space(4.0);
+}
+
+// One chip per emoji under a bubble, with the count; ours are highlighted and clicking a chip toggles our reaction
+fn show_reaction_chips(
+    ui: &mut egui::Ui,
+    message: &Message,
+    text_color: egui::Color32,
+    gui_to_daemon_tx: &Option<mpsc::Sender<GuiToDaemonCommand>>,
+    rt: &Arc<tokio::runtime::Runtime>
+) {
+    ui.add_space(2.0);
+    ui.horizontal_wrapped(|ui| {
+        ui.spacing_mut().item_spacing.x = 4.0;
+        for reaction in &message.reactions {
+            let label = egui::RichText::new(format!("{} {}", reaction.emoji, reaction.reactors.len())).size(12.0).color(text_color);
+            let reactor_names: Vec<String> = reaction.reactors.iter().map(|reactor| crate::display_name(reactor, &[])).collect();
+            let chip = ui.add(egui::Button::new(label).selected(reaction.by_self).corner_radius(10.0).small())
+                .on_hover_text(reactor_names.join(", "));
+            if chip.clicked() {
+                toggle_reaction(&message.id, &reaction.emoji, gui_to_daemon_tx, rt);
+            }
+        }
+    });
+}
+
+fn toggle_reaction(
+    message_id: &str,
+    emoji: &str,
+    gui_to_daemon_tx: &Option<mpsc::Sender<GuiToDaemonCommand>>,
+    rt: &Arc<tokio::runtime::Runtime>
+) {
+    send_command(
+        GuiToDaemonCommand::ToggleReaction { message_id: message_id.to_string(), emoji: emoji.to_string() },
+        gui_to_daemon_tx,
+        rt
+    );
+}
+
+// First line of a quoted message, cut to fit in a bubble
+fn quote_text(content: &str) -> String {
+    let first_line = content.lines().next().unwrap_or_default();
+    let mut text: String = first_line.chars().take(80).collect();
+    if text.len() < content.len() {
+        text.push('…');
+    }
+    text
+}
+
+// File name line with size and an "Open" button under an attachment message
+fn show_attachment(ui: &mut egui::Ui, attachment: &Attachment, secondary_color: egui::Color32) {
+    ui.horizontal(|ui| {
+        ui.label(
+            egui::RichText::new(format!("{} · {}", attachment.file_name, format_file_size(attachment.size_bytes)))
+                .size(11.0)
+                .color(secondary_color)
+        );
+        match attachment.local_path {
+            Some(ref local_path) => {
+                if ui.small_button("Open").on_hover_text(local_path).clicked() {
+                    ui.ctx().open_url(egui::OpenUrl::new_tab(format!("file://{}", local_path)));
                 }
-            } else {
-                println!("GUI: SendMessage attempted but no recipient_id (peer not selected or empty).");
             }
-        } else {
-            println!("GUI: SendMessage attempted but no peer selected (current_chat_peer_id is None).");
+            None => {
+                ui.label(egui::RichText::new("(not saved)").size(11.0).italics().color(secondary_color));
+            }
         }
+    });
+}
+
+// Width of show_attachment's line, so the bubble can fit it
+fn attachment_width(ui: &egui::Ui, attachment: &Attachment) -> f32 {
+    let text = format!("{} · {}", attachment.file_name, format_file_size(attachment.size_bytes));
+    let text_width = ui.fonts(|fonts| fonts.layout_no_wrap(text, egui::FontId::proportional(11.0), egui::Color32::WHITE).size().x);
+    text_width + 60.0 // The "Open" button
+}
+
+fn format_file_size(size_bytes: u64) -> String {
+    match size_bytes {
+        0..=1023 => format!("{} B", size_bytes),
+        1024..=1_048_575 => format!("{:.1} KB", size_bytes as f64 / 1024.0),
+        _ => format!("{:.1} MB", size_bytes as f64 / (1024.0 * 1024.0)),
+    }
+}
+
+// Hands a command to the daemon connection task
+fn send_command(
+    command: GuiToDaemonCommand,
+    gui_to_daemon_tx: &Option<mpsc::Sender<GuiToDaemonCommand>>,
+    rt: &Arc<tokio::runtime::Runtime>
+) {
+    let Some(tx) = gui_to_daemon_tx.clone() else {
+        eprintln!("Error: gui_to_daemon_tx is None, cannot send {:?}.", command);
+        return;
+    };
+    rt.spawn(async move {
+        if let Err(e) = tx.send(command).await {
+            eprintln!("Failed to send command to daemon: {}", e);
+        }
+    });
+}
+
+// Sends a text message to the open conversation. It shows up once the daemon echoes it back as sent.
+// Returns false (and sends nothing) when no peer or room is selected.
+pub fn send_text(
+    content_to_send: String,
+    reply_to: Option<String>, // Message this one answers, in the same conversation
+    current_chat_peer_id: &Option<String>,
+    gui_to_daemon_tx: &Option<mpsc::Sender<GuiToDaemonCommand>>,
+    rt: &Arc<tokio::runtime::Runtime>
+) -> bool {
+    let Some(recipient_id) = current_chat_peer_id.as_ref().filter(|id| !id.is_empty()) else {
+        println!("GUI: SendMessage attempted but no peer selected (current_chat_peer_id is None).");
+        return false;
+    };
+
+    let command = GuiToDaemonCommand::SendMessage {
+        recipient_id: recipient_id.clone(),
+        content: content_to_send.clone(), // Use cloned content
+        reply_to,
+    };
+
+    if let Some(tx) = gui_to_daemon_tx {
+        let tx_clone = tx.clone();
+        rt.spawn(async move {
+            println!("GUI: Sending SendMessage command to daemon ({} bytes)", content_to_send.len());
+            if let Err(e) = tx_clone.send(command).await {
+                eprintln!("Failed to send SendMessage command: {}", e);
+            }
+        });
     } else {
-        message_input.clear(); // Also clear if it was just whitespace
+        eprintln!("Error: gui_to_daemon_tx is None, cannot send message.");
     }
-} 
\ No newline at end of file
+    true
+}
@@ -1,7 +1,389 @@
 use eframe::egui;
+use chrono::{Datelike, NaiveDate, TimeZone};
+use std::collections::HashMap;
+use crate::{display_name, ConversationSummary, IpcPeer, Message, SearchHit};
 
-// Placeholder for history UI
-pub fn show(ui: &mut egui::Ui) {
-    ui.label("History Panel Content");
-    // TODO: Implement history display
-} 
\ No newline at end of file
+// What the user picked in the History panel; ChatApp carries it out (it owns the daemon connection and the Chat panel)
+pub enum HistoryAction {
+    OpenConversation(String),
+    OpenMessage(Box<Message>), // A search hit or starred message: open its conversation scrolled to it
+    Search {
+        query: String,
+        after: Option<chrono::DateTime<chrono::Utc>>,
+        before: Option<chrono::DateTime<chrono::Utc>>,
+        offset: u32, // Hits already shown; non-zero for "Load more"
+    },
+    LoadStarred, // The Starred list was opened; ask the daemon for it
+    Unstar(String), // Message id
+}
+
+// The search as sent to the daemon, so results can be matched to the inputs that produced them
+#[derive(Clone, PartialEq)]
+struct SearchKey {
+    query: String,
+    after: Option<chrono::DateTime<chrono::Utc>>,
+    before: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+pub struct HistoryState {
+    search_query: String,
+    use_date_range: bool,
+    from_date: NaiveDate,
+    to_date: NaiveDate,
+    requested_search: Option<SearchKey>, // Last search sent to the daemon
+    results: Vec<SearchHit>, // Hits for requested_search, best match first
+    has_more_results: bool, // The daemon has another page
+    is_searching: bool,
+    showing_starred: bool, // The Starred list instead of conversations and search
+    starred: Vec<Message>, // Most recently starred first, from the daemon
+    starred_loaded: bool,
+}
+
+impl HistoryState {
+    pub fn new() -> Self {
+        let today = chrono::Local::now().date_naive();
+        Self {
+            search_query: String::new(),
+            use_date_range: false,
+            from_date: today.with_day(1).unwrap_or(today), // Start of this month
+            to_date: today,
+            requested_search: None,
+            results: Vec::new(),
+            has_more_results: false,
+            is_searching: false,
+            showing_starred: false,
+            starred: Vec::new(),
+            starred_loaded: false,
+        }
+    }
+
+    // Called with the daemon's StarredMessages, sent on request and after every star change
+    pub fn set_starred(&mut self, messages: Vec<Message>) {
+        self.starred = messages;
+        self.starred_loaded = true;
+    }
+
+    // Drops messages the daemon purged (disappearing messages) from the Starred list and the search results
+    pub fn remove_messages(&mut self, message_ids: &[String]) {
+        self.starred.retain(|message| !message_ids.contains(&message.id));
+        self.results.retain(|hit| !message_ids.contains(&hit.message.id));
+    }
+
+    // Keeps the Starred list in step with MessageUpdated (an edit, or a deleted message losing its star)
+    pub fn update_starred(&mut self, message: &Message) {
+        if message.deleted || !message.starred {
+            self.starred.retain(|m| m.id != message.id);
+        } else if let Some(existing) = self.starred.iter_mut().find(|m| m.id == message.id) {
+            *existing = message.clone();
+        }
+    }
+
+    // Called with the daemon's SearchResults; results for an older query (or a page we didn't ask for) are dropped
+    pub fn set_results(&mut self, query: &str, offset: usize, hits: Vec<SearchHit>, has_more: bool) {
+        if self.requested_search.as_ref().is_some_and(|search| search.query == query) && offset == self.results.len() {
+            self.results.extend(hits);
+            self.has_more_results = has_more;
+            self.is_searching = false;
+        }
+    }
+
+    // The search the inputs describe; None when there is nothing to search for (the conversation list is shown)
+    fn current_search(&self) -> Option<SearchKey> {
+        let query = self.search_query.trim().to_string();
+        let (after, before) = if self.use_date_range {
+            // Whole local days, from the start of `from_date` to the end of `to_date`
+            (local_midnight_utc(self.from_date), self.to_date.succ_opt().and_then(local_midnight_utc))
+        } else {
+            (None, None)
+        };
+        if query.is_empty() && after.is_none() && before.is_none() {
+            None
+        } else {
+            Some(SearchKey { query, after, before })
+        }
+    }
+}
+
+fn local_midnight_utc(date: NaiveDate) -> Option<chrono::DateTime<chrono::Utc>> {
+    let midnight = date.and_hms_opt(0, 0, 0)?;
+    chrono::Local.from_local_datetime(&midnight).earliest().map(|local| local.with_timezone(&chrono::Utc))
+}
+
+pub fn show(
+    ui: &mut egui::Ui,
+    state: &mut HistoryState,
+    conversation_summaries: &HashMap<String, ConversationSummary>,
+    peers: &[IpcPeer],
+) -> Option<HistoryAction> {
+    let mut action = None;
+    let label_color = egui::Color32::from_rgb(220, 220, 220);
+    let subtle_color = egui::Color32::from_rgb(150, 150, 160);
+    let hover_color = egui::Color32::from_rgb(35, 35, 40);
+    let match_color = egui::Color32::from_rgb(255, 200, 60); // Search matches in results
+    let colors = RowColors { hover_color, label_color, subtle_color };
+
+    ui.add_space(12.0);
+    ui.heading(egui::RichText::new("History").size(18.0).color(label_color).strong());
+    ui.add_space(8.0);
+
+    ui.horizontal(|ui| {
+        ui.selectable_value(&mut state.showing_starred, false, "Conversations");
+        if ui.selectable_value(&mut state.showing_starred, true, "⭐ Starred").clicked() {
+            action = Some(HistoryAction::LoadStarred); // Refreshed on every visit; stars may have come from other clients
+        }
+    });
+    ui.add_space(8.0);
+    if state.showing_starred {
+        ui.separator();
+        return show_starred(ui, state, peers, &colors).or(action);
+    }
+
+    // --- Search box and date range ---
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::TextEdit::singleline(&mut state.search_query)
+                .hint_text("Search messages and names…")
+                .desired_width(280.0)
+        );
+        if !state.search_query.is_empty() && ui.small_button("✕").on_hover_text("Clear search").clicked() {
+            state.search_query.clear();
+        }
+        ui.add_space(12.0);
+        ui.checkbox(&mut state.use_date_range, "Date range");
+        if state.use_date_range {
+            ui.add(egui_extras::DatePickerButton::new(&mut state.from_date).id_salt("history_from_date"));
+            ui.label("to");
+            ui.add(egui_extras::DatePickerButton::new(&mut state.to_date).id_salt("history_to_date"));
+            if state.to_date < state.from_date {
+                state.to_date = state.from_date; // Keep the range the right way round
+            }
+        }
+    });
+    ui.add_space(8.0);
+    ui.separator();
+
+    // Any change to the inputs runs the search again; the daemon answers from its local database
+    let current_search = state.current_search();
+    if current_search != state.requested_search {
+        state.requested_search = current_search.clone();
+        state.results.clear();
+        state.has_more_results = false;
+        if let Some(search) = current_search {
+            state.is_searching = true;
+            action = Some(HistoryAction::Search { query: search.query, after: search.after, before: search.before, offset: 0 });
+        }
+    }
+
+    egui::ScrollArea::vertical()
+        .auto_shrink([false, false])
+        .id_salt("history_scroll_area")
+        .show(ui, |ui| {
+            ui.add_space(4.0);
+            match state.requested_search.as_ref() {
+                // --- No search: every stored conversation, most recent first ---
+                None => {
+                    let mut summaries: Vec<&ConversationSummary> = conversation_summaries.values().collect();
+                    summaries.sort_by_key(|summary| std::cmp::Reverse(summary.last_activity));
+                    if summaries.is_empty() {
+                        empty_state(ui, "No conversations yet", subtle_color);
+                    }
+                    for summary in summaries {
+                        let preview_sender = if summary.last_message_is_self {
+                            "You".to_string()
+                        } else {
+                            display_name(&summary.last_message_sender, peers)
+                        };
+                        let preview = format!("{}: {}", preview_sender, preview_text(&summary.last_message_preview));
+                        let response = history_row(
+                            ui,
+                            &summary.conversation_id,
+                            &display_name(&summary.conversation_id, peers),
+                            &format_timestamp(summary.last_activity),
+                            egui::text::LayoutJob::simple_singleline(preview, egui::FontId::proportional(12.0), subtle_color),
+                            &colors,
+                        );
+                        if response.clicked() {
+                            action = Some(HistoryAction::OpenConversation(summary.conversation_id.clone()));
+                        }
+                    }
+                }
+                // --- Search results ---
+                Some(search) => {
+                    if state.results.is_empty() {
+                        if state.is_searching {
+                            ui.horizontal(|ui| {
+                                ui.spinner();
+                                ui.label(egui::RichText::new("Searching…").color(subtle_color));
+                            });
+                        } else {
+                            empty_state(ui, "No matching messages", subtle_color);
+                        }
+                    }
+                    for hit in &state.results {
+                        let message = &hit.message;
+                        let sender = if message.is_self { "You".to_string() } else { display_name(&message.sender, peers) };
+                        let mut title = display_name(&hit.conversation_id, peers);
+                        if crate::is_room_id(&hit.conversation_id) || message.is_self {
+                            title = format!("{} · {}", title, sender);
+                        }
+                        let snippet = highlighted_job(&hit.snippet, &hit.highlights, label_color, match_color);
+                        let response = history_row(
+                            ui,
+                            &message.id,
+                            &title,
+                            &format_timestamp(message.timestamp),
+                            snippet,
+                            &colors,
+                        );
+                        if response.clicked() {
+                            action = Some(HistoryAction::OpenMessage(Box::new(message.clone())));
+                        }
+                    }
+                    if state.has_more_results {
+                        ui.add_space(6.0);
+                        ui.vertical_centered(|ui| {
+                            if state.is_searching {
+                                ui.spinner();
+                            } else if ui.button("Load more").clicked() {
+                                state.is_searching = true;
+                                action = Some(HistoryAction::Search {
+                                    query: search.query.clone(),
+                                    after: search.after,
+                                    before: search.before,
+                                    offset: state.results.len() as u32,
+                                });
+                            }
+                        });
+                    }
+                }
+            }
+            ui.add_space(12.0);
+        });
+
+    action
+}
+
+// Starred messages from every conversation, most recently starred first; right-click one to remove its star
+fn show_starred(ui: &mut egui::Ui, state: &HistoryState, peers: &[IpcPeer], colors: &RowColors) -> Option<HistoryAction> {
+    let mut action = None;
+    egui::ScrollArea::vertical()
+        .auto_shrink([false, false])
+        .id_salt("starred_scroll_area")
+        .show(ui, |ui| {
+            ui.add_space(4.0);
+            if state.starred.is_empty() {
+                if state.starred_loaded {
+                    empty_state(ui, "No starred messages. Right-click a message to star it.", colors.subtle_color);
+                } else {
+                    ui.spinner();
+                }
+            }
+            for message in &state.starred {
+                let conversation = crate::conversation_id(message);
+                let sender = if message.is_self { "You".to_string() } else { display_name(&message.sender, peers) };
+                let mut title = display_name(conversation, peers);
+                if crate::is_room_id(conversation) || message.is_self {
+                    title = format!("{} · {}", title, sender);
+                }
+                let body = egui::text::LayoutJob::simple_singleline(
+                    preview_text(&message.content),
+                    egui::FontId::proportional(13.0),
+                    colors.label_color
+                );
+                let response = history_row(ui, &message.id, &title, &format_timestamp(message.timestamp), body, colors);
+                if response.clicked() {
+                    action = Some(HistoryAction::OpenMessage(Box::new(message.clone())));
+                }
+                response.context_menu(|ui| {
+                    if ui.button("⭐ Remove star").clicked() {
+                        action = Some(HistoryAction::Unstar(message.id.clone()));
+                        ui.close_menu();
+                    }
+                });
+            }
+            ui.add_space(12.0);
+        });
+    action
+}
+
+fn empty_state(ui: &mut egui::Ui, text: &str, color: egui::Color32) {
+    ui.vertical_centered(|ui| {
+        ui.add_space(40.0);
+        ui.label(egui::RichText::new(text).size(14.0).color(color).italics());
+    });
+}
+
+struct RowColors {
+    hover_color: egui::Color32,
+    label_color: egui::Color32,
+    subtle_color: egui::Color32,
+}
+
+// One clickable line: title and time on top, preview (or highlighted snippet) below
+fn history_row(
+    ui: &mut egui::Ui,
+    id: &str,
+    title: &str,
+    timestamp: &str,
+    body: egui::text::LayoutJob,
+    colors: &RowColors,
+) -> egui::Response {
+    let item_response = ui.interact(ui.available_rect_before_wrap(), egui::Id::new(id).with("history_item"), egui::Sense::click());
+    let background_fill = if item_response.hovered() { colors.hover_color } else { egui::Color32::TRANSPARENT };
+    egui::Frame::new()
+        .fill(background_fill)
+        .corner_radius(8.0)
+        .inner_margin(egui::vec2(12.0, 8.0))
+        .show(ui, |ui| {
+            ui.set_width(ui.available_width());
+            ui.horizontal(|ui| {
+                ui.label(egui::RichText::new(title).size(14.0).color(colors.label_color).strong());
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    ui.label(egui::RichText::new(timestamp).size(11.0).color(colors.subtle_color));
+                });
+            });
+            ui.label(body);
+        });
+    if item_response.hovered() {
+        ui.ctx().set_cursor_icon(egui::CursorIcon::PointingHand);
+    }
+    item_response
+}
+
+// First line only, cut to a readable length
+fn preview_text(content: &str) -> String {
+    let first_line = content.lines().next().unwrap_or_default();
+    let mut preview: String = first_line.chars().take(100).collect();
+    if preview.len() < content.len() {
+        preview.push('…');
+    }
+    preview
+}
+
+fn format_timestamp(timestamp: chrono::DateTime<chrono::Utc>) -> String {
+    let local_time = timestamp.with_timezone(&chrono::Local);
+    if local_time.date_naive() == chrono::Local::now().date_naive() {
+        local_time.format("%H:%M").to_string()
+    } else {
+        local_time.format("%b %d, %Y %H:%M").to_string()
+    }
+}
+
+// `text` with the given byte ranges (matched words, from the daemon) drawn in `match_color`
+fn highlighted_job(text: &str, highlights: &[(usize, usize)], text_color: egui::Color32, match_color: egui::Color32) -> egui::text::LayoutJob {
+    let normal = egui::TextFormat { font_id: egui::FontId::proportional(13.0), color: text_color, ..Default::default() };
+    let highlighted = egui::TextFormat { color: match_color, underline: egui::Stroke::new(1.0, match_color), ..normal.clone() };
+    let mut job = egui::text::LayoutJob::default();
+    let mut position = 0;
+    for &(start, end) in highlights {
+        // Skip ranges that don't fit the text rather than panic on a bad slice
+        let (Some(before), Some(matched)) = (text.get(position..start), text.get(start..end)) else {
+            continue;
+        };
+        job.append(before, 0.0, normal.clone());
+        job.append(matched, 0.0, highlighted.clone());
+        position = end;
+    }
+    job.append(text.get(position..).unwrap_or_default(), 0.0, normal);
+    job
+}
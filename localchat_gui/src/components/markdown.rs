@@ -0,0 +1,379 @@
+use eframe::egui;
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag, TagEnd};
+
+// Renders the markdown subset we support in message bubbles: bold, italic, strikethrough, inline code,
+// fenced code blocks (with a copy button), lists, quotes and links. Anything else shows as plain text.
+// Single newlines are kept as line breaks, since that's what people mean when they type them in a chat.
+
+// Colors and sizes for one bubble
+pub struct MarkdownStyle {
+    pub text_color: egui::Color32,
+    pub secondary_color: egui::Color32, // Code block captions, quote bars, list markers
+    pub link_color: egui::Color32,
+    pub mention_color: egui::Color32,
+    pub mention_names: Vec<String>, // Usernames the message @mentions; "@name" is highlighted
+    pub text_size: f32,
+}
+
+const CODE_SIZE: f32 = 12.5;
+const CODE_BLOCK_MARGIN: f32 = 8.0;
+
+enum Block {
+    Paragraph(Vec<Inline>),
+    Heading(Vec<Inline>),
+    Code { language: Option<String>, code: String },
+    List { start: Option<u64>, items: Vec<Vec<Block>> },
+    Quote(Vec<Block>),
+    Rule,
+}
+
+#[derive(Clone, Copy, Default)]
+struct InlineStyle {
+    strong: bool,
+    emphasis: bool,
+    strikethrough: bool,
+    code: bool,
+    mention: bool,
+}
+
+enum Inline {
+    Text { text: String, style: InlineStyle, link: Option<String> },
+    LineBreak,
+}
+
+// Blocks being filled while parsing; the outermost is the message itself
+enum Container {
+    Root(Vec<Block>),
+    Quote(Vec<Block>),
+    List { start: Option<u64>, items: Vec<Vec<Block>> },
+    Item(Vec<Block>),
+}
+
+impl Container {
+    fn blocks_mut(&mut self) -> Option<&mut Vec<Block>> {
+        match self {
+            Container::Root(blocks) | Container::Quote(blocks) | Container::Item(blocks) => Some(blocks),
+            Container::List { .. } => None,
+        }
+    }
+}
+
+struct BlockBuilder<'a> {
+    containers: Vec<Container>,
+    inlines: Vec<Inline>, // Text of the paragraph/heading/list item being read
+    style: InlineStyle,
+    links: Vec<String>, // Destinations of the links we're inside
+    code_block: Option<(Option<String>, String)>, // Language and text of an open code block
+    mention_names: &'a [String],
+}
+
+impl BlockBuilder<'_> {
+    fn push_block(&mut self, block: Block) {
+        if let Some(blocks) = self.containers.last_mut().and_then(Container::blocks_mut) {
+            blocks.push(block);
+        }
+    }
+
+    // Tight list items have no paragraph around their text, so loose text is flushed before any block starts
+    fn flush_paragraph(&mut self) {
+        if !self.inlines.is_empty() {
+            let inlines = std::mem::take(&mut self.inlines);
+            self.push_block(Block::Paragraph(inlines));
+        }
+    }
+
+    fn push_text(&mut self, text: &str) {
+        let link = self.links.last().cloned();
+        let style = self.style;
+        if link.is_some() || style.code {
+            self.inlines.push(Inline::Text { text: text.to_string(), style, link });
+            return;
+        }
+        // Bare URLs become links too; markdown only links <https://...> and [text](url)
+        for span in linkify::LinkFinder::new().kinds(&[linkify::LinkKind::Url]).spans(text) {
+            if span.kind().is_some() {
+                self.inlines.push(Inline::Text { text: span.as_str().to_string(), style, link: Some(span.as_str().to_string()) });
+                continue;
+            }
+            for (part, is_mention) in split_mentions(span.as_str(), self.mention_names) {
+                let style = InlineStyle { mention: is_mention, ..style };
+                self.inlines.push(Inline::Text { text: part.to_string(), style, link: None });
+            }
+        }
+    }
+}
+
+// `text` cut into plain parts and "@name" parts (true) for the given names; the longest name wins,
+// matched case-insensitively up to a word boundary like the daemon does
+fn split_mentions<'t>(text: &'t str, names: &[String]) -> Vec<(&'t str, bool)> {
+    let mut parts = Vec::new();
+    let mut plain_start = 0;
+    let mut search_from = 0;
+    while let Some(offset) = text[search_from..].find('@') {
+        let at_index = search_from + offset;
+        search_from = at_index + 1;
+        if text[..at_index].chars().next_back().is_some_and(|c| c.is_alphanumeric()) {
+            continue;
+        }
+        let rest = &text[at_index + 1..];
+        let matched_len = names.iter()
+            .filter_map(|name| mention_length(rest, name))
+            .max();
+        if let Some(name_len) = matched_len {
+            let mention_end = at_index + 1 + name_len;
+            if plain_start < at_index {
+                parts.push((&text[plain_start..at_index], false));
+            }
+            parts.push((&text[at_index..mention_end], true));
+            plain_start = mention_end;
+            search_from = mention_end;
+        }
+    }
+    if plain_start < text.len() {
+        parts.push((&text[plain_start..], false));
+    }
+    parts
+}
+
+// Byte length of `name` at the start of `text`, if it's there as a whole word
+fn mention_length(text: &str, name: &str) -> Option<usize> {
+    if name.is_empty() {
+        return None;
+    }
+    let name_char_count = name.chars().count();
+    let end = text.char_indices().nth(name_char_count).map_or(text.len(), |(index, _)| index);
+    let candidate = &text[..end];
+    let next_char = text[end..].chars().next();
+    let is_match = candidate.chars().count() == name_char_count
+        && candidate.to_lowercase() == name.to_lowercase()
+        && !next_char.is_some_and(|c| c.is_alphanumeric() || c == '_');
+    is_match.then_some(end)
+}
+
+fn parse(content: &str, mention_names: &[String]) -> Vec<Block> {
+    let mut builder = BlockBuilder {
+        containers: vec![Container::Root(Vec::new())],
+        inlines: Vec::new(),
+        style: InlineStyle::default(),
+        links: Vec::new(),
+        code_block: None,
+        mention_names,
+    };
+    for event in Parser::new_ext(content, Options::ENABLE_STRIKETHROUGH) {
+        match event {
+            Event::Start(Tag::Paragraph) | Event::Start(Tag::Heading { .. }) => builder.flush_paragraph(),
+            Event::End(TagEnd::Paragraph) => builder.flush_paragraph(),
+            Event::End(TagEnd::Heading(_)) => {
+                let inlines = std::mem::take(&mut builder.inlines);
+                builder.push_block(Block::Heading(inlines));
+            }
+            Event::Start(Tag::CodeBlock(kind)) => {
+                builder.flush_paragraph();
+                let language = match kind {
+                    CodeBlockKind::Fenced(language) if !language.is_empty() => Some(language.to_string()),
+                    _ => None,
+                };
+                builder.code_block = Some((language, String::new()));
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                if let Some((language, mut code)) = builder.code_block.take() {
+                    code.truncate(code.trim_end().len());
+                    builder.push_block(Block::Code { language, code });
+                }
+            }
+            Event::Start(Tag::BlockQuote(_)) => {
+                builder.flush_paragraph();
+                builder.containers.push(Container::Quote(Vec::new()));
+            }
+            Event::End(TagEnd::BlockQuote(_)) => {
+                builder.flush_paragraph();
+                if let Some(Container::Quote(blocks)) = builder.containers.pop() {
+                    builder.push_block(Block::Quote(blocks));
+                }
+            }
+            Event::Start(Tag::List(start)) => {
+                builder.flush_paragraph();
+                builder.containers.push(Container::List { start, items: Vec::new() });
+            }
+            Event::End(TagEnd::List(_)) => {
+                if let Some(Container::List { start, items }) = builder.containers.pop() {
+                    builder.push_block(Block::List { start, items });
+                }
+            }
+            Event::Start(Tag::Item) => builder.containers.push(Container::Item(Vec::new())),
+            Event::End(TagEnd::Item) => {
+                builder.flush_paragraph();
+                if let Some(Container::Item(blocks)) = builder.containers.pop() {
+                    if let Some(Container::List { items, .. }) = builder.containers.last_mut() {
+                        items.push(blocks);
+                    }
+                }
+            }
+            Event::Start(Tag::Strong) => builder.style.strong = true,
+            Event::End(TagEnd::Strong) => builder.style.strong = false,
+            Event::Start(Tag::Emphasis) => builder.style.emphasis = true,
+            Event::End(TagEnd::Emphasis) => builder.style.emphasis = false,
+            Event::Start(Tag::Strikethrough) => builder.style.strikethrough = true,
+            Event::End(TagEnd::Strikethrough) => builder.style.strikethrough = false,
+            // Images aren't fetched; their alt text links to them instead
+            Event::Start(Tag::Link { dest_url, .. }) | Event::Start(Tag::Image { dest_url, .. }) => builder.links.push(dest_url.to_string()),
+            Event::End(TagEnd::Link) | Event::End(TagEnd::Image) => {
+                builder.links.pop();
+            }
+            Event::Text(text) => match builder.code_block.as_mut() {
+                Some((_, code)) => code.push_str(&text),
+                None => builder.push_text(&text),
+            },
+            Event::Code(code) => {
+                let style = InlineStyle { code: true, ..builder.style };
+                builder.inlines.push(Inline::Text { text: code.to_string(), style, link: builder.links.last().cloned() });
+            }
+            Event::SoftBreak | Event::HardBreak => builder.inlines.push(Inline::LineBreak),
+            Event::Rule => {
+                builder.flush_paragraph();
+                builder.push_block(Block::Rule);
+            }
+            // Raw HTML and the rest are shown as typed
+            Event::Html(text) | Event::InlineHtml(text) => builder.push_text(&text),
+            _ => {}
+        }
+    }
+    builder.flush_paragraph();
+    match builder.containers.into_iter().next() {
+        Some(Container::Root(blocks)) => blocks,
+        _ => Vec::new(),
+    }
+}
+
+pub fn show(ui: &mut egui::Ui, content: &str, style: &MarkdownStyle) {
+    show_blocks(ui, &parse(content, &style.mention_names), style);
+}
+
+fn show_blocks(ui: &mut egui::Ui, blocks: &[Block], style: &MarkdownStyle) {
+    for (index, block) in blocks.iter().enumerate() {
+        if index > 0 {
+            ui.add_space(4.0);
+        }
+        match block {
+            Block::Paragraph(inlines) => show_inlines(ui, inlines, style, style.text_size, false),
+            Block::Heading(inlines) => show_inlines(ui, inlines, style, style.text_size + 1.0, true),
+            Block::Code { language, code } => show_code_block(ui, language.as_deref(), code, style),
+            Block::List { start, items } => {
+                for (item_index, item) in items.iter().enumerate() {
+                    let marker = match start {
+                        Some(first_number) => format!("{}.", first_number + item_index as u64),
+                        None => "•".to_string(),
+                    };
+                    ui.horizontal_top(|ui| {
+                        ui.label(egui::RichText::new(marker).size(style.text_size).color(style.secondary_color));
+                        ui.vertical(|ui| show_blocks(ui, item, style));
+                    });
+                }
+            }
+            Block::Quote(blocks) => {
+                let quote = egui::Frame::new()
+                    .inner_margin(egui::Margin { left: 10, right: 0, top: 0, bottom: 0 })
+                    .show(ui, |ui| show_blocks(ui, blocks, style));
+                let bar = quote.response.rect;
+                ui.painter().line_segment(
+                    [bar.left_top() + egui::vec2(2.0, 0.0), bar.left_bottom() + egui::vec2(2.0, 0.0)],
+                    egui::Stroke::new(3.0, style.secondary_color)
+                );
+            }
+            Block::Rule => {
+                ui.separator();
+            }
+        }
+    }
+}
+
+// One paragraph; labels wrap into each other's lines, and links are separate widgets so they can be clicked
+fn show_inlines(ui: &mut egui::Ui, inlines: &[Inline], style: &MarkdownStyle, size: f32, heading: bool) {
+    ui.horizontal_wrapped(|ui| {
+        ui.spacing_mut().item_spacing = egui::vec2(0.0, 2.0);
+        for inline in inlines {
+            let Inline::Text { text, style: inline_style, link } = inline else {
+                ui.end_row();
+                continue;
+            };
+            let mut rich_text = egui::RichText::new(text).size(size).color(style.text_color);
+            if inline_style.strong || heading {
+                rich_text = rich_text.strong();
+            }
+            if inline_style.emphasis {
+                rich_text = rich_text.italics();
+            }
+            if inline_style.strikethrough {
+                rich_text = rich_text.strikethrough();
+            }
+            if inline_style.code {
+                rich_text = rich_text.code().size(CODE_SIZE);
+            }
+            if inline_style.mention {
+                rich_text = rich_text.strong().color(style.mention_color);
+            }
+            match link {
+                Some(url) if is_openable_link(url) => {
+                    ui.hyperlink_to(rich_text.color(style.link_color).underline(), url);
+                }
+                // Other schemes (file:, javascript:, ...) aren't opened from a chat message
+                Some(url) => {
+                    ui.label(rich_text.underline()).on_hover_text(url);
+                }
+                None => {
+                    ui.label(rich_text);
+                }
+            }
+        }
+    });
+}
+
+fn show_code_block(ui: &mut egui::Ui, language: Option<&str>, code: &str, style: &MarkdownStyle) {
+    egui::Frame::new()
+        .fill(egui::Color32::from_black_alpha(90))
+        .corner_radius(6.0)
+        .inner_margin(CODE_BLOCK_MARGIN)
+        .show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(egui::RichText::new(language.unwrap_or("code")).size(10.0).color(style.secondary_color));
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    if ui.small_button("📋 Copy").clicked() {
+                        ui.ctx().copy_text(code.to_string());
+                    }
+                });
+            });
+            // Code keeps its lines; wide code scrolls instead of wrapping
+            egui::ScrollArea::horizontal().id_salt(ui.next_auto_id()).show(ui, |ui| {
+                ui.add(egui::Label::new(egui::RichText::new(code).monospace().size(CODE_SIZE).color(style.text_color)).extend());
+            });
+        });
+}
+
+fn is_openable_link(url: &str) -> bool {
+    let lowercase_url = url.to_ascii_lowercase();
+    ["http://", "https://", "mailto:"].iter().any(|scheme| lowercase_url.starts_with(scheme))
+}
+
+// Width the content would take without wrapping: its widest line as laid out by egui, with code lines in the
+// code font plus the code block's margins. Markup characters are counted too, which leaves a little slack.
+pub fn content_width(ui: &egui::Ui, content: &str, style: &MarkdownStyle) -> f32 {
+    let text_font = egui::FontId::proportional(style.text_size);
+    let code_font = egui::FontId::monospace(CODE_SIZE);
+    let mut in_code_block = false;
+    ui.fonts(|fonts| {
+        let mut widest: f32 = 0.0;
+        for line in content.lines() {
+            if line.trim_start().starts_with("```") {
+                in_code_block = !in_code_block;
+                continue;
+            }
+            let width = if in_code_block {
+                fonts.layout_no_wrap(line.to_string(), code_font.clone(), style.text_color).size().x + 2.0 * CODE_BLOCK_MARGIN
+            } else {
+                fonts.layout_no_wrap(line.to_string(), text_font.clone(), style.text_color).size().x
+            };
+            widest = widest.max(width);
+        }
+        widest
+    })
+}
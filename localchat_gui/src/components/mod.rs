@@ -1,6 +1,8 @@
 pub mod chat_area;
 pub mod footer;
 pub mod history;
+pub mod markdown;
+pub mod quick_switcher;
 pub mod settings;
 pub mod sidemenu;
 pub mod topnav; 
\ No newline at end of file
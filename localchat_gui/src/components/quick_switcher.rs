@@ -0,0 +1,109 @@
+use eframe::egui;
+use crate::{ConversationSummary, IpcPeer};
+use std::collections::HashMap;
+
+// Rows listed at once; typing narrows the list down
+const MAX_SWITCHER_ROWS: usize = 10;
+
+// The quick switcher (Ctrl+K by default): type part of a name, Enter opens the highlighted conversation
+pub struct QuickSwitcherState {
+    pub open: bool,
+    query: String,
+    selected: usize,
+    focus_requested: bool, // The filter field takes focus the first frame it's shown
+}
+
+impl QuickSwitcherState {
+    pub fn new() -> Self {
+        Self { open: false, query: String::new(), selected: 0, focus_requested: false }
+    }
+
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+        self.query.clear();
+        self.selected = 0;
+        self.focus_requested = false;
+    }
+}
+
+// Shows the switcher if it is open; returns the conversation picked (it closes again then, or on Esc)
+pub fn show(
+    ctx: &egui::Context,
+    state: &mut QuickSwitcherState,
+    peers: &[IpcPeer],
+    rooms: &[String],
+    conversation_summaries: &HashMap<String, ConversationSummary>,
+) -> Option<String> {
+    if !state.open {
+        return None;
+    }
+    // (conversation ID, name shown), most recent conversation first like the sidebar, then by name
+    let query = state.query.trim().to_lowercase();
+    let mut candidates: Vec<(&str, &str)> = rooms.iter().map(|room| (room.as_str(), room.as_str()))
+        .chain(peers.iter().map(|peer| (peer.id.as_str(), peer.username.as_str())))
+        .filter(|(_, name)| name.to_lowercase().contains(&query))
+        .collect();
+    candidates.sort_by(|(a_id, a_name), (b_id, b_name)| {
+        let a_activity = conversation_summaries.get(*a_id).map(|summary| summary.last_activity);
+        let b_activity = conversation_summaries.get(*b_id).map(|summary| summary.last_activity);
+        b_activity.cmp(&a_activity).then_with(|| a_name.to_lowercase().cmp(&b_name.to_lowercase()))
+    });
+    candidates.truncate(MAX_SWITCHER_ROWS);
+
+    // Consumed before the filter field sees them
+    let mut picked = None;
+    ctx.input_mut(|i| {
+        if i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown) {
+            state.selected += 1;
+        }
+        if i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp) {
+            state.selected = state.selected.saturating_sub(1);
+        }
+        if i.consume_key(egui::Modifiers::NONE, egui::Key::Enter) {
+            picked = candidates.get(state.selected.min(candidates.len().saturating_sub(1))).map(|(id, _)| id.to_string());
+        }
+        if i.consume_key(egui::Modifiers::NONE, egui::Key::Escape) {
+            state.open = false;
+        }
+    });
+    state.selected = state.selected.min(candidates.len().saturating_sub(1));
+
+    egui::Window::new("Switch conversation")
+        .title_bar(false)
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 80.0))
+        .fixed_size(egui::vec2(320.0, 0.0))
+        .show(ctx, |ui| {
+            let filter = ui.add(
+                egui::TextEdit::singleline(&mut state.query)
+                    .hint_text("Jump to a peer or room…")
+                    .desired_width(f32::INFINITY)
+            );
+            if filter.changed() {
+                state.selected = 0;
+            }
+            if !state.focus_requested {
+                filter.request_focus();
+                state.focus_requested = true;
+            }
+            ui.add_space(4.0);
+            if candidates.is_empty() {
+                ui.label(egui::RichText::new("No peer or room matches").size(12.0).italics().color(egui::Color32::from_rgb(150, 150, 160)));
+            }
+            for (index, (id, name)) in candidates.iter().enumerate() {
+                let unread = conversation_summaries.get(*id).map_or(0, |summary| summary.unread_count);
+                let text = if unread > 0 { format!("{}  ({} unread)", name, unread) } else { name.to_string() };
+                let prefix = if crate::is_room_id(id) { "" } else { "👤 " };
+                if ui.selectable_label(index == state.selected, format!("{}{}", prefix, text)).clicked() {
+                    picked = Some(id.to_string());
+                }
+            }
+            ui.label(egui::RichText::new("↑/↓ to choose · Enter to open · Esc to close").size(10.0).color(egui::Color32::from_rgb(130, 130, 140)));
+        });
+
+    if picked.is_some() {
+        state.open = false;
+    }
+    picked
+}
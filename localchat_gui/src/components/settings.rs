@@ -3,7 +3,11 @@ use std::sync::Arc;
 use std::path::PathBuf;
 use tokio::runtime::Runtime;
 use tokio::sync::mpsc;
-use crate::{GuiToDaemonCommand, IpcPeer, SettingsState};
+use crate::{DaemonInfo, GuiToDaemonCommand, IpcPeer, SettingsState};
+use crate::daemon_control::{DaemonAction, DaemonControl};
+use crate::keybindings::{self, Action, Keybindings};
+use crate::notifications::{MuteChange, NotificationSettings};
+use chrono::Timelike;
 
 pub fn show(
     ui: &mut egui::Ui,
@@ -128,4 +132,210 @@ pub fn show(
             });
         }
     }
-} 
\ No newline at end of file
+}
+
+// The keyboard shortcut table: click a shortcut, then press the new one. Returns true when a shortcut changed,
+// for ChatApp to save the settings.
+pub fn show_keybindings(ui: &mut egui::Ui, settings_state: &mut SettingsState, keybindings: &mut Keybindings) -> bool {
+    let mut changed = false;
+    ui.separator();
+    ui.add_space(10.0);
+    ui.heading("Keyboard Shortcuts");
+    ui.add_space(10.0);
+
+    // The first key pressed (with its modifiers) while waiting becomes the action's shortcut; Esc gives up
+    if let Some(action) = settings_state.recording_shortcut {
+        let pressed = ui.input_mut(|i| {
+            let index = i.events.iter().position(|event| keybindings::shortcut_from_event(event).is_some())?;
+            keybindings::shortcut_from_event(&i.events.remove(index))
+        });
+        if let Some(shortcut) = pressed {
+            if shortcut.logical_key == egui::Key::Escape && shortcut.modifiers.is_none() {
+                settings_state.recording_shortcut = None;
+            } else {
+                match keybindings::check_shortcut(&shortcut) {
+                    Ok(()) => {
+                        keybindings.set(action, shortcut);
+                        settings_state.recording_shortcut = None;
+                        settings_state.shortcut_error = None;
+                        changed = true;
+                    }
+                    Err(e) => settings_state.shortcut_error = Some(e),
+                }
+            }
+        }
+    }
+
+    egui::Grid::new("keybindings_grid").num_columns(3).spacing([16.0, 6.0]).show(ui, |ui| {
+        for action in Action::ALL {
+            ui.label(action.label());
+            let recording = settings_state.recording_shortcut == Some(action);
+            let shortcut_text = if recording { "Press the new shortcut… (Esc to cancel)".to_string() } else { keybindings.describe(action) };
+            if ui.selectable_label(recording, egui::RichText::new(shortcut_text).monospace()).clicked() {
+                settings_state.recording_shortcut = if recording { None } else { Some(action) };
+                settings_state.shortcut_error = None;
+            }
+            match keybindings.conflict(action) {
+                Some(other) => ui.colored_label(egui::Color32::from_rgb(255, 190, 80), format!("⚠ Also \"{}\"", other.label())),
+                None => ui.label(""),
+            };
+            ui.end_row();
+        }
+    });
+    if let Some(error) = settings_state.shortcut_error.as_ref() {
+        ui.colored_label(egui::Color32::from_rgb(255, 120, 120), error);
+    }
+    ui.add_space(6.0);
+    if ui.button("Reset Shortcuts to Defaults").clicked() {
+        *keybindings = Keybindings::default();
+        settings_state.recording_shortcut = None;
+        settings_state.shortcut_error = None;
+        changed = true;
+    }
+    changed
+}
+
+// Do-not-disturb, quiet hours, hiding message text, and the muted conversations (muted with /mute or from the
+// sidebar). Returns true when something changed, for ChatApp to save the settings.
+pub fn show_notifications(ui: &mut egui::Ui, notifications: &mut NotificationSettings, peers: &[IpcPeer]) -> bool {
+    let mut changed = false;
+    ui.separator();
+    ui.add_space(10.0);
+    ui.heading("Notifications");
+    ui.add_space(10.0);
+
+    changed |= ui.checkbox(&mut notifications.do_not_disturb, "Do not disturb (no notifications at all, not even mentions)").changed();
+    ui.horizontal(|ui| {
+        changed |= ui.checkbox(&mut notifications.quiet_hours_enabled, "Quiet hours from").changed();
+        ui.add_enabled_ui(notifications.quiet_hours_enabled, |ui| {
+            changed |= time_picker(ui, &mut notifications.quiet_hours_start);
+            ui.label("to");
+            changed |= time_picker(ui, &mut notifications.quiet_hours_end);
+        });
+    });
+    changed |= ui.checkbox(&mut notifications.hide_message_text, "Hide message text in notifications").changed();
+    ui.label(
+        egui::RichText::new("Messages in the conversation you're looking at never pop up.")
+            .size(12.0)
+            .color(egui::Color32::from_rgb(160, 160, 180))
+    );
+
+    ui.add_space(10.0);
+    ui.label("Muted conversations:");
+    let now = chrono::Utc::now();
+    let muted: Vec<(String, String)> = notifications.muted.keys()
+        .filter_map(|conversation| notifications.describe_mute(conversation, now).map(|mute| (conversation.clone(), mute)))
+        .collect();
+    if muted.is_empty() {
+        ui.label(egui::RichText::new("None. Right-click a peer or room in the sidebar, or type /mute, to mute it.").size(12.0).italics());
+    }
+    for (conversation, mute) in muted {
+        ui.horizontal(|ui| {
+            ui.label(format!("{} · {}", crate::display_name(&conversation, peers), mute));
+            if ui.small_button("Unmute").clicked() {
+                notifications.change_mute(&conversation, MuteChange::Off, now);
+                changed = true;
+            }
+        });
+    }
+    changed
+}
+
+// The daemon this GUI talks to: whether it's answering, its version, PID and uptime, Start/Stop/Restart, and whether
+// quitting the GUI stops it. Returns the button clicked, for ChatApp to carry out.
+pub fn show_daemon(
+    ui: &mut egui::Ui,
+    daemon: &DaemonControl,
+    info: Option<&(DaemonInfo, std::time::Instant)>,
+    connected: bool,
+    stop_daemon_on_quit: &mut bool,
+) -> Option<DaemonAction> {
+    let mut action = None;
+    ui.separator();
+    ui.add_space(10.0);
+    ui.heading("Daemon");
+    ui.add_space(10.0);
+
+    let hint_color = egui::Color32::from_rgb(160, 160, 180);
+    // Answers older than this mean it stopped answering, though the connection is still open
+    let stale_after = crate::DAEMON_INFO_INTERVAL * 2;
+    if daemon.is_restarting() {
+        ui.horizontal(|ui| {
+            ui.spinner();
+            ui.label("Restarting…");
+        });
+    } else if !connected {
+        ui.colored_label(egui::Color32::from_rgb(220, 120, 100), format!("● Not connected ({})", daemon.socket_path()));
+    } else {
+        match info {
+            Some((info, received_at)) if received_at.elapsed() < stale_after => {
+                ui.colored_label(egui::Color32::from_rgb(100, 200, 120), "● Running and responding");
+                let uptime = chrono::Utc::now().signed_duration_since(info.started_at);
+                ui.label(format!("Version {} · PID {} · up {}", info.version, info.pid, format_uptime(uptime)));
+                ui.label(format!("{} peer(s) discovered · {} client(s) attached", info.peers, info.ipc_clients));
+                ui.label(egui::RichText::new(format!("Socket: {}", info.socket_path)).size(12.0).color(hint_color));
+            }
+            Some(_) => {
+                ui.colored_label(egui::Color32::from_rgb(230, 180, 80), "● Connected, but not responding");
+            }
+            None => {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label("Asking the daemon…");
+                });
+            }
+        }
+    }
+    let origin = if daemon.started_here() { "Started by this GUI." } else { "Already running when this GUI started (or started elsewhere)." };
+    ui.label(egui::RichText::new(origin).size(12.0).color(hint_color));
+    if let Some(error) = &daemon.last_error {
+        ui.colored_label(egui::Color32::from_rgb(220, 120, 100), error);
+    }
+
+    ui.add_space(6.0);
+    ui.horizontal(|ui| {
+        let busy = daemon.is_restarting();
+        if ui.add_enabled(connected && !busy, egui::Button::new("Restart Daemon")).clicked() {
+            action = Some(DaemonAction::Restart);
+        }
+        if ui.add_enabled(connected && !busy, egui::Button::new("Stop Daemon")).clicked() {
+            action = Some(DaemonAction::Stop);
+        }
+        if ui.add_enabled(!connected && !busy, egui::Button::new("Start Daemon")).clicked() {
+            action = Some(DaemonAction::Start);
+        }
+    });
+    ui.add_space(6.0);
+    ui.checkbox(stop_daemon_on_quit, "Stop the daemon when quitting the GUI");
+    ui.label(
+        egui::RichText::new("Left running, it keeps receiving messages and sending scheduled ones, and the GUI attaches to it next time.")
+            .size(12.0)
+            .color(hint_color)
+    );
+    action
+}
+
+// "3d 4h", "2h 05m", "12m"
+fn format_uptime(uptime: chrono::TimeDelta) -> String {
+    let minutes = uptime.num_minutes().max(0);
+    let (days, hours, minutes) = (minutes / (24 * 60), minutes / 60 % 24, minutes % 60);
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {:02}m", hours, minutes)
+    } else {
+        format!("{}m", minutes)
+    }
+}
+
+// Hour and minute fields for a local time of day
+fn time_picker(ui: &mut egui::Ui, time: &mut chrono::NaiveTime) -> bool {
+    let mut hour = time.hour();
+    let mut minute = time.minute();
+    let changed = ui.add(egui::DragValue::new(&mut hour).range(0..=23).custom_formatter(|n, _| format!("{:02}", n as u32))).changed()
+        | ui.add(egui::DragValue::new(&mut minute).range(0..=59).speed(0.5).custom_formatter(|n, _| format!(":{:02}", n as u32))).changed();
+    if changed {
+        *time = chrono::NaiveTime::from_hms_opt(hour, minute, 0).unwrap_or(*time);
+    }
+    changed
+}
@@ -1,17 +1,31 @@
 use eframe::egui;
-use crate::{IpcPeer, GuiToDaemonCommand}; // Assuming IpcPeer is in crate root (main.rs)
+use crate::{ConversationSummary, IpcPeer, GuiToDaemonCommand}; // Assuming IpcPeer is in crate root (main.rs)
+use crate::notifications::{MuteChange, NotificationSettings};
 use tokio::sync::mpsc; // For Sender type
+use std::collections::HashMap;
 use std::sync::Arc;
 
-// Modern side menu UI with updated peer list styling
+// Offered in a row's right-click menu; /mute takes any other length
+const MUTE_CHOICES: [(&str, MuteChange); 4] = [
+    ("Mute for 1 hour", MuteChange::For(60 * 60)),
+    ("Mute for 8 hours", MuteChange::For(8 * 60 * 60)),
+    ("Mute for 1 day", MuteChange::For(24 * 60 * 60)),
+    ("Mute until I unmute", MuteChange::UntilUnmuted),
+];
+
+// Modern side menu UI with updated peer list styling.
+// Returns a mute picked from a row's right-click menu, for ChatApp to apply and save.
 pub fn show(
     ui: &mut egui::Ui, 
     peers: &[IpcPeer], 
+    rooms: &[String], // "#everyone" plus joined rooms
     current_chat_peer_id: &mut Option<String>,
     gui_to_daemon_tx: &Option<mpsc::Sender<GuiToDaemonCommand>>,
     rt: &Arc<tokio::runtime::Runtime>,
-    current_user_id: &Option<String> // Added parameter for current username
-) {
+    markers: ConversationMarkers<'_>
+) -> Option<(String, MuteChange)> {
+    let ConversationMarkers { conversation_summaries, drafts, notifications } = markers;
+    let mut mute_change = None;
     // Modern color scheme that works with dark background
     let accent_color = egui::Color32::from_rgb(25, 118, 210); // Primary blue
     let hover_color = egui::Color32::from_rgb(35, 35, 40);    // Slightly lighter than background for hover
@@ -34,12 +48,14 @@ pub fn show(
         // Use a layout that arranges children left-to-right and centers them vertically within header_rect
         header_content_ui.with_layout(egui::Layout::left_to_right(egui::Align::Center), |centered_header_ui| {
             centered_header_ui.heading(
-                egui::RichText::new("Peers")
+                egui::RichText::new("Chats")
                     .size(18.0)
                     .color(label_color)
                     .strong()
             );
             
+            let total_unread: u32 = conversation_summaries.values().map(|summary| summary.unread_count).sum();
+
             // Refresh button section, aligned to the right and vertically centered
             centered_header_ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |refresh_ui| {
                 let refresh_btn = refresh_ui.add(
@@ -68,6 +84,35 @@ pub fn show(
                 if refresh_btn.hovered() {
                     refresh_ui.ctx().set_cursor_icon(egui::CursorIcon::PointingHand);
                 }
+
+                // Only offered while something is unread
+                if total_unread > 0 {
+                    let mark_all_btn = refresh_ui.add(
+                        egui::Button::new(
+                            egui::RichText::new("✔")
+                                .size(14.0)
+                                .color(label_color)
+                        )
+                        .fill(hover_color)
+                        .corner_radius(8.0)
+                        .min_size(egui::vec2(32.0, 32.0))
+                    ).on_hover_text(format!("Mark all as read ({} unread)", total_unread));
+
+                    if mark_all_btn.clicked() {
+                        if let Some(tx) = gui_to_daemon_tx {
+                            let tx_clone = tx.clone();
+                            rt.spawn(async move {
+                                println!("GUI: Sending MarkAllRead request.");
+                                if let Err(e) = tx_clone.send(GuiToDaemonCommand::MarkAllRead).await {
+                                    eprintln!("Failed to send MarkAllRead request: {}", e);
+                                }
+                            });
+                        }
+                    }
+                    if mark_all_btn.hovered() {
+                        refresh_ui.ctx().set_cursor_icon(egui::CursorIcon::PointingHand);
+                    }
+                }
             });
         });
     });
@@ -77,131 +122,104 @@ pub fn show(
     ui.separator();
     ui.add_space(12.0);
     
-    // Peers list section
-    if peers.is_empty() {
-        // Styled empty state
-        ui.vertical_centered(|ui| {
-            ui.add_space(40.0);
-            ui.label(
-                egui::RichText::new("No peers found")
-                    .size(14.0)
-                    .color(subtle_color)
-                    .italics()
-            );
-            ui.add_space(8.0);
-            ui.label(
-                egui::RichText::new("Try refreshing the list")
-                    .size(12.0)
-                    .color(subtle_color.linear_multiply(0.8))
-            );
-            ui.add_space(40.0);
-        });
-    } else {
-        // Scrollable area with improved styling
-        egui::ScrollArea::vertical()
-            .auto_shrink([false, false])
-            .id_source("peer_list_scroll_area")
-            .show(ui, |ui| {
-                ui.spacing_mut().item_spacing.y = 4.0; // Tighter spacing between items
-                
-                for peer in peers {
-                    let is_selected = current_chat_peer_id.as_ref().map_or(false, |id| *id == peer.id);
-                    
-                    // Determine background fill based on selection and hover state
-                    // The response for hover state will be captured after the frame is shown
-                    // We will rely on response.hovered() later to conditionally set cursor icon
-                    // and potentially add other hover-specific effects if needed, but background is tricky here.
-                    // For now, let's keep the selection background logic and remove the manual hover paint.
-                    // The hover background will be handled by a conditional fill in the Frame itself.
-
-                    // We need to get the hover state *before* defining the frame,
-                    // so we'll create an invisible button or sense hover on an area first.
-                    // This is a common egui pattern. Let's define an interaction area.
-                    
-                    let item_response = ui.interact(ui.available_rect_before_wrap(), egui::Id::new(&peer.id).with("peer_item"), egui::Sense::click());
-                    let is_hovered = item_response.hovered();
-
-                    let background_fill = if is_selected {
-                        accent_color.linear_multiply(0.4) // Stronger selected background
-                    } else if is_hovered {
-                        hover_color // Hover background
+    let colors = RowColors { accent_color, hover_color, label_color, subtle_color };
+    let unread_count = |id: &str| conversation_summaries.get(id).map_or(0, |summary| summary.unread_count);
+    let now = chrono::Utc::now();
+
+    // Most recent conversation first; peers we never talked to follow alphabetically
+    let mut sorted_peers: Vec<&IpcPeer> = peers.iter().collect();
+    sorted_peers.sort_by(|a, b| {
+        let a_activity = conversation_summaries.get(&a.id).map(|summary| summary.last_activity);
+        let b_activity = conversation_summaries.get(&b.id).map(|summary| summary.last_activity);
+        b_activity.cmp(&a_activity).then_with(|| a.username.to_lowercase().cmp(&b.username.to_lowercase()))
+    });
+    egui::ScrollArea::vertical()
+        .auto_shrink([false, false])
+        .id_source("peer_list_scroll_area")
+        .show(ui, |ui| {
+            ui.spacing_mut().item_spacing.y = 4.0; // Tighter spacing between items
+
+            // Rooms section: the #everyone broadcast channel and anything joined with /join
+            section_label(ui, "ROOMS", subtle_color);
+            for room in rooms {
+                let is_selected = current_chat_peer_id.as_deref() == Some(room.as_str());
+                let state = RowState {
+                    is_selected,
+                    unread_count: unread_count(room),
+                    draft: drafts.get(room).filter(|_| !is_selected).cloned(),
+                    muted: notifications.describe_mute(room, now),
+                };
+                let member_count = peers.iter().filter(|peer| peer.rooms.contains(room)).count();
+                let subtitle = if room == "#everyone" { None } else { Some(format!("{} online", member_count)) };
+                let item_response = conversation_row(ui, room, "#", room.trim_start_matches('#'), subtitle.as_deref(), &state, &colors);
+                if let Some(change) = mute_menu(&item_response, state.muted.is_some()) {
+                    mute_change = Some((room.clone(), change));
+                }
+                if item_response.clicked() {
+                    if state.is_selected {
+                        *current_chat_peer_id = None; // Click again to deselect
                     } else {
-                        egui::Color32::TRANSPARENT
-                    };
-
-                    let peer_frame = egui::Frame::none()
-                        .fill(background_fill)
-                        .rounding(8.0)
-                        .inner_margin(egui::vec2(12.0, 10.0))
-                        .outer_margin(egui::vec2(0.0, 2.0));
-                    
-                    // Use the item_response for click handling
-                    let inner_response = peer_frame.show(ui, |ui| {
-                        ui.horizontal(|h_ui| {
-                            // Icon (non-selectable)
-                            let icon_color = if is_selected {
-                                accent_color.gamma_multiply(1.5) // Brighter icon for selected
-                            } else if is_hovered {
-                                subtle_color.gamma_multiply(1.2) // Slightly brighter icon on hover
-                            } else {
-                                subtle_color
-                            };
-                            let icon_font_id = egui::FontId::proportional(14.0);
-                            let mut icon_job = egui::text::LayoutJob::default();
-                            icon_job.append(
-                                "👤",
-                                0.0,
-                                egui::TextFormat {
-                                    font_id: icon_font_id,
-                                    color: icon_color,
-                                    ..Default::default()
-                                },
-                            );
-                            let icon_galley = h_ui.fonts(|f| f.layout_job(icon_job));
-                            let (icon_rect, _icon_response) = h_ui.allocate_exact_size(icon_galley.size(), egui::Sense::focusable_noninteractive());
-                            h_ui.painter().galley(icon_rect.min, icon_galley, egui::Color32::WHITE);
-
-                            h_ui.add_space(8.0);
-                            
-                            // Username (non-selectable)
-                            let username_color = if is_selected {
-                                label_color.gamma_multiply(1.2) // Brighter text for selected
-                            } else {
-                                label_color
-                            };
-                            // Respect .text_style(egui::TextStyle::Monospace) and .strong()
-                            let username_font_id = egui::FontId::monospace(14.0);
-                            let mut username_job = egui::text::LayoutJob::default();
-                            username_job.append(
-                                &peer.username,
-                                0.0,
-                                egui::TextFormat {
-                                    color: username_color,
-                                    ..Default::default()
-                                },
-                            );
-                            let username_galley = h_ui.fonts(|f| f.layout_job(username_job));
-                            let (username_rect, _username_response) = h_ui.allocate_exact_size(username_galley.size(), egui::Sense::focusable_noninteractive());
-                            h_ui.painter().galley(username_rect.min, username_galley, egui::Color32::WHITE);
-                         });
-                     }).response; // This is the response of the frame's content
-                    
-                    // Handle click and hover effects using the item_response
-                    if item_response.clicked() {
-                        if is_selected {
-                            *current_chat_peer_id = None; // Click again to deselect
-                        } else {
-                            *current_chat_peer_id = Some(peer.id.clone());
-                            println!("GUI: Selected peer for chat: {} ({})", peer.username, peer.id);
-                        }
+                        *current_chat_peer_id = Some(room.clone());
+                        println!("GUI: Selected room for chat: {}", room);
                     }
-                    
-                    if item_response.hovered() {
-                        ui.ctx().set_cursor_icon(egui::CursorIcon::PointingHand);
+                }
+            }
+            if rooms.len() <= 1 {
+                ui.label(
+                    egui::RichText::new("Type /join <room> to join a room")
+                        .size(11.0)
+                        .color(subtle_color.linear_multiply(0.8))
+                );
+            }
+
+            ui.add_space(8.0);
+            section_label(ui, "PEERS", subtle_color);
+
+            // Peers list section
+            if peers.is_empty() {
+                // Styled empty state
+                ui.vertical_centered(|ui| {
+                    ui.add_space(40.0);
+                    ui.label(
+                        egui::RichText::new("No peers found")
+                            .size(14.0)
+                            .color(subtle_color)
+                            .italics()
+                    );
+                    ui.add_space(8.0);
+                    ui.label(
+                        egui::RichText::new("Try refreshing the list")
+                            .size(12.0)
+                            .color(subtle_color.linear_multiply(0.8))
+                    );
+                    ui.add_space(40.0);
+                });
+            }
+
+            for peer in sorted_peers {
+                let is_selected = current_chat_peer_id.as_ref().map_or(false, |id| *id == peer.id);
+                let state = RowState {
+                    is_selected,
+                    unread_count: unread_count(&peer.id),
+                    draft: drafts.get(&peer.id).filter(|_| !is_selected).cloned(),
+                    muted: notifications.describe_mute(&peer.id, now),
+                };
+                let item_response = conversation_row(ui, &peer.id, "👤", &peer.username, peer.status.as_deref(), &state, &colors);
+                if let Some(change) = mute_menu(&item_response, state.muted.is_some()) {
+                    mute_change = Some((peer.id.clone(), change));
+                }
+
+                // Handle click and hover effects using the item_response
+                if item_response.clicked() {
+                    if state.is_selected {
+                        *current_chat_peer_id = None; // Click again to deselect
+                    } else {
+                        *current_chat_peer_id = Some(peer.id.clone());
+                        println!("GUI: Selected peer for chat: {} ({})", peer.username, peer.id);
                     }
                 }
-            });
-    }
+            }
+        });
     
     // Bottom section with connection info or status
     // ui.add_space(8.0);
@@ -228,4 +246,170 @@ pub fn show(
     //         });
     //     }
     // });
-} 
\ No newline at end of file
+    mute_change
+}
+
+// Right-click menu of a row: mute for a while, or unmute
+fn mute_menu(item_response: &egui::Response, is_muted: bool) -> Option<MuteChange> {
+    let mut picked = None;
+    item_response.context_menu(|ui| {
+        if is_muted && ui.button("🔔 Unmute").clicked() {
+            picked = Some(MuteChange::Off);
+            ui.close_menu();
+        }
+        for (label, change) in MUTE_CHOICES {
+            if ui.button(format!("🔕 {}", label)).clicked() {
+                picked = Some(change);
+                ui.close_menu();
+            }
+        }
+    });
+    picked
+}
+
+// What the rows show besides names, per peer/room ID
+pub struct ConversationMarkers<'a> {
+    pub conversation_summaries: &'a HashMap<String, ConversationSummary>, // Unread counts and last activity
+    pub drafts: &'a HashMap<String, String>, // Unsent text, marked with ✏
+    pub notifications: &'a NotificationSettings, // Muted conversations are marked with 🔕
+}
+
+struct RowColors {
+    accent_color: egui::Color32,
+    hover_color: egui::Color32,
+    label_color: egui::Color32,
+    subtle_color: egui::Color32,
+}
+
+struct RowState {
+    is_selected: bool,
+    unread_count: u32, // Shown as a badge, with the name in bold, when non-zero
+    draft: Option<String>, // Unsent text; the open conversation has it in the input instead
+    muted: Option<String>, // "Muted until 17:30", shown when hovering the 🔕
+}
+
+fn section_label(ui: &mut egui::Ui, text: &str, color: egui::Color32) {
+    ui.label(egui::RichText::new(text).size(11.0).strong().color(color));
+}
+
+// One clickable entry in the list (a room or a peer). `subtitle` is shown dimmed after the name, e.g. a peer's status.
+fn conversation_row(
+    ui: &mut egui::Ui,
+    id: &str,
+    icon: &str,
+    title: &str,
+    subtitle: Option<&str>,
+    state: &RowState,
+    colors: &RowColors
+) -> egui::Response {
+    let is_selected = state.is_selected;
+    let has_unread = state.unread_count > 0;
+    // We need to get the hover state *before* defining the frame,
+    // so we'll create an invisible button or sense hover on an area first.
+    // This is a common egui pattern. Let's define an interaction area.
+    let item_response = ui.interact(ui.available_rect_before_wrap(), egui::Id::new(id).with("peer_item"), egui::Sense::click());
+    let is_hovered = item_response.hovered();
+
+    let background_fill = if is_selected {
+        colors.accent_color.linear_multiply(0.4) // Stronger selected background
+    } else if is_hovered {
+        colors.hover_color // Hover background
+    } else {
+        egui::Color32::TRANSPARENT
+    };
+
+    let row_frame = egui::Frame::new()
+        .fill(background_fill)
+        .corner_radius(8.0)
+        .inner_margin(egui::vec2(12.0, 10.0))
+        .outer_margin(egui::vec2(0.0, 2.0));
+
+    row_frame.show(ui, |ui| {
+        ui.horizontal(|h_ui| {
+            // Icon (non-selectable)
+            let icon_color = if is_selected {
+                colors.accent_color.gamma_multiply(1.5) // Brighter icon for selected
+            } else if is_hovered {
+                colors.subtle_color.gamma_multiply(1.2) // Slightly brighter icon on hover
+            } else {
+                colors.subtle_color
+            };
+            let icon_font_id = egui::FontId::proportional(14.0);
+            let mut icon_job = egui::text::LayoutJob::default();
+            icon_job.append(
+                icon,
+                0.0,
+                egui::TextFormat {
+                    font_id: icon_font_id,
+                    color: icon_color,
+                    ..Default::default()
+                },
+            );
+            let icon_galley = h_ui.fonts(|f| f.layout_job(icon_job));
+            let (icon_rect, _icon_response) = h_ui.allocate_exact_size(icon_galley.size(), egui::Sense::focusable_noninteractive());
+            h_ui.painter().galley(icon_rect.min, icon_galley, egui::Color32::WHITE);
+
+            h_ui.add_space(8.0);
+
+            // Name (non-selectable)
+            let title_color = if is_selected || has_unread {
+                egui::Color32::WHITE // Brighter text for selected or unread
+            } else {
+                colors.label_color
+            };
+            let mut title_job = egui::text::LayoutJob::default();
+            let title_format = egui::TextFormat {
+                color: title_color,
+                ..Default::default()
+            };
+            title_job.append(title, 0.0, title_format);
+            if let Some(subtitle) = subtitle {
+                title_job.append(
+                    subtitle,
+                    6.0,
+                    egui::TextFormat {
+                        font_id: egui::FontId::proportional(11.0),
+                        color: colors.subtle_color,
+                        italics: true,
+                        ..Default::default()
+                    },
+                );
+            }
+            let title_galley = h_ui.fonts(|f| f.layout_job(title_job));
+            let (title_rect, _title_response) = h_ui.allocate_exact_size(title_galley.size(), egui::Sense::focusable_noninteractive());
+            // The default fonts have no bold face, so unread names are drawn twice, slightly offset, to look bold
+            if has_unread {
+                h_ui.painter().galley(title_rect.min + egui::vec2(0.6, 0.0), title_galley.clone(), egui::Color32::WHITE);
+            }
+            h_ui.painter().galley(title_rect.min, title_galley, egui::Color32::WHITE);
+
+            // Unread badge, draft and mute markers, right-aligned
+            if has_unread || state.draft.is_some() || state.muted.is_some() {
+                h_ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |badge_ui| {
+                    if has_unread {
+                        let badge_text = if state.unread_count > 99 { "99+".to_string() } else { state.unread_count.to_string() };
+                        egui::Frame::new()
+                            .fill(colors.accent_color)
+                            .corner_radius(9.0)
+                            .inner_margin(egui::vec2(6.0, 1.0))
+                            .show(badge_ui, |badge_ui| {
+                                badge_ui.label(egui::RichText::new(badge_text).size(11.0).strong().color(egui::Color32::WHITE));
+                            });
+                    }
+                    if let Some(draft) = state.draft.as_ref() {
+                        badge_ui.label(egui::RichText::new("✏").size(12.0).color(colors.subtle_color))
+                            .on_hover_text(format!("Draft: {}", draft));
+                    }
+                    if let Some(muted) = state.muted.as_ref() {
+                        badge_ui.label(egui::RichText::new("🔕").size(12.0).color(colors.subtle_color)).on_hover_text(muted);
+                    }
+                });
+            }
+        });
+    });
+
+    if item_response.hovered() {
+        ui.ctx().set_cursor_icon(egui::CursorIcon::PointingHand);
+    }
+    item_response
+}
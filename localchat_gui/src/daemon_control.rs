@@ -0,0 +1,187 @@
+// Finding, starting and stopping this GUI instance's daemon. A daemon already listening on the instance's socket
+// (left running by an earlier GUI, or started by hand) is attached to instead of starting a second one.
+// Stop and restart ask the daemon over its socket, so they work for a daemon this GUI didn't start as well.
+use crate::GuiToDaemonCommand;
+use std::env;
+use std::error::Error;
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use std::process::{Child, Command};
+use std::time::{Duration, Instant};
+
+// How long a restart waits for the old daemon to go away before giving up (or killing it, if we started it)
+const RESTART_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Between connect-probes while a restart waits
+const PROBE_INTERVAL: Duration = Duration::from_millis(500);
+
+// What the Daemon section of the Settings panel asks for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DaemonAction {
+    Start,
+    Stop,
+    Restart,
+}
+
+// A restart waiting for the old daemon to stop listening
+struct PendingRestart {
+    requested_at: Instant,
+    probed_at: Instant,
+}
+
+pub struct DaemonControl {
+    instance: u16,
+    socket_path: String,
+    tcp_port: u16,
+    child: Option<Child>, // The daemon (or `cargo run` for it) this GUI started; None when attached to another one
+    restart: Option<PendingRestart>,
+    pub last_error: Option<String>, // Why the last start, stop or restart failed, for the Settings panel
+}
+
+impl DaemonControl {
+    pub fn new(instance: u16) -> Self {
+        Self {
+            instance,
+            socket_path: format!("/tmp/localchat_daemon{}.sock", instance),
+            tcp_port: 12345 + (instance - 1),
+            child: None,
+            restart: None,
+            last_error: None,
+        }
+    }
+
+    pub fn socket_path(&self) -> &str {
+        &self.socket_path
+    }
+
+    // Whether a daemon answers on the socket. A socket file left behind by a daemon that died refuses the connection.
+    pub fn is_listening(&self) -> bool {
+        UnixStream::connect(&self.socket_path).is_ok()
+    }
+
+    // Attaches to a running daemon, or starts one
+    pub fn ensure_running(&mut self) {
+        if self.is_listening() {
+            println!("A daemon is already listening on {} for instance {}; attaching to it.", self.socket_path, self.instance);
+            return;
+        }
+        if let Err(e) = self.start() {
+            eprintln!("Failed to start localchat_daemon for instance {}: {}.", self.instance, e);
+            self.last_error = Some(format!("Failed to start the daemon: {}", e));
+        }
+    }
+
+    pub fn start(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut daemon_command = if cfg!(debug_assertions) {
+            println!("DEBUG mode: Starting daemon with 'cargo run' for instance {}", self.instance);
+            let mut command = Command::new("cargo");
+            command.args(["run", "--quiet", "-p", "localchat_daemon"]);
+            command
+        } else {
+            println!("RELEASE mode: Attempting to start pre-compiled daemon for instance {}", self.instance);
+            let mut exe_path = env::current_exe()?;
+            exe_path.pop();
+            exe_path.push(if cfg!(windows) { "localchat_daemon.exe" } else { "localchat_daemon" });
+            println!("Attempting to run daemon from: {:?}", exe_path);
+            Command::new(exe_path)
+        };
+
+        // Set environment variables for the daemon process
+        daemon_command.env("LOCALCHAT_TCP_PORT", self.tcp_port.to_string());
+        daemon_command.env("LOCALCHAT_SOCKET_PATH", &self.socket_path);
+
+        println!(
+            "Attempting to start localchat_daemon for instance {} with TCP Port: {}, Socket: {}, Command: {:?}",
+            self.instance, self.tcp_port, self.socket_path, daemon_command
+        );
+        let child = daemon_command.spawn()?;
+        println!("Successfully spawned localchat_daemon process for instance {}. PID: {}", self.instance, child.id());
+        self.child = Some(child);
+        self.last_error = None;
+        Ok(())
+    }
+
+    // Whether the daemon was started by this GUI (and hasn't exited)
+    pub fn started_here(&self) -> bool {
+        self.child.is_some()
+    }
+
+    pub fn is_restarting(&self) -> bool {
+        self.restart.is_some()
+    }
+
+    // Asks the daemon to shut down; it replies and exits on its own
+    pub fn stop(&mut self) -> Result<(), Box<dyn Error>> {
+        self.send_command(&GuiToDaemonCommand::Shutdown)?;
+        println!("Asked the daemon on {} to stop.", self.socket_path);
+        Ok(())
+    }
+
+    // Stops the daemon; poll() starts a new one once the old one is gone
+    pub fn restart(&mut self) -> Result<(), Box<dyn Error>> {
+        self.stop()?;
+        let now = Instant::now();
+        self.restart = Some(PendingRestart { requested_at: now, probed_at: now });
+        Ok(())
+    }
+
+    pub fn run(&mut self, action: DaemonAction) {
+        let result = match action {
+            DaemonAction::Start => self.start(),
+            DaemonAction::Stop => self.stop(),
+            DaemonAction::Restart => self.restart(),
+        };
+        if let Err(e) = result {
+            eprintln!("Daemon {:?} failed: {}", action, e);
+            self.last_error = Some(format!("{:?} failed: {}", action, e));
+        }
+    }
+
+    // Called every frame: notices our daemon exiting, and carries a restart on
+    pub fn poll(&mut self) {
+        if let Some(child) = self.child.as_mut() {
+            match child.try_wait() {
+                Ok(Some(status)) => {
+                    println!("Daemon process for instance {} exited: {}", self.instance, status);
+                    self.child = None;
+                }
+                Ok(None) => {}
+                Err(e) => eprintln!("Failed to check on the daemon process: {}", e),
+            }
+        }
+
+        let Some(restart) = self.restart.as_mut() else {
+            return;
+        };
+        if restart.probed_at.elapsed() < PROBE_INTERVAL {
+            return;
+        }
+        restart.probed_at = Instant::now();
+        let waited = restart.requested_at.elapsed();
+        let still_running = self.is_listening();
+        if still_running && waited < RESTART_TIMEOUT {
+            return;
+        }
+        self.restart = None;
+        if still_running {
+            // Only a daemon we started can be killed; another one is left alone
+            let Some(mut child) = self.child.take() else {
+                self.last_error = Some("The daemon didn't stop, so it wasn't restarted.".to_string());
+                return;
+            };
+            eprintln!("Daemon didn't stop within {:?}; killing it.", RESTART_TIMEOUT);
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+        self.run(DaemonAction::Start);
+    }
+
+    // One command over a short-lived connection of its own; the reply isn't read
+    fn send_command(&self, command: &GuiToDaemonCommand) -> Result<(), Box<dyn Error>> {
+        let mut stream = UnixStream::connect(&self.socket_path)?;
+        stream.set_write_timeout(Some(Duration::from_secs(2)))?;
+        let json_command = serde_json::to_string(command)?;
+        stream.write_all(format!("{}\n", json_command).as_bytes())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,55 @@
+// GUI preferences kept between runs (keyboard shortcuts, notifications, the daemon on quit), in a JSON file next to the username file
+// (one per instance).
+// The username stays in its own file; the daemon is told about it, these settings only matter to the GUI.
+use crate::keybindings::Keybindings;
+use crate::notifications::NotificationSettings;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)] // Settings added later get their default in an older file
+pub struct GuiSettings {
+    pub keybindings: Keybindings,
+    pub notifications: NotificationSettings,
+    pub stop_daemon_on_quit: bool, // Off: the daemon keeps receiving messages after the GUI quits
+}
+
+impl GuiSettings {
+    // A missing file means defaults; an unreadable one is reported and replaced on the next save
+    pub fn load(path: &Option<PathBuf>) -> Self {
+        let Some(path) = path else {
+            return Self::default();
+        };
+        match std::fs::read_to_string(path) {
+            Ok(json) => match serde_json::from_str(&json) {
+                Ok(settings) => {
+                    println!("GUI: Loaded settings from {:?}", path);
+                    settings
+                }
+                Err(e) => {
+                    eprintln!("GUI: Ignoring unreadable settings file {:?}: {}", path, e);
+                    Self::default()
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(e) => {
+                eprintln!("GUI: Failed to read settings file {:?}: {}", path, e);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self, path: &Option<PathBuf>) {
+        let Some(path) = path else {
+            eprintln!("GUI: No settings file path; settings are not saved.");
+            return;
+        };
+        let result = serde_json::to_string_pretty(self)
+            .map_err(|e| e.to_string())
+            .and_then(|json| std::fs::write(path, json).map_err(|e| e.to_string()));
+        match result {
+            Ok(()) => println!("GUI: Saved settings to {:?}", path),
+            Err(e) => eprintln!("GUI: Failed to save settings to {:?}: {}", path, e),
+        }
+    }
+}
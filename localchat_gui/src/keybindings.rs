@@ -0,0 +1,237 @@
+// Keyboard shortcuts of the chat window. Every action has one shortcut; they are changed in the Settings panel
+// and saved with the other GUI settings (gui_settings.rs) as text like "Ctrl+K".
+use eframe::egui::{self, Key, KeyboardShortcut, Modifiers};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Action {
+    SendMessage,
+    InsertNewline,
+    EditLastMessage, // Only while the input is empty
+    QuickSwitcher,
+}
+
+impl Action {
+    // In the order the Settings panel lists them
+    pub const ALL: [Action; 4] = [Action::SendMessage, Action::InsertNewline, Action::EditLastMessage, Action::QuickSwitcher];
+
+    // Key in the settings file
+    fn name(self) -> &'static str {
+        match self {
+            Action::SendMessage => "send_message",
+            Action::InsertNewline => "insert_newline",
+            Action::EditLastMessage => "edit_last_message",
+            Action::QuickSwitcher => "quick_switcher",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::SendMessage => "Send message",
+            Action::InsertNewline => "New line in a message",
+            Action::EditLastMessage => "Edit last sent message (empty input)",
+            Action::QuickSwitcher => "Switch conversation",
+        }
+    }
+
+    fn default_shortcut(self) -> KeyboardShortcut {
+        match self {
+            Action::SendMessage => KeyboardShortcut::new(Modifiers::NONE, Key::Enter),
+            Action::InsertNewline => KeyboardShortcut::new(Modifiers::SHIFT, Key::Enter),
+            Action::EditLastMessage => KeyboardShortcut::new(Modifiers::NONE, Key::ArrowUp),
+            Action::QuickSwitcher => KeyboardShortcut::new(Modifiers::COMMAND, Key::K),
+        }
+    }
+}
+
+// Saved as { "quick_switcher": "Ctrl+K", ... }; actions missing from the file (or unreadable) keep their default
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "BTreeMap<String, String>", into = "BTreeMap<String, String>")]
+pub struct Keybindings {
+    shortcuts: BTreeMap<Action, KeyboardShortcut>,
+}
+
+impl Default for Keybindings {
+    fn default() -> Self {
+        Self { shortcuts: Action::ALL.into_iter().map(|action| (action, action.default_shortcut())).collect() }
+    }
+}
+
+impl From<BTreeMap<String, String>> for Keybindings {
+    fn from(saved: BTreeMap<String, String>) -> Self {
+        let mut keybindings = Self::default();
+        for (name, text) in saved {
+            match (Action::ALL.into_iter().find(|action| action.name() == name), parse_shortcut(&text)) {
+                (Some(action), Some(shortcut)) => {
+                    keybindings.shortcuts.insert(action, shortcut);
+                }
+                _ => eprintln!("Settings: Ignoring unknown keybinding {} = '{}'", name, text),
+            }
+        }
+        keybindings
+    }
+}
+
+impl From<Keybindings> for BTreeMap<String, String> {
+    fn from(keybindings: Keybindings) -> Self {
+        keybindings.shortcuts.iter().map(|(action, shortcut)| (action.name().to_string(), format_shortcut(shortcut))).collect()
+    }
+}
+
+impl Keybindings {
+    pub fn shortcut(&self, action: Action) -> KeyboardShortcut {
+        self.shortcuts.get(&action).copied().unwrap_or_else(|| action.default_shortcut())
+    }
+
+    pub fn set(&mut self, action: Action, shortcut: KeyboardShortcut) {
+        self.shortcuts.insert(action, shortcut);
+    }
+
+    // "Shift+Enter", for hints and the Settings panel
+    pub fn describe(&self, action: Action) -> String {
+        format_shortcut(&self.shortcut(action))
+    }
+
+    // Another action on the same shortcut; only one of them can ever fire
+    pub fn conflict(&self, action: Action) -> Option<Action> {
+        let shortcut = self.shortcut(action);
+        Action::ALL.into_iter().find(|&other| other != action && self.shortcut(other) == shortcut)
+    }
+
+    // Whether the action's shortcut was pressed this frame. The key press is consumed so no widget acts on it as well.
+    // Unlike egui's consume_shortcut the modifiers must match exactly, so Shift+Enter doesn't also count as Enter.
+    pub fn consume(&self, ctx: &egui::Context, action: Action) -> bool {
+        let shortcut = self.shortcut(action);
+        ctx.input_mut(|i| {
+            let before = i.events.len();
+            i.events.retain(|event| !matches!(
+                event,
+                egui::Event::Key { key, pressed: true, modifiers, .. }
+                    if *key == shortcut.logical_key && modifiers.matches_exact(shortcut.modifiers)
+            ));
+            i.events.len() < before
+        })
+    }
+}
+
+// A shortcut the user pressed while the Settings panel was waiting for one. Ctrl and Cmd are the same modifier
+// (Modifiers::COMMAND), so a saved "Ctrl+K" works on every platform.
+pub fn shortcut_from_event(event: &egui::Event) -> Option<KeyboardShortcut> {
+    let egui::Event::Key { key, pressed: true, modifiers, .. } = event else {
+        return None;
+    };
+    let mut shortcut_modifiers = Modifiers::NONE;
+    if modifiers.ctrl || modifiers.command || modifiers.mac_cmd {
+        shortcut_modifiers |= Modifiers::COMMAND;
+    }
+    if modifiers.alt {
+        shortcut_modifiers |= Modifiers::ALT;
+    }
+    if modifiers.shift {
+        shortcut_modifiers |= Modifiers::SHIFT;
+    }
+    Some(KeyboardShortcut::new(shortcut_modifiers, *key))
+}
+
+// Whether a shortcut may be used at all: a plain letter (or Shift+letter) would fire while typing a message
+pub fn check_shortcut(shortcut: &KeyboardShortcut) -> Result<(), String> {
+    // Letters and digits have one-character names; punctuation keys have a symbol ("-" for Minus), as do the arrows
+    let key = shortcut.logical_key;
+    let is_arrow = matches!(key, Key::ArrowUp | Key::ArrowDown | Key::ArrowLeft | Key::ArrowRight);
+    let types_text = key == Key::Space || key.name().chars().count() == 1 || (key.symbol_or_name() != key.name() && !is_arrow);
+    if types_text && !shortcut.modifiers.command && !shortcut.modifiers.alt {
+        return Err(format!("{} types text; add Ctrl or Alt to it.", format_shortcut(shortcut)));
+    }
+    Ok(())
+}
+
+fn format_shortcut(shortcut: &KeyboardShortcut) -> String {
+    shortcut.format(&egui::ModifierNames::NAMES, false)
+}
+
+// Reads what format_shortcut wrote: modifiers and a key name joined by '+'
+fn parse_shortcut(text: &str) -> Option<KeyboardShortcut> {
+    let (modifier_text, key_name) = text.rsplit_once('+').unwrap_or(("", text));
+    let mut modifiers = Modifiers::NONE;
+    for modifier in modifier_text.split('+').filter(|part| !part.is_empty()) {
+        modifiers |= match modifier.trim() {
+            "Ctrl" | "Cmd" => Modifiers::COMMAND,
+            "Alt" | "Option" => Modifiers::ALT,
+            "Shift" => Modifiers::SHIFT,
+            _ => return None,
+        };
+    }
+    Key::from_name(key_name.trim()).map(|key| KeyboardShortcut::new(modifiers, key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn saved(entries: &[(&str, &str)]) -> Keybindings {
+        entries.iter().map(|(name, text)| (name.to_string(), text.to_string())).collect::<BTreeMap<_, _>>().into()
+    }
+
+    #[test]
+    fn shortcuts_survive_a_save_and_load() {
+        let mut keybindings = Keybindings::default();
+        keybindings.set(Action::QuickSwitcher, KeyboardShortcut::new(Modifiers::COMMAND | Modifiers::SHIFT, Key::P));
+        keybindings.set(Action::EditLastMessage, KeyboardShortcut::new(Modifiers::ALT, Key::Minus));
+        let json = serde_json::to_string(&keybindings).unwrap();
+        let loaded: Keybindings = serde_json::from_str(&json).unwrap();
+        for action in Action::ALL {
+            assert_eq!(loaded.shortcut(action), keybindings.shortcut(action), "{:?} in {}", action, json);
+        }
+    }
+
+    #[test]
+    fn shortcut_text_is_parsed_with_either_command_name() {
+        let ctrl_k = Some(KeyboardShortcut::new(Modifiers::COMMAND, Key::K));
+        assert_eq!(parse_shortcut("Ctrl+K"), ctrl_k);
+        assert_eq!(parse_shortcut("Cmd + K"), ctrl_k);
+        assert_eq!(parse_shortcut("Shift+Alt+K"), Some(KeyboardShortcut::new(Modifiers::SHIFT | Modifiers::ALT, Key::K)));
+        assert_eq!(parse_shortcut("Enter"), Some(KeyboardShortcut::new(Modifiers::NONE, Key::Enter)));
+        assert_eq!(parse_shortcut("Hyper+K"), None);
+        assert_eq!(parse_shortcut("Ctrl+NoSuchKey"), None);
+        assert_eq!(parse_shortcut(""), None);
+    }
+
+    #[test]
+    fn unknown_or_unreadable_entries_keep_the_defaults() {
+        let keybindings = saved(&[("quick_switcher", "Alt+P"), ("send_message", "Ctrl+???"), ("launch_rockets", "Ctrl+R")]);
+        assert_eq!(keybindings.shortcut(Action::QuickSwitcher), KeyboardShortcut::new(Modifiers::ALT, Key::P));
+        assert_eq!(keybindings.shortcut(Action::SendMessage), Action::SendMessage.default_shortcut());
+        assert_eq!(keybindings.shortcut(Action::InsertNewline), Action::InsertNewline.default_shortcut());
+    }
+
+    #[test]
+    fn conflicts_are_found_both_ways() {
+        let keybindings = Keybindings::default();
+        assert!(Action::ALL.into_iter().all(|action| keybindings.conflict(action).is_none()));
+        let keybindings = saved(&[("quick_switcher", "Enter")]);
+        assert_eq!(keybindings.conflict(Action::QuickSwitcher), Some(Action::SendMessage));
+        assert_eq!(keybindings.conflict(Action::SendMessage), Some(Action::QuickSwitcher));
+    }
+
+    #[test]
+    fn shortcuts_that_type_text_need_ctrl_or_alt() {
+        let shortcut = |modifiers, key| KeyboardShortcut::new(modifiers, key);
+        for (modifiers, key) in [(Modifiers::NONE, Key::K), (Modifiers::SHIFT, Key::K), (Modifiers::NONE, Key::Num1), (Modifiers::NONE, Key::Space), (Modifiers::SHIFT, Key::Minus)] {
+            assert!(check_shortcut(&shortcut(modifiers, key)).is_err(), "{:?}+{:?}", modifiers, key);
+        }
+        for (modifiers, key) in [(Modifiers::COMMAND, Key::K), (Modifiers::ALT, Key::Minus), (Modifiers::NONE, Key::Enter), (Modifiers::NONE, Key::ArrowUp), (Modifiers::NONE, Key::F5)] {
+            assert!(check_shortcut(&shortcut(modifiers, key)).is_ok(), "{:?}+{:?}", modifiers, key);
+        }
+    }
+
+    #[test]
+    fn ctrl_and_cmd_presses_record_the_same_shortcut() {
+        let press = |modifiers| egui::Event::Key { key: Key::K, physical_key: None, pressed: true, repeat: false, modifiers };
+        let expected = Some(KeyboardShortcut::new(Modifiers::COMMAND, Key::K));
+        assert_eq!(shortcut_from_event(&press(Modifiers::CTRL)), expected);
+        assert_eq!(shortcut_from_event(&press(Modifiers::MAC_CMD)), expected);
+        let release = egui::Event::Key { key: Key::K, physical_key: None, pressed: false, repeat: false, modifiers: Modifiers::CTRL };
+        assert_eq!(shortcut_from_event(&release), None);
+    }
+}
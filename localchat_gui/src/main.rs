@@ -1,9 +1,7 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
 use eframe::egui;
-use std::env; // Added for std::env::current_exe
 use std::error::Error;
-use std::process::Command;
 use serde::{Deserialize, Serialize}; // For IPC message serialization
 use std::sync::Arc; // For Arc<tokio::runtime::Runtime>
 use tokio::sync::{mpsc, Mutex as TokioMutex}; // mpsc for channels, Mutex for shared writers
@@ -11,17 +9,34 @@ use tokio::net::UnixStream;
 use tokio_util::codec::{FramedRead, LinesCodec}; // For reading lines from UnixStream
 use futures::stream::StreamExt; // For stream.next()
 use clap::Parser; // Added for CLI argument parsing
-use notify_rust::Notification; // Added for desktop notifications
 use home; // Added for persisting username
 use std::path::PathBuf; // Added for instance-specific username file path
+use std::collections::{HashMap, HashSet};
 
 mod components; // Added to use the components module
+mod slash_commands; // Parser for "/me", "/join", ... typed into the chat input
+mod keybindings; // Configurable shortcuts (send, new line, edit last message, quick switcher)
+mod gui_settings; // Preferences saved between runs
+mod notifications; // Mutes, do-not-disturb and quiet hours for desktop notifications
+mod tray; // Tray icon with the unread count; the window closes to it
+mod daemon_control; // Attaching to, starting, stopping and restarting the daemon
+
+// How often the text being typed is saved to the daemon as the conversation's draft
+const DRAFT_SAVE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+
+// How often the daemon's info is refreshed while the Settings panel is open; an answer older than twice this
+// counts as the daemon not responding
+const DAEMON_INFO_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
 
 // --- IPC Structures ---
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IpcPeer {
     pub id: String,       // Unique identifier for the peer (e.g., from mDNS)
     pub username: String, // Display name
+    #[serde(default)]
+    pub status: Option<String>, // e.g. "away"; None means online
+    #[serde(default)]
+    pub rooms: Vec<String>, // Rooms the peer has joined
     // Add other relevant peer info if needed, e.g., IP address, port for direct comms if ever used
 }
 
@@ -31,13 +46,61 @@ pub enum GuiToDaemonCommand {
     SendMessage {
         recipient_id: String, // ID of the peer to send to (or a broadcast/group ID)
         content: String,
+        #[serde(default)]
+        reply_to: Option<String>, // Id of the message being answered, in the same conversation
     },
     RequestHistory { // Example for history
         peer_id: String, 
         since_timestamp: Option<chrono::DateTime<chrono::Utc>>,
+        #[serde(default)]
+        around_message_id: Option<String>, // Load from shortly before this message onwards (jumping to a search hit)
     },
     SetUsername { username: String }, // Added command
     ClearDaemonPeerCache, // Added command to tell daemon to clear its peer list
+    SetStatus { status: Option<String> }, // None = back to online
+    JoinRoom { room: String },
+    LeaveRoom { room: String },
+    GetRooms,
+    SendFile { recipient_id: String, file_path: String }, // The daemon reads the file itself
+    GetConversations,
+    MarkRead { conversation_id: String, message_id: Option<String> }, // None = up to the latest message
+    MarkAllRead,
+    EditMessage { message_id: String, content: String }, // Only our own messages, within the daemon's time window
+    DeleteMessage { message_id: String }, // For everyone in the conversation
+    ToggleReaction { message_id: String, emoji: String }, // Adds our reaction, or removes it if it's there
+    SetPinned { message_id: String, pinned: bool }, // For everyone in the conversation
+    GetPinned { conversation_id: String },
+    SetStarred { message_id: String, starred: bool }, // Only for us
+    GetStarred,
+    SetExpiry { conversation_id: String, expiry_secs: Option<u64> }, // For everyone in the conversation; None = off
+    GetExpiry { conversation_id: String },
+    ScheduleMessage { recipient_id: String, content: String, send_at: chrono::DateTime<chrono::Utc> }, // Sent by the daemon at that time
+    EditScheduled {
+        scheduled_id: String,
+        #[serde(default)]
+        content: Option<String>, // None keeps the text
+        #[serde(default)]
+        send_at: Option<chrono::DateTime<chrono::Utc>>, // None keeps the time
+    },
+    CancelScheduled { scheduled_id: String },
+    GetScheduled,
+    SetDraft { conversation_id: String, content: String }, // Empty content removes the draft
+    GetDrafts,
+    SearchMessages {
+        query: String, // Words matched against content and sender names; may be empty when a date range is given
+        #[serde(default)]
+        peer_id: Option<String>, // Only this conversation
+        #[serde(default)]
+        before: Option<chrono::DateTime<chrono::Utc>>,
+        #[serde(default)]
+        after: Option<chrono::DateTime<chrono::Utc>>,
+        #[serde(default)]
+        limit: Option<u32>, // None = the daemon's default page size
+        #[serde(default)]
+        offset: u32, // Hits already shown, for "Load more"
+    },
+    GetDaemonInfo,
+    Shutdown, // Stops the daemon (Settings → Daemon, or quitting with "stop the daemon" on)
     // Add other commands as needed (e.g., set username, status updates)
 }
 
@@ -55,7 +118,82 @@ pub enum DaemonToGuiMessage {
     },
     Error(String), // For generic error reporting from daemon to GUI
     IdentityInfo { user_id: String }, // Added new variant
+    DaemonInfo(DaemonInfo), // Answer to GetDaemonInfo
+    DaemonStopping { reason: String }, // The daemon is shutting down; the connection closes right after
+    #[serde(skip)]
+    IpcConnected, // From our own IPC task after (re)connecting, never from the daemon
+    #[serde(skip)]
+    IpcDisconnected, // Same, when the connection is lost or couldn't be made
     Success(String), // Added for success confirmations from daemon
+    RoomList(Vec<String>), // Rooms the daemon is in, "#everyone" first
+    ConversationList(Vec<ConversationSummary>), // Pushed whenever unread counts change
+    MessageUpdated(Message), // Edited, deleted, reacted to, pinned or starred; replaces the message with the same id
+    PinnedMessages {
+        conversation_id: String,
+        messages: Vec<Message>, // Most recently pinned first
+    }, // Pushed whenever a pin in the conversation changes
+    StarredMessages(Vec<Message>), // Most recently starred first; pushed whenever a star changes
+    ConversationTimer(ConversationTimer), // Pushed whenever anyone in the conversation changes it
+    MessagesExpired {
+        conversation_id: String,
+        message_ids: Vec<String>, // Purged by the daemon; removed from view
+    },
+    ScheduledMessages(Vec<ScheduledMessage>), // Next to be sent first; pushed whenever one is added, changed or sent
+    Drafts(Vec<Draft>), // Every saved draft; the answer to SetDraft and GetDrafts
+    SearchResults {
+        query: String,
+        offset: u32,
+        hits: Vec<SearchHit>, // Best match first (newest first for a date range without text)
+        has_more: bool,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub conversation_id: String,
+    pub message: Message,
+    pub snippet: String, // Excerpt around the matches
+    pub highlights: Vec<(usize, usize)>, // Byte ranges of matched words in `snippet`
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationTimer {
+    pub conversation_id: String,
+    pub expiry_secs: Option<u64>, // None: messages don't disappear
+    pub set_by: Option<String>, // Full ID; None if it was never set
+    pub set_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledMessage {
+    pub id: String,
+    pub recipient: String, // Peer ID or room, like a conversation ID
+    pub content: String,
+    pub send_at: chrono::DateTime<chrono::Utc>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    #[serde(default)]
+    pub last_error: Option<String>, // Why the daemon's latest attempt failed; it tries again
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Draft {
+    pub conversation_id: String,
+    pub content: String,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationSummary {
+    pub conversation_id: String, // Peer ID or room
+    pub unread_count: u32,
+    pub last_read_message_id: Option<String>,
+    pub last_activity: chrono::DateTime<chrono::Utc>,
+    #[serde(default)]
+    pub last_message_preview: String, // Content of the newest message
+    #[serde(default)]
+    pub last_message_sender: String,
+    #[serde(default)]
+    pub last_message_is_self: bool,
 }
 // --- End IPC Structures ---
 
@@ -67,6 +205,95 @@ pub struct Message {
     pub content: String,
     pub timestamp: chrono::DateTime<chrono::Utc>,
     pub is_self: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attachment: Option<Attachment>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edited_at: Option<chrono::DateTime<chrono::Utc>>, // Set once the sender changed the text
+    #[serde(default)]
+    pub deleted: bool, // Deleted for everyone: shown as a tombstone
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<String>, // Id of the message this one answers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_preview: Option<ReplyPreview>, // The parent as the sender saw it, shown if we don't have the parent
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reactions: Vec<Reaction>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mentions: Vec<String>, // Full IDs @mentioned in a room message
+    #[serde(default)]
+    pub mentions_me: bool, // We are one of them: notified even in a muted room
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pinned_at: Option<chrono::DateTime<chrono::Utc>>, // Pinned for everyone in the conversation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pinned_by: Option<String>, // Full ID
+    #[serde(default)]
+    pub starred: bool, // Starred by us; nobody else sees it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_in: Option<u64>, // Seconds after `timestamp` until every copy is purged
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reaction {
+    pub emoji: String,
+    pub reactors: Vec<String>, // Full IDs
+    #[serde(default)]
+    pub by_self: bool, // Ours is one of them; clicking the chip takes it back
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplyPreview {
+    pub sender: String,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attachment {
+    pub file_name: String,
+    pub size_bytes: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub local_path: Option<String>, // Where the file is on this machine (None if the daemon couldn't save it)
+}
+
+// Version, PID and load of the daemon we're attached to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DaemonInfo {
+    pub version: String,
+    pub pid: u32,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    pub socket_path: String,
+    pub ipc_clients: usize, // GUIs, CLIs and WebSockets attached, us included
+    pub peers: usize,
+    #[serde(default)]
+    pub user_id: Option<String>, // The identity the daemon announces; None until a username is set
+}
+
+// The name part of a full id ("alice - a1b2c3d4" -> "alice"); the daemon never puts " - " in the suffix
+fn username_of(user_id: &str) -> &str {
+    user_id.rsplit_once(" - ").map_or(user_id, |(username, _)| username)
+}
+
+// Room IDs ("#lunch") share the recipient field with peer IDs
+pub fn is_room_id(id: &str) -> bool {
+    id.starts_with('#')
+}
+
+// Which sidebar entry a message belongs to: the room for room messages, otherwise the other peer
+pub fn conversation_id(message: &Message) -> &str {
+    if message.is_self || is_room_id(&message.recipient) {
+        &message.recipient
+    } else {
+        &message.sender
+    }
+}
+
+// Name to show for a conversation or sender: rooms as-is, peers by username (falling back to the name part of the ID)
+pub fn display_name(id: &str, peers: &[IpcPeer]) -> String {
+    if is_room_id(id) {
+        return id.to_string();
+    }
+    match peers.iter().find(|peer| peer.id == id) {
+        Some(peer) => peer.username.clone(),
+        None => id.split(" - ").next().unwrap_or(id).to_string(),
+    }
 }
 
 #[derive(PartialEq, Debug)]
@@ -85,11 +312,13 @@ type DaemonToGuiRx = mpsc::Receiver<DaemonToGuiMessage>;
 // State for the settings panel
 struct SettingsState {
     edit_username_input: String,
+    recording_shortcut: Option<keybindings::Action>, // Waiting for the user to press the new shortcut for this action
+    shortcut_error: Option<String>, // Why the last shortcut pressed wasn't taken
 }
 
 impl SettingsState {
     fn new() -> Self {
-        Self { edit_username_input: String::new() }
+        Self { edit_username_input: String::new(), recording_shortcut: None, shortcut_error: None }
     }
 }
 
@@ -102,18 +331,39 @@ struct Args {
 }
 
 struct ChatApp {
-    message_input: String,
-    messages: Vec<Message>,
+    message_input: String, // What's typed for the open conversation (see sync_draft)
+    drafts: HashMap<String, String>, // Unsent text per conversation as last saved to the daemon
+    draft_conversation: Option<String>, // Conversation whose text is in message_input
+    draft_saved_at: std::time::Instant, // Last time a draft was sent to the daemon
+    conversations: HashMap<String, Vec<Message>>, // Messages per peer/room ID (see conversation_id), oldest first
+    loaded_conversations: HashSet<String>, // Conversations whose history has been requested from the daemon
+    conversation_summaries: HashMap<String, ConversationSummary>, // Unread counts and last activity, from the daemon
+    visible_conversation: Option<String>, // Conversation on screen last frame (Chat panel open and window focused)
     current_panel: CurrentPanel,
     peers: Vec<IpcPeer>,             // To store discovered peers
+    rooms: Vec<String>,              // Rooms we're in ("#everyone" plus joined ones), from the daemon
+    slash_hint: Option<String>,      // Inline help/feedback shown above the chat input
     daemon_status: Option<DaemonToGuiMessage>, // To store last known daemon status
     current_chat_peer_id: Option<String>, // ID of the peer the user is currently chatting with
     current_user_id: Option<String>, // Changed to Option<String>
     username_input: String, // Added for username prompt
     show_username_prompt: bool, // Added to control username prompt visibility
     username_file_path: Option<PathBuf>, // Added for instance-specific username file path
+    gui_settings: gui_settings::GuiSettings, // Keybindings and other preferences, saved to settings_file_path
+    settings_file_path: Option<PathBuf>, // Instance-specific like the username file
     is_loading: bool, // Added to show a loader until identity is confirmed
     settings_state: SettingsState, // Added for settings panel UI state
+    history_state: components::history::HistoryState, // Search box, date range and results of the History panel
+    chat_area_state: components::chat_area::ChatAreaState, // Jump to a message (from History) and the message being edited
+    quick_switcher: components::quick_switcher::QuickSwitcherState, // Ctrl+K conversation picker
+    notifier: notifications::Notifier, // Desktop notifications, one per conversation, and what was clicked on them
+    tray: Option<tray::TrayIcon>, // None without a tray host; closing the window then quits
+    quitting: bool, // Quit was picked in the tray, so the next close request really closes
+    daemon: daemon_control::DaemonControl, // The daemon process: attached to or started, stop and restart
+    daemon_info: Option<(DaemonInfo, std::time::Instant)>, // Last GetDaemonInfo answer and when it came
+    daemon_info_requested_at: std::time::Instant,
+    identity_check_pending: bool, // Set on (re)connect; the next DaemonInfo decides whether to send SetUsername
+    ipc_connected: bool, // Connected to the daemon's socket right now
     
     // IPC related fields
     rt: Arc<tokio::runtime::Runtime>,
@@ -125,16 +375,18 @@ struct ChatApp {
 
 impl ChatApp {
     fn new(
-        _cc: &eframe::CreationContext<'_>, 
-        daemon_socket_path_for_instance: String,
-        username_file_path_for_instance: Option<PathBuf> // Added new parameter
+        cc: &eframe::CreationContext<'_>, 
+        daemon: daemon_control::DaemonControl,
+        username_file_path_for_instance: Option<PathBuf>, // Added new parameter
+        settings_file_path_for_instance: Option<PathBuf>,
+        instance: u16
     ) -> Self {
         let rt = Arc::new(tokio::runtime::Runtime::new().expect("Failed to create Tokio runtime"));
         let (daemon_to_gui_tx, daemon_to_gui_rx_local) = mpsc::channel::<DaemonToGuiMessage>(32);
         let daemon_to_gui_rx_arc = Arc::new(TokioMutex::new(Some(daemon_to_gui_rx_local)));
 
         let (gui_cmd_tx, mut gui_cmd_rx) = mpsc::channel::<GuiToDaemonCommand>(32);
-        let task_socket_path = daemon_socket_path_for_instance.clone();
+        let task_socket_path = daemon.socket_path().to_string();
 
         // Attempt to load username from file
         let mut loaded_username = String::new();
@@ -170,15 +422,17 @@ impl ChatApp {
                             is_connected_to_network: true, 
                             active_interface_name: Some("Connected".to_string()) 
                         }).await;
+                        // A new or restarted daemon needs our username again
+                        let _ = daemon_to_gui_tx.send(DaemonToGuiMessage::IpcConnected).await;
                         
                         let (reader, mut writer) = tokio::io::split(stream);
                         let mut framed_reader = FramedRead::new(reader, LinesCodec::new());
                         
                         loop {
                             tokio::select! {
-                                Some(line_result) = framed_reader.next() => {
+                                line_result = framed_reader.next() => {
                                     match line_result {
-                                        Ok(line) => {
+                                        Some(Ok(line)) => {
                                             match serde_json::from_str::<DaemonToGuiMessage>(&line) {
                                                 Ok(msg) => {
                                                     if daemon_to_gui_tx.send(msg).await.is_err() {
@@ -187,15 +441,24 @@ impl ChatApp {
                                                     }
                                                 }
                                                 Err(e) => {
-                                                    eprintln!("Failed to deserialize message from daemon: {}. Line: {}", e, line);
+                                                    eprintln!("Failed to deserialize message from daemon: {} ({} bytes)", e, line.len());
                                                 }
                                             }
                                         }
-                                        Err(e) => {
+                                        Some(Err(e)) => {
                                             eprintln!("Error reading from daemon socket: {}", e);
                                             let _ = daemon_to_gui_tx.send(DaemonToGuiMessage::Error("Socket read error".to_string())).await;
                                             break; 
                                         }
+                                        None => {
+                                            // The daemon stopped (or was restarted); reconnect below
+                                            println!("Daemon closed the connection at {}", task_socket_path);
+                                            let _ = daemon_to_gui_tx.send(DaemonToGuiMessage::DaemonStatus {
+                                                is_connected_to_network: false,
+                                                active_interface_name: Some("Daemon disconnected".to_string())
+                                            }).await;
+                                            break;
+                                        }
                                     }
                                 },
                                 Some(command_to_send) = gui_cmd_rx.recv() => {
@@ -228,52 +491,378 @@ impl ChatApp {
                         }).await;
                     }
                 }
+                let _ = daemon_to_gui_tx.send(DaemonToGuiMessage::IpcDisconnected).await;
                 println!("Retrying connection to {} in 5s...", task_socket_path);
                 tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
             }
         });
 
-        let mut app = Self {
+        Self {
             message_input: String::new(),
-            messages: Vec::new(),
+            drafts: HashMap::new(),
+            draft_conversation: None,
+            draft_saved_at: std::time::Instant::now(),
+            conversations: HashMap::new(),
+            loaded_conversations: HashSet::new(),
+            conversation_summaries: HashMap::new(),
+            visible_conversation: None,
             current_panel: CurrentPanel::Chat,
             peers: Vec::new(),
+            rooms: Vec::new(),
+            slash_hint: None,
             daemon_status: None,
             current_chat_peer_id: None,
             current_user_id: None, // Initialized to None
-            username_input: loaded_username.clone(), // Use loaded username or empty
+            username_input: loaded_username, // Use loaded username or empty
             show_username_prompt: initial_show_username_prompt, // Show prompt based on loaded status
             username_file_path: username_file_path_for_instance.clone(), // Store the passed path
+            gui_settings: gui_settings::GuiSettings::load(&settings_file_path_for_instance),
+            settings_file_path: settings_file_path_for_instance,
             is_loading: !initial_show_username_prompt, // If username loaded, start in loading state, else prompt will show first
             settings_state: SettingsState::new(), // Initialize settings state
+            history_state: components::history::HistoryState::new(),
+            chat_area_state: components::chat_area::ChatAreaState::new(),
+            quick_switcher: components::quick_switcher::QuickSwitcherState::new(),
+            notifier: notifications::Notifier::new(cc.egui_ctx.clone()),
+            tray: tray::TrayIcon::start(&rt_clone_for_app, cc.egui_ctx.clone(), instance),
+            quitting: false,
+            daemon,
+            daemon_info: None,
+            daemon_info_requested_at: std::time::Instant::now(),
+            identity_check_pending: false,
+            ipc_connected: false,
             rt: rt_clone_for_app, 
             gui_to_daemon_tx: Some(gui_cmd_tx_clone_for_app), 
             daemon_to_gui_rx: daemon_to_gui_rx_arc,
             ipc_connection_status: "Connecting...".to_string(),
             requested_initial_peers: false, // Initialize flag
-        };
+        }
+        // The loaded username is sent to the daemon once connected (IpcConnected)
+    }
+
+    // The username saved by the prompt or the Settings panel, if there is one
+    fn saved_username(&self) -> Option<String> {
+        let path = self.username_file_path.as_ref()?;
+        let username = std::fs::read_to_string(path).ok()?;
+        Some(username.trim().to_string()).filter(|username| !username.is_empty())
+    }
+
+    // Adopts the daemon's identity (answer to SetUsername, or the one it already had) and loads the chat state once
+    fn apply_identity(&mut self, user_id: String) {
+        let old_id_log_display = self.current_user_id.as_deref().unwrap_or("None").to_string(); // Clone to avoid borrow issue
+        self.current_user_id = Some(user_id.clone());
+        self.show_username_prompt = false; // Hide prompt after getting identity
+        self.is_loading = false; // Stop loading screen
+        println!("GUI: Received IdentityInfo, current_user_id set from '{}' to: {}", old_id_log_display, user_id);
 
-        // If username was loaded, send it to the daemon
-        if !initial_show_username_prompt {
-            if let Some(tx) = &app.gui_to_daemon_tx {
-                let command = GuiToDaemonCommand::SetUsername { username: loaded_username.clone() };
+        // Request peers once after identity is confirmed and if not already requested
+        if !self.requested_initial_peers {
+            if let Some(tx) = &self.gui_to_daemon_tx {
                 let tx_clone = tx.clone();
-                let username_to_send = loaded_username.clone(); // Clone for the async block
-                app.rt.spawn(async move {
-                    println!("GUI: Sending pre-loaded username '{}' to daemon.", username_to_send);
-                    if let Err(e) = tx_clone.send(command).await {
-                        eprintln!("Failed to send pre-loaded SetUsername command: {}", e);
+                self.rt.spawn(async move {
+                    println!("GUI: Sending initial GetPeers request.");
+                    if let Err(e) = tx_clone.send(GuiToDaemonCommand::GetPeers).await {
+                        eprintln!("Failed to send initial GetPeers request: {}", e);
+                    }
+                    if let Err(e) = tx_clone.send(GuiToDaemonCommand::GetRooms).await {
+                        eprintln!("Failed to send initial GetRooms request: {}", e);
+                    }
+                    if let Err(e) = tx_clone.send(GuiToDaemonCommand::GetConversations).await {
+                        eprintln!("Failed to send initial GetConversations request: {}", e);
+                    }
+                    if let Err(e) = tx_clone.send(GuiToDaemonCommand::GetScheduled).await {
+                        eprintln!("Failed to send initial GetScheduled request: {}", e);
+                    }
+                    if let Err(e) = tx_clone.send(GuiToDaemonCommand::GetDrafts).await {
+                        eprintln!("Failed to send initial GetDrafts request: {}", e);
                     }
                 });
+                self.requested_initial_peers = true;
             }
         }
-        app
+    }
+
+    // After (re)connecting: keeps the daemon's identity if it has ours, so reconnecting or attaching a second GUI doesn't
+    // give the user a new id. SetUsername goes out only if the daemon has no identity or one with a different name.
+    fn check_daemon_identity(&mut self, daemon_user_id: Option<String>) {
+        let saved_username = self.saved_username();
+        match (daemon_user_id, saved_username) {
+            (Some(user_id), Some(username)) if username_of(&user_id) != username => {
+                println!("GUI: Daemon's identity is '{}'; sending saved username '{}'.", user_id, username);
+                self.send_to_daemon(GuiToDaemonCommand::SetUsername { username });
+            }
+            (Some(user_id), _) => self.apply_identity(user_id),
+            (None, Some(username)) => {
+                println!("GUI: Sending saved username '{}' to daemon.", username);
+                self.send_to_daemon(GuiToDaemonCommand::SetUsername { username });
+            }
+            (None, None) => {} // The username prompt asks for one
+        }
+    }
+
+    fn request_daemon_info(&mut self) {
+        self.daemon_info_requested_at = std::time::Instant::now();
+        self.send_to_daemon(GuiToDaemonCommand::GetDaemonInfo);
+    }
+
+    fn send_to_daemon(&self, command: GuiToDaemonCommand) {
+        if let Some(tx) = &self.gui_to_daemon_tx {
+            let tx_clone = tx.clone();
+            self.rt.spawn(async move {
+                if let Err(e) = tx_clone.send(command).await {
+                    eprintln!("Failed to send command to daemon: {}", e);
+                }
+            });
+        } else {
+            eprintln!("Error: gui_to_daemon_tx is None, cannot send command.");
+        }
+    }
+
+    // Carries out a slash command parsed by chat_area; feedback goes to the inline hint above the input
+    fn run_slash_command(&mut self, command: slash_commands::SlashCommand) {
+        use slash_commands::SlashCommand;
+        let current_user_id = self.current_user_id.clone().unwrap_or_default();
+        // Full IDs look like "alice - a1b2c3d4"; actions and hints use just the name
+        let display_name = current_user_id.rsplit_once(" - ").map_or(current_user_id.as_str(), |(name, _)| name).to_string();
+
+        match command {
+            SlashCommand::Me(action) => {
+                let content = format!("* {} {}", display_name, action);
+                self.send_text_to_current_chat(content);
+            }
+            SlashCommand::Shrug(text) => {
+                let content = if text.is_empty() { slash_commands::SHRUG.to_string() } else { format!("{} {}", text, slash_commands::SHRUG) };
+                self.send_text_to_current_chat(content);
+            }
+            SlashCommand::Status(status) => {
+                self.slash_hint = Some(match status {
+                    Some(ref s) => format!("Status set to '{}'.", s),
+                    None => "You're shown as online.".to_string(),
+                });
+                if let Some(tray) = &self.tray {
+                    tray.set_status(status.clone());
+                }
+                self.send_to_daemon(GuiToDaemonCommand::SetStatus { status });
+            }
+            SlashCommand::Nick(username) => {
+                // Same flow as the Settings panel: save, tell the daemon, wait for the new IdentityInfo
+                if let Some(ref path) = self.username_file_path {
+                    if let Err(e) = std::fs::write(path, username.as_bytes()) {
+                        eprintln!("Failed to save username to file {:?}: {}", path, e);
+                    }
+                }
+                self.send_to_daemon(GuiToDaemonCommand::SetUsername { username });
+                self.is_loading = true;
+            }
+            SlashCommand::Clear => {
+                // Only hides the messages here; the daemon keeps the history
+                if let Some(conversation) = &self.current_chat_peer_id {
+                    self.conversations.remove(conversation);
+                }
+                self.slash_hint = None;
+            }
+            SlashCommand::File(file_path) => {
+                let Some(recipient_id) = self.current_chat_peer_id.clone() else {
+                    self.slash_hint = Some("Select a peer or room before sending a file.".to_string());
+                    return;
+                };
+                // Quick local check for a friendlier hint; the daemon reports size limits or delivery errors via Error
+                match std::fs::metadata(&file_path) {
+                    Ok(metadata) if metadata.is_file() => {}
+                    Ok(_) => {
+                        self.slash_hint = Some(format!("'{}' is not a file.", file_path));
+                        return;
+                    }
+                    Err(e) => {
+                        self.slash_hint = Some(format!("Can't read '{}': {}", file_path, e));
+                        return;
+                    }
+                }
+                // Shows up in the conversation once the daemon has sent it and echoes it back
+                self.send_to_daemon(GuiToDaemonCommand::SendFile { recipient_id, file_path });
+            }
+            SlashCommand::Mute(change) => {
+                let Some(conversation) = self.current_chat_peer_id.clone() else {
+                    self.slash_hint = Some("Select a peer or room to mute.".to_string());
+                    return;
+                };
+                let conversation_name = crate::display_name(&conversation, &self.peers);
+                let now = chrono::Utc::now();
+                let change = change.unwrap_or(if self.gui_settings.notifications.is_muted(&conversation, now) {
+                    notifications::MuteChange::Off
+                } else {
+                    notifications::MuteChange::UntilUnmuted
+                });
+                self.change_mute(&conversation, change);
+                self.slash_hint = Some(match self.gui_settings.notifications.describe_mute(&conversation, now) {
+                    Some(mute) => format!("{}: {}. Type /mute off to unmute.", conversation_name, mute),
+                    None => format!("Notifications from {} are back on.", conversation_name),
+                });
+            }
+            SlashCommand::Timer(expiry_secs) => {
+                let Some(conversation_id) = self.current_chat_peer_id.clone() else {
+                    self.slash_hint = Some("Select a peer or room to set its timer.".to_string());
+                    return;
+                };
+                // The daemon answers with ConversationTimer once the conversation has the change
+                self.send_to_daemon(GuiToDaemonCommand::SetExpiry { conversation_id, expiry_secs });
+            }
+            SlashCommand::Later { send_at, text } => {
+                let Some(recipient_id) = self.current_chat_peer_id.clone() else {
+                    self.slash_hint = Some("Select a peer or room to schedule a message for.".to_string());
+                    return;
+                };
+                self.slash_hint = Some(format!("Scheduled for {}. It's listed above the conversation until it's sent.", slash_commands::describe_send_time(send_at)));
+                self.send_to_daemon(GuiToDaemonCommand::ScheduleMessage { recipient_id, content: text, send_at });
+            }
+            SlashCommand::Join(room) => {
+                self.slash_hint = Some(format!("Joining {}… it will show up under Rooms.", room));
+                self.send_to_daemon(GuiToDaemonCommand::JoinRoom { room });
+            }
+            SlashCommand::Leave(room) => {
+                let room = room.or_else(|| self.current_chat_peer_id.clone().filter(|id| is_room_id(id)));
+                match room {
+                    Some(room) => {
+                        self.slash_hint = None;
+                        self.send_to_daemon(GuiToDaemonCommand::LeaveRoom { room });
+                    }
+                    None => self.slash_hint = Some("Open a room or name one: /leave <room>".to_string()),
+                }
+            }
+            SlashCommand::Help => {
+                self.slash_hint = Some(slash_commands::help_text());
+            }
+        }
+    }
+
+    fn send_text_to_current_chat(&mut self, content: String) {
+        components::chat_area::send_text(
+            content,
+            None,
+            &self.current_chat_peer_id,
+            &self.gui_to_daemon_tx,
+            &self.rt
+        );
+    }
+
+    // Gives every conversation its own input text. Switching conversations saves the text as the old one's draft
+    // and brings back the new one's; while typing, the draft is saved every DRAFT_SAVE_INTERVAL (and right away
+    // once the input is emptied, e.g. by sending, so a sent message doesn't come back as a draft).
+    fn sync_draft(&mut self) {
+        if self.chat_area_state.is_editing() {
+            return; // The input holds a sent message being edited, not a draft
+        }
+        if self.current_chat_peer_id != self.draft_conversation {
+            if let Some(previous) = self.draft_conversation.take() {
+                self.save_draft(previous, self.message_input.clone());
+            }
+            self.message_input = self.current_chat_peer_id.as_ref().and_then(|id| self.drafts.get(id)).cloned().unwrap_or_default();
+            self.draft_conversation = self.current_chat_peer_id.clone();
+            return;
+        }
+        let Some(conversation) = self.draft_conversation.clone() else {
+            return;
+        };
+        let saved = self.drafts.get(&conversation).map_or("", String::as_str);
+        if saved != self.message_input && (self.message_input.is_empty() || self.draft_saved_at.elapsed() >= DRAFT_SAVE_INTERVAL) {
+            self.save_draft(conversation, self.message_input.clone());
+        }
+    }
+
+    fn save_draft(&mut self, conversation: String, content: String) {
+        if self.drafts.get(&conversation).map_or("", String::as_str) == content {
+            return;
+        }
+        if content.is_empty() {
+            self.drafts.remove(&conversation);
+        } else {
+            self.drafts.insert(conversation.clone(), content.clone());
+        }
+        self.draft_saved_at = std::time::Instant::now();
+        self.send_to_daemon(GuiToDaemonCommand::SetDraft { conversation_id: conversation, content });
+    }
+
+    // Adds a message to its conversation, ignoring one we already have (e.g. from both history and a live event)
+    fn add_message(&mut self, message: Message) -> bool {
+        let conversation = self.conversations.entry(conversation_id(&message).to_string()).or_default();
+        if conversation.iter().any(|m| m.id == message.id) {
+            return false;
+        }
+        let needs_sort = conversation.last().is_some_and(|last| last.timestamp > message.timestamp);
+        conversation.push(message);
+        if needs_sort {
+            conversation.sort_by_key(|m| m.timestamp);
+        }
+        true
+    }
+
+    // From /mute or a sidebar row's menu; saved right away
+    fn change_mute(&mut self, conversation: &str, change: notifications::MuteChange) {
+        self.gui_settings.notifications.change_mute(conversation, change, chrono::Utc::now());
+        self.gui_settings.save(&self.settings_file_path);
+    }
+
+    // Called when a conversation comes into view: loads its history and pinned messages the first time and marks it read
+    fn show_conversation(&mut self, conversation: String) {
+        if self.loaded_conversations.insert(conversation.clone()) {
+            self.send_to_daemon(GuiToDaemonCommand::RequestHistory { peer_id: conversation.clone(), since_timestamp: None, around_message_id: None });
+            self.send_to_daemon(GuiToDaemonCommand::GetPinned { conversation_id: conversation.clone() });
+            self.send_to_daemon(GuiToDaemonCommand::GetExpiry { conversation_id: conversation.clone() });
+        }
+        self.notifier.clear(&conversation);
+        self.mark_conversation_read(conversation);
+    }
+
+    // Carries out a click or search from the History panel
+    fn run_history_action(&mut self, action: components::history::HistoryAction) {
+        use components::history::HistoryAction;
+        match action {
+            HistoryAction::OpenConversation(conversation) => {
+                self.current_panel = CurrentPanel::Chat;
+                self.current_chat_peer_id = Some(conversation);
+            }
+            HistoryAction::OpenMessage(message) => {
+                let conversation = conversation_id(&message).to_string();
+                let is_loaded = self.conversations.get(&conversation).is_some_and(|messages| messages.iter().any(|m| m.id == message.id));
+                if !is_loaded {
+                    // Older than the page we have (or never opened): load the stretch around it, which merges into the thread
+                    if self.loaded_conversations.insert(conversation.clone()) {
+                        self.send_to_daemon(GuiToDaemonCommand::GetPinned { conversation_id: conversation.clone() });
+                        self.send_to_daemon(GuiToDaemonCommand::GetExpiry { conversation_id: conversation.clone() });
+                    }
+                    self.send_to_daemon(GuiToDaemonCommand::RequestHistory {
+                        peer_id: conversation.clone(),
+                        since_timestamp: None,
+                        around_message_id: Some(message.id.clone()),
+                    });
+                }
+                self.chat_area_state.message_jump = Some(components::chat_area::MessageJump::new(message.id));
+                self.current_panel = CurrentPanel::Chat;
+                self.current_chat_peer_id = Some(conversation);
+            }
+            HistoryAction::Search { query, after, before, offset } => {
+                self.send_to_daemon(GuiToDaemonCommand::SearchMessages { query, peer_id: None, before, after, limit: None, offset });
+            }
+            HistoryAction::LoadStarred => self.send_to_daemon(GuiToDaemonCommand::GetStarred),
+            HistoryAction::Unstar(message_id) => self.send_to_daemon(GuiToDaemonCommand::SetStarred { message_id, starred: false }),
+        }
+    }
+
+    // Tells the daemon the user has seen this conversation, if it has unread messages.
+    // The daemon sends NewMessage before the updated ConversationList, so everything it counts is already on screen.
+    fn mark_conversation_read(&mut self, conversation: String) {
+        let has_unread = self.conversation_summaries.get(&conversation).is_some_and(|summary| summary.unread_count > 0);
+        if has_unread {
+            self.send_to_daemon(GuiToDaemonCommand::MarkRead { conversation_id: conversation, message_id: None });
+        }
     }
 }
 
 impl eframe::App for ChatApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        if let Ok(mut guard) = self.daemon_to_gui_rx.try_lock() {
+        // Own handle on the receiver so the handlers below can borrow all of `self`
+        let daemon_to_gui_rx = self.daemon_to_gui_rx.clone();
+        if let Ok(mut guard) = daemon_to_gui_rx.try_lock() {
             if let Some(ref mut rx) = *guard {
                 // Explicitly tell try_recv what type to expect
                 while let Ok(msg) = rx.try_recv() {
@@ -289,74 +878,161 @@ impl eframe::App for ChatApp {
                             self.peers = peers;
                         }
                         DaemonToGuiMessage::NewMessage(message) => { // message is already a full Message struct from daemon
-                            println!("GUI: NewMessage handler received: ID={}, Sender='{}', Content='{}', is_self (from daemon)={}, CurrentUserID='{:?}'",
-                                message.id, message.sender, message.content, message.is_self, self.current_user_id);
+                            // Never the content: stdout often ends up in a terminal scrollback or a journal
+                            println!("GUI: NewMessage handler received: ID={}, Sender='{}', is_self (from daemon)={}",
+                                message.id, message.sender, message.is_self);
                         
-                            // Any NewMessage from the daemon is considered a message from a foreign peer, as own messages are added locally
-                            // and the daemon is not expected to echo them back as NewMessage.
-                            // The daemon's handle_peer_tcp_connection already sets received_message.is_self = false.
-
-                            // We still check sender against current_user_id as a safeguard or if daemon logic changes.
-                            if self.current_user_id.as_ref().map_or(false, |uid| *uid == message.sender) {
-                                // This case implies an echo of our own message, which we typically don't expect here
-                                // if we've added it locally. If daemon *does* echo, this log helps.
-                                println!("GUI: Received NewMessage where sender ('{}') matches current_user_id ('{:?}'). ID: {}. This might be an unexpected echo. Ignoring.",
-                                    message.sender, self.current_user_id, message.id);
+                            // Our own messages come back too (is_self), once the daemon has sent them
+                            let is_own_message = message.is_self;
+                            let mentions_me = message.mentions_me;
+                            let summary = if mentions_me {
+                                format!("{} mentioned you in {}", display_name(&message.sender, &self.peers), message.recipient)
+                            } else if is_room_id(&message.recipient) {
+                                format!("New message in {} from {}", message.recipient, message.sender)
                             } else {
-                                // This is confirmed to be a message from another peer.
-                                // Add it to our list if its ID is not already present (to prevent daemon resend-duplicates).
-                                if !self.messages.iter().any(|m| m.id == message.id) {
-                                    println!("GUI: New foreign message (ID: {} from sender '{}'). Adding to list.", message.id, message.sender);
-                                    
-                                    if let Err(e) = Notification::new()
-                                        .summary(&format!("New message from {}", message.sender))
-                                        .body(&message.content)
-                                        .icon("dialog-information")
-                                        .appname("LocalChatGUI")
-                                        .show() {
-                                        eprintln!("Error displaying notification: {}", e);
-                                    }
+                                format!("New message from {}", message.sender)
+                            };
+                            // Room notifications list several senders, so each line says who wrote it
+                            let notification_line = if self.gui_settings.notifications.hide_message_text {
+                                None
+                            } else if is_room_id(&message.recipient) {
+                                Some(format!("{}: {}", message.sender, message.content))
+                            } else {
+                                Some(message.content.clone())
+                            };
+                            let message_conversation = conversation_id(&message).to_string();
 
-                                    let mut display_message = message; // clone the received message
-                                    display_message.is_self = false; // Ensure is_self is false for display purposes
-                                    self.messages.push(display_message);
-                                } else {
-                                    println!("GUI: Duplicate foreign message (ID: {} from sender '{}'). Not adding.", message.id, message.sender);
-                                }
+                            // Duplicates happen when a peer resends or history already brought the message in
+                            if !self.add_message(message) {
+                                println!("GUI: Duplicate message in conversation '{}'. Not adding.", message_conversation);
+                            } else if is_own_message {
+                                println!("GUI: Own message echoed by daemon, added to conversation '{}'.", message_conversation);
+                            } else if self.visible_conversation.as_ref() == Some(&message_conversation) {
+                                println!("GUI: Conversation '{}' is open in the focused window, skipping notification.", message_conversation);
+                            } else if let Some(reason) = self.gui_settings.notifications.silenced_because(&message_conversation, mentions_me, chrono::Local::now()) {
+                                println!("GUI: Skipping notification for '{}': {}.", message_conversation, reason);
+                            } else {
+                                let conversation_name = display_name(&message_conversation, &self.peers);
+                                self.notifier.notify(&message_conversation, &conversation_name, &summary, notification_line);
                             }
                         }
-                        DaemonToGuiMessage::HistoryResponse { messages, .. } => {
-                            self.messages.extend(messages);
+                        DaemonToGuiMessage::HistoryResponse { peer_id, messages } => {
+                            println!("GUI: Received {} history message(s) for '{}'", messages.len(), peer_id);
+                            for message in messages {
+                                self.add_message(message);
+                            }
                         }
                         DaemonToGuiMessage::Error(err_msg) => {
                             self.ipc_connection_status = format!("Daemon Error: {}", err_msg);
                             eprintln!("Received error from daemon: {}", err_msg);
                         }
-                        DaemonToGuiMessage::IdentityInfo { user_id } => {
-                            let old_id_log_display = self.current_user_id.as_deref().unwrap_or("None").to_string(); // Clone to avoid borrow issue
-                            self.current_user_id = Some(user_id.clone());
-                            self.show_username_prompt = false; // Hide prompt after getting identity
-                            self.is_loading = false; // Stop loading screen
-                            println!("GUI: Received IdentityInfo, current_user_id set from '{}' to: {}", old_id_log_display, user_id);
-                            
-                            // Request peers once after identity is confirmed and if not already requested
-                            if !self.requested_initial_peers {
-                                if let Some(tx) = &self.gui_to_daemon_tx {
-                                    let tx_clone = tx.clone();
-                                    self.rt.spawn(async move {
-                                        println!("GUI: Sending initial GetPeers request.");
-                                        if let Err(e) = tx_clone.send(GuiToDaemonCommand::GetPeers).await {
-                                            eprintln!("Failed to send initial GetPeers request: {}", e);
-                                        }
-                                    });
-                                    self.requested_initial_peers = true;
-                                }
-                            }
-                        }
+                        DaemonToGuiMessage::IdentityInfo { user_id } => self.apply_identity(user_id),
                         DaemonToGuiMessage::Success(msg) => {
                             self.ipc_connection_status = format!("Success: {}", msg);
                             println!("Received success message from daemon: {}", msg);
                         }
+                        DaemonToGuiMessage::RoomList(rooms) => {
+                            println!("GUI: Received RoomList: {:?}", rooms);
+                            // Leaving the open room (e.g. via /leave) closes it
+                            if self.current_chat_peer_id.as_ref().is_some_and(|id| is_room_id(id) && !rooms.contains(id)) {
+                                self.current_chat_peer_id = None;
+                            }
+                            self.rooms = rooms;
+                        }
+                        DaemonToGuiMessage::ConversationList(summaries) => {
+                            self.conversation_summaries = summaries
+                                .into_iter()
+                                .map(|summary| (summary.conversation_id.clone(), summary))
+                                .collect();
+                            // A message that just arrived in the conversation on screen has already been seen
+                            if let Some(conversation) = self.visible_conversation.clone() {
+                                self.mark_conversation_read(conversation);
+                            }
+                        }
+                        DaemonToGuiMessage::MessageUpdated(message) => {
+                            self.chat_area_state.update_pinned(&message);
+                            self.history_state.update_starred(&message);
+                            let conversation = self.conversations.get_mut(conversation_id(&message));
+                            if let Some(existing) = conversation.and_then(|messages| messages.iter_mut().find(|m| m.id == message.id)) {
+                                *existing = message;
+                            }
+                        }
+                        DaemonToGuiMessage::SearchResults { query, offset, hits, has_more } => {
+                            self.history_state.set_results(&query, offset as usize, hits, has_more);
+                        }
+                        DaemonToGuiMessage::PinnedMessages { conversation_id, messages } => {
+                            self.chat_area_state.pinned_messages.insert(conversation_id, messages);
+                        }
+                        DaemonToGuiMessage::StarredMessages(messages) => {
+                            self.history_state.set_starred(messages);
+                        }
+                        DaemonToGuiMessage::ConversationTimer(timer) => {
+                            let previous = self.chat_area_state.conversation_timers.insert(timer.conversation_id.clone(), timer.expiry_secs);
+                            // Say so when it changes in the open conversation (not when it's first loaded)
+                            if previous.is_some_and(|previous| previous != timer.expiry_secs)
+                                && self.current_chat_peer_id.as_ref() == Some(&timer.conversation_id)
+                            {
+                                let set_by = timer.set_by.as_deref().map_or_else(|| "Someone".to_string(), |id| display_name(id, &self.peers));
+                                self.slash_hint = Some(match timer.expiry_secs {
+                                    Some(secs) => format!("{} set new messages to disappear after {}.", set_by, slash_commands::describe_duration(secs)),
+                                    None => format!("{} turned disappearing messages off.", set_by),
+                                });
+                            }
+                        }
+                        DaemonToGuiMessage::ScheduledMessages(scheduled) => {
+                            self.chat_area_state.scheduled_messages = scheduled;
+                        }
+                        DaemonToGuiMessage::Drafts(drafts) => {
+                            let drafts: HashMap<String, String> = drafts.into_iter().map(|draft| (draft.conversation_id, draft.content)).collect();
+                            // The open conversation's text is whatever is in the input; its saved draft only
+                            // fills the input if the conversation was opened before the drafts came in
+                            if let Some(saved) = self.draft_conversation.as_ref()
+                                .filter(|conversation| self.message_input.is_empty() && !self.drafts.contains_key(*conversation))
+                                .filter(|_| !self.chat_area_state.is_editing())
+                                .and_then(|conversation| drafts.get(conversation))
+                            {
+                                self.message_input = saved.clone();
+                            }
+                            self.drafts = drafts;
+                        }
+                        DaemonToGuiMessage::DaemonInfo(info) => {
+                            if self.identity_check_pending {
+                                self.identity_check_pending = false;
+                                self.check_daemon_identity(info.user_id.clone());
+                            }
+                            self.daemon_info = Some((info, std::time::Instant::now()));
+                        }
+                        DaemonToGuiMessage::DaemonStopping { reason } => {
+                            // The IPC task notices the connection closing and keeps retrying, so a restarted daemon is picked up
+                            println!("GUI: Daemon is stopping: {}", reason);
+                            self.ipc_connection_status = "Daemon stopping".to_string();
+                            self.daemon_info = None;
+                        }
+                        DaemonToGuiMessage::IpcConnected => {
+                            self.ipc_connected = true;
+                            self.daemon_info = None;
+                            self.request_daemon_info();
+                            // The DaemonInfo answer says which identity the daemon (maybe a new or restarted one) already has;
+                            // the username is only sent if it has none or a different one. Then peers, rooms and unread counts are fetched again.
+                            self.requested_initial_peers = false;
+                            self.identity_check_pending = true;
+                        }
+                        DaemonToGuiMessage::IpcDisconnected => {
+                            self.ipc_connected = false;
+                        }
+                        DaemonToGuiMessage::MessagesExpired { conversation_id, message_ids } => {
+                            if let Some(messages) = self.conversations.get_mut(&conversation_id) {
+                                messages.retain(|message| !message_ids.contains(&message.id));
+                                // Replies keep their place but no longer quote the text (the daemon dropped it too)
+                                for reply in messages.iter_mut().filter(|m| m.reply_to.as_ref().is_some_and(|id| message_ids.contains(id))) {
+                                    reply.reply_preview = None;
+                                }
+                            }
+                            if let Some(pinned) = self.chat_area_state.pinned_messages.get_mut(&conversation_id) {
+                                pinned.retain(|message| !message_ids.contains(&message.id));
+                            }
+                            self.history_state.remove_messages(&message_ids);
+                        }
                     }
                 }
             }
@@ -364,6 +1040,72 @@ impl eframe::App for ChatApp {
 
         ctx.request_repaint_after(std::time::Duration::from_millis(100));
 
+        // A click on a notification brings the window to the front on that conversation
+        for action in self.notifier.poll() {
+            let (notifications::NotificationAction::Open(conversation) | notifications::NotificationAction::Reply(conversation)) = &action;
+            println!("GUI: Notification action {:?}", action);
+            show_window(ctx);
+            self.current_panel = CurrentPanel::Chat;
+            self.current_chat_peer_id = Some(conversation.clone());
+            if matches!(action, notifications::NotificationAction::Reply(_)) {
+                components::chat_area::focus_input(ctx);
+            }
+        }
+
+        // Notices a daemon we started exiting, and starts the new one during a restart. The Settings panel keeps its
+        // version, PID and health current.
+        self.daemon.poll();
+        if self.ipc_connected
+            && self.current_panel == CurrentPanel::Settings
+            && self.daemon_info_requested_at.elapsed() >= DAEMON_INFO_INTERVAL
+        {
+            self.request_daemon_info();
+        }
+
+        // The tray shows the total unread count; its menu opens the window, sets the status or quits
+        let tray_events = match self.tray.as_mut() {
+            Some(tray) => {
+                tray.set_unread(self.conversation_summaries.values().map(|summary| summary.unread_count).sum());
+                tray.poll()
+            }
+            None => Vec::new(),
+        };
+        for event in tray_events {
+            println!("GUI: Tray event {:?}", event);
+            match event {
+                tray::TrayEvent::Open => show_window(ctx),
+                tray::TrayEvent::SetStatus(status) => self.send_to_daemon(GuiToDaemonCommand::SetStatus { status }),
+                tray::TrayEvent::Quit => {
+                    self.quitting = true;
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                }
+            }
+        }
+        // Closing the window only hides it while the tray icon is there, so messages keep being received and notified.
+        // Wayland can't hide a window (winit ignores it there), so it's minimized instead.
+        let close_requested = ctx.input(|i| i.viewport().close_requested());
+        if close_requested && !self.quitting && self.tray.as_ref().is_some_and(|tray| tray.is_running()) {
+            ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+            if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+                ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(true));
+            } else {
+                ctx.send_viewport_cmd(egui::ViewportCommand::Visible(false));
+            }
+            println!("GUI: Window hidden to the tray.");
+        }
+
+        // Only a conversation the user can actually see gets marked as read
+        let window_focused = ctx.input(|i| i.viewport().focused.unwrap_or(true));
+        let visible_conversation = self.current_chat_peer_id.clone().filter(|_| {
+            window_focused && self.current_panel == CurrentPanel::Chat && !self.show_username_prompt && !self.is_loading
+        });
+        if visible_conversation != self.visible_conversation {
+            self.visible_conversation = visible_conversation.clone();
+            if let Some(conversation) = visible_conversation {
+                self.show_conversation(conversation);
+            }
+        }
+
         // --- Username Prompt Modal --- / Loading State --- / Main UI ---
         if self.show_username_prompt {
             let modal_width = 400.0;
@@ -418,7 +1160,7 @@ impl eframe::App for ChatApp {
                         
                         // Styled button
                         let button_bg = egui::Color32::from_rgb(25, 118, 210);
-                        if ui.add_sized(
+                        let submitted = ui.add_sized(
                             [modal_width - 80.0, 50.0],
                             egui::Button::new(
                                 egui::RichText::new("Set Username")
@@ -429,35 +1171,34 @@ impl eframe::App for ChatApp {
                             .fill(button_bg)
                             .corner_radius(12.0)
                             .stroke(egui::Stroke::NONE)
-                        ).clicked() || (ui.input(|i| i.key_pressed(egui::Key::Enter)) && !self.username_input.trim().is_empty()) {
-                            if !self.username_input.trim().is_empty() {
-                                if let Some(tx) = &self.gui_to_daemon_tx {
-                                    let command = GuiToDaemonCommand::SetUsername { username: self.username_input.trim().to_string() };
-                                    let tx_clone = tx.clone();
-                                    let username_to_save = self.username_input.trim().to_string(); // for logging & saving
-                                    let username_for_async_block = username_to_save.clone(); // Clone for the async block
-                                    self.rt.spawn(async move {
-                                        println!("GUI: Sending SetUsername command with username: {}", username_for_async_block);
-                                        if let Err(e) = tx_clone.send(command).await {
-                                            eprintln!("Failed to send SetUsername command: {}", e);
-                                        }
-                                    });
-                                    // Save the username
-                                    if let Some(ref path) = self.username_file_path { 
-                                        if let Err(e) = std::fs::write(path, username_to_save.as_bytes()) { 
-                                            eprintln!("Failed to save username to file {:?}: {}", path, e);
-                                        } else {
-                                            println!("GUI: Saved username '{}' to file {:?}.", username_to_save, path);
-                                        }
+                        ).clicked() || ui.input(|i| i.key_pressed(egui::Key::Enter));
+                        if submitted && !self.username_input.trim().is_empty() {
+                            if let Some(tx) = &self.gui_to_daemon_tx {
+                                let command = GuiToDaemonCommand::SetUsername { username: self.username_input.trim().to_string() };
+                                let tx_clone = tx.clone();
+                                let username_to_save = self.username_input.trim().to_string(); // for logging & saving
+                                let username_for_async_block = username_to_save.clone(); // Clone for the async block
+                                self.rt.spawn(async move {
+                                    println!("GUI: Sending SetUsername command with username: {}", username_for_async_block);
+                                    if let Err(e) = tx_clone.send(command).await {
+                                        eprintln!("Failed to send SetUsername command: {}", e);
+                                    }
+                                });
+                                // Save the username
+                                if let Some(ref path) = self.username_file_path { 
+                                    if let Err(e) = std::fs::write(path, username_to_save.as_bytes()) { 
+                                        eprintln!("Failed to save username to file {:?}: {}", path, e);
                                     } else {
-                                        eprintln!("Failed to determine instance-specific username file path to save username.");
+                                        println!("GUI: Saved username '{}' to file {:?}.", username_to_save, path);
                                     }
-                                    // After attempting to send and save, hide prompt and show loader
-                                    self.show_username_prompt = false;
-                                    self.is_loading = true; 
                                 } else {
-                                    eprintln!("Error: gui_to_daemon_tx is None, cannot send SetUsername");
+                                    eprintln!("Failed to determine instance-specific username file path to save username.");
                                 }
+                                // After attempting to send and save, hide prompt and show loader
+                                self.show_username_prompt = false;
+                                self.is_loading = true; 
+                            } else {
+                                eprintln!("Error: gui_to_daemon_tx is None, cannot send SetUsername");
                             }
                         }
                         
@@ -487,120 +1228,165 @@ impl eframe::App for ChatApp {
                 );
             });
 
+            // The quick switcher works from every panel; it goes first so its keys (Enter, Esc, arrows) aren't seen by the chat input.
+            // Not while Settings is waiting for a new shortcut, which may be this one.
+            if self.settings_state.recording_shortcut.is_none() && self.gui_settings.keybindings.consume(ctx, keybindings::Action::QuickSwitcher) {
+                self.quick_switcher.toggle();
+            }
+            if let Some(conversation) = components::quick_switcher::show(ctx, &mut self.quick_switcher, &self.peers, &self.rooms, &self.conversation_summaries) {
+                self.current_panel = CurrentPanel::Chat;
+                self.current_chat_peer_id = Some(conversation);
+                components::chat_area::focus_input(ctx);
+            }
+
+            let mut mute_change = None;
             egui::SidePanel::left("side_panel").show(ctx, |ui| {
-                components::sidemenu::show(
+                mute_change = components::sidemenu::show(
                     ui, 
                     &self.peers, 
+                    &self.rooms,
                     &mut self.current_chat_peer_id,
                     &self.gui_to_daemon_tx, // Pass the sender
                     &self.rt, // Pass the Tokio runtime Arc
-                    &self.current_user_id // Pass the current username
+                    components::sidemenu::ConversationMarkers {
+                        conversation_summaries: &self.conversation_summaries,
+                        drafts: &self.drafts,
+                        notifications: &self.gui_settings.notifications,
+                    }
                 );
             });
+            if let Some((conversation, change)) = mute_change {
+                self.change_mute(&conversation, change);
+            }
+            // Before the chat area, so a conversation picked in the sidebar opens with its own draft
+            self.sync_draft();
+
 
+            let mut slash_command = None; // Run after the panel so the command can use all of `self`
+            let mut history_action = None; // Same for clicks in the History panel
             egui::CentralPanel::default().show(ctx, |ui| match self.current_panel {
                 CurrentPanel::Chat => {
-                    components::chat_area::show(
+                    let current_conversation = self.current_chat_peer_id.as_ref()
+                        .and_then(|id| self.conversations.get(id))
+                        .map_or(&[][..], |messages| messages.as_slice());
+                    // @mention autocompletion offers the room's members (everyone we know in #everyone)
+                    self.chat_area_state.mention_candidates = match self.current_chat_peer_id.as_deref() {
+                        Some(room) if is_room_id(room) => self.peers.iter()
+                            .filter(|peer| room == "#everyone" || peer.rooms.iter().any(|peer_room| peer_room == room))
+                            .map(|peer| peer.username.clone())
+                            .collect(),
+                        _ => Vec::new(),
+                    };
+                    slash_command = components::chat_area::show(
                         ui, 
-                        &mut self.messages, 
+                        components::chat_area::ChatAreaView {
+                            messages: current_conversation,
+                            current_chat_peer_id: &self.current_chat_peer_id,
+                            keybindings: &self.gui_settings.keybindings,
+                        },
                         &mut self.message_input, 
-                        &self.current_user_id.as_deref().unwrap_or_default(),
-                        &self.current_chat_peer_id,
                         &self.gui_to_daemon_tx, // Pass the sender
-                        &self.rt // Pass the Tokio runtime Arc
+                        &self.rt, // Pass the Tokio runtime Arc
+                        &mut self.slash_hint,
+                        &mut self.chat_area_state
                     );
                 }
                 CurrentPanel::History => {
-                    components::history::show(ui);
+                    history_action = components::history::show(
+                        ui,
+                        &mut self.history_state,
+                        &self.conversation_summaries,
+                        &self.peers
+                    );
                 }
                 CurrentPanel::Settings => {
-                    components::settings::show(
-                        ui, 
-                        &mut self.settings_state,
-                        &mut self.current_user_id,
-                        &self.username_file_path,
-                        &self.gui_to_daemon_tx,
-                        &self.rt,
-                        &mut self.show_username_prompt,
-                        &mut self.is_loading,
-                        &mut self.peers
-                    );
+                    // Taller than the window on small screens
+                    egui::ScrollArea::vertical().auto_shrink([false, false]).show(ui, |ui| {
+                        components::settings::show(
+                            ui, 
+                            &mut self.settings_state,
+                            &mut self.current_user_id,
+                            &self.username_file_path,
+                            &self.gui_to_daemon_tx,
+                            &self.rt,
+                            &mut self.show_username_prompt,
+                            &mut self.is_loading,
+                            &mut self.peers
+                        );
+                        let notifications_changed = components::settings::show_notifications(ui, &mut self.gui_settings.notifications, &self.peers);
+                        let keybindings_changed = components::settings::show_keybindings(ui, &mut self.settings_state, &mut self.gui_settings.keybindings);
+                        let stop_daemon_on_quit = self.gui_settings.stop_daemon_on_quit;
+                        let daemon_action = components::settings::show_daemon(
+                            ui,
+                            &self.daemon,
+                            self.daemon_info.as_ref(),
+                            self.ipc_connected,
+                            &mut self.gui_settings.stop_daemon_on_quit
+                        );
+                        if keybindings_changed || notifications_changed || stop_daemon_on_quit != self.gui_settings.stop_daemon_on_quit {
+                            self.gui_settings.save(&self.settings_file_path);
+                        }
+                        if let Some(action) = daemon_action {
+                            println!("GUI: Daemon action {:?}", action);
+                            self.daemon.run(action);
+                        }
+                    });
                 }
             });
+            if let Some(command) = slash_command {
+                self.run_slash_command(command);
+            }
+            if let Some(action) = history_action {
+                self.run_history_action(action);
+            }
         }
     }
+
+    // Otherwise the daemon keeps running (receiving messages, sending scheduled ones) and the next GUI attaches to it
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        if self.gui_settings.stop_daemon_on_quit {
+            if let Err(e) = self.daemon.stop() {
+                eprintln!("GUI: Failed to stop the daemon on exit: {}", e);
+            }
+        }
+    }
+}
+
+// Brings the window back from the tray, the taskbar or behind other windows
+fn show_window(ctx: &egui::Context) {
+    ctx.send_viewport_cmd(egui::ViewportCommand::Visible(true));
+    ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(false));
+    ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     let args = Args::parse();
     println!("GUI Instance: {}", args.instance);
 
-    let daemon_tcp_port_for_instance = 12345 + (args.instance - 1);
-    let daemon_socket_path_for_instance = format!("/tmp/localchat_daemon{}.sock", args.instance);
-
     // Construct instance-specific username file path
     let username_file_path_for_instance = home::home_dir().map(|mut path| {
         path.push(format!(".localchat_gui_username_{}", args.instance));
         path
     });
+    // Other GUI preferences (keybindings, ...) go in a JSON file beside it
+    let settings_file_path_for_instance = home::home_dir().map(|mut path| {
+        path.push(format!(".localchat_gui_settings_{}.json", args.instance));
+        path
+    });
 
-    let mut daemon_command = Command::new("");
-
-    if cfg!(debug_assertions) {
-        println!("DEBUG mode: Starting daemon with 'cargo run' for instance {}", args.instance);
-        daemon_command = Command::new("cargo");
-        daemon_command.args(["run", "--quiet", "-p", "localchat_daemon"]);
-    } else {
-        println!("RELEASE mode: Attempting to start pre-compiled daemon for instance {}", args.instance);
-        match env::current_exe() {
-            Ok(mut exe_path) => {
-                exe_path.pop(); 
-                let daemon_name = if cfg!(windows) { "localchat_daemon.exe" } else { "localchat_daemon" };
-                exe_path.push(daemon_name);
-                println!("Attempting to run daemon from: {:?}", exe_path);
-                daemon_command = Command::new(exe_path);
-            }
-            Err(e) => {
-                eprintln!("Failed to get current executable path: {}", e);
-                return Err(Box::new(e));
-            }
-        }
-    }
-
-    // Set environment variables for the daemon process
-    daemon_command.env("LOCALCHAT_TCP_PORT", daemon_tcp_port_for_instance.to_string());
-    daemon_command.env("LOCALCHAT_SOCKET_PATH", &daemon_socket_path_for_instance);
-
-    println!(
-        "Attempting to start localchat_daemon for instance {} with TCP Port: {}, Socket: {}, Command: {:?}",
-        args.instance, daemon_tcp_port_for_instance, daemon_socket_path_for_instance, daemon_command
-    );
-    match daemon_command.spawn() {
-        Ok(mut child) => {
-            println!(
-                "Successfully spawned localchat_daemon process for instance {}. PID: {}",
-                args.instance, child.id()
-            );
-            // Optionally, manage child process, e.g., kill on GUI exit
-        }
-        Err(e) => {
-            eprintln!("Failed to start localchat_daemon for instance {}: {}.", args.instance, e);
-            // Continue without daemon for GUI testing if needed, or return error
-        }
-    }
+    // Attach to this instance's daemon if one is already running, otherwise start it
+    let mut daemon = daemon_control::DaemonControl::new(args.instance);
+    daemon.ensure_running();
 
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default().with_inner_size([1024.0, 768.0]),
         ..Default::default()
     };
 
-    // Pass the correct socket path to ChatApp::new
-    let app_socket_path = daemon_socket_path_for_instance.clone();
-
     eframe::run_native(
         &format!("Local Chat GUI - Instance {}", args.instance), // Unique window title
         options,
-        Box::new(move |cc| Ok(Box::new(ChatApp::new(cc, app_socket_path, username_file_path_for_instance)))), // Pass new path
+        Box::new(move |cc| Ok(Box::new(ChatApp::new(cc, daemon, username_file_path_for_instance, settings_file_path_for_instance, args.instance)))), // Pass new path
     ).map_err(|e| Box::new(e) as Box<dyn Error>)?;
     Ok(())
 }
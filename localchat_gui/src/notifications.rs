@@ -0,0 +1,337 @@
+// Which incoming messages get a desktop notification, and what it shows: per-peer/room mutes (for a while or until
+// unmuted), do-not-disturb, quiet hours, and hiding the message text. Saved with the other GUI settings.
+// Notifier shows them: one notification per conversation that updates as messages come in, with Open and Reply
+// actions where the notification server supports them (freedesktop servers on Linux/BSD).
+use chrono::{DateTime, Local, NaiveTime, Utc};
+use eframe::egui;
+use notify_rust::Notification;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::mpsc;
+
+// Shown instead of the message when hide_message_text is on
+pub const HIDDEN_MESSAGE_TEXT: &str = "Open LocalChat to read it.";
+
+// Latest messages listed in a conversation's notification; the title counts all of them
+const MAX_NOTIFICATION_LINES: usize = 3;
+
+// How /mute or the sidebar menu changes a conversation's mute
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MuteChange {
+    For(u64), // Seconds
+    UntilUnmuted,
+    Off,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationSettings {
+    pub muted: BTreeMap<String, Option<DateTime<Utc>>>, // Peer/room ID -> muted until (None: until unmuted)
+    pub do_not_disturb: bool,
+    pub quiet_hours_enabled: bool,
+    pub quiet_hours_start: NaiveTime, // Local time
+    pub quiet_hours_end: NaiveTime, // May be before the start: 22:00 to 07:00 spans midnight
+    pub hide_message_text: bool,
+}
+
+impl Default for NotificationSettings {
+    fn default() -> Self {
+        Self {
+            muted: BTreeMap::new(),
+            do_not_disturb: false,
+            quiet_hours_enabled: false,
+            quiet_hours_start: NaiveTime::from_hms_opt(22, 0, 0).unwrap_or_default(),
+            quiet_hours_end: NaiveTime::from_hms_opt(7, 0, 0).unwrap_or_default(),
+            hide_message_text: false,
+        }
+    }
+}
+
+impl NotificationSettings {
+    // Muted, and the mute hasn't run out yet
+    pub fn is_muted(&self, conversation: &str, now: DateTime<Utc>) -> bool {
+        self.muted.get(conversation).is_some_and(|until| until.is_none_or(|until| until > now))
+    }
+
+    // "Muted until 17:30" or "Muted until you unmute", for hints and hover text; None if not muted
+    pub fn describe_mute(&self, conversation: &str, now: DateTime<Utc>) -> Option<String> {
+        if !self.is_muted(conversation, now) {
+            return None;
+        }
+        Some(match self.muted.get(conversation).copied().flatten() {
+            Some(until) => {
+                let local = until.with_timezone(&Local);
+                let format = if local.date_naive() == Local::now().date_naive() { "%H:%M" } else { "%a %b %d, %H:%M" };
+                format!("Muted until {}", local.format(format))
+            }
+            None => "Muted until you unmute".to_string(),
+        })
+    }
+
+    pub fn change_mute(&mut self, conversation: &str, change: MuteChange, now: DateTime<Utc>) {
+        match change {
+            MuteChange::For(secs) => {
+                // A mute too long for a date (like "/mute 10000000000000000s") lasts until unmuted
+                let until = i64::try_from(secs).ok()
+                    .and_then(chrono::TimeDelta::try_seconds)
+                    .and_then(|length| now.checked_add_signed(length));
+                self.muted.insert(conversation.to_string(), until);
+            }
+            MuteChange::UntilUnmuted => {
+                self.muted.insert(conversation.to_string(), None);
+            }
+            MuteChange::Off => {
+                self.muted.remove(conversation);
+            }
+        }
+        // Mutes that ran out would otherwise stay in the settings file forever
+        self.muted.retain(|_, until| until.is_none_or(|until| until > now));
+    }
+
+    pub fn in_quiet_hours(&self, now: NaiveTime) -> bool {
+        if !self.quiet_hours_enabled {
+            return false;
+        }
+        if self.quiet_hours_start <= self.quiet_hours_end {
+            self.quiet_hours_start <= now && now < self.quiet_hours_end
+        } else {
+            now >= self.quiet_hours_start || now < self.quiet_hours_end
+        }
+    }
+
+    // Why a message in this conversation gets no notification, or None to show one.
+    // A mention still gets through a muted conversation, but not do-not-disturb or quiet hours.
+    pub fn silenced_because(&self, conversation: &str, mentions_me: bool, now: DateTime<Local>) -> Option<&'static str> {
+        if self.do_not_disturb {
+            Some("do not disturb is on")
+        } else if self.in_quiet_hours(now.time()) {
+            Some("quiet hours")
+        } else if self.is_muted(conversation, now.with_timezone(&Utc)) && !mentions_me {
+            Some("conversation is muted")
+        } else {
+            None
+        }
+    }
+}
+
+// What the user did with a notification; either way the window comes to the front on that conversation
+#[derive(Debug, Clone, PartialEq)]
+pub enum NotificationAction {
+    Open(String), // Conversation ID
+    Reply(String), // Same, with the cursor in the message input (the server can't hand us typed text)
+}
+
+// Sent from the thread waiting on a notification
+enum NotificationEvent {
+    Action(NotificationAction),
+    Closed { conversation: String, id: u32 }, // Dismissed or timed out without an action
+}
+
+// The notification currently on screen for a conversation
+struct ShownNotification {
+    id: u32,
+    count: usize, // Messages it stands for
+    lines: Vec<String>, // The latest MAX_NOTIFICATION_LINES of them
+}
+
+pub struct Notifier {
+    shown: HashMap<String, ShownNotification>, // Per conversation until it's acted on, closed, or the conversation is read
+    events_tx: mpsc::Sender<NotificationEvent>,
+    events_rx: mpsc::Receiver<NotificationEvent>,
+    repaint_ctx: egui::Context, // Woken when an action comes in, so the window reacts right away
+    supports_actions: Option<bool>, // Asked from the notification server the first time
+}
+
+impl Notifier {
+    pub fn new(repaint_ctx: egui::Context) -> Self {
+        let (events_tx, events_rx) = mpsc::channel();
+        Self { shown: HashMap::new(), events_tx, events_rx, repaint_ctx, supports_actions: None }
+    }
+
+    // Shows a notification for a new message, or updates the one already shown for its conversation.
+    // `title` is used for a single message, `conversation_name` once several are counted; `line` is None when
+    // message text is hidden.
+    pub fn notify(&mut self, conversation: &str, conversation_name: &str, title: &str, line: Option<String>) {
+        let previous = self.shown.remove(conversation);
+        let count = previous.as_ref().map_or(0, |shown| shown.count) + 1;
+        let mut lines = previous.as_ref().map(|shown| shown.lines.clone()).unwrap_or_default();
+        if let Some(line) = line.as_ref() {
+            lines.push(line.clone());
+            if lines.len() > MAX_NOTIFICATION_LINES {
+                lines.remove(0);
+            }
+        }
+        let summary = if count == 1 {
+            title.to_string()
+        } else if crate::is_room_id(conversation) {
+            format!("{} new messages in {}", count, conversation_name)
+        } else {
+            format!("{} new messages from {}", count, conversation_name)
+        };
+        let body = if line.is_none() { HIDDEN_MESSAGE_TEXT.to_string() } else { lines.join("\n") };
+
+        let mut notification = Notification::new();
+        notification.summary(&summary).body(&body).icon("dialog-information").appname("LocalChatGUI");
+        match self.show(notification, conversation, previous.map(|shown| shown.id)) {
+            Ok(id) => {
+                self.shown.insert(conversation.to_string(), ShownNotification { id, count, lines });
+            }
+            Err(e) => eprintln!("Error displaying notification: {}", e),
+        }
+    }
+
+    // The conversation has been read: its next message starts a new notification
+    pub fn clear(&mut self, conversation: &str) {
+        self.shown.remove(conversation);
+    }
+
+    // Actions taken on notifications since the last frame
+    pub fn poll(&mut self) -> Vec<NotificationAction> {
+        let mut actions = Vec::new();
+        while let Ok(event) = self.events_rx.try_recv() {
+            match event {
+                NotificationEvent::Action(action) => {
+                    let (NotificationAction::Open(conversation) | NotificationAction::Reply(conversation)) = &action;
+                    self.shown.remove(conversation);
+                    actions.push(action);
+                }
+                NotificationEvent::Closed { conversation, id } => {
+                    if self.shown.get(&conversation).is_some_and(|shown| shown.id == id) {
+                        self.shown.remove(&conversation);
+                    }
+                }
+            }
+        }
+        actions
+    }
+
+    // Replaces the notification `replaces` (same ID) if given. The first notification of a conversation gets a thread
+    // that waits for its action; updates keep the ID, so that thread hears about them too.
+    #[cfg(all(unix, not(target_os = "macos")))]
+    fn show(&mut self, mut notification: Notification, conversation: &str, replaces: Option<u32>) -> Result<u32, notify_rust::error::Error> {
+        let supports_actions = *self.supports_actions.get_or_insert_with(|| {
+            notify_rust::get_capabilities().is_ok_and(|capabilities| capabilities.iter().any(|capability| capability == "actions"))
+        });
+        if supports_actions {
+            notification.action("default", "Open").action("reply", "Reply");
+        }
+        if let Some(id) = replaces {
+            notification.id(id);
+        }
+        let handle = notification.show()?;
+        let id = handle.id();
+        // A notification that was closed meanwhile comes back under a new ID and needs its own thread
+        if supports_actions && replaces != Some(id) {
+            let events_tx = self.events_tx.clone();
+            let repaint_ctx = self.repaint_ctx.clone();
+            let conversation = conversation.to_string();
+            std::thread::spawn(move || {
+                handle.wait_for_action(|action| {
+                    let event = match action {
+                        "default" => NotificationEvent::Action(NotificationAction::Open(conversation)),
+                        "reply" => NotificationEvent::Action(NotificationAction::Reply(conversation)),
+                        _ => NotificationEvent::Closed { conversation, id }, // "__closed"
+                    };
+                    let _ = events_tx.send(event);
+                    repaint_ctx.request_repaint();
+                });
+            });
+        }
+        Ok(id)
+    }
+
+    // Elsewhere notifications can't be replaced or acted on; each update is a new one
+    #[cfg(not(all(unix, not(target_os = "macos"))))]
+    fn show(&mut self, notification: Notification, _conversation: &str, _replaces: Option<u32>) -> Result<u32, notify_rust::error::Error> {
+        notification.show().map(|_| 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn time(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    fn quiet_hours(start: NaiveTime, end: NaiveTime) -> NotificationSettings {
+        NotificationSettings { quiet_hours_enabled: true, quiet_hours_start: start, quiet_hours_end: end, ..Default::default() }
+    }
+
+    #[test]
+    fn quiet_hours_within_one_day() {
+        let settings = quiet_hours(time(12, 0), time(13, 30));
+        assert!(!settings.in_quiet_hours(time(11, 59)));
+        assert!(settings.in_quiet_hours(time(12, 0)));
+        assert!(settings.in_quiet_hours(time(13, 29)));
+        assert!(!settings.in_quiet_hours(time(13, 30)));
+    }
+
+    #[test]
+    fn quiet_hours_across_midnight() {
+        let settings = quiet_hours(time(22, 0), time(7, 0));
+        assert!(settings.in_quiet_hours(time(22, 0)));
+        assert!(settings.in_quiet_hours(time(0, 0)));
+        assert!(settings.in_quiet_hours(time(6, 59)));
+        assert!(!settings.in_quiet_hours(time(7, 0)));
+        assert!(!settings.in_quiet_hours(time(21, 59)));
+    }
+
+    #[test]
+    fn quiet_hours_only_apply_when_enabled() {
+        let settings = NotificationSettings { quiet_hours_enabled: false, ..quiet_hours(time(0, 0), time(23, 59)) };
+        assert!(!settings.in_quiet_hours(time(12, 0)));
+    }
+
+    #[test]
+    fn timed_mutes_run_out_and_untimed_ones_do_not() {
+        let now = Utc::now();
+        let mut settings = NotificationSettings::default();
+        settings.change_mute("#lunch", MuteChange::For(3_600), now);
+        settings.change_mute("bob - 1", MuteChange::UntilUnmuted, now);
+        assert!(settings.is_muted("#lunch", now + chrono::TimeDelta::minutes(59)));
+        assert!(!settings.is_muted("#lunch", now + chrono::TimeDelta::hours(1)));
+        assert!(settings.is_muted("bob - 1", now + chrono::TimeDelta::days(365)));
+        assert!(!settings.is_muted("carol - 2", now));
+        assert_eq!(settings.describe_mute("bob - 1", now).as_deref(), Some("Muted until you unmute"));
+        assert_eq!(settings.describe_mute("#lunch", now + chrono::TimeDelta::hours(2)), None);
+    }
+
+    #[test]
+    fn mutes_too_long_for_a_date_last_until_unmuted() {
+        let now = Utc::now();
+        let mut settings = NotificationSettings::default();
+        for (conversation, secs) in [("#lunch", 10_000_000_000_000_000), ("bob - 1", 15_250_000 * 7 * 86_400), ("carol - 2", u64::MAX)] {
+            settings.change_mute(conversation, MuteChange::For(secs), now);
+            assert_eq!(settings.muted.get(conversation), Some(&None), "{}", conversation);
+            assert_eq!(settings.describe_mute(conversation, now).as_deref(), Some("Muted until you unmute"));
+        }
+    }
+
+    #[test]
+    fn unmuting_and_expired_mutes_are_removed_from_the_settings() {
+        let now = Utc::now();
+        let mut settings = NotificationSettings::default();
+        settings.change_mute("#lunch", MuteChange::For(60), now);
+        settings.change_mute("bob - 1", MuteChange::UntilUnmuted, now);
+        settings.change_mute("bob - 1", MuteChange::Off, now + chrono::TimeDelta::minutes(5));
+        assert!(settings.muted.is_empty()); // #lunch ran out before the second change
+    }
+
+    #[test]
+    fn mentions_get_through_a_mute_but_not_do_not_disturb_or_quiet_hours() {
+        let now = Local.with_ymd_and_hms(2026, 1, 5, 12, 0, 0).unwrap();
+        let mut settings = NotificationSettings::default();
+        settings.change_mute("#lunch", MuteChange::UntilUnmuted, now.with_timezone(&Utc));
+        assert_eq!(settings.silenced_because("#lunch", false, now), Some("conversation is muted"));
+        assert_eq!(settings.silenced_because("#lunch", true, now), None);
+        assert_eq!(settings.silenced_because("bob - 1", false, now), None);
+
+        let quiet = NotificationSettings { muted: settings.muted.clone(), ..quiet_hours(time(11, 0), time(13, 0)) };
+        assert_eq!(quiet.silenced_because("#lunch", true, now), Some("quiet hours"));
+        let do_not_disturb = NotificationSettings { do_not_disturb: true, ..quiet };
+        assert_eq!(do_not_disturb.silenced_because("bob - 1", true, now), Some("do not disturb is on"));
+    }
+}
@@ -0,0 +1,422 @@
+// Slash commands typed into the chat input ("/me waves", "/join lunch", ...).
+// Parsing lives here; ChatApp::run_slash_command turns a parsed command into daemon commands or local UI actions.
+use crate::notifications::MuteChange;
+
+pub struct SlashCommandSpec {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub description: &'static str,
+}
+
+// Order matters: it's the order shown in autocompletion and /help
+pub const SLASH_COMMANDS: &[SlashCommandSpec] = &[
+    SlashCommandSpec { name: "me", usage: "/me <action>", description: "Send an action, e.g. \"* alice waves\"" },
+    SlashCommandSpec { name: "status", usage: "/status [away|busy|text]", description: "Set your status; no argument sets you back to online" },
+    SlashCommandSpec { name: "nick", usage: "/nick <name>", description: "Change your username" },
+    SlashCommandSpec { name: "clear", usage: "/clear", description: "Clear this conversation from the window (history is kept)" },
+    SlashCommandSpec { name: "file", usage: "/file <path>", description: "Send a file (up to 10 MB)" },
+    SlashCommandSpec { name: "mute", usage: "/mute [1h|8h|1d|off]", description: "Mute notifications for this conversation (for a while, or until /mute again)" },
+    SlashCommandSpec { name: "join", usage: "/join <room>", description: "Join a room, e.g. /join lunch" },
+    SlashCommandSpec { name: "leave", usage: "/leave [room]", description: "Leave a room (the open one if none is given)" },
+    SlashCommandSpec { name: "timer", usage: "/timer <30m|1h|1d|1w|off>", description: "Make new messages in this conversation disappear, for everyone in it" },
+    SlashCommandSpec { name: "later", usage: "/later <17:00|30m|tomorrow 9:00> <text>", description: "Send a message later (or once the peer is back online)" },
+    SlashCommandSpec { name: "shrug", usage: "/shrug [text]", description: "Send your text followed by ¯\\_(ツ)_/¯" },
+    SlashCommandSpec { name: "help", usage: "/help", description: "List the available commands" },
+];
+
+// The daemon refuses scheduled messages further ahead than this
+const MAX_SCHEDULE_AHEAD_DAYS: i64 = 365;
+
+pub const SHRUG: &str = "¯\\_(ツ)_/¯";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SlashCommand {
+    Me(String),
+    Status(Option<String>), // None = back to online
+    Nick(String),
+    Clear,
+    File(String),
+    Mute(Option<MuteChange>), // None toggles between muted (until unmuted) and not muted
+    Join(String),
+    Leave(Option<String>),
+    Timer(Option<u64>), // Seconds; None turns disappearing messages off
+    Later { send_at: chrono::DateTime<chrono::Utc>, text: String },
+    Shrug(String),
+    Help,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParsedInput {
+    Text(String), // A normal message; "//text" is sent as "/text"
+    Command(SlashCommand),
+    Invalid(String), // Inline help to show instead of sending anything
+}
+
+pub fn parse(input: &str) -> ParsedInput {
+    let trimmed_input = input.trim();
+    if let Some(escaped) = trimmed_input.strip_prefix("//") {
+        return ParsedInput::Text(format!("/{}", escaped));
+    }
+    let Some(command_line) = trimmed_input.strip_prefix('/') else {
+        return ParsedInput::Text(trimmed_input.to_string());
+    };
+
+    let (name, args) = match command_line.split_once(char::is_whitespace) {
+        Some((name, args)) => (name, args.trim()),
+        None => (command_line, ""),
+    };
+    match parse_command(&name.to_lowercase(), args) {
+        Ok(command) => ParsedInput::Command(command),
+        Err(help) => ParsedInput::Invalid(help),
+    }
+}
+
+fn parse_command(name: &str, args: &str) -> Result<SlashCommand, String> {
+    let command = match name {
+        "me" => SlashCommand::Me(required_arg("me", args)?),
+        "status" => SlashCommand::Status(match args {
+            "" => None,
+            online if online.eq_ignore_ascii_case("online") => None,
+            status => Some(status.to_string()),
+        }),
+        "nick" => SlashCommand::Nick(required_arg("nick", args)?),
+        "clear" => SlashCommand::Clear,
+        "file" => SlashCommand::File(expand_home(unquote(&required_arg("file", args)?))),
+        "mute" => SlashCommand::Mute(match args {
+            "" => None,
+            duration => match parse_duration(duration) {
+                Ok(Some(secs)) if secs > 0 => Some(MuteChange::For(secs)),
+                Ok(None) => Some(MuteChange::Off),
+                _ => return Err(format!("Usage: {}  —  {}", spec("mute").usage, spec("mute").description)),
+            },
+        }),
+        "join" => SlashCommand::Join(required_arg("join", args)?),
+        "leave" => SlashCommand::Leave(Some(args.to_string()).filter(|room| !room.is_empty())),
+        "timer" => SlashCommand::Timer(parse_duration(&required_arg("timer", args)?)?),
+        "later" => {
+            let usage = || format!("Usage: {}  —  {}", spec("later").usage, spec("later").description);
+            let args = required_arg("later", args)?;
+            // "tomorrow 9:00" is the only time that takes two words
+            let when_words = if args.to_lowercase().starts_with("tomorrow ") { 2 } else { 1 };
+            let mut words = args.splitn(when_words + 1, char::is_whitespace);
+            let when = words.by_ref().take(when_words).collect::<Vec<_>>().join(" ");
+            let text = words.next().map(str::trim).unwrap_or_default().to_string();
+            if text.is_empty() {
+                return Err(usage());
+            }
+            SlashCommand::Later { send_at: parse_send_time(&when)?, text }
+        }
+        "shrug" => SlashCommand::Shrug(args.to_string()),
+        "help" | "?" => SlashCommand::Help,
+        _ => return Err(unknown_command_help(name)),
+    };
+    Ok(command)
+}
+
+// Commands matching what has been typed so far; empty once the user is past the command name
+pub fn completions(input: &str) -> Vec<&'static SlashCommandSpec> {
+    let Some(partial_name) = input.strip_prefix('/') else {
+        return Vec::new();
+    };
+    if partial_name.starts_with('/') || partial_name.contains(char::is_whitespace) {
+        return Vec::new();
+    }
+    let partial_name = partial_name.to_lowercase();
+    SLASH_COMMANDS.iter().filter(|spec| spec.name.starts_with(&partial_name)).collect()
+}
+
+pub fn help_text() -> String {
+    let mut help = String::from("Commands:");
+    for spec in SLASH_COMMANDS {
+        help.push_str(&format!("\n{}  —  {}", spec.usage, spec.description));
+    }
+    help.push_str("\nStart a message with // to send text that begins with /.");
+    help
+}
+
+fn spec(name: &str) -> &'static SlashCommandSpec {
+    SLASH_COMMANDS.iter().find(|spec| spec.name == name).expect("every parsed command has a spec")
+}
+
+// A missing argument becomes the command's usage line
+fn required_arg(name: &str, args: &str) -> Result<String, String> {
+    if args.is_empty() {
+        let spec = spec(name);
+        Err(format!("Usage: {}  —  {}", spec.usage, spec.description))
+    } else {
+        Ok(args.to_string())
+    }
+}
+
+fn unknown_command_help(name: &str) -> String {
+    let suggestions: Vec<&str> = SLASH_COMMANDS
+        .iter()
+        .filter(|spec| spec.name.starts_with(name) || edit_distance(spec.name, name) <= 2)
+        .map(|spec| spec.usage)
+        .collect();
+    let mut help = format!("Unknown command /{}.", name);
+    if !suggestions.is_empty() {
+        help.push_str(&format!(" Did you mean {}?", suggestions.join(" or ")));
+    }
+    help.push_str(" Type /help to list commands, or start with // to send text beginning with /.");
+    help
+}
+
+// "30s", "10m", "1h", "2d", "1w" into seconds; "off" into None
+fn parse_duration(text: &str) -> Result<Option<u64>, String> {
+    let text = text.to_lowercase();
+    if text == "off" {
+        return Ok(None);
+    }
+    let split_at = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let (count, unit) = text.split_at(split_at);
+    let unit_secs = match unit.trim() {
+        "s" => 1,
+        "m" | "" => 60, // A bare number is minutes
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(format!("Usage: {}  —  {}", spec("timer").usage, spec("timer").description)),
+    };
+    match count.parse::<u64>().ok().and_then(|count| count.checked_mul(unit_secs)) {
+        Some(secs) => Ok(Some(secs)),
+        None => Err(format!("Usage: {}  —  {}", spec("timer").usage, spec("timer").description)),
+    }
+}
+
+// When to send a scheduled message: "17:00" (the next time the clock shows it), "tomorrow 9:00",
+// a delay like "30m" or "2h", or "2025-06-01 09:00"; all in local time
+pub fn parse_send_time(text: &str) -> Result<chrono::DateTime<chrono::Utc>, String> {
+    use chrono::TimeZone;
+    let text = text.trim().to_lowercase();
+    let invalid = || format!("'{}' is not a time like 17:00, tomorrow 9:00, 30m or 2025-06-01 09:00.", text);
+    let now = chrono::Local::now();
+    let local_date_time = if let Ok(date_time) = chrono::NaiveDateTime::parse_from_str(&text, "%Y-%m-%d %H:%M") {
+        date_time
+    } else if let Some(time) = text.strip_prefix("tomorrow ") {
+        let time = chrono::NaiveTime::parse_from_str(time.trim(), "%H:%M").map_err(|_| invalid())?;
+        now.date_naive().succ_opt().ok_or_else(invalid)?.and_time(time)
+    } else if let Ok(time) = chrono::NaiveTime::parse_from_str(&text, "%H:%M") {
+        let date = if time > now.time() { now.date_naive() } else { now.date_naive().succ_opt().ok_or_else(invalid)? };
+        date.and_time(time)
+    } else {
+        return match parse_duration(&text) {
+            Ok(Some(secs)) if secs > 0 => delay_from_now(secs)
+                .ok_or_else(|| format!("Messages can be scheduled at most {} days ahead.", MAX_SCHEDULE_AHEAD_DAYS)),
+            _ => Err(invalid()),
+        };
+    };
+    chrono::Local.from_local_datetime(&local_date_time)
+        .earliest()
+        .map(|local| local.with_timezone(&chrono::Utc))
+        .ok_or_else(|| format!("{} doesn't exist in the local time zone.", local_date_time))
+}
+
+// Now plus a delay, or None past the daemon's scheduling limit (which also keeps the date arithmetic from overflowing)
+fn delay_from_now(secs: u64) -> Option<chrono::DateTime<chrono::Utc>> {
+    let delay = chrono::TimeDelta::try_seconds(i64::try_from(secs).ok()?)?;
+    if delay > chrono::TimeDelta::days(MAX_SCHEDULE_AHEAD_DAYS) {
+        return None;
+    }
+    chrono::Utc::now().checked_add_signed(delay)
+}
+
+// "Today 17:00", "Tomorrow 09:00" or "Tue Jun 03, 09:00", in local time
+pub fn describe_send_time(send_at: chrono::DateTime<chrono::Utc>) -> String {
+    let local = send_at.with_timezone(&chrono::Local);
+    let today = chrono::Local::now().date_naive();
+    if local.date_naive() == today {
+        format!("Today {}", local.format("%H:%M"))
+    } else if today.succ_opt() == Some(local.date_naive()) {
+        format!("Tomorrow {}", local.format("%H:%M"))
+    } else {
+        local.format("%a %b %d, %H:%M").to_string()
+    }
+}
+
+// "1 hour", "2 days"; the largest unit the duration is a whole number of
+pub fn describe_duration(secs: u64) -> String {
+    let (count, unit) = match secs {
+        secs if secs % (7 * 86_400) == 0 => (secs / (7 * 86_400), "week"),
+        secs if secs % 86_400 == 0 => (secs / 86_400, "day"),
+        secs if secs % 3_600 == 0 => (secs / 3_600, "hour"),
+        secs if secs % 60 == 0 => (secs / 60, "minute"),
+        secs => (secs, "second"),
+    };
+    format!("{} {}{}", count, unit, if count == 1 { "" } else { "s" })
+}
+
+// Paths copied from a file manager often come quoted
+fn unquote(path: &str) -> &str {
+    path.strip_prefix('"').and_then(|p| p.strip_suffix('"'))
+        .or_else(|| path.strip_prefix('\'').and_then(|p| p.strip_suffix('\'')))
+        .unwrap_or(path)
+}
+
+fn expand_home(path: &str) -> String {
+    if let Some(rest) = path.strip_prefix("~/") {
+        if let Some(home_dir) = home::home_dir() {
+            return home_dir.join(rest).to_string_lossy().into_owned();
+        }
+    }
+    path.to_string()
+}
+
+// Levenshtein distance, only used to suggest a command for typos like /stauts
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b_chars: Vec<char> = b.chars().collect();
+    let mut previous_row: Vec<usize> = (0..=b_chars.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current_row = vec![i + 1];
+        for (j, b_char) in b_chars.iter().enumerate() {
+            let substitution_cost = if a_char == *b_char { 0 } else { 1 };
+            current_row.push(
+                (previous_row[j] + substitution_cost)
+                    .min(previous_row[j + 1] + 1)
+                    .min(current_row[j] + 1),
+            );
+        }
+        previous_row = current_row;
+    }
+    previous_row[b_chars.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(input: &str) -> SlashCommand {
+        match parse(input) {
+            ParsedInput::Command(command) => command,
+            other => panic!("{:?} parsed as {:?}", input, other),
+        }
+    }
+
+    fn is_invalid(input: &str) -> bool {
+        matches!(parse(input), ParsedInput::Invalid(_))
+    }
+
+    #[test]
+    fn plain_and_escaped_text_is_sent_as_is() {
+        assert_eq!(parse("  hello  "), ParsedInput::Text("hello".to_string()));
+        assert_eq!(parse("//me is not a command"), ParsedInput::Text("/me is not a command".to_string()));
+        assert_eq!(parse("a /me in the middle"), ParsedInput::Text("a /me in the middle".to_string()));
+    }
+
+    #[test]
+    fn commands_take_their_arguments() {
+        assert_eq!(command("/me waves  "), SlashCommand::Me("waves".to_string()));
+        assert_eq!(command("/ME waves"), SlashCommand::Me("waves".to_string()));
+        assert_eq!(command("/nick Ann Lee"), SlashCommand::Nick("Ann Lee".to_string()));
+        assert_eq!(command("/join lunch"), SlashCommand::Join("lunch".to_string()));
+        assert_eq!(command("/leave"), SlashCommand::Leave(None));
+        assert_eq!(command("/leave #lunch"), SlashCommand::Leave(Some("#lunch".to_string())));
+        assert_eq!(command("/shrug"), SlashCommand::Shrug(String::new()));
+        assert_eq!(command("/?"), SlashCommand::Help);
+        assert_eq!(command("/file \"notes.txt\""), SlashCommand::File("notes.txt".to_string()));
+    }
+
+    #[test]
+    fn missing_arguments_give_the_usage_line() {
+        for input in ["/me", "/nick  ", "/join", "/file", "/timer", "/later", "/later 17:00"] {
+            assert!(is_invalid(input), "{} should be invalid", input);
+        }
+        assert_eq!(parse("/me"), ParsedInput::Invalid(format!("Usage: {}  —  {}", spec("me").usage, spec("me").description)));
+    }
+
+    #[test]
+    fn status_online_or_empty_clears_it() {
+        assert_eq!(command("/status"), SlashCommand::Status(None));
+        assert_eq!(command("/status Online"), SlashCommand::Status(None));
+        assert_eq!(command("/status in a meeting"), SlashCommand::Status(Some("in a meeting".to_string())));
+    }
+
+    #[test]
+    fn mute_and_timer_take_durations() {
+        assert_eq!(command("/mute"), SlashCommand::Mute(None));
+        assert_eq!(command("/mute 8h"), SlashCommand::Mute(Some(MuteChange::For(8 * 3_600))));
+        assert_eq!(command("/mute off"), SlashCommand::Mute(Some(MuteChange::Off)));
+        assert!(is_invalid("/mute 0m"));
+        assert!(is_invalid("/mute soon"));
+        assert_eq!(command("/timer 1w"), SlashCommand::Timer(Some(7 * 86_400)));
+        assert_eq!(command("/timer 45"), SlashCommand::Timer(Some(45 * 60)));
+        assert_eq!(command("/timer OFF"), SlashCommand::Timer(None));
+        assert!(is_invalid("/timer 99999999999999999w"));
+    }
+
+    #[test]
+    fn later_splits_the_time_from_the_text() {
+        let before = chrono::Utc::now();
+        let SlashCommand::Later { send_at, text } = command("/later 30m stand-up in  five") else {
+            panic!("not a /later command");
+        };
+        assert_eq!(text, "stand-up in  five");
+        assert!(send_at >= before + chrono::TimeDelta::minutes(30));
+        assert!(send_at <= chrono::Utc::now() + chrono::TimeDelta::minutes(30));
+
+        let SlashCommand::Later { send_at, text } = command("/later tomorrow 9:00 call the bank") else {
+            panic!("not a /later command");
+        };
+        assert_eq!(text, "call the bank");
+        let local = send_at.with_timezone(&chrono::Local);
+        assert_eq!(local.date_naive(), chrono::Local::now().date_naive().succ_opt().unwrap());
+        assert_eq!(local.format("%H:%M").to_string(), "09:00");
+    }
+
+    #[test]
+    fn send_times_are_in_the_future() {
+        let now = chrono::Utc::now();
+        assert!(parse_send_time("17:00").unwrap() > now);
+        assert!(parse_send_time("2h").unwrap() > now + chrono::TimeDelta::minutes(119));
+        assert!(parse_send_time("0m").is_err());
+        assert!(parse_send_time("teatime").is_err());
+        assert!(parse_send_time("25:00").is_err());
+    }
+
+    #[test]
+    fn delays_past_the_scheduling_limit_are_refused() {
+        assert!(parse_send_time(&format!("{}d", MAX_SCHEDULE_AHEAD_DAYS)).is_ok());
+        for delay in [format!("{}d", MAX_SCHEDULE_AHEAD_DAYS + 1), "10000000000000000s".to_string(), "15250000w".to_string()] {
+            assert!(parse_send_time(&delay).is_err(), "{} should be refused", delay);
+        }
+    }
+
+    #[test]
+    fn huge_durations_never_panic() {
+        assert!(is_invalid("/later 10000000000000000s hi"));
+        assert!(is_invalid("/later 15250000w hi"));
+        assert!(is_invalid("/later 99999999999999999w hi"));
+        assert!(is_invalid("/mute 99999999999999999w"));
+        assert!(is_invalid("/timer 18446744073709551616s"));
+        // Fits in seconds; NotificationSettings::change_mute turns it into a mute until unmuted
+        assert_eq!(command("/mute 10000000000000000s"), SlashCommand::Mute(Some(MuteChange::For(10_000_000_000_000_000))));
+    }
+
+    #[test]
+    fn unknown_commands_suggest_close_ones() {
+        let ParsedInput::Invalid(help) = parse("/stauts away") else {
+            panic!("/stauts should be invalid");
+        };
+        assert!(help.starts_with("Unknown command /stauts."), "{}", help);
+        assert!(help.contains(spec("status").usage), "{}", help);
+    }
+
+    #[test]
+    fn completions_follow_the_typed_prefix() {
+        let names = |input: &str| completions(input).iter().map(|spec| spec.name).collect::<Vec<_>>();
+        assert_eq!(names("/s"), vec!["status", "shrug"]);
+        assert_eq!(names("/L"), vec!["leave", "later"]);
+        assert_eq!(names("/").len(), SLASH_COMMANDS.len());
+        assert!(names("/me waves").is_empty());
+        assert!(names("//").is_empty());
+        assert!(names("hello").is_empty());
+    }
+
+    #[test]
+    fn durations_are_described_in_whole_units() {
+        assert_eq!(describe_duration(45), "45 seconds");
+        assert_eq!(describe_duration(3_600), "1 hour");
+        assert_eq!(describe_duration(2 * 86_400), "2 days");
+        assert_eq!(describe_duration(14 * 86_400), "2 weeks");
+    }
+}
@@ -0,0 +1,201 @@
+// Tray icon (StatusNotifierItem over D-Bus, shown by KDE, most Linux panels and GNOME with the AppIndicator
+// extension). It shows the unread count and has a menu to open the window, set the status, or quit.
+// While it's there, closing the window only hides it, so notifications keep coming.
+// Without the "tray" feature (or off Linux) there is never an icon, and closing the window quits as before.
+use eframe::egui;
+#[cfg(all(feature = "tray", target_os = "linux"))]
+use ksni::TrayMethods;
+#[cfg(all(feature = "tray", target_os = "linux"))]
+use std::sync::mpsc;
+use std::sync::Arc;
+
+// Statuses offered in the tray menu; None is online, like /status without an argument
+#[cfg(all(feature = "tray", target_os = "linux"))]
+const TRAY_STATUSES: [(&str, Option<&str>); 3] = [("Online", None), ("Away", Some("away")), ("Busy", Some("busy"))];
+
+// What was picked in the tray; handled by the window in its next frame
+#[derive(Debug, Clone, PartialEq)]
+pub enum TrayEvent {
+    Open,
+    SetStatus(Option<String>),
+    Quit,
+}
+
+// State the tray host reads its title, icon and menu from
+#[cfg(all(feature = "tray", target_os = "linux"))]
+struct ChatTray {
+    instance: u16,
+    unread: u32,
+    status: Option<String>,
+    events_tx: mpsc::Sender<TrayEvent>,
+    repaint_ctx: egui::Context, // Woken so the window handles the event right away, even while hidden
+}
+
+#[cfg(all(feature = "tray", target_os = "linux"))]
+impl ChatTray {
+    fn send(&self, event: TrayEvent) {
+        let _ = self.events_tx.send(event);
+        self.repaint_ctx.request_repaint();
+    }
+}
+
+#[cfg(all(feature = "tray", target_os = "linux"))]
+impl ksni::Tray for ChatTray {
+    fn id(&self) -> String {
+        format!("localchat-gui-{}", self.instance)
+    }
+
+    fn title(&self) -> String {
+        if self.unread > 0 { format!("LocalChat ({} unread)", self.unread) } else { "LocalChat".to_string() }
+    }
+
+    fn icon_name(&self) -> String {
+        if self.unread > 0 { "mail-unread".to_string() } else { "internet-group-chat".to_string() }
+    }
+
+    // Panels that highlight NeedsAttention items show this one while there are unread messages
+    fn attention_icon_name(&self) -> String {
+        "mail-unread".to_string()
+    }
+
+    fn status(&self) -> ksni::Status {
+        if self.unread > 0 { ksni::Status::NeedsAttention } else { ksni::Status::Active }
+    }
+
+    fn tool_tip(&self) -> ksni::ToolTip {
+        let unread = match self.unread {
+            0 => "No unread messages".to_string(),
+            1 => "1 unread message".to_string(),
+            n => format!("{} unread messages", n),
+        };
+        let status = self.status.as_deref().unwrap_or("online");
+        ksni::ToolTip { title: self.title(), description: format!("{} · {}", unread, status), ..Default::default() }
+    }
+
+    // Left click
+    fn activate(&mut self, _x: i32, _y: i32) {
+        self.send(TrayEvent::Open);
+    }
+
+    fn menu(&self) -> Vec<ksni::MenuItem<Self>> {
+        use ksni::menu::{RadioGroup, RadioItem, StandardItem, SubMenu};
+        // A custom status (set with /status) selects none of the three
+        let selected = TRAY_STATUSES
+            .iter()
+            .position(|(_, status)| *status == self.status.as_deref())
+            .unwrap_or(TRAY_STATUSES.len());
+        vec![
+            StandardItem {
+                label: "Open LocalChat".to_string(),
+                activate: Box::new(|tray: &mut Self| tray.send(TrayEvent::Open)),
+                ..Default::default()
+            }
+            .into(),
+            SubMenu {
+                label: "Status".to_string(),
+                submenu: vec![RadioGroup {
+                    selected,
+                    select: Box::new(|tray: &mut Self, index| {
+                        if let Some((_, status)) = TRAY_STATUSES.get(index) {
+                            tray.status = status.map(str::to_string);
+                            tray.send(TrayEvent::SetStatus(tray.status.clone()));
+                        }
+                    }),
+                    options: TRAY_STATUSES
+                        .iter()
+                        .map(|(label, _)| RadioItem { label: label.to_string(), ..Default::default() })
+                        .collect(),
+                }
+                .into()],
+                ..Default::default()
+            }
+            .into(),
+            ksni::MenuItem::Separator,
+            StandardItem {
+                label: "Quit".to_string(),
+                icon_name: "application-exit".to_string(),
+                activate: Box::new(|tray: &mut Self| tray.send(TrayEvent::Quit)),
+                ..Default::default()
+            }
+            .into(),
+        ]
+    }
+}
+
+#[cfg(all(feature = "tray", target_os = "linux"))]
+pub struct TrayIcon {
+    handle: ksni::Handle<ChatTray>,
+    events_rx: mpsc::Receiver<TrayEvent>,
+    rt: Arc<tokio::runtime::Runtime>,
+    unread: u32, // Last count sent to the tray, so it's only updated on a change
+}
+
+#[cfg(all(feature = "tray", target_os = "linux"))]
+impl TrayIcon {
+    // None when there is no session bus or no tray host; the window then closes normally
+    pub fn start(rt: &Arc<tokio::runtime::Runtime>, repaint_ctx: egui::Context, instance: u16) -> Option<Self> {
+        let (events_tx, events_rx) = mpsc::channel();
+        let tray = ChatTray { instance, unread: 0, status: None, events_tx, repaint_ctx };
+        match rt.block_on(tray.spawn()) {
+            Ok(handle) => {
+                println!("GUI: Tray icon started; closing the window hides it to the tray.");
+                Some(Self { handle, events_rx, rt: rt.clone(), unread: 0 })
+            }
+            Err(e) => {
+                eprintln!("GUI: No tray icon ({}); closing the window quits.", e);
+                None
+            }
+        }
+    }
+
+    pub fn set_unread(&mut self, unread: u32) {
+        if unread == self.unread {
+            return;
+        }
+        self.unread = unread;
+        let handle = self.handle.clone();
+        self.rt.spawn(async move {
+            handle.update(|tray| tray.unread = unread).await;
+        });
+    }
+
+    // Keeps the menu's radio buttons in step with a status set elsewhere (/status)
+    pub fn set_status(&self, status: Option<String>) {
+        let handle = self.handle.clone();
+        self.rt.spawn(async move {
+            handle.update(|tray| tray.status = status).await;
+        });
+    }
+
+    // Items picked since the last frame
+    pub fn poll(&self) -> Vec<TrayEvent> {
+        self.events_rx.try_iter().collect()
+    }
+
+    // Whether the tray host is still there; if it went away, closing the window must quit again
+    pub fn is_running(&self) -> bool {
+        !self.handle.is_closed()
+    }
+}
+
+#[cfg(not(all(feature = "tray", target_os = "linux")))]
+pub struct TrayIcon;
+
+#[cfg(not(all(feature = "tray", target_os = "linux")))]
+impl TrayIcon {
+    pub fn start(_rt: &Arc<tokio::runtime::Runtime>, _repaint_ctx: egui::Context, _instance: u16) -> Option<Self> {
+        None
+    }
+
+    pub fn set_unread(&mut self, _unread: u32) {}
+
+    pub fn set_status(&self, _status: Option<String>) {}
+
+    pub fn poll(&self) -> Vec<TrayEvent> {
+        Vec::new()
+    }
+
+    pub fn is_running(&self) -> bool {
+        false
+    }
+}